@@ -0,0 +1,67 @@
+//! Baseline performance of the collision search. Scenes are generated from fixed seeds,
+//! so runs are comparable. Run with: cargo bench -p m_engine --features bench
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use m_engine::{bench, generators, Particle, ParticleClass, Polygon, Vec2, Wall, WallClass};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+const TIME_STEP: f64 = 0.01;
+
+// Particles on a square grid inside a box that fits it. Spacing is close enough for
+// neighbors to be checked
+fn make_box_scene(num_particles: usize) -> (Vec<Particle>, Vec<Wall>) {
+    let num_cells = ((num_particles as f64).sqrt().ceil() as usize).max(2) - 1;
+    let size = num_cells as f64 * 0.5;
+    let layout = generators::GridLayout::new(Vec2::ZERO, Vec2::UNIT_X, size, size, num_cells, num_cells);
+    let particles = generators::generate_grid(&layout, generators::random_velocity_seeded(5.0, 42), 0);
+    let walls = Wall::make_box(-1.0, -1.0, size + 1.0, size + 1.0, 0.5, 0);
+    return (particles, walls);
+}
+
+fn bench_resolve(c: &mut Criterion) {
+    let mut particle_classes = HashMap::new();
+    particle_classes.insert(0, ParticleClass::new("Particle", 1.0, 0.2));
+    let mut wall_classes = HashMap::new();
+    wall_classes.insert(0, WallClass::new("Wall", 0.0, 0.0));
+
+    let mut group = c.benchmark_group("resolve");
+    for num_particles in [100, 1000, 5000] {
+        let (particles, walls) = make_box_scene(num_particles);
+        // Cost of the search is measured in pair checks. Throughput shows them per second
+        let pair_checks = bench::resolve(&mut particles.clone(), &particle_classes, &walls, &wall_classes, TIME_STEP)
+            .pair_checks;
+        println!("resolve/{}: {} particles, {} pair checks", num_particles, particles.len(), pair_checks);
+        group.throughput(Throughput::Elements(pair_checks as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(num_particles), &particles, |b, particles| {
+            b.iter_batched(
+                || particles.clone(),
+                |mut particles| bench::resolve(&mut particles, &particle_classes, &walls, &wall_classes, TIME_STEP),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_particle_vs_polygon(c: &mut Criterion) {
+    let mut group = c.benchmark_group("particle_vs_polygon");
+    for num_vertices in [4, 16, 64, 256] {
+        // Regular polygon around the origin, particle flies into it from the side
+        let polygon = Polygon::from(
+            (0..num_vertices)
+                .map(|i| Vec2::from_angle_rad(2.0 * PI * i as f64 / num_vertices as f64) * 5.0)
+                .collect::<Vec<Vec2>>(),
+        );
+        let center = Vec2::new(-10.0, 0.3);
+        let velocity = Vec2::new(10.0, 0.0);
+        assert!(bench::find_particle_vs_polygon_collision(center, 0.2, velocity, &polygon).is_some());
+        group.bench_with_input(BenchmarkId::from_parameter(num_vertices), &polygon, |b, polygon| {
+            b.iter(|| bench::find_particle_vs_polygon_collision(center, 0.2, velocity, polygon))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_resolve, bench_particle_vs_polygon);
+criterion_main!(benches);
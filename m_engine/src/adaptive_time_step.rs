@@ -0,0 +1,79 @@
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{Particle, ParticleClass};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Chooses time step so that the fastest particle moves at most `fraction`
+/// of the smallest particle radius during the step.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AdaptiveTimeStep {
+    pub fraction: f64,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl AdaptiveTimeStep {
+    pub fn new(fraction: f64, min: Duration, max: Duration) -> Self {
+        assert!(min <= max);
+        AdaptiveTimeStep { fraction, min, max }
+    }
+
+    /// Time step for the current state of particles. Clamped to [min, max].
+    /// Returns max if nothing moves
+    pub fn choose(
+        &self,
+        particles: &[Particle],
+        particle_classes: &HashMap<ClassId, ParticleClass>,
+    ) -> Duration {
+        let mut max_speed: f64 = 0.0;
+        let mut min_radius = f64::INFINITY;
+        for particle in particles {
+            let class = get_class(particle_classes, particle.class());
+            max_speed = max_speed.max(particle.velocity.length());
+            min_radius = min_radius.min(particle.radius(class));
+        }
+        if max_speed <= 0.0 {
+            return self.max;
+        }
+        let time_step = Duration::from_secs_f64(self.fraction * min_radius / max_speed);
+        return time_step.clamp(self.min, self.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec2;
+
+    #[test]
+    fn test_time_step_shrinks_for_fast_particle() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        let adaptive = AdaptiveTimeStep::new(
+            0.1,
+            Duration::from_micros(10),
+            Duration::from_millis(10),
+        );
+
+        // Nothing moves
+        let mut particles = vec![Particle::new(Vec2::ZERO, Vec2::ZERO, 1)];
+        assert_eq!(adaptive.choose(&particles, &classes), Duration::from_millis(10));
+
+        // Slow particle. 0.1 * 0.5 / 10 = 5ms
+        particles.push(Particle::new(Vec2::new(5.0, 0.0), Vec2::new(10.0, 0.0), 1));
+        let slow = adaptive.choose(&particles, &classes);
+        assert_eq!(slow, Duration::from_millis(5));
+
+        // Fast particle makes it shorter. 0.1 * 0.5 / 1000 = 50us
+        particles.push(Particle::new(Vec2::new(-5.0, 0.0), Vec2::new(0.0, 1000.0), 1));
+        let fast = adaptive.choose(&particles, &classes);
+        assert!(fast < slow);
+        assert_eq!(fast, Duration::from_micros(50));
+
+        // Very fast particle hits the lower clamp
+        particles.push(Particle::new(Vec2::new(0.0, 5.0), Vec2::new(1e6, 0.0), 1));
+        assert_eq!(adaptive.choose(&particles, &classes), Duration::from_micros(10));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::Vec2;
+
+// Cells are not split deeper than this. Protects against coincident bodies
+const MAX_DEPTH: usize = 32;
+
+// Node of the quadtree. Leaves keep the list of their bodies,
+// inner nodes keep indices of their non-empty children
+#[derive(Debug)]
+struct Node {
+    center: Vec2,
+    half_size: f64,
+    mass: f64,
+    mass_center: Vec2,
+    children: Vec<usize>,
+    bodies: Vec<usize>,
+}
+
+impl Node {
+    fn contains(&self, point: Vec2) -> bool {
+        return (point.x - self.center.x).abs() <= self.half_size
+            && (point.y - self.center.y).abs() <= self.half_size;
+    }
+}
+
+/// Barnes-Hut quadtree over point masses. Approximates far-field gravity of a
+/// cell by its center of mass. Rebuild it whenever positions change.
+#[derive(Debug)]
+pub struct QuadTree {
+    nodes: Vec<Node>,
+    positions: Vec<Vec2>,
+    masses: Vec<f64>,
+}
+
+impl QuadTree {
+    /// Builds the tree. `positions` and `masses` must have the same length
+    pub fn new(positions: &[Vec2], masses: &[f64]) -> Self {
+        assert_eq!(positions.len(), masses.len());
+        let mut tree = QuadTree {
+            nodes: Vec::new(),
+            positions: positions.to_vec(),
+            masses: masses.to_vec(),
+        };
+        if positions.is_empty() {
+            return tree;
+        }
+        // Root cell is the square that encloses all bodies
+        let mut min = positions[0];
+        let mut max = positions[0];
+        for p in positions {
+            min = Vec2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vec2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        let center = (min + max) * 0.5;
+        let half_size = ((max.x - min.x).max(max.y - min.y) * 0.5).max(f64::EPSILON);
+        tree.build((0..positions.len()).collect(), center, half_size, 0);
+        return tree;
+    }
+
+    /// Recursively builds node from the bodies. Returns index of the node
+    fn build(&mut self, bodies: Vec<usize>, center: Vec2, half_size: f64, depth: usize) -> usize {
+        let mass: f64 = bodies.iter().map(|&b| self.masses[b]).sum();
+        let mass_center = if mass > 0.0 {
+            bodies.iter().fold(Vec2::ZERO, |acc, &b| {
+                acc + self.positions[b] * self.masses[b]
+            }) / mass
+        } else {
+            center
+        };
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            center,
+            half_size,
+            mass,
+            mass_center,
+            children: Vec::new(),
+            bodies: Vec::new(),
+        });
+        if bodies.len() <= 1 || depth >= MAX_DEPTH {
+            self.nodes[index].bodies = bodies;
+            return index;
+        }
+
+        // Split bodies between quadrants
+        let mut quadrants: [Vec<usize>; 4] = Default::default();
+        for b in bodies {
+            let p = self.positions[b];
+            let q = (p.x >= center.x) as usize + 2 * (p.y >= center.y) as usize;
+            quadrants[q].push(b);
+        }
+        let quarter = half_size * 0.5;
+        let mut children = Vec::new();
+        for (q, quadrant) in quadrants.into_iter().enumerate() {
+            if quadrant.is_empty() {
+                continue;
+            }
+            let offset = Vec2::new(
+                if q & 1 == 1 { quarter } else { -quarter },
+                if q & 2 == 2 { quarter } else { -quarter },
+            );
+            children.push(self.build(quadrant, center + offset, quarter, depth + 1));
+        }
+        self.nodes[index].children = children;
+        return index;
+    }
+
+    /// Gravitational acceleration of the body with given index caused by all other bodies.
+    /// Cells that look smaller than `theta` (cell size / distance) are replaced by their
+    /// center of mass. `theta` = 0 gives the exact sum.
+    pub fn acceleration(&self, body: usize, theta: f64, constant: f64, softening: f64) -> Vec2 {
+        if self.nodes.is_empty() {
+            return Vec2::ZERO;
+        }
+        let position = self.positions[body];
+        let mut acceleration = Vec2::ZERO;
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if node.children.is_empty() {
+                for &other in node.bodies.iter().filter(|&&other| other != body) {
+                    acceleration += pair_acceleration(
+                        position,
+                        self.positions[other],
+                        self.masses[other],
+                        constant,
+                        softening,
+                    );
+                }
+                continue;
+            }
+            let distance = (node.mass_center - position).length();
+            // Never approximate the cell the body belongs to
+            if !node.contains(position) && 2.0 * node.half_size < theta * distance {
+                acceleration +=
+                    pair_acceleration(position, node.mass_center, node.mass, constant, softening);
+            } else {
+                stack.extend(node.children.iter());
+            }
+        }
+        return acceleration;
+    }
+}
+
+/// Exact O(n²) gravitational accelerations of all bodies
+pub fn exact_accelerations(
+    positions: &[Vec2],
+    masses: &[f64],
+    constant: f64,
+    softening: f64,
+) -> Vec<Vec2> {
+    return (0..positions.len())
+        .map(|i| {
+            (0..positions.len())
+                .filter(|&j| j != i)
+                .fold(Vec2::ZERO, |acc, j| {
+                    acc + pair_acceleration(
+                        positions[i],
+                        positions[j],
+                        masses[j],
+                        constant,
+                        softening,
+                    )
+                })
+        })
+        .collect();
+}
+
+// Acceleration of the body at `position` towards the mass at `source`.
+// Softening keeps the force finite when bodies come very close
+fn pair_acceleration(
+    position: Vec2,
+    source: Vec2,
+    mass: f64,
+    constant: f64,
+    softening: f64,
+) -> Vec2 {
+    let delta = source - position;
+    let dist_sq = delta.length_sq() + softening * softening;
+    if dist_sq == 0.0 {
+        return Vec2::ZERO;
+    }
+    return delta * (constant * mass / (dist_sq * dist_sq.sqrt()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_barnes_hut_converges_to_exact() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let positions: Vec<Vec2> = (0..200)
+            .map(|_| Vec2::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0)))
+            .collect();
+        let masses: Vec<f64> = (0..200).map(|_| rng.gen_range(0.5..2.0)).collect();
+        let exact = exact_accelerations(&positions, &masses, 1.0, 0.1);
+        let tree = QuadTree::new(&positions, &masses);
+
+        // Largest error relative to the typical acceleration
+        let max_error = |theta: f64| {
+            let mut max_error: f64 = 0.0;
+            let mut mean_magnitude = 0.0;
+            for (i, exact_acceleration) in exact.iter().enumerate() {
+                let approx = tree.acceleration(i, theta, 1.0, 0.1);
+                max_error = max_error.max((approx - *exact_acceleration).length());
+                mean_magnitude += exact_acceleration.length() / positions.len() as f64;
+            }
+            max_error / mean_magnitude
+        };
+
+        let errors: Vec<f64> = [1.0, 0.5, 0.25, 0.0]
+            .iter()
+            .map(|&t| max_error(t))
+            .collect();
+        assert!(errors[0] < 0.2);
+        for pair in errors.windows(2) {
+            assert!(pair[1] < pair[0]);
+        }
+        assert!(errors[3] < 1e-10);
+    }
+}
@@ -0,0 +1,56 @@
+//! Entry points for the benchmarks in `benches/`. The internals they measure are not
+//! part of the public API, so this module only exists with the `bench` feature
+
+use crate::collision_model::ElasticModel;
+use crate::collision_utils;
+use crate::motion_resolver::{self, CollisionHandling};
+use crate::prelude::*;
+use crate::velocity_verlet_integrator::NEIGHBOR_CUTOFF_MARGIN;
+use crate::{
+    neighbor_grid, NeighborGrid, Particle, ParticleClass, ParticlePairRules, Polygon, StepEnvironment,
+    StepReport, Units, Vec2, Wall, WallClass,
+};
+use std::collections::HashMap;
+
+/// Resolves collisions of a single step with elastic model, as the integrator does without
+/// forces. The neighbor grid is built the same way, its cost is included
+pub fn resolve(
+    particles: &mut Vec<Particle>,
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    walls: &[Wall],
+    wall_classes: &HashMap<ClassId, WallClass>,
+    time_step: f64,
+) -> StepReport {
+    let units = Units::default();
+    let particle_pair_rules = ParticlePairRules::new();
+    let particle_vs_particle_resolver =
+        motion_resolver::particle_vs_particle_velocity_resolver(&ElasticModel, particle_classes, &particle_pair_rules);
+    let particle_vs_wall_resolver =
+        motion_resolver::particle_vs_wall_velocity_resolver(&ElasticModel, particle_classes, wall_classes, &units, None);
+    let mut neighbor_grid = NeighborGrid::new();
+    let collision_cutoff = motion_resolver::collision_cutoff(particles, particle_classes, time_step);
+    let cutoff = (collision_cutoff * NEIGHBOR_CUTOFF_MARGIN)
+        .max(neighbor_grid::interaction_range(particles, particle_classes));
+    if cutoff > 0.0 {
+        neighbor_grid.rebuild(particles, cutoff);
+    }
+    return motion_resolver::resolve(
+        particles,
+        &StepEnvironment::new(particle_classes, &particle_pair_rules, walls, wall_classes),
+        &neighbor_grid,
+        time_step,
+        &CollisionHandling::new(&particle_vs_particle_resolver, &particle_vs_wall_resolver),
+        None,
+        None,
+    );
+}
+
+/// Time and normal of the collision of moving particle with the polygon, if any
+pub fn find_particle_vs_polygon_collision(
+    center: Vec2,
+    radius: f64,
+    velocity: Vec2,
+    polygon: &Polygon,
+) -> Option<(f64, Vec2)> {
+    return collision_utils::find_particle_vs_polygon_collision(center, radius, velocity, polygon);
+}
@@ -0,0 +1,73 @@
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{BoundaryCondition, Particle, ParticleClass};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Parameters of the spring that connects 2 particles
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct SpringParams {
+    /// Distance between particle centers at which spring applies no force
+    pub rest_length: f64,
+    /// Force per unit of stretch
+    pub stiffness: f64,
+    /// Force per unit of relative speed along the spring
+    pub damping: f64,
+}
+
+impl SpringParams {
+    pub fn new(rest_length: f64, stiffness: f64, damping: f64) -> Self {
+        SpringParams {
+            rest_length,
+            stiffness,
+            damping,
+        }
+    }
+}
+
+/// Spring between 2 particles identified by their persistent ids
+pub type Bond = (ParticleId, ParticleId, SpringParams);
+
+/// Applies spring forces of all bonds to particle velocities over the time step.
+/// Bonds referencing particles that no longer exist are ignored.
+/// In periodic domain springs connect the nearest images of particles
+pub(crate) fn apply_bonds(
+    particles: &mut [Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    bonds: &[Bond],
+    boundary: BoundaryCondition,
+    time_step_sec: f64,
+) {
+    if bonds.is_empty() {
+        return;
+    }
+    // Particles may be reordered. Look them up by id
+    let index_by_id: HashMap<ParticleId, usize> = particles
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.id().map(|id| (id, i)))
+        .collect();
+
+    for (id1, id2, spring) in bonds {
+        let (Some(&i1), Some(&i2)) = (index_by_id.get(id1), index_by_id.get(id2)) else {
+            continue;
+        };
+        let p1 = particles[i1];
+        let p2 = particles[i2];
+        let delta = boundary.minimum_image(p2.position - p1.position);
+        let direction = match delta.normalized() {
+            Some(direction) => direction,
+            None => continue,
+        };
+        // Hooke's law plus damping of relative motion along the spring.
+        // Positive force pulls particles together
+        let stretch = delta.length() - spring.rest_length;
+        let stretch_speed = (p2.velocity - p1.velocity).dot(direction);
+        let force = spring.stiffness * stretch + spring.damping * stretch_speed;
+
+        let mass1 = p1.mass(get_class(particle_classes, p1.class()));
+        let mass2 = p2.mass(get_class(particle_classes, p2.class()));
+        particles[i1].velocity += direction * (force / mass1 * time_step_sec);
+        particles[i2].velocity -= direction * (force / mass2 * time_step_sec);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// What happens to particles at the edges of the domain
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum BoundaryCondition {
+    /// Space is unbounded. Only walls stop particles
+    #[default]
+    Open,
+    /// Toroidal domain. Particle that leaves through one edge comes back through the
+    /// opposite one with the same velocity, and particles near opposite edges collide.
+    /// Domain must be larger than twice the distance between centers of particles that may
+    /// collide during a step, so only the nearest image of each particle matters
+    Periodic { min: Vec2, max: Vec2 },
+}
+
+impl BoundaryCondition {
+    /// Returns position moved into the domain
+    pub fn wrap(&self, position: Vec2) -> Vec2 {
+        match self {
+            BoundaryCondition::Open => position,
+            BoundaryCondition::Periodic { min, max } => Vec2::new(
+                min.x + (position.x - min.x).rem_euclid(max.x - min.x),
+                min.y + (position.y - min.y).rem_euclid(max.y - min.y),
+            ),
+        }
+    }
+
+    /// Shortest offset between two points that is equivalent to `offset`, i.e. the offset
+    /// to the nearest image of the second point
+    pub fn minimum_image(&self, offset: Vec2) -> Vec2 {
+        match self {
+            BoundaryCondition::Open => offset,
+            BoundaryCondition::Periodic { min, max } => {
+                let size = *max - *min;
+                Vec2::new(
+                    offset.x - size.x * (offset.x / size.x).round(),
+                    offset.y - size.y * (offset.y / size.y).round(),
+                )
+            }
+        }
+    }
+
+    pub fn is_periodic(&self) -> bool {
+        matches!(self, BoundaryCondition::Periodic { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic_wrap() {
+        let boundary = BoundaryCondition::Periodic {
+            min: Vec2::new(-5.0, 0.0),
+            max: Vec2::new(5.0, 4.0),
+        };
+        assert_eq!(boundary.wrap(Vec2::new(6.0, 1.0)), Vec2::new(-4.0, 1.0));
+        assert_eq!(boundary.wrap(Vec2::new(-7.0, -1.0)), Vec2::new(3.0, 3.0));
+        assert_eq!(boundary.wrap(Vec2::new(2.0, 9.0)), Vec2::new(2.0, 1.0));
+        assert_eq!(boundary.minimum_image(Vec2::new(9.0, 1.0)), Vec2::new(-1.0, 1.0));
+        assert_eq!(boundary.minimum_image(Vec2::new(-8.5, -3.5)), Vec2::new(1.5, 0.5));
+        assert!(boundary.is_periodic());
+
+        let open = BoundaryCondition::Open;
+        assert_eq!(open.wrap(Vec2::new(6.0, 1.0)), Vec2::new(6.0, 1.0));
+        assert_eq!(open.minimum_image(Vec2::new(9.0, 1.0)), Vec2::new(9.0, 1.0));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::collision_utils;
+use crate::{Particle, ParticleClass, Units, Vec2, Wall, WallClass};
+use rand::RngCore;
+
+/// Wall side of a particle vs wall collision
+#[derive(Debug, Clone, Copy)]
+pub struct WallContact<'a> {
+    pub wall: &'a Wall,
+    pub wall_class: &'a WallClass,
+    /// Points from the wall towards the particle
+    pub normal: Vec2,
+}
+
+/// Physics of a single collision. Given the state right before the collision,
+/// returns velocities right after it. Implement it to plug custom restitution models
+/// into the integrator. Models are shared by the threads that resolve collision islands
+pub trait CollisionModel: Send + Sync {
+    /// Returns new velocities of both particles.
+    /// `normal` is the collision normal at the moment of contact
+    fn resolve_particles(
+        &self,
+        p1: &Particle,
+        class1: &ParticleClass,
+        p2: &Particle,
+        class2: &ParticleClass,
+        normal: Vec2,
+    ) -> (Vec2, Vec2);
+
+    /// Returns new velocity of the particle that hits the wall at `contact`.
+    /// `units` relate wall temperature to particle energy.
+    /// Random choices (wall temperature, scattering) should be drawn from `rng`,
+    /// so seeded integrators reproduce the collision
+    fn resolve_wall(
+        &self,
+        particle: &Particle,
+        particle_class: &ParticleClass,
+        contact: &WallContact,
+        units: &Units,
+        rng: &mut dyn RngCore,
+    ) -> Vec2;
+}
+
+/// Default model. Particles bounce elastically. Walls exchange heat with particles
+/// and scatter them diffusely if the wall class asks for it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ElasticModel;
+
+impl CollisionModel for ElasticModel {
+    fn resolve_particles(
+        &self,
+        p1: &Particle,
+        class1: &ParticleClass,
+        p2: &Particle,
+        class2: &ParticleClass,
+        normal: Vec2,
+    ) -> (Vec2, Vec2) {
+        collision_utils::particles_collision_separation_velocity(
+            p1.velocity,
+            p1.mass(class1),
+            p2.velocity,
+            p2.mass(class2),
+            normal,
+            1.0,
+        )
+    }
+
+    fn resolve_wall(
+        &self,
+        particle: &Particle,
+        particle_class: &ParticleClass,
+        contact: &WallContact,
+        units: &Units,
+        mut rng: &mut dyn RngCore,
+    ) -> Vec2 {
+        let WallContact { wall, wall_class, normal } = *contact;
+        if wall_class.diffuse_reflection() {
+            collision_utils::particles_vs_wall_diffuse_separation_velocity(
+                particle.velocity,
+                particle.mass(particle_class),
+                normal,
+                wall.temperature(wall_class),
+                wall_class.heat_conductivity(),
+                units.boltzmann,
+                &mut rng,
+            )
+        } else {
+            collision_utils::particles_vs_wall_collision_separation_velocity(
+                particle.velocity,
+                particle.mass(particle_class),
+                normal,
+                wall.temperature(wall_class),
+                wall_class.heat_conductivity(),
+                units.boltzmann,
+                &mut rng,
+            )
+        }
+    }
+}
+
+/// Collisions lose energy. The coefficient of restitution is the ratio of normal
+/// relative speeds after and before the collision: 1 is elastic, 0 is perfectly inelastic.
+/// Heat exchange with walls happens as in `ElasticModel`, then the normal component
+/// of the outgoing velocity is scaled by the coefficient
+#[derive(Debug, Clone, Copy)]
+pub struct InelasticModel(pub f64);
+
+impl CollisionModel for InelasticModel {
+    fn resolve_particles(
+        &self,
+        p1: &Particle,
+        class1: &ParticleClass,
+        p2: &Particle,
+        class2: &ParticleClass,
+        normal: Vec2,
+    ) -> (Vec2, Vec2) {
+        collision_utils::particles_collision_separation_velocity(
+            p1.velocity,
+            p1.mass(class1),
+            p2.velocity,
+            p2.mass(class2),
+            normal,
+            self.0,
+        )
+    }
+
+    fn resolve_wall(
+        &self,
+        particle: &Particle,
+        particle_class: &ParticleClass,
+        contact: &WallContact,
+        units: &Units,
+        rng: &mut dyn RngCore,
+    ) -> Vec2 {
+        let velocity = ElasticModel.resolve_wall(particle, particle_class, contact, units, rng);
+        let normal_speed = velocity.dot(contact.normal);
+        return velocity - contact.normal * (normal_speed * (1.0 - self.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::{Simulation, VelocityVerletIntegrator};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    // Particles that touch stop dead. Walls are ignored
+    struct StickyModel;
+
+    impl CollisionModel for StickyModel {
+        fn resolve_particles(
+            &self,
+            p1: &Particle,
+            class1: &ParticleClass,
+            p2: &Particle,
+            class2: &ParticleClass,
+            _normal: Vec2,
+        ) -> (Vec2, Vec2) {
+            // Common velocity conserves momentum
+            let m1 = p1.mass(class1);
+            let m2 = p2.mass(class2);
+            let v = (p1.velocity * m1 + p2.velocity * m2) / (m1 + m2);
+            (v, v)
+        }
+
+        fn resolve_wall(
+            &self,
+            particle: &Particle,
+            _particle_class: &ParticleClass,
+            _contact: &WallContact,
+            _units: &Units,
+            _rng: &mut dyn RngCore,
+        ) -> Vec2 {
+            particle.velocity
+        }
+    }
+
+    // Two particles collide head-on. Returns their velocities after the step
+    fn collide(integrator: &VelocityVerletIntegrator) -> (Vec2, Vec2) {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        simulation.spawn_particles(&[
+            Particle::new(Vec2::new(-1.0, 0.0), Vec2::new(2.0, 0.0), 1),
+            Particle::new(Vec2::new(1.0, 0.0), Vec2::new(-2.0, 0.0), 1),
+        ]);
+        simulation.step(integrator, Duration::from_secs(1), None);
+        let particles = simulation.particles();
+        (particles[0].velocity, particles[1].velocity)
+    }
+
+    #[test]
+    fn test_custom_collision_model() {
+        let elastic = collide(&VelocityVerletIntegrator::new());
+        assert!(elastic
+            .0
+            .approx_eq(Vec2::new(-2.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        let inelastic = collide(
+            &VelocityVerletIntegrator::new().with_collision_model(Box::new(InelasticModel(0.5))),
+        );
+        assert!(inelastic
+            .0
+            .approx_eq(Vec2::new(-1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(inelastic
+            .1
+            .approx_eq(Vec2::new(1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        let sticky =
+            collide(&VelocityVerletIntegrator::new().with_collision_model(Box::new(StickyModel)));
+        assert!(sticky.0.approx_eq(Vec2::ZERO, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(sticky.1.approx_eq(Vec2::ZERO, DOUBLE_COMPARE_EPS_STRICT));
+    }
+}
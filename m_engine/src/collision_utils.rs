@@ -1,8 +1,16 @@
-use crate::prelude::{DISTANCE_EPS, DOUBLE_COMPARE_EPS_STRICT};
-use crate::{math_core, LineSegment};
+use crate::prelude::DISTANCE_EPS;
+use crate::{math_core, Capsule, LineSegment};
 use crate::{Plane, Polygon, Vec2};
+use rand::Rng;
 use std::option::Option;
 
+/// Relative impact parameter above which the collision is treated as grazing and ignored.
+/// Impact parameter is the distance from the origin to the line of motion. Collision with
+/// `b >= r * (1 - GRAZING_IMPACT_EPS)` would transfer momentum proportional to
+/// `sqrt(1 - (b / r)^2) < 1.5e-6` of the approach speed, so skipping it changes nothing.
+/// Particle vs particle and particle vs polygon vertex use the same boundary
+pub(crate) const GRAZING_IMPACT_EPS: f64 = 1e-12;
+
 /// Function that calculates the collision between circle
 /// and [0,0] point.
 /// Returns the time of collision if any.
@@ -16,33 +24,20 @@ pub(crate) fn find_circle_vs_origin_collision(
     if velocity1.dot(center1) >= 0.0 {
         return None;
     }
-    // The equation we need to solve is:
-    // (cx + vx * t)^2 + (cy + vy * t)^2 = (r1)^2
-    // After algebraic manipulations we get:
-    // (vx^2 + vy^2)t^2 + 2(cx * vx + cy * vy)t + (cx^2 + cy^2 - r1^2) = 0
-    // Which is conviniently a quadratic equation.
-    let a = velocity1.x * velocity1.x + velocity1.y * velocity1.y;
-    let b = 2.0 * (center1.x * velocity1.x + center1.y * velocity1.y);
-    let c = center1.x * center1.x + center1.y * center1.y - radius1 * radius1;
-    let t = math_core::solve_quadratic(a, b, c);
-    match t {
-        Some((t1, t2)) => {
-            // No exra logic is needed because we handled the case of moving apart
-            let t = t1.min(t2);
-            // We need to filter out the tangential collision
-            let res_center = center1 + velocity1 * t;
-            let v1 = res_center.normalized().unwrap();
-            let v2 = velocity1.normalized().unwrap();
-            if v1.dot(v2).abs() < DOUBLE_COMPARE_EPS_STRICT {
-                return None;
-            }
-
-            return Some(t);
-        }
-        None => {
-            return None;
-        }
+    // Circle moves along the line. It hits the origin if the line passes closer than
+    // the radius. Solving it through the impact parameter, rather than the quadratic
+    // equation, keeps the accept/reject decision stable for grazing collisions
+    let speed = velocity1.length();
+    let direction = velocity1 / speed;
+    let impact = center1.cross(direction).abs();
+    if impact >= radius1 * (1.0 - GRAZING_IMPACT_EPS) {
+        return None;
     }
+    // Distance along the line to the closest approach, minus half of the chord inside the circle.
+    // If we already have overlap, the time is negative
+    let closest = -center1.dot(direction);
+    let half_chord = ((radius1 - impact) * (radius1 + impact)).sqrt();
+    return Some((closest - half_chord) / speed);
 }
 
 /// Function calculates collision between 2 moving particles
@@ -164,6 +159,98 @@ pub(crate) fn find_particle_vs_polygon_collision(
     return result;
 }
 
+/// Function calculate the collision between moving particle and circle.
+/// Particle bounces off a solid disk from outside, or off the inside of `inward` circle.
+/// Returns time and collision normal, pointing from the circle towards the particle, if any
+pub(crate) fn find_particle_vs_circle_collision(
+    center: Vec2,
+    radius: f64,
+    velocity: Vec2,
+    circle_center: Vec2,
+    circle_radius: f64,
+    inward: bool,
+) -> Option<(f64, Vec2)> {
+    let local_center = center - circle_center;
+    if !inward {
+        // Same as collision with a particle of the circle radius that stands still
+        let t = find_circle_vs_origin_collision(local_center, radius + circle_radius, velocity)?;
+        let expected_collision = local_center + velocity * t;
+        let normal = particles_collision_normal(Vec2::ZERO, Vec2::ZERO, expected_collision, velocity)?;
+        return Some((t, normal));
+    }
+    // Particle center stays within the circle shrunk by the particle radius. It hits the
+    // wall where it leaves that circle, i.e. at the larger root of |c + v * t| = r
+    let reach = circle_radius - radius;
+    let speed_sq = velocity.length_sq();
+    if reach <= 0.0 || speed_sq == 0.0 {
+        return None;
+    }
+    let outward = local_center.dot(velocity);
+    let excess = local_center.length_sq() - reach * reach;
+    // Center is already beyond the reach and moves back in. Nothing to hit
+    if excess > 0.0 && outward <= 0.0 {
+        return None;
+    }
+    // Discriminant can't be negative inside the circle. Outside of it the line of motion
+    // may miss the circle only due to rounding
+    let discriminant = (outward * outward - speed_sq * excess).max(0.0);
+    // If the center is already beyond the reach, the time is negative
+    let t = (-outward + discriminant.sqrt()) / speed_sq;
+    let normal = (-(local_center + velocity * t)).normalized()?;
+    return Some((t, normal));
+}
+
+/// Function calculate the collision between moving particle and capsule.
+/// Capsule is a Minkowski sum of its segment and a disk, so particle center collides
+/// with the segment grown by both radii: two flat sides and two end circles.
+/// Returns time and collision normal, if any
+pub(crate) fn find_particle_vs_capsule_collision(
+    center: Vec2,
+    radius: f64,
+    velocity: Vec2,
+    capsule: Capsule,
+) -> Option<(f64, Vec2)> {
+    let reach = radius + capsule.radius;
+    let mut result: Option<(f64, Vec2)> = None;
+    // keep the earliest collision
+    let mut take = |collision: (f64, Vec2)| {
+        match result {
+            Some((t, _)) if t <= collision.0 => {}
+            _ => result = Some(collision),
+        }
+    };
+
+    // Rounded ends. The normal points from the end to the particle center at contact,
+    // so at the edge of the cap it matches the side normal
+    for end in [capsule.begin, capsule.end] {
+        let local_center = center - end;
+        if let Some(t) = find_circle_vs_origin_collision(local_center, reach, velocity) {
+            let expected_collision = local_center + velocity * t;
+            let normal = particles_collision_normal(Vec2::ZERO, Vec2::ZERO, expected_collision, velocity);
+            if let Some(normal) = normal {
+                take((t, normal));
+            }
+        }
+    }
+
+    // Flat sides. Degenerate segment has none, the capsule is just a disk then.
+    // Skip the side if the center is already past the core segment, same as polygon edges
+    for side in [capsule.segment(), LineSegment::new(capsule.end, capsule.begin)] {
+        let Some(plane) = side.plane() else {
+            continue;
+        };
+        if plane.distance(center) < 0.0 {
+            continue;
+        }
+        let off_side = side.offseted(reach).expect("Failed to offset side");
+        if let Some(t) = find_point_vs_segment_collision(center, velocity, off_side) {
+            take((t, plane.normal));
+        }
+    }
+
+    return result;
+}
+
 /// Calculates collision normal of 2 colliding particles.
 /// Collision normal can't be calculated if centers are identical and velocities are equal.
 pub(crate) fn particles_collision_normal(
@@ -237,33 +324,98 @@ pub(crate) fn particles_collision_separation_velocity(
     return (new_velocity1, new_velocity2);
 }
 
-/// Calculate separation velocity after collision
-pub(crate) fn particles_vs_wall_collision_separation_velocity(
+/// Calculates heat exchange between moving particle and the wall it hits.
+/// Returns energy of the particle before collision and energy it gains (or loses)
+fn wall_heat_exchange(
     velocity1: Vec2,
     mass1: f64,
     collision_normal: Vec2,
     wall_temperature: f64,
     wall_heat_conductivity: f64,
-) -> Vec2 {
-
-    // If particle not moving - return nothing
-    let direction_opt = velocity1.normalized();
-    if direction_opt.is_none() {
-        return velocity1;
-    }
-
+    boltzmann: f64,
+    rng: &mut impl Rng,
+) -> (f64, f64) {
     // Total energy of the particle and wall
-    let sampled_temperature = math_core::random_0_to_mean(wall_temperature);
-    let wall_energy = math_core::energy_from_temp(sampled_temperature);
+    let sampled_temperature = math_core::random_0_to_mean(wall_temperature, rng);
+    let wall_energy = math_core::energy_from_temp(sampled_temperature, boltzmann);
     let particle_energy = math_core::kinetic_energy_from_velocity(mass1, velocity1.length());
 
     // The amount of energy gained or lost depends on the collision angle
-    let angle_dot = -direction_opt.unwrap().dot(collision_normal);
+    let angle_dot = match velocity1.normalized() {
+        Some(direction) => -direction.dot(collision_normal),
+        None => 0.0,
+    };
     // Tricky moment. The proportion of velocity that can be traded depends on angle of collision
     // But proportion of energy that can be traded depends on square of that.
     let sq_angle_dot = angle_dot * angle_dot;
 
     let delta_e = (wall_energy - particle_energy) * sq_angle_dot * wall_heat_conductivity;
+    return (particle_energy, delta_e);
+}
+
+/// Samples direction of particle scattered by rough wall. Directions follow cosine
+/// distribution about the normal (Lambert scattering)
+pub(crate) fn diffuse_reflection_direction(collision_normal: Vec2, rng: &mut impl Rng) -> Vec2 {
+    // Cumulative distribution of cosine is (sin + 1) / 2. Invert it
+    let sin_angle = rng.gen::<f64>() * 2.0 - 1.0;
+    let cos_angle = (1.0 - sin_angle * sin_angle).sqrt();
+    return collision_normal * cos_angle + collision_normal.rotated_90_ccw() * sin_angle;
+}
+
+/// Calculate separation velocity after collision with rough wall. Outgoing direction
+/// doesn't depend on incoming one. Speed is preserved, except for heat exchange
+pub(crate) fn particles_vs_wall_diffuse_separation_velocity(
+    velocity1: Vec2,
+    mass1: f64,
+    collision_normal: Vec2,
+    wall_temperature: f64,
+    wall_heat_conductivity: f64,
+    boltzmann: f64,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    // If particle not moving - return nothing
+    if velocity1.normalized().is_none() {
+        return velocity1;
+    }
+    let (particle_energy, delta_e) = wall_heat_exchange(
+        velocity1,
+        mass1,
+        collision_normal,
+        wall_temperature,
+        wall_heat_conductivity,
+        boltzmann,
+        rng,
+    );
+    let speed = math_core::velocity_from_kinetic_energy(mass1, particle_energy + delta_e);
+    return diffuse_reflection_direction(collision_normal, rng) * speed;
+}
+
+/// Calculate separation velocity after collision. `rng` samples the wall temperature
+pub(crate) fn particles_vs_wall_collision_separation_velocity(
+    velocity1: Vec2,
+    mass1: f64,
+    collision_normal: Vec2,
+    wall_temperature: f64,
+    wall_heat_conductivity: f64,
+    boltzmann: f64,
+    rng: &mut impl Rng,
+) -> Vec2 {
+
+    // If particle not moving - return nothing
+    let direction_opt = velocity1.normalized();
+    if direction_opt.is_none() {
+        return velocity1;
+    }
+
+    let (particle_energy, delta_e) = wall_heat_exchange(
+        velocity1,
+        mass1,
+        collision_normal,
+        wall_temperature,
+        wall_heat_conductivity,
+        boltzmann,
+        rng,
+    );
 
     // First simmulate the collision with energy loss (or gain) (fully elastic)
     let impulse = collision_impulse_stationary(mass1, velocity1, collision_normal, 1.0);
@@ -348,6 +500,73 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_grazing_boundary() {
+        // Offsets of the line of motion from head-on to beyond the radius. Collision time
+        // must grow with the offset, and once rejected, collisions stay rejected
+        let mut offsets: Vec<f64> = (0..1000).map(|i| i as f64 / 1000.0).collect();
+        offsets.extend((1..=16).map(|k| 1.0 - 10f64.powi(-k)));
+        offsets.extend([1.0, 1.0 + 1e-12, 1.1]);
+        offsets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let times: Vec<Option<f64>> = offsets
+            .iter()
+            .map(|&b| find_circle_vs_origin_collision(Vec2::new(-5.0, b), 1.0, Vec2::new(2.0, 0.0)))
+            .collect();
+        let num_accepted = times.iter().take_while(|t| t.is_some()).count();
+        assert!(times[num_accepted..].iter().all(|t| t.is_none()));
+        for pair in times[..num_accepted].windows(2) {
+            assert!(pair[0].unwrap() <= pair[1].unwrap());
+        }
+        // Boundary is at the documented threshold
+        for (&b, t) in offsets.iter().zip(times.iter()) {
+            if b < 1.0 - 10.0 * GRAZING_IMPACT_EPS {
+                let expected = (5.0 - (1.0 - b * b).sqrt()) / 2.0;
+                assert!(math_core::approx_eq(t.unwrap(), expected, 1e-9));
+            } else if b >= 1.0 - 0.1 * GRAZING_IMPACT_EPS {
+                assert!(t.is_none());
+            }
+        }
+
+        // Particle pairs and polygon vertices decide the same way. Scene is moved and
+        // rotated, so the relative values carry rounding errors
+        let angle = 0.3f64;
+        let rotate = |v: Vec2| {
+            Vec2::new(
+                v.x * angle.cos() - v.y * angle.sin(),
+                v.x * angle.sin() + v.y * angle.cos(),
+            )
+        };
+        let shift = Vec2::new(100.0, -40.0);
+        let drift = Vec2::new(3.0, -1.0);
+        let square = Polygon::new_rectangle(0.0, -2.0, 2.0, 0.0);
+        for k in 1..=16 {
+            let b = 1.0 - 10f64.powi(-k);
+            let expected = b < 1.0 - 10.0 * GRAZING_IMPACT_EPS;
+            let pair = find_particle_vs_particle_collision(
+                shift + rotate(Vec2::new(-5.0, b)),
+                0.4,
+                rotate(Vec2::new(2.0, 0.0)) + drift,
+                shift,
+                0.6,
+                drift,
+            );
+            // Upper left corner of the square is hit from the left-top side
+            let vertex = find_particle_vs_polygon_collision(
+                Vec2::new(-5.0, b),
+                1.0,
+                Vec2::new(2.0, 0.0),
+                &square,
+            );
+            if expected {
+                assert!(pair.is_some(), "Offset 1 - 1e-{}", k);
+                assert!(vertex.is_some(), "Offset 1 - 1e-{}", k);
+            } else if b >= 1.0 - 0.1 * GRAZING_IMPACT_EPS {
+                assert!(pair.is_none(), "Offset 1 - 1e-{}", k);
+                assert!(vertex.is_none(), "Offset 1 - 1e-{}", k);
+            }
+        }
+    }
+
     #[test]
     fn test_find_particle_vs_particle_collision() {
         // particle 2 is catching up particle 1
@@ -596,6 +815,86 @@ mod tests {
         assert!(res.1.approx_eq(Vec2::new(1.0, 0.0), 0.2));
     }
 
+    #[test]
+    fn test_find_particle_vs_capsule_side_collision() {
+        // Horizontal capsule from (0,0) to (4,0) with radius 1
+        let capsule = Capsule::new(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), 1.0);
+
+        // Falling onto the top side
+        let (t, normal) = find_particle_vs_capsule_collision(
+            Vec2::new(2.0, 5.0), 1.0, Vec2::new(0.0, -1.0), capsule)
+            .expect("Collision expected");
+        assert!(math_core::approx_eq(t, 3.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(0.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Rising onto the bottom side
+        let (t, normal) = find_particle_vs_capsule_collision(
+            Vec2::new(1.0, -4.0), 0.5, Vec2::new(0.0, 2.0), capsule)
+            .expect("Collision expected");
+        assert!(math_core::approx_eq(t, 1.25, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(0.0, -1.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Moving away
+        assert!(find_particle_vs_capsule_collision(
+            Vec2::new(2.0, 5.0), 1.0, Vec2::new(0.0, 1.0), capsule).is_none());
+        // Passing by
+        assert!(find_particle_vs_capsule_collision(
+            Vec2::new(-5.0, 3.0), 1.0, Vec2::new(1.0, 0.0), capsule).is_none());
+    }
+
+    #[test]
+    fn test_find_particle_vs_capsule_cap_collision() {
+        let capsule = Capsule::new(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), 1.0);
+
+        // Head-on into the right cap
+        let (t, normal) = find_particle_vs_capsule_collision(
+            Vec2::new(10.0, 0.0), 1.0, Vec2::new(-2.0, 0.0), capsule)
+            .expect("Collision expected");
+        assert!(math_core::approx_eq(t, 2.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Diagonally into the left cap. Contact at 45 degrees
+        let reach = 2.0;
+        let contact = Vec2::new(-1.0, 1.0).normalized().unwrap() * reach;
+        let velocity = Vec2::new(1.0, -1.0);
+        let (t, normal) = find_particle_vs_capsule_collision(
+            contact - velocity * 3.0, 1.0, velocity, capsule)
+            .expect("Collision expected");
+        assert!(math_core::approx_eq(t, 3.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(-1.0, 1.0).normalized().unwrap(), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Degenerate capsule is a disk
+        let disk = Capsule::new(Vec2::new(1.0, 1.0), Vec2::new(1.0, 1.0), 1.0);
+        let (t, normal) = find_particle_vs_capsule_collision(
+            Vec2::new(1.0, 5.0), 1.0, Vec2::new(0.0, -1.0), disk)
+            .expect("Collision expected");
+        assert!(math_core::approx_eq(t, 2.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(0.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+    }
+
+    #[test]
+    fn test_capsule_normal_continuity() {
+        // Sweep vertical drops across the top of the capsule, from the middle of the side
+        // over the rounded end. Normal must turn smoothly, with no jump where side meets cap
+        let capsule = Capsule::new(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), 1.0);
+        let mut previous: Option<Vec2> = None;
+        let steps = 400;
+        for i in 0..steps {
+            let x = 2.0 + 3.9 * i as f64 / steps as f64;
+            let (t, normal) = find_particle_vs_capsule_collision(
+                Vec2::new(x, 10.0), 1.0, Vec2::new(0.0, -1.0), capsule)
+                .expect("Collision expected");
+            // Contact point is on the grown capsule surface
+            let contact = Vec2::new(x, 10.0 - t);
+            assert!(math_core::approx_eq(capsule.distance_to(contact), 1.0, 1e-9));
+            assert!(normal.approx_eq(capsule.normal_at(contact).unwrap(), 1e-9));
+            if let Some(previous) = previous {
+                assert!((normal - previous).length() < 0.1);
+            }
+            previous = Some(normal);
+        }
+    }
+
     #[test]
     fn test_particles_collision_normal() {
         // Identical centers and velocities
@@ -706,6 +1005,61 @@ mod tests {
         );
     }
 
+    // Compares separation velocities of elastic disk collision against the analytic solution.
+    // `impact` is impact parameter as a fraction of the sum of radii: 0 is head-on, close to 1 is glancing.
+    // Analytic solution: in the center of mass frame the relative velocity keeps its magnitude and
+    // turns by the scattering angle chi = 2 * acos(impact). Center of mass velocity is unchanged
+    fn check_elastic_against_analytic(m1: f64, v1: Vec2, m2: f64, v2: Vec2, impact: f64) {
+        let relative = v1 - v2;
+        let along = relative.normalized().unwrap();
+        let across = along.rotated_90_ccw();
+        // Normal at contact points from the first disk towards the second one
+        let normal = along * (1.0 - impact * impact).sqrt() + across * impact;
+
+        let (res1, res2) = particles_collision_separation_velocity(v1, m1, v2, m2, normal, 1.0);
+
+        let chi = 2.0 * impact.acos();
+        // Second disk is offset to the left, so the first one is deflected to the right
+        let (sin, cos) = (-chi).sin_cos();
+        let turned = Vec2::new(
+            relative.x * cos - relative.y * sin,
+            relative.x * sin + relative.y * cos,
+        );
+        let center_of_mass = (v1 * m1 + v2 * m2) / (m1 + m2);
+        let expected1 = center_of_mass + turned * (m2 / (m1 + m2));
+        let expected2 = center_of_mass - turned * (m1 / (m1 + m2));
+        assert!(
+            res1.approx_eq(expected1, DOUBLE_COMPARE_EPS_STRICT),
+            "m1={m1} v1={v1} m2={m2} v2={v2} impact={impact}: {res1} != {expected1}"
+        );
+        assert!(
+            res2.approx_eq(expected2, DOUBLE_COMPARE_EPS_STRICT),
+            "m1={m1} v1={v1} m2={m2} v2={v2} impact={impact}: {res2} != {expected2}"
+        );
+    }
+
+    #[test]
+    fn test_elastic_collision_matches_analytic() {
+        let masses = [(1.0, 1.0), (1.0, 3.0), (5.0, 0.5)];
+        let velocities = [
+            // Target at rest
+            (Vec2::new(2.0, 0.0), Vec2::ZERO),
+            // Head-on approach
+            (Vec2::new(2.0, 1.0), Vec2::new(-3.0, -0.5)),
+            // Catching up
+            (Vec2::new(0.0, 5.0), Vec2::new(0.3, 1.0)),
+        ];
+        // Head-on, intermediate and glancing impacts, on both sides
+        let impacts = [0.0, 0.5, -0.5, 0.95, -0.99];
+        for &(m1, m2) in &masses {
+            for &(v1, v2) in &velocities {
+                for &impact in &impacts {
+                    check_elastic_against_analytic(m1, v1, m2, v2, impact);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_collision_impulse_stationary() {
         // Hitting at 45 deg
@@ -722,4 +1076,117 @@ mod tests {
         let res_v = apply_impulse(2.0, v, impulse * n);
         assert!(res_v.approx_eq(Vec2::new(1.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
     }
+
+    #[test]
+    fn test_diffuse_reflection_distribution() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let normal = Vec2::new(1.0, 1.0).normalized().unwrap();
+        let velocity = Vec2::new(-3.0, -1.0);
+
+        let num_samples = 100000;
+        let mut within_30_deg = 0;
+        let mut within_60_deg = 0;
+        let mut sum_cos = 0.0;
+        for _ in 0..num_samples {
+            let res_v = particles_vs_wall_diffuse_separation_velocity(
+                velocity, 2.0, normal, 0.0, 0.0, 1.0, &mut rng,
+            );
+            // Speed is preserved without heat exchange
+            assert!(math_core::approx_eq(res_v.length(), velocity.length(), DISTANCE_EPS));
+            let cos = res_v.normalized().unwrap().dot(normal);
+            // Always bounces away from the wall
+            assert!(cos >= 0.0);
+            sum_cos += cos;
+            if cos > (30.0_f64).to_radians().cos() {
+                within_30_deg += 1;
+            }
+            if cos > (60.0_f64).to_radians().cos() {
+                within_60_deg += 1;
+            }
+        }
+        // For cosine distribution the probability to be within angle is sin of angle
+        let n = num_samples as f64;
+        assert!(math_core::approx_eq(within_30_deg as f64 / n, 0.5, 0.01));
+        assert!(math_core::approx_eq(within_60_deg as f64 / n, 0.75_f64.sqrt(), 0.01));
+        // Mean cosine is pi/4
+        assert!(math_core::approx_eq(sum_cos / n, std::f64::consts::PI / 4.0, 0.01));
+    }
+
+    #[test]
+    fn test_find_particle_vs_circle_collision() {
+        let circle_center = Vec2::new(1.0, 1.0);
+        // Inside the container, heading right. Center reaches 5 - 0.5 from the circle center
+        let (t, normal) = find_particle_vs_circle_collision(
+            Vec2::new(1.0, 1.0),
+            0.5,
+            Vec2::new(2.0, 0.0),
+            circle_center,
+            5.0,
+            true,
+        )
+        .unwrap();
+        assert!(math_core::approx_eq(t, 2.25, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(-1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        // Off-center chord. Normal points back to the center
+        let (t, normal) = find_particle_vs_circle_collision(
+            Vec2::new(1.0, 4.0),
+            1.0,
+            Vec2::new(1.0, 0.0),
+            circle_center,
+            4.0,
+            true,
+        )
+        .unwrap();
+        assert!(math_core::approx_eq(t, 0.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(0.0, -1.0), DOUBLE_COMPARE_EPS_STRICT));
+        // Slightly beyond the reach and moving out: collision in the past
+        let (t, _) = find_particle_vs_circle_collision(
+            Vec2::new(5.6, 1.0),
+            0.5,
+            Vec2::new(1.0, 0.0),
+            circle_center,
+            5.0,
+            true,
+        )
+        .unwrap();
+        assert!(math_core::approx_eq(t, -0.1, 1e-12));
+        // Beyond the reach and moving back in
+        assert!(find_particle_vs_circle_collision(
+            Vec2::new(5.6, 1.0),
+            0.5,
+            Vec2::new(-1.0, 0.0),
+            circle_center,
+            5.0,
+            true
+        )
+        .is_none());
+        // Particle larger than the container
+        assert!(
+            find_particle_vs_circle_collision(Vec2::ZERO, 6.0, Vec2::new(1.0, 0.0), circle_center, 5.0, true)
+                .is_none()
+        );
+
+        // Solid disk is hit from outside
+        let (t, normal) = find_particle_vs_circle_collision(
+            Vec2::new(-5.0, 1.0),
+            0.5,
+            Vec2::new(2.0, 0.0),
+            circle_center,
+            1.5,
+            false,
+        )
+        .unwrap();
+        assert!(math_core::approx_eq(t, 2.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(normal.approx_eq(Vec2::new(-1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(find_particle_vs_circle_collision(
+            Vec2::new(-5.0, 4.0),
+            0.5,
+            Vec2::new(2.0, 0.0),
+            circle_center,
+            1.5,
+            false
+        )
+        .is_none());
+    }
 }
@@ -0,0 +1,258 @@
+use crate::motion_resolver::{self, OtherObject};
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{Particle, ParticleClass, ParticlePairRules, StepEnvironment, Vec2, Wall};
+use std::collections::HashMap;
+
+/// The object particle collides with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPartner {
+    /// Index of the other particle
+    Particle(usize),
+    /// Index of the wall
+    Wall(usize),
+}
+
+/// Collision that would happen if particles keep moving without interaction
+#[derive(Debug, Clone, Copy)]
+pub struct DetectedCollision {
+    /// Time of collision, measured from now
+    pub time: f64,
+    /// Index of the particle
+    pub particle: usize,
+    pub partner: CollisionPartner,
+    /// Collision normal. Points from particle towards partner
+    pub normal: Vec2,
+    /// Point where the particle touches the partner
+    pub contact: Vec2,
+}
+
+/// Collision that was resolved during a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionEvent {
+    /// Time of collision, measured from the start of the step
+    pub time: f64,
+    /// Index of the particle
+    pub particle: usize,
+    pub partner: CollisionPartner,
+    /// Collision normal. Points from particle towards partner
+    pub normal: Vec2,
+    /// Magnitude of the impulse the particle received
+    pub impulse: f64,
+}
+
+impl CollisionEvent {
+    pub(crate) fn new(collision: &motion_resolver::Collision, impulse: f64) -> Self {
+        // Resolver keeps the outward normal of the wall. Turn it towards the wall
+        let (partner, normal) = match collision.other {
+            OtherObject::Particle(i) => (CollisionPartner::Particle(i), collision.normal),
+            OtherObject::Wall(i) => (CollisionPartner::Wall(i), -collision.normal),
+        };
+        return CollisionEvent {
+            time: collision.time.0,
+            particle: collision.particle,
+            partner,
+            normal,
+            impulse,
+        };
+    }
+
+    /// Same event with particle indices mapped by `index`
+    pub(crate) fn reindexed(&self, index: impl Fn(usize) -> usize) -> Self {
+        let partner = match self.partner {
+            CollisionPartner::Particle(i) => CollisionPartner::Particle(index(i)),
+            CollisionPartner::Wall(i) => CollisionPartner::Wall(i),
+        };
+        return CollisionEvent { particle: index(self.particle), partner, ..*self };
+    }
+}
+
+/// Detects all collisions within `dt` in the current state. Nothing is resolved, so
+/// collisions that would be prevented by earlier ones are reported as well.
+/// Each particle pair is reported once, unless the pair passes through. Result is sorted by time.
+pub fn detect_all(
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    particle_pair_rules: &ParticlePairRules,
+    walls: &[Wall],
+    dt: f64,
+) -> Vec<DetectedCollision> {
+    let mut collisions = vec![];
+    for i in 0..particles.len() {
+        collisions.extend(find_collisions(
+            i,
+            i + 1..particles.len(),
+            particles,
+            particle_classes,
+            particle_pair_rules,
+            walls,
+            dt,
+        ));
+    }
+    collisions.sort();
+
+    return collisions
+        .iter()
+        .map(|c| to_detected(c, particles, particle_classes))
+        .collect();
+}
+
+/// Earliest collision of the particle at `index` within `dt`, with particles or walls.
+/// Like `detect_all`, the other particles are assumed to move without interaction.
+/// Reported from the side of this particle, i.e. `particle` is `index`
+pub fn detect_earliest_for_particle(
+    index: usize,
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    particle_pair_rules: &ParticlePairRules,
+    walls: &[Wall],
+    dt: f64,
+) -> Option<DetectedCollision> {
+    let collisions = find_collisions(
+        index,
+        0..particles.len(),
+        particles,
+        particle_classes,
+        particle_pair_rules,
+        walls,
+        dt,
+    );
+    return collisions
+        .iter()
+        .min()
+        .map(|c| to_detected(c, particles, particle_classes));
+}
+
+// Collisions of the particle with the given particles and all walls
+fn find_collisions(
+    index: usize,
+    others: impl IntoIterator<Item = usize>,
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    particle_pair_rules: &ParticlePairRules,
+    walls: &[Wall],
+    dt: f64,
+) -> Vec<motion_resolver::Collision> {
+    let particle_times = vec![0.0; particles.len()];
+    // Wall classes only matter for resolving collisions
+    let wall_classes = HashMap::new();
+    let environment = StepEnvironment::new(particle_classes, particle_pair_rules, walls, &wall_classes);
+    let mut collisions = motion_resolver::find_collisions_with_particles(
+        index,
+        others,
+        particles,
+        &environment,
+        &particle_times,
+        dt,
+        TIME_SEC_EPS,
+    );
+    collisions.extend(motion_resolver::find_collisions_with_walls(
+        index,
+        &particles[index],
+        get_class(particle_classes, particles[index].class()),
+        walls,
+        0.0,
+        dt,
+        TIME_SEC_EPS,
+    ));
+    return collisions;
+}
+
+fn to_detected(
+    c: &motion_resolver::Collision,
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+) -> DetectedCollision {
+    let particle = &particles[c.particle];
+    let radius = particle.radius(get_class(particle_classes, particle.class()));
+    let center = particle.position + particle.velocity * c.time.0;
+    // Resolver keeps the outward normal of the wall. Turn it towards the wall
+    let (partner, normal) = match c.other {
+        OtherObject::Particle(i) => (CollisionPartner::Particle(i), c.normal),
+        OtherObject::Wall(i) => (CollisionPartner::Wall(i), -c.normal),
+    };
+    return DetectedCollision {
+        time: c.time.0,
+        particle: c.particle,
+        partner,
+        normal,
+        contact: center + normal * radius,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_core;
+
+    #[test]
+    fn test_detect_all() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        classes.insert(2, ParticleClass::new("Class2", 1.0, 2.0));
+
+        // Same scene as in resolver test
+        let particles = vec![
+            Particle::new(Vec2::new(10.0, 0.0), Vec2::new(0.0, 0.0), 1),
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 2),
+            Particle::new(Vec2::new(14.0, 0.0), Vec2::new(0.0, 0.0), 1),
+            Particle::new(Vec2::new(14.0, -15.0), Vec2::new(0.0, 1.0), 1),
+            Particle::new(Vec2::new(-8.0, 5.0), Vec2::new(1.0, 0.0), 1),
+            Particle::new(Vec2::new(20.0, 20.0), Vec2::new(1.0, 1.0), 2),
+            Particle::new(Vec2::new(12.0, 12.0), Vec2::new(0.0, -1.0), 1),
+        ];
+        let walls = vec![Wall::new(crate::Polygon::new_rectangle(30.0, -1.0, 31.0, 1.0), 0)];
+
+        let collisions = detect_all(&particles, &classes, &ParticlePairRules::new(), &walls, 30.0);
+        // First event: #1 hits #0 at 7sec
+        let first = collisions[0];
+        assert_eq!(first.particle, 0);
+        assert_eq!(first.partner, CollisionPartner::Particle(1));
+        assert!(math_core::approx_eq(first.time, 7.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(first.normal.approx_eq(Vec2::new(-1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        // #3 would hit #2 at 13 sec. It's reported even though resolve won't let it happen
+        assert!(collisions.iter().any(|c| c.particle == 2
+            && c.partner == CollisionPartner::Particle(3)
+            && math_core::approx_eq(c.time, 13.0, DOUBLE_COMPARE_EPS_STRICT)));
+        // Without resolution #1 flies through all and hits the wall
+        assert!(collisions.iter().any(|c| c.particle == 1
+            && c.partner == CollisionPartner::Wall(0)
+            && math_core::approx_eq(c.time, 28.0, DOUBLE_COMPARE_EPS_STRICT)));
+        // Sorted by time
+        assert!(collisions.windows(2).all(|w| w[0].time <= w[1].time));
+    }
+
+    #[test]
+    fn test_detect_earliest_for_particle() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 1),
+            // Approaches #0 from above. Touches it at 3 sec, before #0 reaches the wall
+            Particle::new(Vec2::new(3.0, 5.0), Vec2::new(0.0, -1.0), 1),
+            // Moves away
+            Particle::new(Vec2::new(-5.0, 0.0), Vec2::new(-1.0, 0.0), 1),
+        ];
+        let walls = vec![Wall::new(crate::Polygon::new_rectangle(10.0, -5.0, 11.0, 5.0), 0)];
+
+        let earliest = detect_earliest_for_particle(0, &particles, &classes, &ParticlePairRules::new(), &walls, 30.0)
+            .unwrap();
+        assert_eq!(earliest.particle, 0);
+        assert_eq!(earliest.partner, CollisionPartner::Particle(1));
+        assert!(math_core::approx_eq(earliest.time, 3.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(earliest.normal.approx_eq(Vec2::new(0.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(earliest.contact.approx_eq(Vec2::new(3.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Without the other particle #0 reaches the wall at 9 sec
+        let earliest = detect_earliest_for_particle(0, &particles[..1], &classes, &ParticlePairRules::new(), &walls, 30.0)
+            .unwrap();
+        assert_eq!(earliest.partner, CollisionPartner::Wall(0));
+        assert!(math_core::approx_eq(earliest.time, 9.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(earliest.normal.approx_eq(Vec2::new(1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(earliest.contact.approx_eq(Vec2::new(10.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Nothing within the horizon
+        assert!(detect_earliest_for_particle(2, &particles, &classes, &ParticlePairRules::new(), &walls, 30.0).is_none());
+        assert!(detect_earliest_for_particle(0, &particles, &classes, &ParticlePairRules::new(), &walls, 2.0).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Statistics;
+use serde::Serialize;
+use std::fmt;
+
+/// Tracks how far the total energy of a run strays from its initial value.
+/// In elastic scenes without gravity the energy is conserved, so a large drift
+/// points to a bug in collision handling
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EnergyDrift {
+    /// Total energy of the first sample
+    initial: Option<f64>,
+    max_deviation: f64,
+}
+
+impl EnergyDrift {
+    pub fn new() -> Self {
+        EnergyDrift::default()
+    }
+
+    /// Adds a sample. The first one is the reference
+    pub fn add(&mut self, statistics: &Statistics) {
+        match self.initial {
+            Some(initial) => {
+                self.max_deviation = self.max_deviation.max((statistics.total_energy - initial).abs());
+            }
+            None => self.initial = Some(statistics.total_energy),
+        }
+    }
+
+    pub fn initial(&self) -> Option<f64> {
+        self.initial
+    }
+
+    /// Largest absolute difference between the total energy and the initial one
+    pub fn max_deviation(&self) -> f64 {
+        self.max_deviation
+    }
+
+    /// Max deviation relative to the initial energy. None if there are no samples
+    /// or the initial energy is zero
+    pub fn relative_max_deviation(&self) -> Option<f64> {
+        let initial = self.initial?;
+        if initial == 0.0 {
+            return None;
+        }
+        return Some(self.max_deviation / initial.abs());
+    }
+}
+
+impl fmt::Display for EnergyDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(initial) = self.initial else {
+            return write!(f, "Energy drift: no statistics samples");
+        };
+        write!(f, "Energy drift: up to {} from initial {}", self.max_deviation, initial)?;
+        if let Some(relative) = self.relative_max_deviation() {
+            write!(f, " ({:.4}%)", relative * 100.0)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_energy_drift() {
+        let mut drift = EnergyDrift::new();
+        assert_eq!(drift.relative_max_deviation(), None);
+
+        for energy in [100.0, 101.0, 97.0, 99.0] {
+            drift.add(&Statistics::sample(0.0, energy, None));
+        }
+        assert_eq!(drift.initial(), Some(100.0));
+        assert_eq!(drift.max_deviation(), 3.0);
+        assert_eq!(drift.relative_max_deviation(), Some(0.03));
+        assert_eq!(drift.to_string(), "Energy drift: up to 3 from initial 100 (3.0000%)");
+    }
+}
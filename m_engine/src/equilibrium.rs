@@ -0,0 +1,81 @@
+use crate::Statistics;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Run is considered settled when temperature and total energy of the last `window`
+/// statistics samples each stay within `tolerance` of their mean, relative to the mean
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct EquilibriumCriterion {
+    pub window: usize,
+    pub tolerance: f64,
+}
+
+impl EquilibriumCriterion {
+    pub fn new(window: usize, tolerance: f64) -> Self {
+        EquilibriumCriterion { window, tolerance }
+    }
+
+    /// Window must hold at least 2 samples, tolerance must be finite and not negative
+    pub fn is_valid(&self) -> bool {
+        return self.window >= 2 && self.tolerance.is_finite() && self.tolerance >= 0.0;
+    }
+}
+
+/// Watches the statistics samples of a run and tells when the criterion is met
+#[derive(Debug, Clone)]
+pub struct EquilibriumDetector {
+    criterion: EquilibriumCriterion,
+    /// Temperature and total energy of the most recent samples
+    samples: VecDeque<(f64, f64)>,
+}
+
+impl EquilibriumDetector {
+    pub fn new(criterion: EquilibriumCriterion) -> Self {
+        EquilibriumDetector {
+            criterion,
+            samples: VecDeque::with_capacity(criterion.window),
+        }
+    }
+
+    /// Adds a sample. Returns true if the run is settled
+    pub fn add(&mut self, statistics: &Statistics) -> bool {
+        if self.samples.len() == self.criterion.window.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((statistics.temperature, statistics.total_energy));
+        if self.samples.len() < self.criterion.window {
+            return false;
+        }
+        return self.settled(|s| s.0) && self.settled(|s| s.1);
+    }
+
+    fn settled(&self, quantity: impl Fn(&(f64, f64)) -> f64) -> bool {
+        let values: Vec<f64> = self.samples.iter().map(quantity).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let allowed = self.criterion.tolerance * mean.abs();
+        return values.iter().all(|v| (v - mean).abs() <= allowed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equilibrium_detector() {
+        let mut detector = EquilibriumDetector::new(EquilibriumCriterion::new(3, 0.01));
+        // Temperature is still drifting
+        assert!(!detector.add(&Statistics::sample(10.0, 100.0, None)));
+        assert!(!detector.add(&Statistics::sample(11.0, 100.0, None)));
+        assert!(!detector.add(&Statistics::sample(12.0, 100.0, None)));
+        // Old samples leave the window
+        assert!(!detector.add(&Statistics::sample(12.0, 100.0, None)));
+        assert!(detector.add(&Statistics::sample(12.05, 100.5, None)));
+        // Energy jump breaks it again
+        assert!(!detector.add(&Statistics::sample(12.0, 110.0, None)));
+
+        assert!(!EquilibriumCriterion::new(1, 0.01).is_valid());
+        assert!(!EquilibriumCriterion::new(3, -0.01).is_valid());
+        assert!(EquilibriumCriterion::new(3, 0.0).is_valid());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::bond;
+use crate::force_field::{self, ForceField};
+use crate::motion_resolver;
+use crate::mutual_gravity;
+use crate::neighbor_grid;
+use crate::sim_error::get_class;
+use crate::motion_resolver::CollisionHandling;
+use crate::velocity_verlet_integrator::NEIGHBOR_CUTOFF_MARGIN;
+use crate::{Integrator, NeighborGrid, Particle, StepEnvironment, StepReport};
+use crate::collision_model::{CollisionModel, ElasticModel};
+use std::fmt;
+use std::time::Duration;
+
+/// Semi-implicit Euler integrator, mostly for comparison with `VelocityVerletIntegrator`.
+/// All forces change velocities at the starting positions, then particles move (and collide)
+/// with the new velocities. With gravity, bonds and mutual gravity this is what
+/// `VelocityVerletIntegrator` does too. They differ for the force field: whole impulse is
+/// applied here before the move, while Verlet splits it between the start and the end of it
+pub struct SemiImplicitEulerIntegrator {
+    collision_model: Box<dyn CollisionModel>,
+    force_field: Option<Box<dyn ForceField>>,
+}
+
+impl SemiImplicitEulerIntegrator {
+    /// Creates integrator with elastic collisions
+    pub fn new() -> Self {
+        SemiImplicitEulerIntegrator {
+            collision_model: Box::new(ElasticModel),
+            force_field: None,
+        }
+    }
+
+    /// Returns integrator that resolves collisions with the given model
+    pub fn with_collision_model(mut self, collision_model: Box<dyn CollisionModel>) -> Self {
+        self.collision_model = collision_model;
+        self
+    }
+
+    /// Returns integrator that applies the smooth pairwise force between particles within
+    /// their interaction cutoffs. Its impulse is evaluated at the starting positions
+    pub fn with_force_field(mut self, force_field: Box<dyn ForceField>) -> Self {
+        self.force_field = Some(force_field);
+        self
+    }
+}
+
+impl Default for SemiImplicitEulerIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SemiImplicitEulerIntegrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemiImplicitEulerIntegrator").finish_non_exhaustive()
+    }
+}
+
+/// Applies gravity (of the zone or global one), mutual gravity and bonds to velocities
+fn apply_forces(particles: &mut [Particle], environment: &StepEnvironment, time_step_sec: f64) {
+    let particle_classes = environment.particle_classes;
+    for particle in particles.iter_mut() {
+        let scale = get_class(particle_classes, particle.class()).gravity_scale();
+        particle.velocity += environment.gravity_at(particle.position) * (scale * time_step_sec);
+    }
+    if let Some(mutual_gravity) = environment.mutual_gravity {
+        mutual_gravity::apply_mutual_gravity(particles, particle_classes, mutual_gravity, time_step_sec);
+    }
+    bond::apply_bonds(particles, particle_classes, environment.bonds, environment.boundary, time_step_sec);
+}
+
+impl Integrator for SemiImplicitEulerIntegrator {
+    fn step(&self, particles: &mut Vec<Particle>, environment: &StepEnvironment, time_step: Duration) -> StepReport {
+        let time_step_sec = time_step.as_secs_f64();
+        let particle_classes = environment.particle_classes;
+        let particle_vs_particle_resolver = motion_resolver::particle_vs_particle_velocity_resolver(
+            self.collision_model.as_ref(),
+            particle_classes,
+            environment.particle_pair_rules,
+        );
+        let particle_vs_wall_resolver = motion_resolver::particle_vs_wall_velocity_resolver(
+            self.collision_model.as_ref(),
+            particle_classes,
+            environment.wall_classes,
+            &environment.units,
+            None,
+        );
+
+        // Forces may speed particles up before collisions are searched, so the collision
+        // cutoff has a margin. Soft forces may reach further
+        let mut neighbor_grid = NeighborGrid::new();
+        let collision_cutoff = motion_resolver::collision_cutoff(particles, particle_classes, time_step_sec);
+        let cutoff = (collision_cutoff * NEIGHBOR_CUTOFF_MARGIN)
+            .max(neighbor_grid::interaction_range(particles, particle_classes));
+        if cutoff > 0.0 {
+            neighbor_grid.rebuild(particles, cutoff);
+        }
+
+        // Velocities first, at the starting positions
+        apply_forces(particles, environment, time_step_sec);
+        if let Some(force_field) = &self.force_field {
+            if !neighbor_grid.is_empty() {
+                force_field::apply_force_field(
+                    particles,
+                    particle_classes,
+                    &neighbor_grid,
+                    environment.boundary,
+                    force_field.as_ref(),
+                    time_step_sec,
+                );
+            }
+        }
+
+        // Then positions, with the new velocities
+        return motion_resolver::resolve(
+            particles,
+            environment,
+            &neighbor_grid,
+            time_step_sec,
+            &CollisionHandling::new(&particle_vs_particle_resolver, &particle_vs_wall_resolver),
+            None,
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::{math_core, LennardJones, ParticleClass, ParticlePairRules, Vec2, VelocityVerletIntegrator};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_falling_particle() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let gravity = Vec2::new(0.0, -10.0);
+        let time_step = 0.1;
+        let steps = 10;
+        let pair_rules = ParticlePairRules::new();
+        let wall_classes = HashMap::new();
+        let environment = StepEnvironment::new(&classes, &pair_rules, &[], &wall_classes).with_gravity(gravity);
+        let fall = |integrator: &dyn Integrator| {
+            let mut particles = vec![Particle::new(Vec2::ZERO, Vec2::ZERO, 1)];
+            for _ in 0..steps {
+                integrator.step(&mut particles, &environment, Duration::from_secs_f64(time_step));
+            }
+            particles[0]
+        };
+        let euler = fall(&SemiImplicitEulerIntegrator::new());
+        let verlet = fall(&VelocityVerletIntegrator::new());
+
+        // Under constant force both move with the speed of the step end
+        let n = steps as f64;
+        let speed = 10.0 * n * time_step;
+        let drop = 10.0 * time_step * time_step * n * (n + 1.0) / 2.0;
+        for particle in [euler, verlet] {
+            assert!(particle.velocity.approx_eq(Vec2::new(0.0, -speed), DISTANCE_EPS));
+            assert!(math_core::approx_eq(particle.position.y, -drop, DISTANCE_EPS));
+            assert_eq!(particle.position.x, 0.0);
+        }
+        // So they overshoot the exact drop
+        let exact_drop = 10.0 * (n * time_step).powi(2) / 2.0;
+        assert!(exact_drop < drop);
+    }
+
+    #[test]
+    fn test_force_field() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Atom", 1.0, 0.2).with_interaction_cutoff(1.25));
+        let pair_rules = ParticlePairRules::new();
+        let wall_classes = HashMap::new();
+        let environment = StepEnvironment::new(&classes, &pair_rules, &[], &wall_classes);
+        let field = LennardJones::new(1.0, 1.0);
+        let time_step = 0.1;
+        let attract = |integrator: &dyn Integrator| {
+            let mut particles = vec![
+                Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+                Particle::new(Vec2::new(1.5, 0.0), Vec2::ZERO, 1),
+            ];
+            integrator.step(&mut particles, &environment, Duration::from_secs_f64(time_step));
+            particles
+        };
+        let euler = attract(&SemiImplicitEulerIntegrator::new().with_force_field(Box::new(field)));
+        let verlet = attract(&VelocityVerletIntegrator::new().with_force_field(Box::new(field)));
+
+        // Whole impulse at the starting distance, and the move with the new velocity
+        let speed = -field.force(1.5) * time_step;
+        assert!(math_core::approx_eq(euler[0].velocity.x, speed, 1e-12));
+        assert!(math_core::approx_eq(euler[1].velocity.x, -speed, 1e-12));
+        assert!(math_core::approx_eq(euler[0].position.x, speed * time_step, 1e-12));
+        // Verlet takes the second half at the new distance, where the pull is different
+        assert!(!math_core::approx_eq(verlet[0].velocity.x, speed, 1e-9));
+    }
+}
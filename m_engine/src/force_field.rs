@@ -0,0 +1,170 @@
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{BoundaryCondition, NeighborGrid, Particle, ParticleClass, Vec2};
+use std::collections::HashMap;
+
+/// Smooth force between pairs of particles, on top of the hard-sphere collisions.
+/// It acts between particles closer than the sum of their interaction cutoffs,
+/// see `ParticleClass::with_interaction_cutoff`. Fields are shared by the threads
+/// that resolve collision islands
+pub trait ForceField: Send + Sync {
+    /// Force between particles whose centers are `distance` apart.
+    /// Positive pushes them apart, negative pulls them together
+    fn force(&self, distance: f64) -> f64;
+
+    /// Potential energy of the pair at `distance`. Zero at infinity
+    fn potential(&self, distance: f64) -> f64;
+}
+
+/// Lennard-Jones potential 4ε((σ/r)^12 - (σ/r)^6). Particles repel when closer than
+/// `equilibrium_distance` and attract when further
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LennardJones {
+    /// Depth of the potential well
+    pub epsilon: f64,
+    /// Distance at which the potential is zero
+    pub sigma: f64,
+}
+
+impl LennardJones {
+    pub fn new(epsilon: f64, sigma: f64) -> Self {
+        LennardJones { epsilon, sigma }
+    }
+
+    /// Distance of the potential minimum, where the force is zero
+    pub fn equilibrium_distance(&self) -> f64 {
+        2.0_f64.powf(1.0 / 6.0) * self.sigma
+    }
+}
+
+impl ForceField for LennardJones {
+    fn force(&self, distance: f64) -> f64 {
+        let s6 = (self.sigma / distance).powi(6);
+        return 24.0 * self.epsilon * (2.0 * s6 * s6 - s6) / distance;
+    }
+
+    fn potential(&self, distance: f64) -> f64 {
+        let s6 = (self.sigma / distance).powi(6);
+        return 4.0 * self.epsilon * (s6 * s6 - s6);
+    }
+}
+
+/// Applies the field between pairs within reach to particle velocities over the time step.
+/// Grid must be built from `particles`, see `NeighborGrid::interaction_pairs`. It doesn't
+/// pair particles across the edges of periodic domain, so then all pairs are checked
+pub(crate) fn apply_force_field(
+    particles: &mut [Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    neighbor_grid: &NeighborGrid,
+    boundary: BoundaryCondition,
+    force_field: &dyn ForceField,
+    time_step_sec: f64,
+) {
+    let pairs: Vec<(usize, usize)> = if boundary.is_periodic() {
+        let cutoff = |p: &Particle| p.interaction_cutoff(get_class(particle_classes, p.class()));
+        (0..particles.len())
+            .flat_map(|i| (i + 1..particles.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| {
+                let distance = boundary.minimum_image(particles[i].position - particles[j].position).length();
+                distance <= cutoff(&particles[i]) + cutoff(&particles[j])
+            })
+            .collect()
+    } else {
+        neighbor_grid.interaction_pairs(particles, particle_classes)
+    };
+    let mut impulses = vec![Vec2::ZERO; particles.len()];
+    for (i, j) in pairs {
+        let offset = boundary.minimum_image(particles[j].position - particles[i].position);
+        let distance = offset.length();
+        if distance < DISTANCE_EPS {
+            // Direction is undefined
+            continue;
+        }
+        let impulse = offset * (force_field.force(distance) * time_step_sec / distance);
+        impulses[i] -= impulse;
+        impulses[j] += impulse;
+    }
+    for (particle, impulse) in particles.iter_mut().zip(impulses) {
+        let mass = particle.mass(get_class(particle_classes, particle.class()));
+        particle.velocity += impulse / mass;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_core;
+
+    #[test]
+    fn test_lennard_jones() {
+        let field = LennardJones::new(2.0, 1.5);
+        let r0 = field.equilibrium_distance();
+        assert!(math_core::approx_eq(field.force(r0), 0.0, 1e-12));
+        assert!(math_core::approx_eq(field.potential(r0), -2.0, 1e-12));
+        assert!(math_core::approx_eq(field.potential(1.5), 0.0, 1e-12));
+        assert!(field.force(0.9 * r0) > 0.0);
+        assert!(field.force(1.1 * r0) < 0.0);
+        // Force is the negative derivative of the potential
+        let r = 1.3 * r0;
+        let h = 1e-6;
+        let derivative = (field.potential(r + h) - field.potential(r - h)) / (2.0 * h);
+        assert!(math_core::approx_eq(field.force(r), -derivative, 1e-6));
+    }
+
+    #[test]
+    fn test_apply_force_field() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Atom", 1.0, 0.2).with_interaction_cutoff(1.25));
+        classes.insert(2, ParticleClass::new("Heavy", 3.0, 0.2).with_interaction_cutoff(1.25));
+        let field = LennardJones::new(1.0, 1.0);
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(1.5, 0.0), Vec2::ZERO, 2),
+            // Out of reach
+            Particle::new(Vec2::new(10.0, 0.0), Vec2::ZERO, 1),
+        ];
+        let mut grid = NeighborGrid::new();
+        grid.rebuild(&particles, 2.5);
+        apply_force_field(&mut particles, &classes, &grid, BoundaryCondition::Open, &field, 0.1);
+
+        // Attracted to each other, momentum is conserved
+        assert!(particles[0].velocity.x > 0.0);
+        assert!(particles[1].velocity.x < 0.0);
+        let momentum = particles[0].velocity.x + 3.0 * particles[1].velocity.x;
+        assert!(math_core::approx_eq(momentum, 0.0, 1e-12));
+        assert!(math_core::approx_eq(particles[0].velocity.x, -field.force(1.5) * 0.1, 1e-12));
+        assert_eq!(particles[0].velocity.y, 0.0);
+        assert_eq!(particles[2].velocity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_apply_force_field_periodic() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Atom", 1.0, 0.2).with_interaction_cutoff(1.25));
+        let field = LennardJones::new(1.0, 1.0);
+        let boundary = BoundaryCondition::Periodic { min: Vec2::new(0.0, 0.0), max: Vec2::new(10.0, 10.0) };
+        // Near opposite edges, 1.5 apart through the edge
+        let make_particles = || {
+            vec![
+                Particle::new(Vec2::new(0.5, 5.0), Vec2::ZERO, 1),
+                Particle::new(Vec2::new(9.0, 5.0), Vec2::ZERO, 1),
+            ]
+        };
+        let mut particles = make_particles();
+        let mut grid = NeighborGrid::new();
+        grid.rebuild(&particles, 2.5);
+        apply_force_field(&mut particles, &classes, &grid, boundary, &field, 0.1);
+
+        // Attracted to each other across the edge
+        assert!(particles[0].velocity.x < 0.0);
+        assert!(particles[1].velocity.x > 0.0);
+        assert!(math_core::approx_eq(particles[0].velocity.x, field.force(1.5) * 0.1, 1e-12));
+        assert!(math_core::approx_eq(particles[0].velocity.x, -particles[1].velocity.x, 1e-12));
+
+        // Open domain keeps them apart
+        let mut particles = make_particles();
+        apply_force_field(&mut particles, &classes, &grid, BoundaryCondition::Open, &field, 0.1);
+        assert_eq!(particles[0].velocity, Vec2::ZERO);
+        assert_eq!(particles[1].velocity, Vec2::ZERO);
+    }
+}
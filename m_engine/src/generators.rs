@@ -1,17 +1,80 @@
 use crate::prelude::*;
-use crate::{Particle, Vec2};
-use rand;
-
-pub fn generate_grid(
-    origin: Vec2,
-    primary_axis_dir: Vec2,
-    size_primary: f64,
-    size_secondary: f64,
-    num_cells_primary: usize,
-    num_cells_secondary: usize,
-    initial_velocity: impl Fn(Vec2) -> Vec2,
-    class_id: ClassId,
-) -> Vec<Particle> {
+use crate::thermo;
+use crate::{Particle, Units, Vec2};
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::fmt;
+
+/// Error returned when the grid is too dense for particles of given radius
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridSpacingError {
+    /// Smallest distance between neighbouring grid nodes
+    pub spacing: f64,
+    /// Particle diameter. Spacing must be at least this
+    pub min_spacing: f64,
+}
+
+impl fmt::Display for GridSpacingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Grid spacing {} is less than particle diameter {}. Particles would overlap",
+            self.spacing, self.min_spacing
+        )
+    }
+}
+
+impl std::error::Error for GridSpacingError {}
+
+/// Geometry of a particle grid. There are (num_cells + 1) nodes along each axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLayout {
+    pub origin: Vec2,
+    /// Unit vector. Secondary axis is rotated 90 degrees counter-clockwise from it
+    pub primary_axis_dir: Vec2,
+    pub size_primary: f64,
+    pub size_secondary: f64,
+    pub num_cells_primary: usize,
+    pub num_cells_secondary: usize,
+}
+
+impl GridLayout {
+    pub fn new(
+        origin: Vec2,
+        primary_axis_dir: Vec2,
+        size_primary: f64,
+        size_secondary: f64,
+        num_cells_primary: usize,
+        num_cells_secondary: usize,
+    ) -> Self {
+        GridLayout {
+            origin,
+            primary_axis_dir,
+            size_primary,
+            size_secondary,
+            num_cells_primary,
+            num_cells_secondary,
+        }
+    }
+}
+
+/// Generates particles in the nodes of the grid.
+/// Order is row-major and doesn't depend on anything but the arguments: rows go along the
+/// secondary axis starting at `origin`, and nodes within a row go along the primary axis.
+/// `initial_velocity` is called once per node in the same order, so seeded velocities
+/// land on the same particles in every run.
+/// The spacing is not checked against the particle size. If it's less than particle diameter,
+/// the particles overlap and the motion resolver can't handle them properly.
+/// Use `generate_grid_checked` to reject such grids
+pub fn generate_grid(layout: &GridLayout, initial_velocity: impl Fn(Vec2) -> Vec2, class_id: ClassId) -> Vec<Particle> {
+    let GridLayout {
+        origin,
+        primary_axis_dir,
+        size_primary,
+        size_secondary,
+        num_cells_primary,
+        num_cells_secondary,
+    } = *layout;
     debug_assert!(primary_axis_dir.is_unit());
     debug_assert!(size_primary > 0.0);
     debug_assert!(size_secondary > 0.0);
@@ -36,22 +99,94 @@ pub fn generate_grid(
     particles
 }
 
+/// Same as `generate_grid`, in the same order, but fails if the spacing between nodes is less than
+/// `2 * radius`, i.e. if particles would overlap
+pub fn generate_grid_checked(
+    layout: &GridLayout,
+    initial_velocity: impl Fn(Vec2) -> Vec2,
+    class_id: ClassId,
+    radius: f64,
+) -> Result<Vec<Particle>, GridSpacingError> {
+    // Grid without cells along the axis has no neighbours along it
+    let spacing_along = |size: f64, num_cells: usize| match num_cells {
+        0 => f64::INFINITY,
+        n => size / n as f64,
+    };
+    let spacing = spacing_along(layout.size_primary, layout.num_cells_primary)
+        .min(spacing_along(layout.size_secondary, layout.num_cells_secondary));
+    let min_spacing = 2.0 * radius;
+    if spacing < min_spacing {
+        return Err(GridSpacingError {
+            spacing,
+            min_spacing,
+        });
+    }
+    return Ok(generate_grid(layout, initial_velocity, class_id));
+}
+
 pub fn constant_velocity(velocity: Vec2) -> impl Fn(Vec2) -> Vec2 {
     move |_| velocity 
 }
 
-pub fn random_velocity(mean_magnitude: f64) -> impl Fn(Vec2) -> Vec2 {
-    move |_| {
-        // Simulate normal distribution by summing 6 random numbers
-        let mut magnitude = 0.0;
-        let steps = 6;
-        for _ in 0..steps {
-            magnitude += rand::random::<f64>() * mean_magnitude * 2.0 / (steps as f64);
-        }
+/// Velocity of magnitude `mean_speed` in a random direction.
+/// Direction is uniform over the circle, every velocity has exactly that speed.
+/// Position of the particle is ignored
+pub fn random_velocity(mean_speed: f64) -> impl Fn(Vec2) -> Vec2 {
+    move |_| sample_velocity(&mut rand::thread_rng(), mean_speed)
+}
 
-        let angle = rand::random::<f64>() * 2.0 * std::f64::consts::PI;
-        Vec2::from_angle_rad(angle) * magnitude
-    }
+/// Same distribution as `random_velocity`, but reproducible. Generators with the same
+/// seed produce the same sequence of velocities
+pub fn random_velocity_seeded(mean_speed: f64, seed: u64) -> impl Fn(Vec2) -> Vec2 {
+    let rng = RefCell::new(rand::rngs::StdRng::seed_from_u64(seed));
+    move |_| sample_velocity(&mut *rng.borrow_mut(), mean_speed)
+}
+
+fn sample_velocity(rng: &mut impl Rng, speed: f64) -> Vec2 {
+    let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+    Vec2::from_angle_rad(angle) * speed
+}
+
+/// Velocity from 2D Maxwell-Boltzmann distribution of particles with given mass, i.e. each
+/// component is normal with zero mean. Speed then has Rayleigh distribution and direction is
+/// uniform over the circle. Mean kinetic energy is the one of the temperature as the engine
+/// defines it, see `thermo::energy_from_temperature`. Position of the particle is ignored
+pub fn maxwell_boltzmann_velocity(temperature: f64, mass: f64, units: &Units) -> impl Fn(Vec2) -> Vec2 {
+    let sigma = maxwell_boltzmann_sigma(temperature, mass, units);
+    move |_| sample_maxwell_boltzmann(&mut rand::thread_rng(), sigma)
+}
+
+/// Same distribution as `maxwell_boltzmann_velocity`, but reproducible. Generators with the
+/// same seed produce the same sequence of velocities
+pub fn maxwell_boltzmann_velocity_seeded(
+    temperature: f64,
+    mass: f64,
+    units: &Units,
+    seed: u64,
+) -> impl Fn(Vec2) -> Vec2 {
+    let sigma = maxwell_boltzmann_sigma(temperature, mass, units);
+    let rng = RefCell::new(rand::rngs::StdRng::seed_from_u64(seed));
+    move |_| sample_maxwell_boltzmann(&mut *rng.borrow_mut(), sigma)
+}
+
+/// Temperature whose Maxwell-Boltzmann distribution has the given mean speed
+pub fn maxwell_boltzmann_temperature(mean_speed: f64, mass: f64, units: &Units) -> f64 {
+    // Mean of Rayleigh distribution is sigma * sqrt(pi / 2)
+    let sigma = mean_speed / (std::f64::consts::PI / 2.0).sqrt();
+    return thermo::temperature_from_energy(mass * sigma * sigma, units);
+}
+
+// Standard deviation of each velocity component. Mean kinetic energy in 2D is m * sigma^2
+fn maxwell_boltzmann_sigma(temperature: f64, mass: f64, units: &Units) -> f64 {
+    assert!(temperature >= 0.0 && mass > 0.0);
+    return (thermo::energy_from_temperature(temperature, units) / mass).sqrt();
+}
+
+fn sample_maxwell_boltzmann(rng: &mut impl Rng, sigma: f64) -> Vec2 {
+    // Rayleigh distributed speed by inverse transform. 1 - u is never zero
+    let speed = sigma * (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+    let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+    return Vec2::from_angle_rad(angle) * speed;
 }
 
 
@@ -71,16 +206,15 @@ mod tests {
         let num_cells_secondary = 1;
         let initial_velocity = constant_velocity(Vec2::new(1.0, 2.0));
         let class_id = 2;
-        let particles = generate_grid(
+        let layout = GridLayout::new(
             origin,
             primary_axis_dir,
             size_primary,
             size_secondary,
             num_cells_primary,
             num_cells_secondary,
-            initial_velocity,
-            class_id,
         );
+        let particles = generate_grid(&layout, initial_velocity, class_id);
         
         assert_eq!(particles.len(), 4);
         assert!(particles[0].position.approx_eq(Vec2::new(5.0, 4.0), DISTANCE_EPS));
@@ -96,5 +230,98 @@ mod tests {
         assert_eq!(particles[2].class(), class_id);
         assert_eq!(particles[3].class(), class_id);
     }
-   
-}
\ No newline at end of file
+
+    #[test]
+    fn test_generate_grid_order() {
+        // Rotated by 90 degrees: primary axis is up, secondary axis is left
+        let seed = 7;
+        let layout = GridLayout::new(Vec2::ZERO, Vec2::UNIT_Y, 2.0, 1.0, 2, 1);
+        let particles = generate_grid(&layout, random_velocity_seeded(1.0, seed), 0);
+        let expected_positions = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(-1.0, 0.0),
+            Vec2::new(-1.0, 1.0),
+        ];
+        let velocities = random_velocity_seeded(1.0, seed);
+        for (particle, position) in particles.iter().zip(expected_positions) {
+            assert!(particle.position.approx_eq(position, DISTANCE_EPS));
+            // Velocities are sampled in the emission order
+            assert_eq!(particle.velocity, velocities(Vec2::ZERO));
+        }
+
+        // Repeated generation gives identical particles in identical order
+        let again = generate_grid(&layout, random_velocity_seeded(1.0, seed), 0);
+        assert!(particles.iter().zip(&again).all(|(a, b)| a.position == b.position && a.velocity == b.velocity));
+    }
+
+    #[test]
+    fn test_generate_grid_checked() {
+        let velocity = constant_velocity(Vec2::ZERO);
+        // 10 cells over 5 units: spacing 0.5 is less than diameter 1.0
+        let layout = GridLayout::new(Vec2::ZERO, Vec2::UNIT_X, 5.0, 20.0, 10, 10);
+        let result = generate_grid_checked(&layout, &velocity, 0, 0.5);
+        assert_eq!(result.unwrap_err(), GridSpacingError { spacing: 0.5, min_spacing: 1.0 });
+
+        // Spacing 1.0 is exactly enough
+        let layout = GridLayout { num_cells_primary: 5, ..layout };
+        let particles = generate_grid_checked(&layout, &velocity, 0, 0.5).unwrap();
+        assert_eq!(particles.len(), 6 * 11);
+    }
+
+    #[test]
+    fn test_random_velocity_distribution() {
+        let mean_speed = 3.0;
+        let velocity = random_velocity_seeded(mean_speed, 42);
+        let samples: Vec<Vec2> = (0..20000).map(|_| velocity(Vec2::ZERO)).collect();
+
+        // Every velocity has the mean speed
+        assert!(samples.iter().all(|v| (v.length() - mean_speed).abs() < DISTANCE_EPS));
+
+        // Directions are uniform. Each of 8 sectors gets about 1/8 of samples
+        let mut sectors = [0usize; 8];
+        for v in &samples {
+            let angle = v.y.atan2(v.x) + std::f64::consts::PI;
+            sectors[((angle / (2.0 * std::f64::consts::PI) * 8.0) as usize).min(7)] += 1;
+        }
+        let expected = samples.len() as f64 / 8.0;
+        assert!(sectors.iter().all(|&n| (n as f64 - expected).abs() < 0.1 * expected));
+
+        // Same seed gives the same sequence
+        let again = random_velocity_seeded(mean_speed, 42);
+        assert!(samples.iter().take(10).all(|&v| v == again(Vec2::ZERO)));
+    }
+
+    #[test]
+    fn test_maxwell_boltzmann_velocity() {
+        let units = Units::new(2.0);
+        let (temperature, mass) = (300.0, 3.0);
+        let velocity = maxwell_boltzmann_velocity_seeded(temperature, mass, &units, 7);
+        let layout = GridLayout::new(Vec2::ZERO, Vec2::UNIT_X, 100.0, 100.0, 99, 99);
+        let particles = generate_grid(&layout, &velocity, 0);
+        assert_eq!(particles.len(), 10000);
+
+        // Mean kinetic energy is the one of the temperature
+        let n = particles.len() as f64;
+        let mean_energy = particles.iter().map(|p| thermo::kinetic_energy(mass, p.velocity.length())).sum::<f64>() / n;
+        let temperature_measured = thermo::temperature_from_energy(mean_energy, &units);
+        assert!((temperature_measured - temperature).abs() < 0.03 * temperature, "{}", temperature_measured);
+
+        // Components are centered and equally spread
+        let mean_velocity = particles.iter().fold(Vec2::ZERO, |sum, p| sum + p.velocity) / n;
+        let sigma = (thermo::energy_from_temperature(temperature, &units) / mass).sqrt();
+        assert!(mean_velocity.length() < 0.05 * sigma);
+        let variance = |component: fn(&Vec2) -> f64| particles.iter().map(|p| component(&p.velocity).powi(2)).sum::<f64>() / n;
+        assert!((variance(|v| v.x) - sigma * sigma).abs() < 0.05 * sigma * sigma);
+        assert!((variance(|v| v.y) - sigma * sigma).abs() < 0.05 * sigma * sigma);
+
+        // Temperature of the mean speed gives that mean speed back
+        let mean_speed = particles.iter().map(|p| p.velocity.length()).sum::<f64>() / n;
+        let from_speed = maxwell_boltzmann_temperature(mean_speed, mass, &units);
+        assert!((from_speed - temperature).abs() < 0.03 * temperature);
+
+        // Cold gas stands still
+        assert_eq!(maxwell_boltzmann_velocity(0.0, mass, &units)(Vec2::ZERO), Vec2::ZERO);
+    }
+}
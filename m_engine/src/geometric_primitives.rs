@@ -1,5 +1,6 @@
 use crate::Vec2;
 use crate::math_core;
+use crate::collision_utils;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Plane
@@ -69,12 +70,82 @@ impl LineSegment
         return Some(LineSegment::new(self.begin + normal * offset, self.end + normal * offset));
     }
 
+    /// Returns the point of this segment closest to `point`.
+    /// Projection of the point is clamped to [begin, end]
+    pub fn closest_point(&self, point: Vec2) -> Vec2
+    {
+        let dir = self.end - self.begin;
+        let length_sq = dir.length_sq();
+        // Degenerate segment is just a point
+        if length_sq == 0.0 {
+            return self.begin;
+        }
+        let t = ((point - self.begin).dot(dir) / length_sq).clamp(0.0, 1.0);
+        return self.begin + dir * t;
+    }
+
+    /// Returns distance from `point` to the closest point of this segment
+    pub fn distance_to(&self, point: Vec2) -> f64
+    {
+        (point - self.closest_point(point)).length()
+    }
+
     pub fn approx_eq(&self, other: Self, epsilon: f64) -> bool
     {
         self.begin.approx_eq(other.begin, epsilon) && self.end.approx_eq(other.end, epsilon)
     }
 }
 
+/// Segment with rounded ends, i.e. all points within `radius` of the segment.
+/// Handy for paddles and bumpers
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule
+{
+    pub begin: Vec2,
+    pub end: Vec2,
+    pub radius: f64,
+}
+
+impl Capsule
+{
+    pub fn new(begin: Vec2, end: Vec2, radius: f64) -> Self
+    {
+        Capsule { begin, end, radius }
+    }
+
+    /// Core segment of the capsule
+    pub fn segment(&self) -> LineSegment
+    {
+        LineSegment::new(self.begin, self.end)
+    }
+
+    /// Signed distance from `point` to the capsule surface. Negative inside
+    pub fn distance_to(&self, point: Vec2) -> f64
+    {
+        self.segment().distance_to(point) - self.radius
+    }
+
+    /// Outward surface normal at the point of the surface closest to `point`.
+    /// None if the point lies on the core segment
+    pub fn normal_at(&self, point: Vec2) -> Option<Vec2>
+    {
+        (point - self.segment().closest_point(point)).normalized()
+    }
+
+    /// Collision of moving particle with this capsule.
+    /// Returns time and collision normal, if any
+    pub fn find_particle_collision(&self, center: Vec2, radius: f64, velocity: Vec2) -> Option<(f64, Vec2)>
+    {
+        collision_utils::find_particle_vs_capsule_collision(center, radius, velocity, *self)
+    }
+
+    pub fn approx_eq(&self, other: Self, epsilon: f64) -> bool
+    {
+        self.begin.approx_eq(other.begin, epsilon)
+            && self.end.approx_eq(other.end, epsilon)
+            && math_core::approx_eq(self.radius, other.radius, epsilon)
+    }
+}
 
 #[cfg(test)]
 mod tests
@@ -122,4 +193,55 @@ mod tests
 
         
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_line_segment_closest_point()
+    {
+        let line = LineSegment::new(Vec2::new(1.0, 1.0), Vec2::new(3.0, 3.0));
+
+        // Projects onto interior
+        let p = Vec2::new(3.0, 1.0);
+        assert!(line.closest_point(p).approx_eq(Vec2::new(2.0, 2.0), DISTANCE_EPS));
+        assert!(math_core::approx_eq(line.distance_to(p), 2.0_f64.sqrt(), DISTANCE_EPS));
+
+        // Point on the segment
+        let p = Vec2::new(1.5, 1.5);
+        assert!(line.closest_point(p).approx_eq(p, DISTANCE_EPS));
+        assert!(math_core::approx_eq(line.distance_to(p), 0.0, DISTANCE_EPS));
+
+        // Beyond begin
+        let p = Vec2::new(0.0, -1.0);
+        assert!(line.closest_point(p).approx_eq(line.begin, DISTANCE_EPS));
+        assert!(math_core::approx_eq(line.distance_to(p), 5.0_f64.sqrt(), DISTANCE_EPS));
+
+        // Beyond end
+        let p = Vec2::new(6.0, 7.0);
+        assert!(line.closest_point(p).approx_eq(line.end, DISTANCE_EPS));
+        assert!(math_core::approx_eq(line.distance_to(p), 5.0, DISTANCE_EPS));
+
+        // Degenerate segment
+        let line = LineSegment::new(Vec2::new(1.0, 1.0), Vec2::new(1.0, 1.0));
+        assert!(math_core::approx_eq(line.distance_to(Vec2::new(4.0, 5.0)), 5.0, DISTANCE_EPS));
+    }
+
+    #[test]
+    fn test_capsule_distance_and_normal()
+    {
+        let capsule = Capsule::new(Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), 1.0);
+
+        // Flat side
+        let p = Vec2::new(2.0, 3.0);
+        assert!(math_core::approx_eq(capsule.distance_to(p), 2.0, DISTANCE_EPS));
+        assert!(capsule.normal_at(p).unwrap().approx_eq(Vec2::new(0.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Rounded cap
+        let p = Vec2::new(7.0, 4.0);
+        assert!(math_core::approx_eq(capsule.distance_to(p), 4.0, DISTANCE_EPS));
+        assert!(capsule.normal_at(p).unwrap().approx_eq(Vec2::new(0.6, 0.8), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Inside
+        assert!(math_core::approx_eq(capsule.distance_to(Vec2::new(1.0, 0.5)), -0.5, DISTANCE_EPS));
+        // On the core segment there is no normal
+        assert!(capsule.normal_at(Vec2::new(1.0, 0.0)).is_none());
+    }
+}
@@ -1,16 +1,88 @@
 use crate::prelude::*;
-use crate::{Particle, ParticleClass, Wall, WallClass};
+use crate::{BoundaryCondition, Bond, MutualGravity, Particle, ParticleClass, ParticlePairRules, Polygon, StepReport, Units, Vec2, Wall, WallClass};
 use std::collections::HashMap;
 use std::time::Duration;
 
+/// Everything besides the particles that a step depends on.
+/// Particles whose center is inside one of `gravity_zones` get the gravity of the first
+/// such zone instead of `gravity`. With periodic `boundary` particles that leave the domain
+/// come back through the opposite edge
+#[derive(Debug, Clone, Copy)]
+pub struct StepEnvironment<'a> {
+    pub particle_classes: &'a HashMap<ClassId, ParticleClass>,
+    pub particle_pair_rules: &'a ParticlePairRules,
+    pub bonds: &'a [Bond],
+    pub walls: &'a [Wall],
+    pub wall_classes: &'a HashMap<ClassId, WallClass>,
+    pub gravity: Vec2,
+    pub gravity_zones: &'a [(Polygon, Vec2)],
+    pub mutual_gravity: Option<&'a MutualGravity>,
+    pub units: Units,
+    pub boundary: BoundaryCondition,
+}
+
+impl<'a> StepEnvironment<'a> {
+    /// Creates environment without bonds and gravity, in default units and open space
+    pub fn new(
+        particle_classes: &'a HashMap<ClassId, ParticleClass>,
+        particle_pair_rules: &'a ParticlePairRules,
+        walls: &'a [Wall],
+        wall_classes: &'a HashMap<ClassId, WallClass>,
+    ) -> Self {
+        StepEnvironment {
+            particle_classes,
+            particle_pair_rules,
+            bonds: &[],
+            walls,
+            wall_classes,
+            gravity: Vec2::ZERO,
+            gravity_zones: &[],
+            mutual_gravity: None,
+            units: Units::default(),
+            boundary: BoundaryCondition::Open,
+        }
+    }
+
+    pub fn with_bonds(mut self, bonds: &'a [Bond]) -> Self {
+        self.bonds = bonds;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vec2) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_gravity_zones(mut self, gravity_zones: &'a [(Polygon, Vec2)]) -> Self {
+        self.gravity_zones = gravity_zones;
+        self
+    }
+
+    pub fn with_mutual_gravity(mut self, mutual_gravity: Option<&'a MutualGravity>) -> Self {
+        self.mutual_gravity = mutual_gravity;
+        self
+    }
+
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub fn with_boundary(mut self, boundary: BoundaryCondition) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Gravity of the first zone that contains `position`, or the global one
+    pub fn gravity_at(&self, position: Vec2) -> Vec2 {
+        self.gravity_zones
+            .iter()
+            .find(|(zone, _)| zone.contains_point(position))
+            .map_or(self.gravity, |(_, zone_gravity)| *zone_gravity)
+    }
+}
+
+/// Advances particles by one time step in the given environment
 pub trait Integrator {
-    fn step(
-        &self,
-        particles: &mut [Particle],
-        particle_classes: &HashMap<ClassId, ParticleClass>,
-        walls: &[Wall],
-        wall_classes: &HashMap<ClassId, WallClass>,
-        gravity: f64,
-        time_step: Duration,
-    );
+    fn step(&self, particles: &mut Vec<Particle>, environment: &StepEnvironment, time_step: Duration) -> StepReport;
 }
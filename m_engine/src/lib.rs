@@ -2,16 +2,40 @@ pub mod prelude;
 pub mod vec2;
 pub mod particle;
 pub mod particle_class;
+pub mod particle_pair_rule;
+pub mod bond;
+pub mod force_field;
+pub mod mutual_gravity;
+pub mod barnes_hut;
+pub mod neighbor_grid;
 pub mod wall;
 pub mod wall_class;
+pub mod wall_load;
 pub mod integrator;
 pub mod velocity_verlet_integrator;
+pub mod euler_integrator;
+pub mod collision_model;
+pub mod adaptive_time_step;
 pub mod generators;
 pub mod simulation;
+pub mod boundary_condition;
 pub mod polygon;
 pub mod geometric_primitives;
 pub mod statistics;
+pub mod statistics_accumulator;
+pub mod equilibrium;
+pub mod energy_drift;
+pub mod relaxation;
+pub mod tensor2;
+pub mod step_report;
+pub mod units;
+pub mod thermo;
 pub mod simulation_spec;
+pub mod collisions;
+pub mod sim_error;
+
+#[cfg(feature = "bench")]
+pub mod bench;
 
 mod collision_utils;
 mod motion_resolver;
@@ -20,12 +44,30 @@ mod math_core;
 pub use vec2::Vec2;
 pub use particle::Particle;
 pub use particle_class::ParticleClass;
-pub use wall::Wall;
-pub use wall_class::WallClass;
-pub use simulation::Simulation;
-pub use integrator::Integrator;
-pub use velocity_verlet_integrator::VelocityVerletIntegrator;
+pub use particle_pair_rule::{ParticlePairRule, ParticlePairRules};
+pub use bond::{Bond, SpringParams};
+pub use force_field::{ForceField, LennardJones};
+pub use mutual_gravity::MutualGravity;
+pub use neighbor_grid::NeighborGrid;
+pub use wall::{Wall, WallGeometry};
+pub use wall_class::{RestitutionCurve, WallClass};
+pub use wall_load::WallLoad;
+pub use boundary_condition::BoundaryCondition;
+pub use simulation::{GravityFn, OverflowPolicy, Simulation, SimulationSnapshot, StepHook};
+pub use units::Units;
+pub use sim_error::SimError;
+pub use integrator::{Integrator, StepEnvironment};
+pub use velocity_verlet_integrator::{ContactResolution, NonFinitePolicy, VelocityVerletIntegrator};
+pub use euler_integrator::SemiImplicitEulerIntegrator;
+pub use collision_model::{CollisionModel, ElasticModel, InelasticModel, WallContact};
+pub use adaptive_time_step::AdaptiveTimeStep;
 pub use polygon::Polygon;
-pub use geometric_primitives::{Plane, LineSegment};
-pub use statistics::Statistics;
-pub use simulation_spec::{SimulationSpec, ParticleClassSpec, WallClassSpec};
\ No newline at end of file
+pub use geometric_primitives::{Plane, LineSegment, Capsule};
+pub use statistics::{ClassStats, Statistics};
+pub use statistics_accumulator::{StatisticsAccumulator, WindowedStatistics, WindowedValue};
+pub use equilibrium::{EquilibriumCriterion, EquilibriumDetector};
+pub use energy_drift::EnergyDrift;
+pub use relaxation::Relaxation;
+pub use tensor2::Tensor2;
+pub use step_report::StepReport;
+pub use simulation_spec::{SimulationSpec, ParticleClassSpec, WallClassSpec, SpecDiagnostic, SpecFileError, SpecMergeError};
\ No newline at end of file
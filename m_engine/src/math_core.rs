@@ -1,3 +1,5 @@
+use rand::Rng;
+
 
 
 pub(crate) fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
@@ -5,10 +7,10 @@ pub(crate) fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
 }
 
 /// Creates positive random number with normal distribution with given mean
-pub(crate) fn random_0_to_mean(mean: f64) -> f64 {
+pub(crate) fn random_0_to_mean(mean: f64, rng: &mut impl Rng) -> f64 {
     let mut sum = 0.0;
     for _ in 0..6 {
-        sum += rand::random::<f64>() * 2.0 * mean;
+        sum += rng.gen::<f64>() * 2.0 * mean;
     }
     return sum / 6.0;
 }
@@ -37,13 +39,11 @@ pub (crate) fn velocity_from_kinetic_energy(mass: f64, energy: f64) -> f64 {
 }
 
 /// Converts kinetic energy to temperature
-pub (crate) fn temp_from_energy(energy : f64) -> f64 {
-    // assume Boltzmann constant = 1
-    energy * 3.0 / 2.0
+pub (crate) fn temp_from_energy(energy : f64, boltzmann : f64) -> f64 {
+    energy * 3.0 / 2.0 / boltzmann
 }
 
 /// Converts temperature to kinetic energy
-pub (crate) fn energy_from_temp(temp : f64) -> f64 {
-    // assume Boltzmann constant = 1
-    temp * 2.0 / 3.0
+pub (crate) fn energy_from_temp(temp : f64, boltzmann : f64) -> f64 {
+    temp * 2.0 / 3.0 * boltzmann
 }
\ No newline at end of file
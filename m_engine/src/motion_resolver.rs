@@ -1,28 +1,88 @@
 use crate::collision_utils;
+use crate::math_core;
 use crate::collision_utils::find_particle_vs_polygon_collision;
 use crate::prelude::*;
-use crate::{Particle, ParticleClass, Vec2, Wall, WallClass};
+use crate::sim_error::get_class;
+use crate::collision_model::{CollisionModel, WallContact};
+use crate::collisions::CollisionEvent;
+use crate::velocity_verlet_integrator::ContactResolution;
+use crate::{
+    BoundaryCondition, NeighborGrid, Particle, ParticleClass, ParticlePairRule, ParticlePairRules, StepEnvironment,
+    StepReport, Tensor2, Units, Vec2, Wall, WallClass, WallGeometry,
+};
 use ordered_float;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::cmp::{Ord, PartialOrd, Reverse};
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
-use std::ops::Range;
 use std::vec::Vec;
 
+// Extra distance, relative to the distance between centers, that warm start requires
+// on top of the contact distance. Covers rounding of positions moved between calls
+const WARM_START_MARGIN: f64 = 1e-9;
+
+/// Collision detection state carried between consecutive calls of `resolve`, e.g.
+/// substeps of a single step. Pairs of particles that are known to stay apart are not
+/// checked again while both particles keep moving along the same straight lines.
+/// Results are the same as without it
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WarmStart {
+    // Positions and velocities at the end of the previous call. Particle that doesn't
+    // match them at the start of the next call moved discontinuously, e.g. was accelerated
+    end_states: Vec<(Vec2, Vec2)>,
+    // Pairs (i < j) that can't touch before this time, counted from the start of the next call
+    separated: HashMap<(usize, usize), f64>,
+}
+
+impl WarmStart {
+    // Which particles continue from where the previous call left them
+    fn continuous(&self, particles: &[Particle]) -> Vec<bool> {
+        if self.end_states.len() != particles.len() {
+            return vec![false; particles.len()];
+        }
+        return particles
+            .iter()
+            .zip(self.end_states.iter())
+            .map(|(p, &(position, velocity))| p.position == position && p.velocity == velocity)
+            .collect();
+    }
+}
+
+// Time before which two particles moving along straight lines surely don't touch
+fn separation_time(
+    p1: &Particle,
+    p2: &Particle,
+    class_map: &HashMap<ClassId, ParticleClass>,
+    boundary: BoundaryCondition,
+) -> f64 {
+    let distance = boundary.minimum_image(p1.position - p2.position).length();
+    let contact = p1.radius(get_class(class_map, p1.class())) + p2.radius(get_class(class_map, p2.class()));
+    let gap = distance - contact - WARM_START_MARGIN * distance;
+    if gap <= 0.0 {
+        return 0.0;
+    }
+    let speed = (p1.velocity - p2.velocity).length();
+    if speed == 0.0 {
+        return f64::INFINITY;
+    }
+    return gap / speed;
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum OtherObject {
+pub(crate) enum OtherObject {
     Particle(usize),
     Wall(usize),
 }
 
 /// Represents a collision between 2 objects
 #[derive(Debug, Clone, Copy)]
-struct Collision {
-    particle: usize,
-    other: OtherObject,
-    normal: Vec2,
+pub(crate) struct Collision {
+    pub(crate) particle: usize,
+    pub(crate) other: OtherObject,
+    pub(crate) normal: Vec2,
     // The time must be this weird type to enable sorting
-    time: ordered_float::OrderedFloat<f64>,
+    pub(crate) time: ordered_float::OrderedFloat<f64>,
 }
 
 impl Collision {
@@ -52,18 +112,21 @@ impl PartialOrd for Collision {
     }
 }
 
-/// Finds all collisions between a particle and a range of particles
-/// The range may contain particle itself, in which case it's ignored.
+/// Finds all collisions between a particle and a set of particles
+/// The set may contain particle itself, in which case it's ignored. So are pairs that pass through.
 /// Some particles already have time advanced for them. If collision happens
-/// in the "past" it's ignored
-fn find_collisions_with_particles(
+/// in the "past" by more than `past_tolerance` it's ignored.
+/// With periodic boundary the nearest image of each other particle is checked
+pub(crate) fn find_collisions_with_particles(
     main_index: usize,
-    other_indices: Range<usize>,
+    other_indices: impl IntoIterator<Item = usize>,
     particles: &[Particle],
-    class_map: &HashMap<ClassId, ParticleClass>,
+    environment: &StepEnvironment,
     particle_times: &[f64],
     time_threshold: f64,
+    past_tolerance: f64,
 ) -> Vec<Collision> {
+    let class_map = environment.particle_classes;
     let mut collisions = vec![];
     for i in other_indices {
         // Skip collisions agains itself
@@ -72,24 +135,30 @@ fn find_collisions_with_particles(
         }
         let p1 = &particles[main_index];
         let p2 = &particles[i];
+        if environment.particle_pair_rules.get(p1.class(), p2.class()) == ParticlePairRule::PassThrough {
+            continue;
+        }
+        let class1 = get_class(class_map, p1.class());
+        let class2 = get_class(class_map, p2.class());
         // Both particles live at different time step. We need to bring them to the same time 0.
         let pos1 = p1.position - p1.velocity * particle_times[main_index];
         let pos2 = p2.position - p2.velocity * particle_times[i];
+        let pos2 = pos1 + environment.boundary.minimum_image(pos2 - pos1);
 
         let collision_time = collision_utils::find_particle_vs_particle_collision(
             pos1,
-            class_map.get(&p1.class()).unwrap().radius(),
+            p1.radius(class1),
             p1.velocity,
             pos2,
-            class_map.get(&p2.class()).unwrap().radius(),
+            p2.radius(class2),
             p2.velocity,
         );
         if let Some(collision_time) = collision_time {
             // Check if the collision is in the future. But not too far in the future
             // Allow for collisions that are slightly in the past. These can appear due to
             // floating point errors
-            if collision_time > (particle_times[main_index] - TIME_SEC_EPS)
-                && collision_time > (particle_times[i] - TIME_SEC_EPS)
+            if collision_time > (particle_times[main_index] - past_tolerance)
+                && collision_time > (particle_times[i] - past_tolerance)
                 && collision_time < time_threshold
             {
                 let normal = collision_utils::particles_collision_normal(
@@ -114,31 +183,45 @@ fn find_collisions_with_particles(
     return collisions;
 }
 
-/// Finds all collisions between a particle and a range of walls
-fn find_collisions_with_walls(
+/// Finds all collisions between a particle and a range of walls.
+/// Collisions earlier than `past_tolerance` before the particle time are ignored
+pub(crate) fn find_collisions_with_walls(
     particle_index: usize,
     particle: &Particle,
     particle_class: &ParticleClass,
     other_walls: &[Wall],
     particle_time: f64,
     time_threshold: f64,
+    past_tolerance: f64,
 ) -> Vec<Collision> {
     let mut collisions = vec![];
 
     for (i, wall) in other_walls.iter().enumerate() {
         // Bring particle to t=0
         let pos = particle.position - particle.velocity * particle_time;
-        let collision_res = find_particle_vs_polygon_collision(
-            pos,
-            particle_class.radius(),
-            particle.velocity,
-            wall.polygon(),
-        );
+        let collision_res = match wall.geometry() {
+            WallGeometry::Polygon => find_particle_vs_polygon_collision(
+                pos,
+                particle.radius(particle_class),
+                particle.velocity,
+                wall.polygon(),
+            ),
+            WallGeometry::Circle { center, radius, inward } => {
+                collision_utils::find_particle_vs_circle_collision(
+                    pos,
+                    particle.radius(particle_class),
+                    particle.velocity,
+                    center,
+                    radius,
+                    inward,
+                )
+            }
+        };
         if let Some((collision_time, collision_normal)) = collision_res {
             // Check if the collision is in the future. But not too far in the future
             // Allow for collisions that are slightly in the past. These can appear due to
             // floating point errors
-            if collision_time > (particle_time - TIME_SEC_EPS) && collision_time < time_threshold {
+            if collision_time > (particle_time - past_tolerance) && collision_time < time_threshold {
                 collisions.push(Collision {
                     particle: particle_index,
                     other: OtherObject::Wall(i),
@@ -174,55 +257,521 @@ fn resolve_particle_vs_particle(
     return (particle1, particle2);
 }
 
+// Collisions closer in time than this are resolved together in batched mode
+const BATCH_TIME_EPS: f64 = 1e-9;
+// Limits of the iterative impulse solver
+const BATCH_MAX_ITERATIONS: usize = 200;
+const BATCH_IMPULSE_TOLERANCE: f64 = 1e-12;
+
+/// Takes from the heap all particle collisions that happen at the time of `collision` and
+/// are connected to it through shared particles. Returns them together with `collision`.
+/// Only elastic particle pairs take part. Returns empty batch if `collision` can't
+fn take_simultaneous_collisions(
+    collision: Collision,
+    collisions: &mut BinaryHeap<Reverse<Collision>>,
+    particles: &[Particle],
+    particle_pair_rules: &ParticlePairRules,
+) -> Vec<Collision> {
+    let batchable = |c: &Collision| match c.other {
+        OtherObject::Particle(other) => {
+            particle_pair_rules.get(particles[c.particle].class(), particles[other].class())
+                == ParticlePairRule::Elastic
+        }
+        OtherObject::Wall(_) => false,
+    };
+    if !batchable(&collision) {
+        return vec![];
+    }
+    let time = collision.time.0;
+    let mut batch = vec![collision];
+    let mut involved = vec![];
+    let joins = |c: &Collision, involved: &[usize]| {
+        batchable(c) && c.time.0 - time <= BATCH_TIME_EPS && involved.iter().any(|&i| c.involves_particle(i))
+    };
+    loop {
+        involved.clear();
+        for c in &batch {
+            if let OtherObject::Particle(other) = c.other {
+                involved.push(c.particle);
+                involved.push(other);
+            }
+        }
+        // Heap is left intact if nothing joins. Order of ties stays as in sequential mode
+        if !collisions.iter().any(|Reverse(c)| joins(c, &involved)) {
+            break;
+        }
+        let (taken, rest): (Vec<_>, Vec<_>) =
+            collisions.drain().partition(|Reverse(c)| joins(c, &involved));
+        *collisions = rest.into_iter().collect();
+        for Reverse(c) in taken {
+            // Same pair may be found from both sides
+            let pair = |c: &Collision| match c.other {
+                OtherObject::Particle(other) => (c.particle.min(other), c.particle.max(other)),
+                OtherObject::Wall(_) => unreachable!(),
+            };
+            if !batch.iter().any(|b| pair(b) == pair(&c)) {
+                batch.push(c);
+            }
+        }
+    }
+    return batch;
+}
+
+/// Resolves particle collisions of the batch at once. All particles are advanced to
+/// `collision_t`. Normal impulses are found with projected Gauss-Seidel iterations, so
+/// that each pair separates as fast as the velocity resolver would separate it alone.
+/// Tangential effects of the resolver are ignored. If the solution adds energy, pairs
+/// are resolved one by one instead. Returns the virial of the batch and the magnitudes of
+/// impulses of its collisions
+fn resolve_collision_batch(
+    batch: &[Collision],
+    particles: &mut [Particle],
+    particle_time: &mut [f64],
+    collision_t: f64,
+    particle_class_map: &HashMap<ClassId, ParticleClass>,
+    velocity_resolver: &impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2),
+    boundary: BoundaryCondition,
+) -> (Tensor2, Vec<f64>) {
+    let contacts: Vec<(usize, usize, Vec2)> = batch
+        .iter()
+        .map(|c| match c.other {
+            OtherObject::Particle(other) => (c.particle, other, c.normal),
+            OtherObject::Wall(_) => unreachable!(),
+        })
+        .collect();
+    let mut involved: Vec<usize> = contacts.iter().flat_map(|&(i, j, _)| [i, j]).collect();
+    involved.sort_unstable();
+    involved.dedup();
+
+    // Advance particles to the moment of collision
+    for &i in &involved {
+        particles[i].position += particles[i].velocity * (collision_t - particle_time[i]);
+        particle_time[i] = collision_t;
+    }
+    let inv_mass = |i: usize, particles: &[Particle]| {
+        1.0 / particles[i].mass(get_class(particle_class_map, particles[i].class()))
+    };
+    let velocities_before: Vec<Vec2> = involved.iter().map(|&i| particles[i].velocity).collect();
+    let energy = |particles: &[Particle]| -> f64 {
+        involved
+            .iter()
+            .map(|&i| {
+                let mass = particles[i].mass(get_class(particle_class_map, particles[i].class()));
+                math_core::kinetic_energy_from_velocity(mass, particles[i].velocity.length())
+            })
+            .sum()
+    };
+    let energy_before = energy(particles);
+
+    // Normal velocity each pair would separate with if it collided alone
+    let targets: Vec<f64> = contacts
+        .iter()
+        .map(|&(i, j, n)| {
+            let (v1, v2) = velocity_resolver(&particles[i], &particles[j], n);
+            (v2 - v1).dot(n)
+        })
+        .collect();
+
+    let mut impulses = vec![0.0; contacts.len()];
+    for _ in 0..BATCH_MAX_ITERATIONS {
+        let mut max_change: f64 = 0.0;
+        for (k, &(i, j, n)) in contacts.iter().enumerate() {
+            let (inv_mass1, inv_mass2) = (inv_mass(i, particles), inv_mass(j, particles));
+            let relative = (particles[j].velocity - particles[i].velocity).dot(n);
+            let impulse = (impulses[k] + (targets[k] - relative) / (inv_mass1 + inv_mass2)).max(0.0);
+            let change = impulse - impulses[k];
+            impulses[k] = impulse;
+            particles[i].velocity -= n * (change * inv_mass1);
+            particles[j].velocity += n * (change * inv_mass2);
+            max_change = max_change.max(change.abs());
+        }
+        let max_impulse = impulses.iter().fold(0.0_f64, |a, &b| a.max(b));
+        if max_change <= BATCH_IMPULSE_TOLERANCE * max_impulse.max(1.0) {
+            break;
+        }
+    }
+
+    if excess_energy_gain(energy_before, energy(particles)).is_some() {
+        // Fall back to one pair at a time
+        for (&i, &velocity) in involved.iter().zip(velocities_before.iter()) {
+            particles[i].velocity = velocity;
+        }
+        let mut virial = Tensor2::ZERO;
+        let mut impulses = vec![];
+        for &(i, j, n) in &contacts {
+            let (v1, v2) = velocity_resolver(&particles[i], &particles[j], n);
+            let mass1 = 1.0 / inv_mass(i, particles);
+            let impulse1 = (v1 - particles[i].velocity) * mass1;
+            let offset = boundary.minimum_image(particles[i].position - particles[j].position);
+            virial += Tensor2::outer(offset, impulse1);
+            impulses.push(impulse1.length());
+            particles[i].velocity = v1;
+            particles[j].velocity = v2;
+        }
+        return (virial, impulses);
+    }
+
+    let mut virial = Tensor2::ZERO;
+    for (&(i, j, n), &impulse) in contacts.iter().zip(impulses.iter()) {
+        // First particle receives the impulse against the normal
+        let offset = boundary.minimum_image(particles[i].position - particles[j].position);
+        virial += Tensor2::outer(offset, n * -impulse);
+    }
+    return (virial, impulses);
+}
+
+/// Returns None if the wall absorbs the particle
 fn resolve_particle_vs_wall(
     mut particle1: Particle,
     wall: &Wall,
     particle1_t: f64,
     collision_t: f64,
     collision_normal: Vec2,
-    velocity_resolver: &impl Fn(&Particle, &Wall, Vec2) -> Vec2,
-) -> Particle {
+    velocity_resolver: &impl Fn(&Particle, &Wall, Vec2) -> Option<Vec2>,
+) -> Option<Particle> {
     // Advance particle to the moment of collision
     particle1.position += particle1.velocity * (collision_t - particle1_t);
 
     // Resolve new velocity
-    let new_velocity = velocity_resolver(&particle1, wall, collision_normal);
+    let new_velocity = velocity_resolver(&particle1, wall, collision_normal)?;
     particle1.velocity = new_velocity;
 
-    return particle1;
+    return Some(particle1);
 }
 
-pub(crate) fn resolve(
-    particles: &mut [Particle],
+/// Merges 2 colliding particles into single one. The merged particle conserves
+/// total mass, momentum and area. It takes the class of the first particle.
+fn coalesce_particles(
+    mut particle1: Particle,
+    mut particle2: Particle,
+    particle1_t: f64,
+    particle2_t: f64,
+    collision_t: f64,
+    particle_class_map: &HashMap<ClassId, ParticleClass>,
+    boundary: BoundaryCondition,
+) -> Particle {
+    // Advance particles to the moment of collision
+    particle1.position += particle1.velocity * (collision_t - particle1_t);
+    particle2.position += particle2.velocity * (collision_t - particle2_t);
+    // Image of the second particle that touches the first one
+    particle2.position = particle1.position + boundary.minimum_image(particle2.position - particle1.position);
+
+    let class1 = get_class(particle_class_map, particle1.class());
+    let class2 = get_class(particle_class_map, particle2.class());
+    let mass1 = particle1.mass(class1);
+    let mass2 = particle2.mass(class2);
+    let radius1 = particle1.radius(class1);
+    let radius2 = particle2.radius(class2);
+
+    let mass = mass1 + mass2;
+    let position = (particle1.position * mass1 + particle2.position * mass2) / mass;
+    let velocity = (particle1.velocity * mass1 + particle2.velocity * mass2) / mass;
+    let radius = (radius1 * radius1 + radius2 * radius2).sqrt();
+
+    // Merged particle keeps the identity of the first one
+    let mut merged =
+        Particle::new(position, velocity, particle1.class()).with_mass_and_radius(mass, radius);
+    merged.set_id(particle1.id());
+    return merged;
+}
+
+/// Resolves collision of 2 particles where the lighter particle may shatter.
+/// Particles must be at the moment of collision. If the energy of approach along the normal
+/// exceeds `energy_threshold` the collision is perfectly inelastic, the lighter particle
+/// breaks into `num_fragments` fragments and energy above the threshold pushes the fragments
+/// apart. Momentum is conserved.
+/// Returns new state of both particles and additional fragments. Returns None if collision
+/// is not energetic enough.
+fn fragment_particles(
+    mut particle1: Particle,
+    mut particle2: Particle,
+    collision_normal: Vec2,
+    energy_threshold: f64,
+    num_fragments: usize,
+    particle_class_map: &HashMap<ClassId, ParticleClass>,
+) -> Option<(Particle, Particle, Vec<Particle>)> {
+    if num_fragments < 2 {
+        return None;
+    }
+    let class1 = get_class(particle_class_map, particle1.class());
+    let class2 = get_class(particle_class_map, particle2.class());
+    let mass1 = particle1.mass(class1);
+    let mass2 = particle2.mass(class2);
+
+    // Energy of approach in the center of mass frame
+    let reduced_mass = mass1 * mass2 / (mass1 + mass2);
+    let approach_speed = (particle2.velocity - particle1.velocity).dot(collision_normal);
+    let approach_energy = math_core::kinetic_energy_from_velocity(reduced_mass, approach_speed);
+    if approach_energy <= energy_threshold {
+        return None;
+    }
+
+    // Perfectly inelastic collision. All energy of approach is lost
+    let (new_velocity1, new_velocity2) = collision_utils::particles_collision_separation_velocity(
+        particle1.velocity,
+        mass1,
+        particle2.velocity,
+        mass2,
+        collision_normal,
+        0.0,
+    );
+    particle1.velocity = new_velocity1;
+    particle2.velocity = new_velocity2;
+
+    // Lighter particle shatters
+    let shatter_first = mass1 < mass2;
+    let (target, target_class) = if shatter_first {
+        (particle1, class1)
+    } else {
+        (particle2, class2)
+    };
+    let mass = target.mass(target_class);
+    let radius = target.radius(target_class);
+    // Fragments share the area of the target equally
+    let fragment_mass = mass / num_fragments as f64;
+    let fragment_radius = radius / (num_fragments as f64).sqrt();
+    // Energy above threshold is spent on pushing fragments apart. All fragments
+    // fly at the same speed relative to the target
+    let spread_speed =
+        math_core::velocity_from_kinetic_energy(mass, approach_energy - energy_threshold);
+    // Fragments are placed in a ring and fly outwards. Evenly spaced directions
+    // cancel each other, so momentum is conserved. The ring starts on the side away
+    // from the partner, so the first fragment doesn't fly into it.
+    let ring_radius = radius - fragment_radius;
+    let away = if shatter_first { -collision_normal } else { collision_normal };
+    let start_angle = away.y.atan2(away.x);
+    let mut fragments: Vec<Particle> = (0..num_fragments)
+        .map(|i| {
+            let angle = start_angle + 2.0 * std::f64::consts::PI * i as f64 / num_fragments as f64;
+            let dir = Vec2::from_angle_rad(angle);
+            Particle::new(
+                target.position + dir * ring_radius,
+                target.velocity + dir * spread_speed,
+                target.class(),
+            )
+            .with_mass_and_radius(fragment_mass, fragment_radius)
+        })
+        .collect();
+
+    // First fragment takes the place (and identity) of the target
+    let mut first = fragments.remove(0);
+    first.set_id(target.id());
+    if shatter_first {
+        particle1 = first;
+    } else {
+        particle2 = first;
+    }
+    return Some((particle1, particle2, fragments));
+}
+
+/// Largest distance between centers of particles that may collide during the step
+/// if they keep their current velocities
+pub(crate) fn collision_cutoff(
+    particles: &[Particle],
     particle_class_map: &HashMap<ClassId, ParticleClass>,
-    walls: &[Wall],
     timestep: f64,
-    particle_vs_particle_velocity_resolver: &impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2),
-    particle_vs_wall_velocity_resolver: &impl Fn(&Particle, &Wall, Vec2) -> Vec2,
-) {
+) -> f64 {
+    let mut max_radius: f64 = 0.0;
+    let mut max_speed: f64 = 0.0;
+    for particle in particles {
+        max_radius = max_radius.max(particle.radius(get_class(particle_class_map, particle.class())));
+        max_speed = max_speed.max(particle.velocity.length());
+    }
+    return 2.0 * max_radius + 2.0 * max_speed * timestep;
+}
+
+/// How `resolve` handles collisions. Velocities after a collision come from the resolvers,
+/// see `particle_vs_particle_velocity_resolver` and `particle_vs_wall_velocity_resolver`.
+/// `past_tolerance` is how far in the past a collision is still accepted. Such collisions
+/// come from floating point errors, which grow with the scale of the scene
+pub(crate) struct CollisionHandling<'a, P, W> {
+    pub particle_vs_particle: &'a P,
+    pub particle_vs_wall: &'a W,
+    pub contact_resolution: ContactResolution,
+    pub past_tolerance: f64,
+}
+
+impl<'a, P, W> CollisionHandling<'a, P, W>
+where
+    P: Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2),
+    W: Fn(&Particle, &Wall, Vec2) -> Option<Vec2>,
+{
+    /// Resolves simultaneous collisions one by one and accepts collisions up to
+    /// `TIME_SEC_EPS` in the past
+    pub fn new(particle_vs_particle: &'a P, particle_vs_wall: &'a W) -> Self {
+        CollisionHandling {
+            particle_vs_particle,
+            particle_vs_wall,
+            contact_resolution: ContactResolution::Sequential,
+            past_tolerance: TIME_SEC_EPS,
+        }
+    }
+}
+
+/// Moves particles through the time step resolving all collisions in order.
+/// `neighbor_grid` must be built from current positions of `particles`. If its cutoff
+/// covers `collision_cutoff`, it limits the initial search to nearby pairs. Otherwise
+/// each pair is checked. `warm_start` lets consecutive calls skip pairs that are known
+/// to stay apart. It must be reused only for the same particles.
+/// If `collision_events` is given, bounces are appended to it in the order they are resolved.
+/// Indices are those of the particles at the start of the call, fragments follow them.
+/// With periodic boundary of the environment particles collide with the nearest images
+/// of others, and are wrapped into the domain at the end
+pub(crate) fn resolve(
+    particles: &mut Vec<Particle>,
+    environment: &StepEnvironment,
+    neighbor_grid: &NeighborGrid,
+    timestep: f64,
+    collision_handling: &CollisionHandling<
+        impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2),
+        impl Fn(&Particle, &Wall, Vec2) -> Option<Vec2>,
+    >,
+    warm_start: Option<&mut WarmStart>,
+    collision_events: Option<&mut Vec<CollisionEvent>>,
+) -> StepReport {
+    let (report, _, removed) = resolve_in_place(
+        particles,
+        environment,
+        neighbor_grid,
+        timestep,
+        collision_handling,
+        warm_start,
+        collision_events,
+    );
+    // And finally get rid of removed particles
+    let mut index = 0;
+    particles.retain(|_| {
+        let keep = !removed[index];
+        index += 1;
+        keep
+    });
+    return report;
+}
+
+// What happened to the particles during `resolve_in_place`. Parallel resolution uses it
+// to check that an island couldn't reach particles of other islands
+#[derive(Debug, Clone, Copy, Default)]
+struct ResolveTrace {
+    // Largest speed and radius any particle had during the call
+    max_speed: f64,
+    max_radius: f64,
+    // Merged particle appears between the centers of the merged ones
+    coalesced: bool,
+    // Fragments were appended to the particles
+    fragmented: bool,
+}
+
+impl ResolveTrace {
+    fn include(&mut self, particle: &Particle, particle_class_map: &HashMap<ClassId, ParticleClass>) {
+        self.max_speed = self.max_speed.max(particle.velocity.length());
+        self.max_radius = self.max_radius.max(particle.radius(get_class(particle_class_map, particle.class())));
+    }
+}
+
+// Same as `resolve`, but removed particles are only marked. Returns the flags of removed
+// particles, including the appended fragments
+fn resolve_in_place(
+    particles: &mut Vec<Particle>,
+    environment: &StepEnvironment,
+    neighbor_grid: &NeighborGrid,
+    timestep: f64,
+    collision_handling: &CollisionHandling<
+        impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2),
+        impl Fn(&Particle, &Wall, Vec2) -> Option<Vec2>,
+    >,
+    warm_start: Option<&mut WarmStart>,
+    mut collision_events: Option<&mut Vec<CollisionEvent>>,
+) -> (StepReport, ResolveTrace, Vec<bool>) {
+    let particle_class_map = environment.particle_classes;
+    let particle_pair_rules = environment.particle_pair_rules;
+    let walls = environment.walls;
+    let boundary = environment.boundary;
+    let particle_vs_particle_velocity_resolver = collision_handling.particle_vs_particle;
+    let particle_vs_wall_velocity_resolver = collision_handling.particle_vs_wall;
+    let past_tolerance = collision_handling.past_tolerance;
+    let mut report = StepReport::default();
+    let mut trace = ResolveTrace::default();
+    for particle in particles.iter() {
+        trace.include(particle, particle_class_map);
+    }
+    let num_initial_particles = particles.len();
+    // Particles that are on the same trajectories as at the end of the previous call
+    let continuous = match &warm_start {
+        Some(warm_start) => warm_start.continuous(particles),
+        None => vec![false; particles.len()],
+    };
+    // Separation is only trusted if collisions in the past can't be mistaken for current
+    let store_separations = warm_start.is_some() && timestep > past_tolerance;
+    let mut separated = HashMap::new();
+    // Particles whose trajectories changed during this call
+    let mut touched: Vec<bool> = vec![false; particles.len()];
     // For each particle we shall track the time we already simulated
     let mut particle_time: Vec<f64> = vec![0.0; particles.len()];
+    // Particles that were removed during this step (i.e. merged into others or absorbed).
+    // They are kept in place until the end of the step to keep indices stable
+    let mut removed: Vec<bool> = vec![false; particles.len()];
     let mut current_collisions = BinaryHeap::new();
     // Lamda for merging incoming collisions into the heap
-    let merge = |left: &mut BinaryHeap<Reverse<Collision>>, right: &[Collision]| {
+    let merge = |left: &mut BinaryHeap<Reverse<Collision>>,
+                 right: &[Collision],
+                 removed: &[bool]| {
         for collision in right {
+            if matches!(collision.other, OtherObject::Particle(i) if removed[i]) {
+                continue;
+            }
             left.push(Reverse(collision.clone())); // sorted
         }
     };
 
+    // Grid is only useful if it contains all pairs that may collide. It doesn't pair
+    // particles across the edges of periodic domain
+    let use_grid = neighbor_grid.len() == particles.len()
+        && !boundary.is_periodic()
+        && neighbor_grid.cell_size() >= collision_cutoff(particles, particle_class_map, timestep);
+
     // Generate collisions for each vs each
     for i in 0..particles.len() {
+        let candidates: Vec<usize> = if use_grid {
+            neighbor_grid.neighbors(i).into_iter().filter(|&j| j > i).collect()
+        } else {
+            (i + 1..particles.len()).collect()
+        };
+        let mut others = vec![];
+        for j in candidates {
+            let known = match &warm_start {
+                Some(warm_start) if continuous[i] && continuous[j] => warm_start.separated.get(&(i, j)),
+                _ => None,
+            };
+            match known {
+                // Still apart for the whole call. Nothing to check
+                Some(&time) if time >= timestep => {
+                    separated.insert((i, j), time - timestep);
+                }
+                _ => {
+                    if store_separations {
+                        let time = separation_time(&particles[i], &particles[j], particle_class_map, boundary);
+                        if time > timestep {
+                            separated.insert((i, j), time - timestep);
+                        }
+                    }
+                    others.push(j);
+                }
+            }
+        }
+        report.pair_checks += others.len();
         // With particles in front
         merge(
             &mut current_collisions,
             &find_collisions_with_particles(
                 i,
-                i + 1..particles.len(),
+                others,
                 particles,
-                particle_class_map,
+                environment,
                 &particle_time,
                 timestep,
+                past_tolerance,
             ),
+            &removed,
         );
         // With all wals
         merge(
@@ -230,11 +779,13 @@ pub(crate) fn resolve(
             &find_collisions_with_walls(
                 i,
                 &particles[i],
-                particle_class_map.get(&particles[i].class()).unwrap(),
+                get_class(particle_class_map, particles[i].class()),
                 walls,
                 particle_time[i],
                 timestep,
+                past_tolerance,
             ),
+            &removed,
         );
     }
 
@@ -245,29 +796,142 @@ pub(crate) fn resolve(
         // Track the particles that collided and need collision reset
         let mut particles_to_reset_collisions = vec![];
 
+        // Collisions that touch the same particles at the same moment
+        let batch = match collision_handling.contact_resolution {
+            ContactResolution::Batched => take_simultaneous_collisions(
+                collision,
+                &mut current_collisions,
+                particles,
+                particle_pair_rules,
+            ),
+            ContactResolution::Sequential => vec![],
+        };
+
+        report.collisions += batch.len().max(1);
         // Collision with other particle
         match collision.other {
-            OtherObject::Particle(particle2_idx) => {
-                let (p1, p2) = resolve_particle_vs_particle(
-                    particles[collision.particle],
-                    particles[particle2_idx],
-                    particle_time[collision.particle],
-                    particle_time[particle2_idx],
+            // Simultaneous collisions are resolved together
+            _ if batch.len() > 1 => {
+                let (virial, impulses) = resolve_collision_batch(
+                    &batch,
+                    particles,
+                    &mut particle_time,
                     time_to_collision,
-                    collision.normal,
+                    particle_class_map,
                     particle_vs_particle_velocity_resolver,
+                    boundary,
                 );
-                particles[collision.particle] = p1;
-                particles[particle2_idx] = p2;
-
-                // Track the particle time
-                particle_time[collision.particle] = time_to_collision;
-                particle_time[particle2_idx] = time_to_collision;
-
-                // Particle had collision. That means all other collisions with this particle
-                // are invalid. We need to recalculate them
-                particles_to_reset_collisions.push(collision.particle);
-                particles_to_reset_collisions.push(particle2_idx);
+                report.collision_virial += virial;
+                if let Some(events) = collision_events.as_deref_mut() {
+                    for (c, impulse) in batch.iter().zip(impulses) {
+                        events.push(CollisionEvent::new(c, impulse));
+                    }
+                }
+                for c in &batch {
+                    if let OtherObject::Particle(other) = c.other {
+                        for idx in [c.particle, other] {
+                            if !particles_to_reset_collisions.contains(&idx) {
+                                particles_to_reset_collisions.push(idx);
+                            }
+                        }
+                    }
+                }
+            }
+            OtherObject::Particle(particle2_idx) => {
+                let rule = particle_pair_rules.get(
+                    particles[collision.particle].class(),
+                    particles[particle2_idx].class(),
+                );
+                // Fragmentation falls back to bounce when collision is too weak
+                let fragmented = match rule {
+                    ParticlePairRule::Fragment {
+                        energy_threshold,
+                        num_fragments,
+                    } => {
+                        // Advance particles to the moment of collision
+                        let mut p1 = particles[collision.particle];
+                        let mut p2 = particles[particle2_idx];
+                        p1.position += p1.velocity * (time_to_collision - particle_time[collision.particle]);
+                        p2.position += p2.velocity * (time_to_collision - particle_time[particle2_idx]);
+                        fragment_particles(
+                            p1,
+                            p2,
+                            collision.normal,
+                            energy_threshold,
+                            num_fragments,
+                            particle_class_map,
+                        )
+                    }
+                    _ => None,
+                };
+
+                if rule == ParticlePairRule::Coalesce {
+                    let merged = coalesce_particles(
+                        particles[collision.particle],
+                        particles[particle2_idx],
+                        particle_time[collision.particle],
+                        particle_time[particle2_idx],
+                        time_to_collision,
+                        particle_class_map,
+                        boundary,
+                    );
+                    // Merged particle takes the place of first one. Second one is gone
+                    particles[collision.particle] = merged;
+                    particle_time[collision.particle] = time_to_collision;
+                    removed[particle2_idx] = true;
+                    trace.coalesced = true;
+
+                    // Collisions of the removed particle are just deleted. The merged one
+                    // needs them recalculated
+                    current_collisions.retain(|Reverse(c)| !c.involves_particle(particle2_idx));
+                    particles_to_reset_collisions.push(collision.particle);
+                } else if let Some((p1, p2, fragments)) = fragmented {
+                    particles[collision.particle] = p1;
+                    particles[particle2_idx] = p2;
+                    particle_time[collision.particle] = time_to_collision;
+                    particle_time[particle2_idx] = time_to_collision;
+                    particles_to_reset_collisions.push(collision.particle);
+                    particles_to_reset_collisions.push(particle2_idx);
+
+                    // Extra fragments are appended. This keeps indices of other particles intact
+                    trace.fragmented |= !fragments.is_empty();
+                    for fragment in fragments {
+                        particles.push(fragment);
+                        particle_time.push(time_to_collision);
+                        removed.push(false);
+                        particles_to_reset_collisions.push(particles.len() - 1);
+                    }
+                } else {
+                    let (p1, p2) = resolve_particle_vs_particle(
+                        particles[collision.particle],
+                        particles[particle2_idx],
+                        particle_time[collision.particle],
+                        particle_time[particle2_idx],
+                        time_to_collision,
+                        collision.normal,
+                        particle_vs_particle_velocity_resolver,
+                    );
+                    // Accumulate virial from the momentum exchange
+                    let mass1 = p1.mass(get_class(particle_class_map, p1.class()));
+                    let impulse1 = (p1.velocity - particles[collision.particle].velocity) * mass1;
+                    let offset = boundary.minimum_image(p1.position - p2.position);
+                    report.collision_virial += Tensor2::outer(offset, impulse1);
+                    if let Some(events) = collision_events.as_deref_mut() {
+                        events.push(CollisionEvent::new(&collision, impulse1.length()));
+                    }
+
+                    particles[collision.particle] = p1;
+                    particles[particle2_idx] = p2;
+
+                    // Track the particle time
+                    particle_time[collision.particle] = time_to_collision;
+                    particle_time[particle2_idx] = time_to_collision;
+
+                    // Particle had collision. That means all other collisions with this particle
+                    // are invalid. We need to recalculate them
+                    particles_to_reset_collisions.push(collision.particle);
+                    particles_to_reset_collisions.push(particle2_idx);
+                }
             }
             OtherObject::Wall(wall_idx) => {
                 let p1 = resolve_particle_vs_wall(
@@ -278,15 +942,57 @@ pub(crate) fn resolve(
                     collision.normal,
                     particle_vs_wall_velocity_resolver,
                 );
-                particles[collision.particle] = p1;
-
-                // Track the particle time
-                particle_time[collision.particle] = time_to_collision;
+                match p1 {
+                    Some(p1) => {
+                        // Energy lost by the particle goes into the wall
+                        let mass = p1.mass(get_class(particle_class_map, p1.class()));
+                        let energy_before = math_core::kinetic_energy_from_velocity(
+                            mass,
+                            particles[collision.particle].velocity.length(),
+                        );
+                        let energy_after = math_core::kinetic_energy_from_velocity(mass, p1.velocity.length());
+                        if report.wall_heat.is_empty() {
+                            report.wall_heat = vec![0.0; walls.len()];
+                        }
+                        report.wall_heat[wall_idx] += energy_before - energy_after;
+                        if report.wall_impulse.is_empty() {
+                            report.wall_impulse = vec![0.0; walls.len()];
+                        }
+                        // Only the normal part pushes on the wall. Tangential part of a diffuse
+                        // bounce is friction, not load
+                        let velocity_change = p1.velocity - particles[collision.particle].velocity;
+                        report.wall_impulse[wall_idx] += (velocity_change * mass).dot(collision.normal).abs();
+                        if let Some(events) = collision_events.as_deref_mut() {
+                            events.push(CollisionEvent::new(&collision, mass * velocity_change.length()));
+                        }
+
+                        particles[collision.particle] = p1;
+
+                        // Track the particle time
+                        particle_time[collision.particle] = time_to_collision;
+
+                        // Particle had collision. That means all other collisions with this
+                        // particle are invalid. We need to recalculate them
+                        particles_to_reset_collisions.push(collision.particle);
+                    }
+                    None => {
+                        // Absorbed particle is gone with all its collisions
+                        removed[collision.particle] = true;
+                        current_collisions
+                            .retain(|Reverse(c)| !c.involves_particle(collision.particle));
+                    }
+                }
+            }
+        }
 
-                // Particle had collision. That means all other collisions with this particle
-                // are invalid. We need to recalculate them
-                particles_to_reset_collisions.push(collision.particle);
+        if collision.particle < touched.len() {
+            touched[collision.particle] = true;
+        }
+        for &particle_idx in &particles_to_reset_collisions {
+            if particle_idx < touched.len() {
+                touched[particle_idx] = true;
             }
+            trace.include(&particles[particle_idx], particle_class_map);
         }
 
         // Delete all collisions of involved partciles
@@ -294,76 +1000,512 @@ pub(crate) fn resolve(
             current_collisions.retain(|Reverse(c)| !c.involves_particle(particle_idx));
         }
 
-        // Generate new collisions for each involved particle
+        // Generate new collisions for each involved particle. Grid still holds all pairs that
+        // may collide if no particle got faster or bigger than it allows, and none jumped
+        let rescan_grid = use_grid
+            && !trace.coalesced
+            && !trace.fragmented
+            && neighbor_grid.cell_size() >= 2.0 * trace.max_radius + 2.0 * trace.max_speed * timestep;
         for &particle_idx in &particles_to_reset_collisions {
+            let candidates: Vec<usize> = if rescan_grid && particle_idx < num_initial_particles {
+                neighbor_grid.neighbors(particle_idx)
+            } else {
+                (0..particles.len()).filter(|&j| j != particle_idx).collect()
+            };
+            report.pair_checks += candidates.len();
             merge(
                 &mut current_collisions,
                 &find_collisions_with_particles(
                     particle_idx,
-                    0..particles.len(),
+                    candidates,
                     particles,
-                    particle_class_map,
+                    environment,
                     &particle_time,
                     timestep,
+                    past_tolerance,
                 ),
+                &removed,
             );
             merge(
                 &mut current_collisions,
                 &find_collisions_with_walls(
                     particle_idx,
                     &particles[particle_idx],
-                    particle_class_map
-                        .get(&particles[particle_idx].class())
-                        .unwrap(),
+                    get_class(particle_class_map, particles[particle_idx].class()),
                     walls,
                     particle_time[particle_idx],
                     timestep,
+                    past_tolerance,
                 ),
+                &removed,
             );
         }
     }
-    // When there are no more collisions left - just advance all particles to the end
+    // When there are no more collisions left - just advance all particles to the end.
+    // Particles that left periodic domain come back through the opposite edge
+    for (particle, time) in particles.iter_mut().zip(particle_time.iter()) {
+        particle.position += particle.velocity * (timestep - time);
+        particle.position = boundary.wrap(particle.position);
+    }
+    // Particles of the next call continue from here. Indices don't survive removal or
+    // fragmentation, so in that case everything is checked again
+    if let Some(warm_start) = warm_start {
+        let same_particles = particles.len() == num_initial_particles && !removed.contains(&true);
+        if same_particles {
+            separated.retain(|&(i, j), _| !touched[i] && !touched[j]);
+            warm_start.separated = separated;
+            warm_start.end_states = particles.iter().map(|p| (p.position, p.velocity)).collect();
+        } else {
+            *warm_start = WarmStart::default();
+        }
+    }
+    return (report, trace, removed);
+}
+
+// Speed limit of particles within an island, relative to the fastest particle at the
+// start. Collisions may speed particles up, but rarely that much
+const ISLAND_SPEED_MARGIN: f64 = 2.0;
+
+// How far each particle may reach during the step. Particles are in the same island if
+// their reaches overlap
+#[derive(Debug, Clone, Copy)]
+struct ReachBound {
+    speed: f64,
+    radius: f64,
+    // Extra displacement, e.g. of merged particles
+    jump: f64,
+}
+
+impl ReachBound {
+    fn covers(&self, trace: &ResolveTrace) -> bool {
+        return trace.max_speed <= self.speed
+            && trace.max_radius <= self.radius
+            && jump_of(trace) <= self.jump;
+    }
+
+    fn extend(&mut self, trace: &ResolveTrace) {
+        self.speed = self.speed.max(trace.max_speed * ISLAND_SPEED_MARGIN);
+        self.radius = self.radius.max(trace.max_radius);
+        self.jump = self.jump.max(jump_of(trace));
+    }
+}
+
+// Merged particle is placed between the centers of the colliding ones
+fn jump_of(trace: &ResolveTrace) -> f64 {
+    if trace.coalesced {
+        return 2.0 * trace.max_radius;
+    }
+    return 0.0;
+}
+
+/// Splits particles into islands that can't touch each other during the step if every
+/// particle stays within its reach bound. Islands are ordered by their smallest index,
+/// indices within an island are ascending
+fn find_islands(
+    particles: &[Particle],
+    bounds: &[ReachBound],
+    neighbor_grid: &NeighborGrid,
+    timestep: f64,
+) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..particles.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        return i;
+    }
+    let reach = |b: &ReachBound| b.radius + b.speed * timestep + b.jump;
+    let max_reach = bounds.iter().map(reach).fold(0.0, f64::max);
+    let use_grid = neighbor_grid.len() == particles.len() && neighbor_grid.cell_size() >= 2.0 * max_reach;
     for i in 0..particles.len() {
-        particles[i].position += particles[i].velocity * (timestep - particle_time[i]);
+        let candidates: Vec<usize> = if use_grid {
+            neighbor_grid.neighbors(i).into_iter().filter(|&j| j > i).collect()
+        } else {
+            (i + 1..particles.len()).collect()
+        };
+        for j in candidates {
+            let distance = (particles[i].position - particles[j].position).length();
+            if distance <= reach(&bounds[i]) + reach(&bounds[j]) {
+                let (root_i, root_j) = (root(&mut parent, i), root(&mut parent, j));
+                parent[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+    }
+    let mut islands: Vec<Vec<usize>> = vec![];
+    let mut island_of_root: HashMap<usize, usize> = HashMap::new();
+    for i in 0..particles.len() {
+        let r = root(&mut parent, i);
+        let island = *island_of_root.entry(r).or_insert_with(|| {
+            islands.push(vec![]);
+            islands.len() - 1
+        });
+        islands[island].push(i);
+    }
+    return islands;
+}
+
+// Result of resolving the particles of one island separately
+struct IslandResult {
+    particles: Vec<Particle>,
+    removed: Vec<bool>,
+    report: StepReport,
+    trace: ResolveTrace,
+    events: Vec<CollisionEvent>,
+}
+
+/// Same as `resolve`, but independent islands of particles are resolved on up to
+/// `num_threads` threads. Particles are in the same island if they may touch during the
+/// step. Results are merged in the order of islands, so they match `resolve`.
+/// An island whose particles got faster or larger than assumed may have reached another
+/// one. It's merged with the islands in its new reach and the combined island is resolved
+/// again as a whole. Fragments are appended in the order of collisions of all particles,
+/// so a step with fragmentation is resolved serially. So is periodic domain, whose
+/// particles near opposite edges may touch
+pub(crate) fn resolve_islands(
+    particles: &mut Vec<Particle>,
+    environment: &StepEnvironment,
+    neighbor_grid: &NeighborGrid,
+    timestep: f64,
+    collision_handling: &CollisionHandling<
+        impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2) + Sync,
+        impl Fn(&Particle, &Wall, Vec2) -> Option<Vec2> + Sync,
+    >,
+    num_threads: usize,
+    collision_events: Option<&mut Vec<CollisionEvent>>,
+) -> StepReport {
+    let particle_class_map = environment.particle_classes;
+    let serial = |particles: &mut Vec<Particle>, collision_events: Option<&mut Vec<CollisionEvent>>| {
+        resolve(particles, environment, neighbor_grid, timestep, collision_handling, None, collision_events)
+    };
+    if num_threads <= 1 || particles.len() < 2 || environment.boundary.is_periodic() {
+        return serial(particles, collision_events);
+    }
+    let record_events = collision_events.is_some();
+    let resolve_island = |island: &[usize]| {
+        let mut island_particles: Vec<Particle> = island.iter().map(|&i| particles[i]).collect();
+        let mut island_grid = NeighborGrid::new();
+        if neighbor_grid.cell_size() > 0.0 {
+            island_grid.rebuild(&island_particles, neighbor_grid.cell_size());
+        }
+        let mut events = vec![];
+        let (report, trace, removed) = resolve_in_place(
+            &mut island_particles,
+            environment,
+            &island_grid,
+            timestep,
+            collision_handling,
+            None,
+            if record_events { Some(&mut events) } else { None },
+        );
+        return IslandResult { particles: island_particles, removed, report, trace, events };
+    };
+
+    let mut start = ResolveTrace::default();
+    for particle in particles.iter() {
+        start.include(particle, particle_class_map);
+    }
+    let initial = ReachBound { speed: start.max_speed * ISLAND_SPEED_MARGIN, radius: start.max_radius, jump: 0.0 };
+    let mut bounds = vec![initial; particles.len()];
+    // Results depend only on the particles of the island, so they are kept until
+    // the islands settle
+    let mut resolved: HashMap<Vec<usize>, IslandResult> = HashMap::new();
+    let islands = loop {
+        let islands = find_islands(particles, &bounds, neighbor_grid, timestep);
+        let pending: Vec<&Vec<usize>> = islands.iter().filter(|island| !resolved.contains_key(*island)).collect();
+        // Each thread takes every n-th island. Results don't depend on the split
+        let mut results: Vec<(usize, IslandResult)> = if pending.len() > 1 {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..num_threads.min(pending.len()))
+                    .map(|thread| {
+                        let pending = &pending;
+                        let resolve_island = &resolve_island;
+                        scope.spawn(move || {
+                            (thread..pending.len())
+                                .step_by(num_threads)
+                                .map(|k| (k, resolve_island(pending[k])))
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+            })
+        } else {
+            pending.iter().enumerate().map(|(k, island)| (k, resolve_island(island))).collect()
+        };
+        results.sort_by_key(|(k, _)| *k);
+
+        for (k, result) in results {
+            if result.trace.fragmented {
+                return serial(particles, collision_events);
+            }
+            resolved.insert(pending[k].clone(), result);
+        }
+
+        // Island that went beyond its bounds may have reached others
+        let mut escaped = false;
+        for island in &islands {
+            let trace = resolved[island].trace;
+            if !island.iter().all(|&i| bounds[i].covers(&trace)) {
+                escaped = true;
+                for &i in island {
+                    bounds[i].extend(&trace);
+                }
+            }
+        }
+        if !escaped {
+            break islands;
+        }
+    };
+
+    // Put the results back in place of the island particles
+    let mut report = StepReport::default();
+    let mut removed = vec![false; particles.len()];
+    for island in &islands {
+        let result = &resolved[island];
+        for (k, &i) in island.iter().enumerate() {
+            particles[i] = result.particles[k];
+            removed[i] = result.removed[k];
+        }
+        report.collision_virial += result.report.collision_virial;
+        report.pair_checks += result.report.pair_checks;
+        report.collisions += result.report.collisions;
+        report.add_wall_heat(&result.report.wall_heat);
+        report.add_wall_impulse(&result.report.wall_impulse);
+    }
+    // Events of islands use island indices. Merged in time order they match serial ones,
+    // except for the order of simultaneous events
+    if let Some(collision_events) = collision_events {
+        let mut events = vec![];
+        for island in &islands {
+            for event in &resolved[island].events {
+                events.push(event.reindexed(|k| island[k]));
+            }
+        }
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        collision_events.extend(events);
     }
+    let mut index = 0;
+    particles.retain(|_| {
+        let keep = !removed[index];
+        index += 1;
+        keep
+    });
+    return report;
 }
 
-pub fn default_particle_vs_particle_velocity_resovler<'a>(
+// Energy may grow by this fraction due to rounding
+const ENERGY_GAIN_TOLERANCE: f64 = 1e-9;
+
+/// Returns the energy gained in a collision if it's beyond the rounding tolerance
+pub(crate) fn excess_energy_gain(energy_before: f64, energy_after: f64) -> Option<f64> {
+    let gain = energy_after - energy_before;
+    if gain > ENERGY_GAIN_TOLERANCE * energy_before.max(1.0) {
+        return Some(gain);
+    }
+    return None;
+}
+
+/// Makes particle vs particle velocity resolver out of the collision model.
+/// Pairs of classes with own coefficient of restitution bounce with it instead of the model.
+/// With `energy-check` feature it panics if the collision adds kinetic energy
+pub fn particle_vs_particle_velocity_resolver<'a>(
+    model: &'a dyn CollisionModel,
     particle_classes: &'a HashMap<ClassId, ParticleClass>,
+    particle_pair_rules: &'a ParticlePairRules,
 ) -> impl Fn(&Particle, &Particle, Vec2) -> (Vec2, Vec2) + 'a {
     move |p1: &Particle, p2: &Particle, n: Vec2| {
-        collision_utils::particles_collision_separation_velocity(
-            p1.velocity,
-            particle_classes.get(&p1.class()).unwrap().mass(),
-            p2.velocity,
-            particle_classes.get(&p2.class()).unwrap().mass(),
-            n,
-            1.0,
-        )
+        let class1 = get_class(particle_classes, p1.class());
+        let class2 = get_class(particle_classes, p2.class());
+        let (v1, v2) = match particle_pair_rules.restitution(p1.class(), p2.class()) {
+            Some(restitution) => collision_utils::particles_collision_separation_velocity(
+                p1.velocity,
+                p1.mass(class1),
+                p2.velocity,
+                p2.mass(class2),
+                n,
+                restitution,
+            ),
+            None => model.resolve_particles(p1, class1, p2, class2, n),
+        };
+        if cfg!(feature = "energy-check") {
+            let (m1, m2) = (p1.mass(class1), p2.mass(class2));
+            let energy = |v1: Vec2, v2: Vec2| {
+                math_core::kinetic_energy_from_velocity(m1, v1.length())
+                    + math_core::kinetic_energy_from_velocity(m2, v2.length())
+            };
+            let before = energy(p1.velocity, p2.velocity);
+            if let Some(gain) = excess_energy_gain(before, energy(v1, v2)) {
+                panic!(
+                    "Particle collision gained energy {}. p1: {:?}, p2: {:?}, normal: {}, \
+                    new velocities: {}, {}",
+                    gain, p1, p2, n, v1, v2
+                );
+            }
+        }
+        (v1, v2)
     }
 }
 
-pub fn default_particle_vs_wall_velocity_resolver<'a>(
+/// Seed of the random generator for a single wall collision. Derived from the state of the
+/// particle, so the outcome doesn't depend on the order in which islands are resolved
+fn wall_collision_seed(seed: u64, p: &Particle, n: Vec2) -> u64 {
+    let bits = [p.position.x, p.position.y, p.velocity.x, p.velocity.y, n.x, n.y];
+    return bits.iter().fold(seed, |hash, value| {
+        (hash ^ value.to_bits()).wrapping_mul(0x100000001b3).rotate_left(29)
+    });
+}
+
+/// Makes particle vs wall velocity resolver out of the collision model.
+/// Returns None if the wall class absorbs particles.
+/// With `seed` random choices of the model are reproducible, otherwise thread RNG is used.
+/// With `energy-check` feature it panics if the collision with a wall that doesn't
+/// conduct heat adds kinetic energy
+pub fn particle_vs_wall_velocity_resolver<'a>(
+    model: &'a dyn CollisionModel,
     particle_classes: &'a HashMap<ClassId, ParticleClass>,
     wall_classes: &'a HashMap<ClassId, WallClass>,
-) -> impl Fn(&Particle, &Wall, Vec2) -> Vec2 + 'a {
+    units: &'a Units,
+    seed: Option<u64>,
+) -> impl Fn(&Particle, &Wall, Vec2) -> Option<Vec2> + 'a {
     move |p: &Particle, w: &Wall, n: Vec2| {
-        let wall_class = wall_classes.get(&w.class()).unwrap();
-        let particle_class = particle_classes.get(&p.class()).unwrap();
-        collision_utils::particles_vs_wall_collision_separation_velocity(
-            p.velocity,
-            particle_class.mass(),
-            n,
-            wall_class.temperature(),
-            wall_class.heat_conductivity(),
-        )
+        let particle_class = get_class(particle_classes, p.class());
+        let wall_class = get_class(wall_classes, w.class());
+        if wall_class.absorbing() {
+            return None;
+        }
+        let contact = WallContact {
+            wall: w,
+            wall_class,
+            normal: n,
+        };
+        let mut v = match seed {
+            Some(seed) => {
+                let mut rng = StdRng::seed_from_u64(wall_collision_seed(seed, p, n));
+                model.resolve_wall(p, particle_class, &contact, units, &mut rng)
+            }
+            None => model.resolve_wall(p, particle_class, &contact, units, &mut rand::thread_rng()),
+        };
+        // Restitution depends on how hard the particle hits the wall
+        if let Some(restitution) = wall_class.restitution() {
+            let impact_speed = (-p.velocity.dot(n)).max(0.0);
+            let normal_speed = v.dot(n);
+            v -= n * (normal_speed * (1.0 - restitution.at(impact_speed)));
+        }
+        // Hot walls legitimately heat particles up
+        if cfg!(feature = "energy-check") && wall_class.heat_conductivity() == 0.0 {
+            let m = p.mass(particle_class);
+            let before = math_core::kinetic_energy_from_velocity(m, p.velocity.length());
+            let after = math_core::kinetic_energy_from_velocity(m, v.length());
+            if let Some(gain) = excess_energy_gain(before, after) {
+                panic!(
+                    "Wall collision gained energy {}. particle: {:?}, wall class: {}, \
+                    normal: {}, new velocity: {}",
+                    gain, p, w.class(), n, v
+                );
+            }
+        }
+        Some(v)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{math_core, wall_class, Polygon, WallClass};
+    use crate::{ElasticModel, Polygon, RestitutionCurve, WallClass};
+    use crate::velocity_verlet_integrator::NEIGHBOR_CUTOFF_MARGIN;
+    use crate::collisions::CollisionPartner;
+
+    #[test]
+    fn test_excess_energy_gain() {
+        assert_eq!(excess_energy_gain(10.0, 10.0), None);
+        assert_eq!(excess_energy_gain(10.0, 5.0), None);
+        // Rounding is tolerated
+        assert_eq!(excess_energy_gain(10.0, 10.0 + 1e-12), None);
+        assert_eq!(excess_energy_gain(10.0, 11.0), Some(1.0));
+    }
+
+    // Model that speeds particles up
+    #[cfg(feature = "energy-check")]
+    struct EnergyAddingModel;
+
+    #[cfg(feature = "energy-check")]
+    impl CollisionModel for EnergyAddingModel {
+        fn resolve_particles(
+            &self,
+            p1: &Particle,
+            _class1: &ParticleClass,
+            p2: &Particle,
+            _class2: &ParticleClass,
+            _normal: Vec2,
+        ) -> (Vec2, Vec2) {
+            (-p1.velocity * 2.0, -p2.velocity * 2.0)
+        }
+
+        fn resolve_wall(
+            &self,
+            particle: &Particle,
+            _particle_class: &ParticleClass,
+            _contact: &WallContact,
+            _units: &Units,
+            _rng: &mut dyn rand::RngCore,
+        ) -> Vec2 {
+            -particle.velocity * 2.0
+        }
+    }
+
+    #[cfg(feature = "energy-check")]
+    #[test]
+    fn test_energy_check_passes_elastic() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        classes.insert(2, ParticleClass::new("Class2", 3.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Cold", 0.0, 0.0));
+        wall_classes.insert(2, WallClass::new("Rough", 0.0, 0.0).with_diffuse_reflection(true));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+        let p1 = Particle::new(Vec2::ZERO, Vec2::new(3.0, 1.0), 1);
+        let p2 = Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.5), 2);
+        let n = Vec2::new(1.0, 0.2).normalized().unwrap();
+        resolve_p_p(&p1, &p2, n);
+        for wall_class in [1, 2] {
+            let wall = Wall::new(Polygon::new_rectangle(0.0, 0.0, 1.0, 1.0), wall_class);
+            resolve_p_w(&p1, &wall, Vec2::new(-1.0, 0.0));
+        }
+    }
+
+    #[cfg(feature = "energy-check")]
+    #[test]
+    #[should_panic(expected = "Particle collision gained energy")]
+    fn test_energy_check_trips_on_particles() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&EnergyAddingModel, &classes, &pair_rules);
+        let p1 = Particle::new(Vec2::ZERO, Vec2::new(1.0, 0.0), 1);
+        let p2 = Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), 1);
+        resolve_p_p(&p1, &p2, Vec2::UNIT_X);
+    }
+
+    #[cfg(feature = "energy-check")]
+    #[test]
+    #[should_panic(expected = "Wall collision gained energy")]
+    fn test_energy_check_trips_on_walls() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Cold", 0.0, 0.0));
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&EnergyAddingModel, &classes, &wall_classes, &units, None);
+        let p = Particle::new(Vec2::ZERO, Vec2::new(1.0, 0.0), 1);
+        let wall = Wall::new(Polygon::new_rectangle(1.0, -1.0, 2.0, 1.0), 1);
+        resolve_p_w(&p, &wall, Vec2::new(-1.0, 0.0));
+    }
 
     // Test the ordered binary heap of collisions
     #[test]
@@ -414,9 +1556,10 @@ mod tests {
                     1,
                     0..particles.len(),
                     &particles,
-                    &classes,
+                    &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
                     &times,
                     time_threshold, // no enough to catch up to last
+                    TIME_SEC_EPS,
                 );
                 assert_eq!(collisions.len(), 2);
                 assert_eq!(collisions[0].particle, 1);
@@ -478,9 +1621,10 @@ mod tests {
                 0,
                 0..2,
                 &[particle1, particle2],
-                &classes,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
                 &[0.0, 0.0],
                 100.0,
+                TIME_SEC_EPS,
             );
             assert_eq!(collisions.len(), 1);
 
@@ -494,9 +1638,10 @@ mod tests {
                 0,
                 0..2,
                 &[particle1, particle2],
-                &classes,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
                 &[0.0, 0.0],
                 100.0,
+                TIME_SEC_EPS,
             );
             assert_eq!(collisions.len(), 0);
         }
@@ -531,6 +1676,7 @@ mod tests {
             &walls,
             particle_time,
             time_threshold,
+            TIME_SEC_EPS,
         );
         assert_eq!(collisions.len(), 2);
         assert_eq!(collisions[0].particle, 123);
@@ -559,13 +1705,13 @@ mod tests {
         let y = 14.0 + TIME_SEC_EPS * 0.9 * 1.0;
         let particle = Particle::new(Vec2::new(4.0, y), Vec2::new(0.0, 1.0), 1);
         let collisions =
-            find_collisions_with_walls(123, &particle, &particle_class, &walls, 0.0, 100.0);
+            find_collisions_with_walls(123, &particle, &particle_class, &walls, 0.0, 100.0, TIME_SEC_EPS);
         assert_eq!(collisions.len(), 1);
         // And case with collision that is deeper than time eps
         let y = 14.0 + TIME_SEC_EPS * 1.1 * 1.0;
         let particle = Particle::new(Vec2::new(4.0, y), Vec2::new(0.0, 1.0), 1);
         let collisions =
-            find_collisions_with_walls(123, &particle, &particle_class, &walls, 0.0, 100.0);
+            find_collisions_with_walls(123, &particle, &particle_class, &walls, 0.0, 100.0, TIME_SEC_EPS);
         assert_eq!(collisions.len(), 0);
     }
 
@@ -579,10 +1725,13 @@ mod tests {
         let mut wall_classes = HashMap::new();
         wall_classes.insert(1, WallClass::new("Wall1", 100.0, 0.0));
         // Lamda that resolve velocity
-        let resolve_velocity = default_particle_vs_particle_velocity_resovler(&classes);
+        let pair_rules = ParticlePairRules::new();
+        let resolve_velocity = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &pair_rules);
 
         // resolver with walls. Is not needed
-        let resolve_wall = default_particle_vs_wall_velocity_resolver(&classes, &wall_classes);
+        let units = Units::default();
+        let resolve_wall =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
 
         // Add particles
         let mut particles = vec![
@@ -605,64 +1754,251 @@ mod tests {
         // It will be hit by #6 at t=10
 
         // Resolve
-        resolve(
+        let report = resolve(
             &mut particles,
-            &classes,
-            &[],
+            &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &wall_classes),
+            &NeighborGrid::new(),
             30.0,
-            &resolve_velocity,
-            &resolve_wall,
+            &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+            None,
+            None,
         );
+        // 4 collisions from the story line
+        assert_eq!(report.collisions, 4);
 
         // Check the result
-        assert!(particles[0]
-            .position
-            .approx_eq(Vec2::new(10.0 + 2.0, 0.0 - 20.0), DISTANCE_EPS));
-        assert!(particles[0]
-            .velocity
-            .approx_eq(Vec2::new(0.0, -1.0), DISTANCE_EPS));
+        let expected = [
+            Particle::new(Vec2::new(10.0 + 2.0, 0.0 - 20.0), Vec2::new(0.0, -1.0), 1),
+            Particle::new(Vec2::new(7.0, 0.0), Vec2::new(0.0, 0.0), 2),
+            Particle::new(Vec2::new(14.0 + 21.0, 0.0), Vec2::new(1.0, 0.0), 1),
+            Particle::new(Vec2::new(14.0 + 10.0, -15.0 + 30.0), Vec2::new(1.0, 1.0), 1),
+            Particle::new(Vec2::new(-8.0 + 20.0, 5.0), Vec2::new(0.0, 0.0), 1),
+            Particle::new(Vec2::new(20.0 + 30.0, 20.0 + 30.0), Vec2::new(1.0, 1.0), 2),
+            Particle::new(Vec2::new(12.0, 2.0), Vec2::new(0.0, 0.0), 1),
+        ];
+        for (i, (particle, expected)) in particles.iter().zip(&expected).enumerate() {
+            assert!(particle.approx_eq(expected, DISTANCE_EPS, DISTANCE_EPS), "particle {}: {:?}", i, particle);
+        }
+    }
 
-        assert!(particles[1]
-            .position
-            .approx_eq(Vec2::new(7.0, 0.0), DISTANCE_EPS));
-        assert!(particles[1]
-            .velocity
-            .approx_eq(Vec2::new(0.0, 0.0), DISTANCE_EPS));
+    #[test]
+    pub fn test_resolve_coalesce() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let wall_classes = HashMap::new();
+        let mut rules = ParticlePairRules::new();
+        rules.set(1, 1, ParticlePairRule::Coalesce);
+        let resolve_velocity = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &rules);
+        let units = Units::default();
+        let resolve_wall =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+
+        // Two equal particles. Head on collision
+        let mut particles = vec![
+            Particle::new(Vec2::new(-3.0, 1.0), Vec2::new(1.0, 0.0), 1),
+            Particle::new(Vec2::new(4.0, 1.0), Vec2::new(-2.0, 0.0), 1),
+        ];
 
-        assert!(particles[2]
-            .position
-            .approx_eq(Vec2::new(14.0 + 21.0, 0.0), DISTANCE_EPS));
-        assert!(particles[2]
-            .velocity
-            .approx_eq(Vec2::new(1.0, 0.0), DISTANCE_EPS));
+        resolve(
+            &mut particles,
+            &StepEnvironment::new(&classes, &rules, &[], &wall_classes),
+            &NeighborGrid::new(),
+            2.0,
+            &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+            None,
+            None,
+        );
 
-        assert!(particles[3]
-            .position
-            .approx_eq(Vec2::new(14.0 + 10.0, -15.0 + 30.0), DISTANCE_EPS));
-        assert!(particles[3]
+        assert_eq!(particles.len(), 1);
+        let merged = particles[0];
+        let class = classes.get(&1).unwrap();
+        // Mass is summed. Area is conserved
+        assert!(math_core::approx_eq(merged.mass(class), 2.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(
+            merged.radius(class),
+            2.0_f64.sqrt(),
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+        // Momentum is conserved
+        assert!(merged
             .velocity
-            .approx_eq(Vec2::new(1.0, 1.0), DISTANCE_EPS));
-
-        assert!(particles[4]
+            .approx_eq(Vec2::new(-0.5, 0.0), DISTANCE_EPS));
+        // Merged particle continues the motion of center of mass.
+        // Center of mass starts at x=0.5 and moves at -0.5
+        assert!(merged
             .position
-            .approx_eq(Vec2::new(-8.0 + 20.0, 5.0), DISTANCE_EPS));
-        assert!(particles[4]
-            .velocity
-            .approx_eq(Vec2::new(0.0, 0.0), DISTANCE_EPS));
+            .approx_eq(Vec2::new(0.5 - 0.5 * 2.0, 1.0), DISTANCE_EPS));
+    }
 
-        assert!(particles[5]
-            .position
-            .approx_eq(Vec2::new(20.0 + 30.0, 20.0 + 30.0), DISTANCE_EPS));
-        assert!(particles[5]
-            .velocity
-            .approx_eq(Vec2::new(1.0, 1.0), DISTANCE_EPS));
+    #[test]
+    pub fn test_resolve_fragment() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Bullet", 4.0, 1.0));
+        classes.insert(2, ParticleClass::new("Target", 1.0, 2.0));
+        let wall_classes = HashMap::new();
+        let mut rules = ParticlePairRules::new();
+        rules.set(
+            1,
+            2,
+            ParticlePairRule::Fragment {
+                energy_threshold: 100.0,
+                num_fragments: 4,
+            },
+        );
+        let resolve_velocity = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &rules);
+        let units = Units::default();
+        let resolve_wall =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+
+        let momentum = |particles: &[Particle]| {
+            particles.iter().fold(Vec2::ZERO, |acc, p| {
+                acc + p.velocity * p.mass(classes.get(&p.class()).unwrap())
+            })
+        };
+
+        // Slow bullet. Energy of approach is 0.5 * 0.8 * 15^2 = 90. Just bounces
+        let mut particles = vec![
+            Particle::new(Vec2::new(-10.0, 0.0), Vec2::new(15.0, 0.0), 1),
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0), 2),
+        ];
+        let grid = NeighborGrid::new();
+        resolve(
+            &mut particles,
+            &StepEnvironment::new(&classes, &rules, &[], &wall_classes),
+            &grid,
+            1.0,
+            &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+            None,
+            None,
+        );
+        assert_eq!(particles.len(), 2);
 
-        assert!(particles[6]
-            .position
-            .approx_eq(Vec2::new(12.0, 2.0), DISTANCE_EPS));
-        assert!(particles[6]
-            .velocity
-            .approx_eq(Vec2::new(0.0, 0.0), DISTANCE_EPS));
+        // Fast bullet. Energy of approach is 0.5 * 0.8 * 20^2 = 160.
+        // Fragments are too slow to shatter further
+        let mut particles = vec![
+            Particle::new(Vec2::new(-10.0, 0.0), Vec2::new(20.0, 0.0), 1),
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0), 2),
+        ];
+        let momentum_before = momentum(&particles);
+        resolve(
+            &mut particles,
+            &StepEnvironment::new(&classes, &rules, &[], &wall_classes),
+            &grid,
+            1.0,
+            &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+            None,
+            None,
+        );
+        assert_eq!(particles.len(), 5);
+        assert!(momentum(&particles).approx_eq(momentum_before, DISTANCE_EPS));
+        // Bullet stays intact. Target is split evenly
+        assert_eq!(particles[0].class(), 1);
+        let target_class = classes.get(&2).unwrap();
+        for fragment in particles.iter().skip(1) {
+            assert_eq!(fragment.class(), 2);
+            assert!(math_core::approx_eq(
+                fragment.mass(target_class),
+                0.25,
+                DOUBLE_COMPARE_EPS_STRICT
+            ));
+            assert!(math_core::approx_eq(
+                fragment.radius(target_class),
+                1.0,
+                DOUBLE_COMPARE_EPS_STRICT
+            ));
+        }
+
+        // Same collision with the target first. Fragments start on the far side of the
+        // target and don't fly into the bullet
+        let target = Particle::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0), 2);
+        let bullet = Particle::new(Vec2::new(3.0, 0.0), Vec2::new(-20.0, 0.0), 1);
+        let (first, bullet, _) =
+            fragment_particles(target, bullet, Vec2::new(1.0, 0.0), 100.0, 4, &classes).unwrap();
+        assert!((first.velocity - bullet.velocity).dot(Vec2::new(1.0, 0.0)) < 0.0);
+        assert!(first.position.x < 0.0);
+
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0), 2),
+            Particle::new(Vec2::new(10.0, 0.0), Vec2::new(-20.0, 0.0), 1),
+        ];
+        let momentum_before = momentum(&particles);
+        resolve(
+            &mut particles,
+            &StepEnvironment::new(&classes, &rules, &[], &wall_classes),
+            &grid,
+            1.0,
+            &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+            None,
+            None,
+        );
+        assert_eq!(particles.len(), 5);
+        assert!(momentum(&particles).approx_eq(momentum_before, DISTANCE_EPS));
+        assert_eq!(particles[1].class(), 1);
+        let bullet = particles[1];
+        for fragment in particles.iter().filter(|p| p.class() == 2) {
+            let offset = bullet.position - fragment.position;
+            assert!((fragment.velocity - bullet.velocity).dot(offset) <= 0.0, "{:?}", fragment);
+        }
+    }
+
+    #[test]
+    fn test_periodic_boundary() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.25));
+        let rules = ParticlePairRules::new();
+        let wall_classes = HashMap::new();
+        let resolve_velocity = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &rules);
+        let units = Units::default();
+        let resolve_wall =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+        let boundary = BoundaryCondition::Periodic {
+            min: Vec2::new(-5.0, -5.0),
+            max: Vec2::new(5.0, 5.0),
+        };
+        let run = |particles: &mut Vec<Particle>, boundary: BoundaryCondition| {
+            // Grid would miss the pair across the edge. Resolver must not rely on it
+            let mut grid = NeighborGrid::new();
+            grid.rebuild(particles, 2.0);
+            resolve(
+                particles,
+                &StepEnvironment::new(&classes, &rules, &[], &wall_classes).with_boundary(boundary),
+                &grid,
+                1.0,
+                &CollisionHandling::new(&resolve_velocity, &resolve_wall),
+                None,
+                None,
+            )
+        };
+
+        // Lone particle leaves through the right edge and comes back through the left one
+        let mut particles = vec![Particle::new(Vec2::new(4.0, 1.0), Vec2::new(2.0, -1.0), 1)];
+        run(&mut particles, boundary);
+        assert!(particles[0].position.approx_eq(Vec2::new(-4.0, 0.0), DISTANCE_EPS));
+        assert_eq!(particles[0].velocity, Vec2::new(2.0, -1.0));
+
+        // Particle at the left edge is 0.9 away through the boundary. They touch at 0.2 s,
+        // then the first one stops and the second one takes its speed
+        let mut particles = vec![
+            Particle::new(Vec2::new(4.5, 0.0), Vec2::new(2.0, 0.0), 1),
+            Particle::new(Vec2::new(-4.6, 0.0), Vec2::ZERO, 1),
+        ];
+        let report = run(&mut particles, boundary);
+        assert_eq!(report.collisions, 1);
+        assert!(particles[0].position.approx_eq(Vec2::new(4.9, 0.0), DISTANCE_EPS));
+        assert!(particles[0].velocity.approx_eq(Vec2::ZERO, DISTANCE_EPS));
+        assert!(particles[1].position.approx_eq(Vec2::new(-3.0, 0.0), DISTANCE_EPS));
+        assert!(particles[1].velocity.approx_eq(Vec2::new(2.0, 0.0), DISTANCE_EPS));
+        // Pressure of the collision points along the line of centers through the boundary
+        assert!(report.collision_virial.xx > 0.0);
+
+        // In open space they miss each other
+        let mut particles = vec![
+            Particle::new(Vec2::new(4.5, 0.0), Vec2::new(2.0, 0.0), 1),
+            Particle::new(Vec2::new(-4.6, 0.0), Vec2::ZERO, 1),
+        ];
+        let report = run(&mut particles, BoundaryCondition::Open);
+        assert_eq!(report.collisions, 0);
+        assert!(particles[0].position.approx_eq(Vec2::new(6.5, 0.0), DISTANCE_EPS));
     }
 
     #[test]
@@ -679,9 +2015,11 @@ mod tests {
         wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
 
         // Lamda that resolve velocity
-        let resolve_p_p = default_particle_vs_particle_velocity_resovler(&particle_classes);
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
         let resolve_p_w =
-            default_particle_vs_wall_velocity_resolver(&particle_classes, &wall_classes);
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
 
         // Make a box for a scene (about 8x8 on the inside)
         let walls = Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1);
@@ -708,22 +2046,27 @@ mod tests {
         // First is simulated in single step
         resolve(
             &mut particles1,
-            &particle_classes,
-            &walls,
+            &StepEnvironment::new(&particle_classes, &ParticlePairRules::new(), &walls, &wall_classes),
+            &NeighborGrid::new(),
             duration,
-            &resolve_p_p,
-            &resolve_p_w,
+            &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+            None,
+            None,
         );
 
-        // Second is simulated in multiple steps
+        // Second is simulated in multiple steps. Grid limits the search to nearby pairs
+        let mut neighbor_grid = NeighborGrid::new();
         for _ in 0..steps {
+            let cutoff = collision_cutoff(&particles2, &particle_classes, time_step);
+            neighbor_grid.rebuild(&particles2, cutoff);
             resolve(
                 &mut particles2,
-                &particle_classes,
-                &walls,
+                &StepEnvironment::new(&particle_classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                &neighbor_grid,
                 time_step,
-                &resolve_p_p,
-                &resolve_p_w,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                None,
+                None,
             );
         }
 
@@ -731,8 +2074,464 @@ mod tests {
         let eps = 0.01;
         // Now compare
         for (p1, p2) in particles1.iter().zip(particles2.iter()) {
-            assert!(p1.position.approx_eq(p2.position, eps));
-            assert!(p1.velocity.approx_eq(p2.velocity, eps));
+            assert!(p1.approx_eq(p2, eps, eps), "{:?} != {:?}", p1, p2);
         }
     }
+
+    #[test]
+    fn test_warm_start_keeps_results() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 1.0, 0.2));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Gas in a box. Some particles collide within the run, most don't
+        let walls = Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1);
+        let mut particles = vec![];
+        for i in 0..36 {
+            let position = Vec2::new((i % 6) as f64 * 1.4 - 3.5, (i / 6) as f64 * 1.4 - 3.5);
+            let velocity = Vec2::from_angle_rad(i as f64 * 2.4) * (1.0 + (i % 5) as f64);
+            particles.push(Particle::new(position, velocity, 1));
+        }
+
+        let substeps = 10;
+        let time_step = 0.02;
+        let run = |warm_start: Option<&mut WarmStart>| {
+            let mut particles = particles.clone();
+            let mut pair_checks = 0;
+            let mut warm_start = warm_start;
+            for _ in 0..substeps {
+                let report = resolve(
+                    &mut particles,
+                    &StepEnvironment::new(
+                        &particle_classes,
+                        &ParticlePairRules::new(),
+                        &walls,
+                        &wall_classes,
+                    ),
+                    &NeighborGrid::new(),
+                    time_step,
+                    &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                    warm_start.as_deref_mut(),
+                    None,
+                );
+                pair_checks += report.pair_checks;
+            }
+            (particles, pair_checks)
+        };
+
+        let (cold, cold_checks) = run(None);
+        let (warm, warm_checks) = run(Some(&mut WarmStart::default()));
+        for (p1, p2) in cold.iter().zip(warm.iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+        // Something happened, but most of pairs were only checked once
+        assert!(cold.iter().zip(particles.iter()).any(|(p1, p2)| p1.velocity != p2.velocity));
+        assert!(warm_checks * 2 < cold_checks);
+    }
+
+    #[test]
+    fn test_grid_stress() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 1.0, 0.3));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Dense gas of 5000 particles in a box
+        let walls = Wall::make_box(-1.0, -1.0, 100.0, 50.0, 1.0, 1);
+        let mut particles = vec![];
+        for i in 0..5000 {
+            let position = Vec2::new((i % 100) as f64, (i / 100) as f64);
+            let velocity = Vec2::from_angle_rad(i as f64 * 2.4) * (1.0 + (i % 5) as f64);
+            particles.push(Particle::new(position, velocity, 1));
+        }
+        let energy = |particles: &[Particle]| -> f64 {
+            particles
+                .iter()
+                .map(|p| math_core::kinetic_energy_from_velocity(1.0, p.velocity.length()))
+                .sum()
+        };
+        let energy_before = energy(&particles);
+
+        let time_step = 0.05;
+        let step = |particles: &mut Vec<Particle>, neighbor_grid: &NeighborGrid| {
+            return resolve(
+                particles,
+                &StepEnvironment::new(&particle_classes, &pair_rules, &walls, &wall_classes),
+                neighbor_grid,
+                time_step,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                None,
+                None,
+            );
+        };
+
+        // Grid finds the same collisions as checking each vs each. With the margin it also
+        // serves searches after particles sped up in collisions. Part of the gas is enough
+        // here, checking each vs each for all of it is slow
+        let mut neighbor_grid = NeighborGrid::new();
+        let mut each_vs_each = particles[..1000].to_vec();
+        let mut with_grid = each_vs_each.clone();
+        let cutoff = collision_cutoff(&with_grid, &particle_classes, time_step) * NEIGHBOR_CUTOFF_MARGIN;
+        neighbor_grid.rebuild(&with_grid, cutoff);
+        let full_report = step(&mut each_vs_each, &NeighborGrid::new());
+        let grid_report = step(&mut with_grid, &neighbor_grid);
+        assert!(grid_report.collisions > 0);
+        assert_eq!(grid_report.collisions, full_report.collisions);
+        assert!(grid_report.pair_checks * 20 < full_report.pair_checks);
+        for (p1, p2) in with_grid.iter().zip(each_vs_each.iter()) {
+            assert!(p1.approx_eq(p2, DISTANCE_EPS, DISTANCE_EPS), "{:?} != {:?}", p1, p2);
+        }
+
+        // Whole gas runs many steps in reasonable time and elastic collisions keep the energy
+        let start = std::time::Instant::now();
+        for _ in 0..20 {
+            let cutoff = collision_cutoff(&particles, &particle_classes, time_step) * NEIGHBOR_CUTOFF_MARGIN;
+            neighbor_grid.rebuild(&particles, cutoff);
+            step(&mut particles, &neighbor_grid);
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(30), "{:?}", start.elapsed());
+        assert_eq!(particles.len(), 5000);
+        let energy_after = energy(&particles);
+        assert!(math_core::approx_eq(energy_after, energy_before, 1e-9 * energy_before));
+    }
+
+    #[test]
+    fn test_collision_events() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 2.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Head-on collision at 1 sec. Each particle of mass 2 reverses speed 1
+        let make_particles = || {
+            vec![
+                Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(1.0, 0.0), 1),
+                Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), 1),
+            ]
+        };
+        let run = |particles: &mut Vec<Particle>, walls: &[Wall], collision_events: Option<&mut Vec<CollisionEvent>>| {
+            return resolve(
+                particles,
+                &StepEnvironment::new(&particle_classes, &pair_rules, walls, &wall_classes),
+                &NeighborGrid::new(),
+                1.5,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                None,
+                collision_events,
+            );
+        };
+        let mut events = vec![];
+        let mut particles = make_particles();
+        run(&mut particles, &[], Some(&mut events));
+        assert_eq!(events.len(), 1);
+        let event = events[0];
+        assert!(math_core::approx_eq(event.time, 1.0, DOUBLE_COMPARE_EPS_STRICT));
+        let (particle, partner) = match event.partner {
+            CollisionPartner::Particle(other) => (event.particle, other),
+            CollisionPartner::Wall(_) => panic!("{:?}", event),
+        };
+        assert_eq!((particle.min(partner), particle.max(partner)), (0, 1));
+        let towards_partner = (particles[partner].position - particles[particle].position).normalized().unwrap();
+        assert!(event.normal.approx_eq(towards_partner, DISTANCE_EPS));
+        assert!(math_core::approx_eq(event.impulse, 4.0, DISTANCE_EPS));
+
+        // Without the sink the results are the same
+        let mut unrecorded = make_particles();
+        run(&mut unrecorded, &[], None);
+        for (p1, p2) in unrecorded.iter().zip(particles.iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+
+        // Wall collisions name the wall. Right wall of the box is the second one
+        let walls = Wall::make_box(-10.0, -10.0, 10.0, 10.0, 1.0, 1);
+        let mut events = vec![];
+        let mut particles = vec![Particle::new(Vec2::new(7.0, 0.0), Vec2::new(2.0, 0.0), 1)];
+        run(&mut particles, &walls, Some(&mut events));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].partner, CollisionPartner::Wall(1));
+        assert_eq!(events[0].particle, 0);
+        assert!(math_core::approx_eq(events[0].time, 0.5, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(events[0].normal.approx_eq(Vec2::UNIT_X, DISTANCE_EPS));
+        assert!(math_core::approx_eq(events[0].impulse, 8.0, DISTANCE_EPS));
+    }
+
+    #[test]
+    fn test_batched_symmetric_impact() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Light", 1.0, 0.5));
+        classes.insert(2, ParticleClass::new("Heavy", 2.0, 0.5));
+        let wall_classes = HashMap::new();
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w = particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+        let run = |particles: &mut Vec<Particle>, walls: &[Wall], mode: ContactResolution| {
+            resolve(
+                particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), walls, &wall_classes),
+                &NeighborGrid::new(),
+                2.0,
+                &CollisionHandling {
+                    contact_resolution: mode,
+                    ..CollisionHandling::new(&resolve_p_p, &resolve_p_w)
+                },
+                None,
+                None,
+            );
+        };
+
+        // Both light particles hit the heavy one at the same moment
+        let mut particles = vec![
+            Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(1.0, 0.0), 1),
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 2),
+            Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), 1),
+        ];
+        run(&mut particles, &[], ContactResolution::Batched);
+        assert!(particles[0].velocity.approx_eq(Vec2::new(-1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[1].velocity.approx_eq(Vec2::ZERO, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[2].velocity.approx_eq(Vec2::new(1.0, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[0].position.approx_eq(-particles[2].position, DOUBLE_COMPARE_EPS_STRICT));
+
+        // One at a time the outcome depends on the order
+        let mut sequential = vec![
+            Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(1.0, 0.0), 1),
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 2),
+            Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), 1),
+        ];
+        run(&mut sequential, &[], ContactResolution::Sequential);
+        assert!(!sequential[1].velocity.approx_eq(Vec2::ZERO, DOUBLE_COMPARE_EPS_STRICT));
+
+        // Collisions at different moments are resolved the same way in both modes
+        let walls = Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1);
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
+        let resolve_p_w = particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+        let separated = vec![
+            Particle::new(Vec2::new(-2.0, 2.0), Vec2::new(1.0, 2.0), 1),
+            Particle::new(Vec2::new(2.0, 2.0), Vec2::new(-1.12, -5.0), 2),
+            Particle::new(Vec2::new(2.0, -2.0), Vec2::new(-3.12, -1.0), 1),
+            Particle::new(Vec2::new(-2.0, -2.0), Vec2::new(8.12, 0.5), 2),
+        ];
+        let mut results = vec![];
+        for mode in [ContactResolution::Sequential, ContactResolution::Batched] {
+            let mut particles = separated.clone();
+            resolve(
+                &mut particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                &NeighborGrid::new(),
+                2.0,
+                &CollisionHandling {
+                    contact_resolution: mode,
+                    ..CollisionHandling::new(&resolve_p_p, &resolve_p_w)
+                },
+                None,
+                None,
+            );
+            results.push(particles);
+        }
+        for (p1, p2) in results[0].iter().zip(results[1].iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+    }
+
+    #[test]
+    pub fn test_resolve_islands_match_serial() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 100.0, 0.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Two clusters in separate boxes far from each other
+        let mut walls = Wall::make_box(-55.0, -5.0, -45.0, 5.0, 1.0, 1);
+        walls.extend(Wall::make_box(45.0, -5.0, 55.0, 5.0, 1.0, 1));
+        let mut particles1 = vec![];
+        for offset in [Vec2::new(-50.0, 0.0), Vec2::new(50.0, 0.0)] {
+            particles1.extend([
+                Particle::new(offset + Vec2::new(-2.0, 2.0), Vec2::new(1.0, 2.0), 1),
+                Particle::new(offset + Vec2::new(2.0, 2.0), Vec2::new(-1.12, -5.0), 1),
+                Particle::new(offset + Vec2::new(2.0, -2.0), Vec2::new(-3.12, -1.0), 1),
+                Particle::new(offset + Vec2::new(-2.0, -2.0), Vec2::new(8.12, 0.5), 1),
+                Particle::new(offset + Vec2::new(0.0, 0.0), Vec2::new(3.0, 1.0), 1),
+            ]);
+        }
+        let mut particles2 = particles1.clone();
+        let time_step = 0.2;
+
+        let mut neighbor_grid = NeighborGrid::new();
+        let bound = ReachBound { speed: 2.0 * 8.12, radius: 1.0, jump: 0.0 };
+        neighbor_grid.rebuild(&particles2, collision_cutoff(&particles2, &particle_classes, time_step));
+        let islands = find_islands(&particles2, &vec![bound; particles2.len()], &neighbor_grid, time_step);
+        assert!(islands.len() >= 2);
+
+        let mut events1 = vec![];
+        let mut events2 = vec![];
+        for _ in 0..50 {
+            let cutoff = collision_cutoff(&particles1, &particle_classes, time_step);
+            neighbor_grid.rebuild(&particles1, cutoff);
+            resolve(
+                &mut particles1,
+                &StepEnvironment::new(&particle_classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                &neighbor_grid,
+                time_step,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                None,
+                Some(&mut events1),
+            );
+            neighbor_grid.rebuild(&particles2, cutoff);
+            resolve_islands(
+                &mut particles2,
+                &StepEnvironment::new(&particle_classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                &neighbor_grid,
+                time_step,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                2,
+                Some(&mut events2),
+            );
+        }
+        assert_eq!(particles1.len(), particles2.len());
+        for (p1, p2) in particles1.iter().zip(particles2.iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+        // Same events, with indices of the whole scene. Order of simultaneous ones may differ.
+        // Events of all steps are together, so times are only ordered within each step
+        assert!(!events1.is_empty());
+        let sorted = |mut events: Vec<CollisionEvent>| {
+            events.sort_by(|a, b| a.time.total_cmp(&b.time).then(a.particle.cmp(&b.particle)));
+            events
+        };
+        assert_eq!(sorted(events1), sorted(events2));
+    }
+
+    #[test]
+    pub fn test_resolve_islands_merge() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let wall_classes = HashMap::new();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Chain of lighter and lighter particles speeds up the last one far beyond
+        // the speed of the first. It reaches the particle that was out of reach at the start
+        let make_particles = || {
+            vec![
+                Particle::new(Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), 1).with_mass_and_radius(1000.0, 1.0),
+                Particle::new(Vec2::new(2.5, 0.0), Vec2::ZERO, 1).with_mass_and_radius(30.0, 1.0),
+                Particle::new(Vec2::new(5.0, 0.0), Vec2::ZERO, 1).with_mass_and_radius(1.0, 1.0),
+                Particle::new(Vec2::new(7.5, 0.0), Vec2::ZERO, 1).with_mass_and_radius(0.03, 1.0),
+                Particle::new(Vec2::new(52.5, 0.0), Vec2::ZERO, 1),
+            ]
+        };
+        let resolve_with = |threads: usize| {
+            let mut particles = make_particles();
+            resolve_islands(
+                &mut particles,
+                &StepEnvironment::new(&particle_classes, &ParticlePairRules::new(), &[], &wall_classes),
+                &NeighborGrid::new(),
+                1.0,
+                &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+                threads,
+                None,
+            );
+            particles
+        };
+        let serial = resolve_with(1);
+        let parallel = resolve_with(2);
+        // Last particle was hit
+        assert!(serial[4].velocity.x > 0.0);
+        for (p1, p2) in serial.iter().zip(parallel.iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+    }
+
+    #[test]
+    pub fn test_wall_restitution_curve() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 2.0, 1.0));
+        let curve = RestitutionCurve::Exponential { max: 1.0, min: 0.3, decay_speed: 10.0 };
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Floor", 0.0, 0.0).with_restitution(curve.clone()));
+        let units = Units::default();
+        let resolve_wall = particle_vs_wall_velocity_resolver(&ElasticModel, &classes, &wall_classes, &units, None);
+        let floor = Wall::new(Polygon::new_rectangle(-10.0, -1.0, 10.0, 0.0), 1);
+
+        // Particle falls on the floor at an angle. Fraction of energy lost in the bounce
+        let energy_loss = |speed: f64| {
+            let particle = Particle::new(Vec2::new(0.0, 1.0), Vec2::new(1.0, -speed), 1);
+            let v = resolve_wall(&particle, &floor, Vec2::new(0.0, 1.0)).unwrap();
+            // Tangential motion is kept, normal one is damped
+            assert_eq!(v.x, 1.0);
+            assert!(math_core::approx_eq(v.y, speed * curve.at(speed), 1e-12));
+            1.0 - v.length_sq() / particle.velocity.length_sq()
+        };
+        let slow = energy_loss(1.0);
+        let fast = energy_loss(20.0);
+        assert!(slow > 0.0);
+        assert!(fast > 2.0 * slow);
+    }
+
+    #[test]
+    fn test_diffuse_wall_impulse_is_normal() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(1, ParticleClass::new("Class1", 2.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Rough", 1.0, 0.0).with_diffuse_reflection(true));
+        let pair_rules = ParticlePairRules::new();
+        let resolve_p_p = particle_vs_particle_velocity_resolver(&ElasticModel, &particle_classes, &pair_rules);
+        let units = Units::default();
+        let resolve_p_w =
+            particle_vs_wall_velocity_resolver(&ElasticModel, &particle_classes, &wall_classes, &units, None);
+
+        // Particle hits the right wall of the box at 0.5 sec and scatters in a random direction
+        let walls = Wall::make_box(-10.0, -10.0, 10.0, 10.0, 1.0, 1);
+        let environment = StepEnvironment::new(&particle_classes, &pair_rules, &walls, &wall_classes);
+        let initial_velocity = Vec2::new(2.0, 0.0);
+        let mut particles = vec![Particle::new(Vec2::new(7.0, 0.0), initial_velocity, 1)];
+        let mut events = vec![];
+        let report = resolve(
+            &mut particles,
+            &environment,
+            &NeighborGrid::new(),
+            1.0,
+            &CollisionHandling::new(&resolve_p_p, &resolve_p_w),
+            None,
+            Some(&mut events),
+        );
+        assert_eq!(events.len(), 1);
+        let velocity_change = particles[0].velocity - initial_velocity;
+        assert!(velocity_change.y.abs() > DISTANCE_EPS);
+        // Wall load counts only the normal part, the event keeps the total impulse
+        let normal_impulse = 2.0 * velocity_change.x.abs();
+        assert!(math_core::approx_eq(report.wall_impulse[1], normal_impulse, DISTANCE_EPS));
+        assert!(math_core::approx_eq(events[0].impulse, 2.0 * velocity_change.length(), DISTANCE_EPS));
+        assert!(events[0].impulse > report.wall_impulse[1]);
+    }
 }
@@ -0,0 +1,50 @@
+use crate::barnes_hut::QuadTree;
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{Particle, ParticleClass, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Parameters of the gravitational attraction between particles (N-body gravity)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct MutualGravity {
+    /// Gravitational constant
+    pub constant: f64,
+    /// Barnes-Hut opening angle. Smaller is more accurate and slower. 0 gives exact sum
+    pub theta: f64,
+    /// Softening length. Keeps the force finite for very close particles
+    pub softening: f64,
+}
+
+impl MutualGravity {
+    pub fn new(constant: f64, theta: f64, softening: f64) -> Self {
+        MutualGravity {
+            constant,
+            theta,
+            softening,
+        }
+    }
+}
+
+/// Applies attraction between all particles to their velocities over the time step.
+/// The Barnes-Hut tree is rebuilt from current positions on every call.
+pub(crate) fn apply_mutual_gravity(
+    particles: &mut [Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    gravity: &MutualGravity,
+    time_step_sec: f64,
+) {
+    if particles.len() < 2 {
+        return;
+    }
+    let positions: Vec<Vec2> = particles.iter().map(|p| p.position).collect();
+    let masses: Vec<f64> = particles
+        .iter()
+        .map(|p| p.mass(get_class(particle_classes, p.class())))
+        .collect();
+    let tree = QuadTree::new(&positions, &masses);
+    for (i, particle) in particles.iter_mut().enumerate() {
+        let acceleration = tree.acceleration(i, gravity.theta, gravity.constant, gravity.softening);
+        particle.velocity += acceleration * time_step_sec;
+    }
+}
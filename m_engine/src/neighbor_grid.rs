@@ -0,0 +1,202 @@
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{Particle, ParticleClass, Vec2};
+use std::collections::HashMap;
+
+/// Spatial hash of particle positions. Finds pairs of particles closer than the cutoff
+/// without checking each vs each. Cell size equals the cutoff, so neighbors of a particle
+/// are in its own cell or in the 8 adjacent ones.
+/// Built once per step and shared by everything that needs neighbors in that step.
+/// Particles are referenced by index in the slice the grid was built from
+#[derive(Debug, Clone, Default)]
+pub struct NeighborGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    positions: Vec<Vec2>,
+}
+
+impl NeighborGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces content of the grid with the current particle positions.
+    /// `cell_size` is also the cutoff distance between particle centers
+    pub fn rebuild(&mut self, particles: &[Particle], cell_size: f64) {
+        assert!(cell_size > 0.0);
+        self.cell_size = cell_size;
+        self.cells.clear();
+        self.positions.clear();
+        for (i, particle) in particles.iter().enumerate() {
+            self.cells
+                .entry(self.cell_of(particle.position))
+                .or_default()
+                .push(i);
+            self.positions.push(particle.position);
+        }
+    }
+
+    /// Cutoff distance the grid was built with
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// Number of particles the grid was built from
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Indices of particles within the cutoff from particle `i`, excluding `i` itself.
+    /// Sorted ascending, so the result doesn't depend on hashing
+    pub fn neighbors(&self, i: usize) -> Vec<usize> {
+        let position = self.positions[i];
+        let (cx, cy) = self.cell_of(position);
+        let mut result = vec![];
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(cell) = self.cells.get(&(cx + dx, cy + dy)) {
+                    result.extend(cell.iter().copied().filter(|&j| {
+                        j != i && (self.positions[j] - position).length() <= self.cell_size
+                    }));
+                }
+            }
+        }
+        result.sort_unstable();
+        return result;
+    }
+
+    /// All pairs of particles within the cutoff. Each pair is reported once as (i, j), i < j.
+    /// Sorted, so the result doesn't depend on hashing
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut result = vec![];
+        for i in 0..self.positions.len() {
+            for j in self.neighbors(i) {
+                if i < j {
+                    result.push((i, j));
+                }
+            }
+        }
+        return result;
+    }
+
+    /// Pairs of particles within reach of soft forces, i.e. closer than the sum of their
+    /// interaction cutoffs. Collisions don't use this, they use the radius.
+    /// Grid must be built from `particles` with cell size of at least `interaction_range`
+    pub fn interaction_pairs(
+        &self,
+        particles: &[Particle],
+        particle_classes: &HashMap<ClassId, ParticleClass>,
+    ) -> Vec<(usize, usize)> {
+        debug_assert!(self.len() == particles.len());
+        debug_assert!(self.cell_size >= interaction_range(particles, particle_classes));
+        let cutoff = |p: &Particle| p.interaction_cutoff(get_class(particle_classes, p.class()));
+        return self
+            .pairs()
+            .into_iter()
+            .filter(|&(i, j)| {
+                let distance = (particles[i].position - particles[j].position).length();
+                distance <= cutoff(&particles[i]) + cutoff(&particles[j])
+            })
+            .collect();
+    }
+
+    fn cell_of(&self, position: Vec2) -> (i64, i64) {
+        (
+            (position.x / self.cell_size).floor() as i64,
+            (position.y / self.cell_size).floor() as i64,
+        )
+    }
+}
+
+/// Largest distance between centers of particles that interact through soft forces
+pub(crate) fn interaction_range(
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+) -> f64 {
+    let mut max_cutoff: f64 = 0.0;
+    for particle in particles {
+        let class = get_class(particle_classes, particle.class());
+        max_cutoff = max_cutoff.max(particle.interaction_cutoff(class));
+    }
+    return 2.0 * max_cutoff;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairs_within_cutoff() {
+        let positions = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.9, 0.0),
+            Vec2::new(0.0, -1.2),
+            Vec2::new(-0.5, -0.5),
+            Vec2::new(10.0, 10.0),
+            Vec2::new(10.5, 9.5),
+            Vec2::new(-3.0, 2.0),
+        ];
+        let particles: Vec<Particle> = positions
+            .iter()
+            .map(|&p| Particle::new(p, Vec2::ZERO, 0))
+            .collect();
+        let cutoff = 1.0;
+        let mut grid = NeighborGrid::new();
+        grid.rebuild(&particles, cutoff);
+
+        // Brute force reference
+        let mut expected = vec![];
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                if (positions[i] - positions[j]).length() <= cutoff {
+                    expected.push((i, j));
+                }
+            }
+        }
+        assert_eq!(expected, vec![(0, 1), (0, 3), (2, 3), (4, 5)]);
+        assert_eq!(grid.pairs(), expected);
+        assert_eq!(grid.neighbors(0), vec![1, 3]);
+        assert_eq!(grid.neighbors(3), vec![0, 2]);
+        assert!(grid.neighbors(6).is_empty());
+
+        // Rebuilding replaces the content
+        grid.rebuild(&particles[..2], 0.5);
+        assert_eq!(grid.len(), 2);
+        assert!(grid.pairs().is_empty());
+    }
+
+    #[test]
+    fn test_interaction_pairs_use_cutoff() {
+        use crate::{Integrator, ParticlePairRules, StepEnvironment, VelocityVerletIntegrator};
+        use std::time::Duration;
+
+        let mut classes = HashMap::new();
+        classes.insert(0, ParticleClass::new("Soft", 1.0, 0.5).with_interaction_cutoff(2.0));
+        classes.insert(1, ParticleClass::new("Hard", 1.0, 0.5));
+        // Radii don't touch, cutoffs of the soft pair overlap
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 0),
+            Particle::new(Vec2::new(3.0, 0.0), Vec2::new(-1.0, 0.0), 0),
+            Particle::new(Vec2::new(0.0, 3.0), Vec2::ZERO, 1),
+        ];
+        let mut grid = NeighborGrid::new();
+        grid.rebuild(&particles, interaction_range(&particles, &classes));
+        assert_eq!(interaction_range(&particles, &classes), 4.0);
+        // Soft to hard reaches 2.0 + 0.5 < 3
+        assert_eq!(grid.interaction_pairs(&particles, &classes), vec![(0, 1)]);
+
+        // Particles don't collide before their radii touch
+        let report = VelocityVerletIntegrator::new().step(
+            &mut particles,
+            &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+            Duration::from_millis(100),
+        );
+        assert_eq!(report.collision_virial, Default::default());
+        assert_eq!(particles[0].velocity, Vec2::new(1.0, 0.0));
+        assert_eq!(particles[1].velocity, Vec2::new(-1.0, 0.0));
+    }
+}
@@ -1,12 +1,19 @@
 use std::fmt::Debug;
 use crate::prelude::*;
-use crate::Vec2;
+use crate::{ParticleClass, Vec2};
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct Particle {
     pub position: Vec2,
     pub velocity: Vec2,
     class: ClassId,
+    // Persistent id. Assigned by the simulation when particle is spawned
+    id: Option<ParticleId>,
+    // Particles produced by coalescence don't match their class anymore.
+    // These override the mass and radius of the class
+    mass_override: Option<f64>,
+    radius_override: Option<f64>,
 }
 
 impl Particle {
@@ -15,10 +22,86 @@ impl Particle {
             position,
             velocity,
             class,
+            id: None,
+            mass_override: None,
+            radius_override: None,
         }
     }
 
+    /// Returns copy of the particle with mass and radius that override the class values
+    pub fn with_mass_and_radius(mut self, mass: f64, radius: f64) -> Self {
+        self.mass_override = Some(mass);
+        self.radius_override = Some(radius);
+        self
+    }
+
     pub fn class(&self) -> ClassId {
         self.class
     }
+
+    /// Persistent id of the particle. It doesn't change when particles are reordered.
+    /// Particles that were never spawned into simulation don't have id
+    pub fn id(&self) -> Option<ParticleId> {
+        self.id
+    }
+
+    pub(crate) fn set_id(&mut self, id: Option<ParticleId>) {
+        self.id = id;
+    }
+
+    /// Mass of this particle. `class` must be the class of this particle
+    pub fn mass(&self, class: &ParticleClass) -> f64 {
+        self.mass_override.unwrap_or(class.mass())
+    }
+
+    /// Radius of this particle. `class` must be the class of this particle
+    pub fn radius(&self, class: &ParticleClass) -> f64 {
+        self.radius_override.unwrap_or(class.radius())
+    }
+
+    /// Range of soft forces of this particle. Falls back to its radius if the class
+    /// doesn't set the cutoff. `class` must be the class of this particle
+    pub fn interaction_cutoff(&self, class: &ParticleClass) -> f64 {
+        class.interaction_cutoff().unwrap_or(self.radius(class))
+    }
+
+    pub fn mass_override(&self) -> Option<f64> {
+        self.mass_override
+    }
+
+    pub fn radius_override(&self) -> Option<f64> {
+        self.radius_override
+    }
+
+    /// Particles are equal if position and velocity match within the epsilons, and class,
+    /// mass and radius overrides are the same. Persistent ids are not compared
+    pub fn approx_eq(&self, other: &Particle, pos_epsilon: f64, vel_epsilon: f64) -> bool {
+        self.position.approx_eq(other.position, pos_epsilon)
+            && self.velocity.approx_eq(other.velocity, vel_epsilon)
+            && self.class == other.class
+            && self.mass_override == other.mass_override
+            && self.radius_override == other.radius_override
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approx_eq() {
+        let particle = Particle::new(Vec2::new(1.0, 2.0), Vec2::new(-3.0, 0.5), 1);
+        let mut close = Particle::new(Vec2::new(1.0 + 1e-7, 2.0), Vec2::new(-3.0, 0.5 - 1e-4), 1);
+        close.set_id(Some(5));
+        assert!(particle.approx_eq(&close, 1e-6, 1e-3));
+        // Each epsilon applies to its own quantity
+        assert!(!particle.approx_eq(&close, 1e-8, 1e-3));
+        assert!(!particle.approx_eq(&close, 1e-6, 1e-5));
+
+        let other_class = Particle::new(particle.position, particle.velocity, 2);
+        assert!(!particle.approx_eq(&other_class, 1e-6, 1e-3));
+        let heavier = particle.with_mass_and_radius(2.0, 1.0);
+        assert!(!particle.approx_eq(&heavier, 1e-6, 1e-3));
+        assert!(heavier.approx_eq(&heavier, 1e-12, 1e-12));
+    }
 }
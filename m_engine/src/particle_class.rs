@@ -1,8 +1,12 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ParticleClass {
     name: String,
     mass: f64,
     radius: f64,
+    gravity_scale: f64,
+    interaction_cutoff: Option<f64>,
 }
 
 impl ParticleClass {
@@ -12,8 +16,24 @@ impl ParticleClass {
             name: name.to_string(),
             mass,
             radius,
+            gravity_scale: 1.0,
+            interaction_cutoff: None,
         }
     }
+
+    /// Returns copy of the class with gravity multiplied by `gravity_scale`.
+    /// 0 makes particles weightless, negative values make them float up
+    pub fn with_gravity_scale(mut self, gravity_scale: f64) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+
+    /// Returns copy of the class with the range of soft forces set apart from the radius.
+    /// Only the neighbor search for forces uses it, collisions always use the radius
+    pub fn with_interaction_cutoff(mut self, interaction_cutoff: f64) -> Self {
+        self.interaction_cutoff = Some(interaction_cutoff);
+        self
+    }
     
     // Getters
     pub fn name(&self) -> &str {
@@ -27,4 +47,13 @@ impl ParticleClass {
     pub fn radius(&self) -> f64 {
         self.radius
     }
+
+    pub fn gravity_scale(&self) -> f64 {
+        self.gravity_scale
+    }
+
+    /// None means the range of forces is the collision radius
+    pub fn interaction_cutoff(&self) -> Option<f64> {
+        self.interaction_cutoff
+    }
 }
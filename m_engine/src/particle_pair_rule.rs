@@ -0,0 +1,83 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Describes what happens when two particles of given classes collide.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum ParticlePairRule {
+    /// Particles bounce off each other. This is default behavior
+    #[default]
+    Elastic,
+    /// Particles stick together and merge into single heavier particle.
+    /// Momentum and area are conserved.
+    Coalesce,
+    /// Lighter particle shatters into fragments when the energy of approach exceeds
+    /// the threshold. Threshold energy is lost. Momentum is conserved.
+    /// Weaker collisions are elastic.
+    Fragment {
+        energy_threshold: f64,
+        num_fragments: usize,
+    },
+    /// Particles don't collide and pass through each other, e.g. tracers in a gas.
+    /// Walls still stop them
+    PassThrough,
+}
+
+/// Table of rules for pairs of particle classes. The order of classes in the pair
+/// doesn't matter. Pairs that are not in the table use default rule.
+/// Pairs may also have own coefficient of restitution for bounces
+#[derive(Debug, Clone, Default)]
+pub struct ParticlePairRules {
+    rules: HashMap<(ClassId, ClassId), ParticlePairRule>,
+    restitutions: HashMap<(ClassId, ClassId), f64>,
+}
+
+impl ParticlePairRules {
+    pub fn new() -> Self {
+        ParticlePairRules {
+            rules: HashMap::new(),
+            restitutions: HashMap::new(),
+        }
+    }
+
+    /// Sets the rule for the pair of classes
+    pub fn set(&mut self, class1: ClassId, class2: ClassId, rule: ParticlePairRule) {
+        self.rules.insert(Self::key(class1, class2), rule);
+    }
+
+    /// Returns the rule for the pair of classes
+    pub fn get(&self, class1: ClassId, class2: ClassId) -> ParticlePairRule {
+        self.rules
+            .get(&Self::key(class1, class2))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the coefficient of restitution for bounces of the pair of classes:
+    /// 1 is elastic, 0 is perfectly inelastic
+    pub fn set_restitution(&mut self, class1: ClassId, class2: ClassId, restitution: f64) {
+        self.restitutions.insert(Self::key(class1, class2), restitution);
+    }
+
+    /// Coefficient of restitution of the pair of classes. None if the pair has no own
+    /// coefficient, then the collision model decides
+    pub fn restitution(&self, class1: ClassId, class2: ClassId) -> Option<f64> {
+        self.restitutions.get(&Self::key(class1, class2)).copied()
+    }
+
+    /// Pairs that have own rule, with the smaller class id first
+    pub fn rules(&self) -> impl Iterator<Item = (ClassId, ClassId, ParticlePairRule)> + '_ {
+        self.rules.iter().map(|(&(class1, class2), &rule)| (class1, class2, rule))
+    }
+
+    /// Pairs that have own coefficient of restitution, with the smaller class id first
+    pub fn restitutions(&self) -> impl Iterator<Item = (ClassId, ClassId, f64)> + '_ {
+        self.restitutions
+            .iter()
+            .map(|(&(class1, class2), &restitution)| (class1, class2, restitution))
+    }
+
+    fn key(class1: ClassId, class2: ClassId) -> (ClassId, ClassId) {
+        (class1.min(class2), class1.max(class2))
+    }
+}
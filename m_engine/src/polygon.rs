@@ -1,6 +1,7 @@
 use crate::{LineSegment, Vec2};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 /// Represents polygon made of points points. Polygon is always closed.
 /// Last edge is implied
 pub struct Polygon {
@@ -33,6 +34,11 @@ impl Polygon {
         self.points.len()
     }
 
+    /// Total length of the edges
+    pub fn perimeter(&self) -> f64 {
+        return self.edges_iter().map(|edge| edge.length()).sum();
+    }
+
     /// Returns edge.
     pub fn edge(&self, index: usize) -> LineSegment {
         let p1 = self.points[index];
@@ -57,6 +63,73 @@ impl Polygon {
         };
     }
 
+    /// Returns true if point is inside polygon. Uses even-odd rule, so orientation
+    /// of the polygon doesn't matter. Points exactly on the boundary may go either way
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        let mut inside = false;
+        for edge in self.edges_iter() {
+            let (a, b) = (edge.begin, edge.end);
+            // Count crossings of the horizontal ray going to the right from the point
+            if (a.y > point.y) != (b.y > point.y) {
+                let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if x > point.x {
+                    inside = !inside;
+                }
+            }
+        }
+        return inside;
+    }
+
+    /// Returns true if all corners turn the same way and edges don't cross.
+    /// Collinear points are allowed. Polygons with less than 3 points aren't convex
+    pub fn is_convex(&self) -> bool {
+        if self.points.len() < 3 {
+            return false;
+        }
+        let num_edges = self.num_edges();
+        let mut turn = 0.0;
+        for i in 0..num_edges {
+            let edge1 = self.edge(i);
+            let edge2 = self.edge((i + 1) % num_edges);
+            let cross = (edge1.end - edge1.begin).cross(edge2.end - edge2.begin);
+            if cross * turn < 0.0 {
+                return false;
+            }
+            if cross != 0.0 {
+                turn = cross;
+            }
+        }
+        // Star shaped polygons turn the same way all the time, but cross themselves
+        return turn != 0.0 && self.is_simple();
+    }
+
+    /// Returns true if edges only touch their neighbours at the shared points,
+    /// i.e. polygon doesn't cross or overlap itself. Polygons with less than
+    /// 3 points aren't simple
+    pub fn is_simple(&self) -> bool {
+        let num_edges = self.num_edges();
+        if num_edges < 3 {
+            return false;
+        }
+        for i in 0..num_edges {
+            let edge1 = self.edge(i);
+            for j in i + 1..num_edges {
+                let edge2 = self.edge(j);
+                let adjacent = j == i + 1 || (i == 0 && j == num_edges - 1);
+                if adjacent {
+                    // Neighbours share a point. They only overlap if one goes back along the other
+                    let (d1, d2) = (edge1.end - edge1.begin, edge2.end - edge2.begin);
+                    if d1.cross(d2) == 0.0 && d1.dot(d2) < 0.0 {
+                        return false;
+                    }
+                } else if segments_intersect(&edge1, &edge2) {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
     /// Gets points as flat array with 2d coordinates
     pub fn points2d_flat_iter(&self) -> impl Iterator<Item = f32> + '_ {
         self.points
@@ -80,6 +153,29 @@ impl Polygon {
     }
 }
 
+// Returns true if closed segments have common points
+fn segments_intersect(a: &LineSegment, b: &LineSegment) -> bool {
+    let orientation = |p: Vec2, q: Vec2, r: Vec2| (q - p).cross(r - p);
+    // Checks if point known to be on the line of the segment lies within it
+    let within = |s: &LineSegment, p: Vec2| {
+        p.x >= s.begin.x.min(s.end.x)
+            && p.x <= s.begin.x.max(s.end.x)
+            && p.y >= s.begin.y.min(s.end.y)
+            && p.y <= s.begin.y.max(s.end.y)
+    };
+    let o1 = orientation(a.begin, a.end, b.begin);
+    let o2 = orientation(a.begin, a.end, b.end);
+    let o3 = orientation(b.begin, b.end, a.begin);
+    let o4 = orientation(b.begin, b.end, a.end);
+    if o1 * o2 < 0.0 && o3 * o4 < 0.0 {
+        return true;
+    }
+    return (o1 == 0.0 && within(a, b.begin))
+        || (o2 == 0.0 && within(a, b.end))
+        || (o3 == 0.0 && within(b, a.begin))
+        || (o4 == 0.0 && within(b, a.end));
+}
+
 impl From<Vec<Vec2>> for Polygon {
     fn from(points: Vec<Vec2>) -> Self {
         Polygon { points }
@@ -113,56 +209,42 @@ mod tests {
         ]);
 
         // Test convex corner
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(3.1, 0.0)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(3.1, 0.1)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(3.1, -0.1)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(2.9, -0.1)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(2.9, 0.1)),
-            false
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(1, Vec2::new(2.9, 0.1)),
-            false
-        );
+        assert!(polygon.is_point_outside_corner(1, Vec2::new(3.1, 0.0)));
+        assert!(polygon.is_point_outside_corner(1, Vec2::new(3.1, 0.1)));
+        assert!(polygon.is_point_outside_corner(1, Vec2::new(3.1, -0.1)));
+        assert!(polygon.is_point_outside_corner(1, Vec2::new(2.9, -0.1)));
+        assert!(!polygon.is_point_outside_corner(1, Vec2::new(2.9, 0.1)));
+        assert!(!polygon.is_point_outside_corner(1, Vec2::new(2.9, 0.1)));
 
         // Test concave corner
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(2.1, 1.1)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(2.2, 1.1)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(2.1, 1.2)),
-            true
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(1.9, 0.9)),
-            false
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(2.1, 0.9)),
-            false
-        );
-        assert_eq!(
-            polygon.is_point_outside_corner(3, Vec2::new(1.9, 1.2)),
-            false
-        );
+        assert!(polygon.is_point_outside_corner(3, Vec2::new(2.1, 1.1)));
+        assert!(polygon.is_point_outside_corner(3, Vec2::new(2.2, 1.1)));
+        assert!(polygon.is_point_outside_corner(3, Vec2::new(2.1, 1.2)));
+        assert!(!polygon.is_point_outside_corner(3, Vec2::new(1.9, 0.9)));
+        assert!(!polygon.is_point_outside_corner(3, Vec2::new(2.1, 0.9)));
+        assert!(!polygon.is_point_outside_corner(3, Vec2::new(1.9, 1.2)));
+    }
+
+    #[test]
+    fn test_contains_point() {
+        // L shape
+        let polygon = Polygon::from(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(3.0, 0.0),
+            Vec2::new(3.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 3.0),
+            Vec2::new(0.0, 3.0),
+        ]);
+        assert!(polygon.contains_point(Vec2::new(0.5, 0.5)));
+        assert!(polygon.contains_point(Vec2::new(2.5, 0.5)));
+        assert!(polygon.contains_point(Vec2::new(0.5, 2.5)));
+        // Inside the notch
+        assert!(!polygon.contains_point(Vec2::new(2.0, 2.0)));
+        // Far outside
+        assert!(!polygon.contains_point(Vec2::new(-1.0, 0.5)));
+        assert!(!polygon.contains_point(Vec2::new(4.0, 0.5)));
+        assert!(!polygon.contains_point(Vec2::new(0.5, 4.0)));
     }
 
     #[test]
@@ -177,4 +259,58 @@ mod tests {
         assert!(edges[1].approx_eq(LineSegment::new(p1, p2), DISTANCE_EPS));
         assert!(edges[2].approx_eq(LineSegment::new(p2, p0), DISTANCE_EPS));
     }
+
+    #[test]
+    fn test_convex_and_simple() {
+        // L shape
+        let l_shape = Polygon::from(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(3.0, 0.0),
+            Vec2::new(3.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 3.0),
+            Vec2::new(0.0, 3.0),
+        ]);
+        assert!(l_shape.is_simple());
+        assert!(!l_shape.is_convex());
+
+        // Orientation doesn't matter
+        let rectangle = Polygon::new_rectangle(0.0, 0.0, 2.0, 1.0);
+        assert!(rectangle.is_simple());
+        assert!(rectangle.is_convex());
+        let mut reversed = rectangle.clone();
+        reversed.points.reverse();
+        assert!(reversed.is_convex());
+
+        // Edges cross in the middle
+        let bow_tie = Polygon::from(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(0.0, 2.0),
+        ]);
+        assert!(!bow_tie.is_simple());
+        assert!(!bow_tie.is_convex());
+
+        // Every corner turns left, but the edges cross
+        let star: Vec<Vec2> = (0..5)
+            .map(|i| Vec2::from_angle_rad(i as f64 * 4.0 * std::f64::consts::PI / 5.0))
+            .collect();
+        let star = Polygon::from(star);
+        assert!(!star.is_simple());
+        assert!(!star.is_convex());
+
+        // Edge goes back along the previous one
+        let spike = Polygon::from(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(3.0, 0.0),
+            Vec2::new(3.0, 1.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(2.0, 0.0),
+        ]);
+        assert!(!spike.is_simple());
+
+        assert!(!Polygon::from(vec![Vec2::ZERO, Vec2::UNIT_X]).is_simple());
+    }
 }
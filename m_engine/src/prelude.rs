@@ -3,5 +3,6 @@ pub const DISTANCE_EPS_SQ: f64 = DISTANCE_EPS * DISTANCE_EPS;
 pub const DOUBLE_COMPARE_EPS_STRICT: f64 = 1e-10;
 pub const TIME_SEC_EPS: f64 = 1e-6;
 
-pub type ClassId = u8;
+pub type ClassId = u32;
 
+pub type ParticleId = usize;
@@ -0,0 +1,117 @@
+use crate::prelude::*;
+use crate::sim_error::get_class;
+use crate::{NeighborGrid, Particle, ParticleClass, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Settings of pushing overlapping particles apart before the simulation starts.
+/// Particles are moved until the deepest overlap is at most `tolerance`, but no more than
+/// `max_iterations` times
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Relaxation {
+    pub max_iterations: usize,
+    pub tolerance: f64,
+}
+
+impl Relaxation {
+    pub fn new(max_iterations: usize, tolerance: f64) -> Self {
+        assert!(tolerance >= 0.0);
+        Relaxation {
+            max_iterations,
+            tolerance,
+        }
+    }
+}
+
+/// Moves overlapping particles apart. Each overlap is split between the pair inversely to
+/// their masses, so the center of mass stays. Only positions change, velocities and
+/// therefore kinetic energy are kept. Walls are not considered.
+/// Returns the deepest overlap that is left
+pub fn relax_overlaps(
+    particles: &mut [Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    relaxation: &Relaxation,
+) -> f64 {
+    let max_radius = particles
+        .iter()
+        .map(|p| p.radius(get_class(particle_classes, p.class())))
+        .fold(0.0, f64::max);
+    if max_radius <= 0.0 {
+        return 0.0;
+    }
+    let mut grid = NeighborGrid::new();
+    let mut iteration = 0;
+    loop {
+        grid.rebuild(particles, 2.0 * max_radius);
+        let pairs = grid.pairs();
+        let deepest = pairs
+            .iter()
+            .map(|&(i, j)| overlap(&particles[i], &particles[j], particle_classes))
+            .fold(0.0, f64::max);
+        if deepest <= relaxation.tolerance || iteration == relaxation.max_iterations {
+            return deepest;
+        }
+        for (i, j) in pairs {
+            let depth = overlap(&particles[i], &particles[j], particle_classes);
+            if depth <= 0.0 {
+                continue;
+            }
+            // Particles at the same point are pushed apart along an arbitrary direction
+            let direction = (particles[j].position - particles[i].position)
+                .normalized()
+                .unwrap_or(Vec2::UNIT_X);
+            let mass_i = particles[i].mass(get_class(particle_classes, particles[i].class()));
+            let mass_j = particles[j].mass(get_class(particle_classes, particles[j].class()));
+            let total_mass = mass_i + mass_j;
+            particles[i].position -= direction * (depth * mass_j / total_mass);
+            particles[j].position += direction * (depth * mass_i / total_mass);
+        }
+        iteration += 1;
+    }
+}
+
+// How deep particles overlap. Negative if they don't
+fn overlap(
+    p1: &Particle,
+    p2: &Particle,
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+) -> f64 {
+    let radii = p1.radius(get_class(particle_classes, p1.class()))
+        + p2.radius(get_class(particle_classes, p2.class()));
+    return radii - (p2.position - p1.position).length();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators;
+
+    #[test]
+    fn test_relax_overlapping_grid() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        classes.insert(2, ParticleClass::new("Heavy", 3.0, 0.5));
+        // Spacing of 0.8 is less than the diameter
+        let layout = generators::GridLayout::new(Vec2::ZERO, Vec2::UNIT_X, 4.0, 4.0, 5, 5);
+        let mut particles =
+            generators::generate_grid(&layout, generators::random_velocity_seeded(2.0, 1), 1);
+        for p in particles.iter_mut().step_by(3) {
+            *p = Particle::new(p.position, p.velocity, 2);
+        }
+        let velocities: Vec<Vec2> = particles.iter().map(|p| p.velocity).collect();
+        let relaxation = Relaxation::new(200, 1e-6);
+        let deepest = relax_overlaps(&mut particles, &classes, &relaxation);
+        assert!(deepest <= relaxation.tolerance);
+        for (i, p1) in particles.iter().enumerate() {
+            for p2 in &particles[i + 1..] {
+                assert!(overlap(p1, p2, &classes) <= relaxation.tolerance);
+            }
+        }
+        assert!(particles.iter().map(|p| p.velocity).eq(velocities));
+
+        // Iteration limit leaves some overlap
+        let mut particles =
+            generators::generate_grid(&layout, generators::constant_velocity(Vec2::ZERO), 1);
+        assert!(relax_overlaps(&mut particles, &classes, &Relaxation::new(0, 1e-6)) > 0.1);
+    }
+}
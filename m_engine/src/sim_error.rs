@@ -0,0 +1,32 @@
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Error of building a simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimError {
+    /// Particle, wall or rule references a class that isn't registered in the simulation
+    UnknownClass(ClassId),
+    /// Particle doesn't fit under the particle limit
+    ParticleLimit(usize),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimError::UnknownClass(id) => write!(f, "Unknown class id {}", id),
+            SimError::ParticleLimit(max) => write!(f, "Particle limit of {} is reached", max),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+/// Looks up the class in the map. Classes are validated when particles and walls
+/// are spawned, so a miss here is a bug. Panics with the offending id
+pub(crate) fn get_class<T>(classes: &HashMap<ClassId, T>, id: ClassId) -> &T {
+    match classes.get(&id) {
+        Some(class) => class,
+        None => panic!("{}", SimError::UnknownClass(id)),
+    }
+}
@@ -1,39 +1,258 @@
 use crate::prelude::*;
-use crate::{Particle, ParticleClass, Wall, WallClass};
-use std::collections::HashMap;
+use crate::sim_error::get_class;
+use crate::bond::{Bond, SpringParams};
+use crate::relaxation::{self, Relaxation};
+use crate::{
+    BoundaryCondition, Integrator, MutualGravity, Particle, Polygon, SimError, StepReport, Units, ParticleClass,
+    ParticlePairRule, ParticlePairRules, StepEnvironment, Vec2, Wall, WallClass,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Gravity acceleration as function of simulation time
+pub type GravityFn = Arc<dyn Fn(Duration) -> Vec2 + Send + Sync>;
+
+/// Called by `Simulation::step` with the simulation and the time at the end of the step
+pub type StepHook<'a> = dyn FnMut(&Simulation, Duration) + 'a;
+
+/// What happens to particles that don't fit under the particle limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum OverflowPolicy {
+    /// New particles are not added
+    #[default]
+    Reject,
+    /// Oldest particles, i.e. with the lowest ids, are removed to make room
+    RemoveOldest,
+}
+
+/// Exact runtime state of a simulation, for saving it and resuming later.
+/// Unlike `SimulationSpec` it doesn't describe how to generate the scene: particles keep
+/// their ids, velocities and overrides, walls keep their temperatures.
+/// Gravity is stored as its value at the snapshot time
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SimulationSnapshot {
+    /// Simulation time the state was taken at
+    pub time_sec: f64,
+    pub particle_classes: HashMap<ClassId, ParticleClass>,
+    /// Pairs of classes with own rule
+    #[serde(default)]
+    pub particle_pair_rules: Vec<(ClassId, ClassId, ParticlePairRule)>,
+    /// Pairs of classes with own coefficient of restitution
+    #[serde(default)]
+    pub restitutions: Vec<(ClassId, ClassId, f64)>,
+    pub particles: Vec<Particle>,
+    pub next_particle_id: ParticleId,
+    #[serde(default)]
+    pub bonds: Vec<Bond>,
+    pub wall_classes: HashMap<ClassId, WallClass>,
+    pub walls: Vec<Wall>,
+    pub gravity: Vec2,
+    #[serde(default)]
+    pub gravity_zones: Vec<(Polygon, Vec2)>,
+    #[serde(default)]
+    pub mutual_gravity: Option<MutualGravity>,
+    #[serde(default)]
+    pub boundary_condition: BoundaryCondition,
+    #[serde(default)]
+    pub units: Units,
+    #[serde(default)]
+    pub max_particles: Option<usize>,
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+}
 
 #[derive(Clone)]
 pub struct Simulation {
     particle_classes: HashMap<ClassId, ParticleClass>,
+    particle_pair_rules: ParticlePairRules,
     particles: Vec<Particle>,
+    next_particle_id: ParticleId,
+    bonds: Vec<Bond>,
     wall_classes: HashMap<ClassId, WallClass>,
     walls: Vec<Wall>,
-    gravity: f64
+    gravity: GravityFn,
+    // Regions with own gravity. The first zone that contains the particle wins
+    gravity_zones: Vec<(Polygon, Vec2)>,
+    mutual_gravity: Option<MutualGravity>,
+    boundary_condition: BoundaryCondition,
+    units: Units,
+    max_particles: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    // Recorded positions of tracked particles, one per step
+    trajectories: HashMap<ParticleId, Vec<Vec2>>,
+    // Impulse each wall received in the last recorded step
+    last_step_wall_impulses: Vec<f64>,
+    // Simulation time reached by `step`
+    time: Duration,
 }
 
 impl Simulation {
     pub fn new(
         particle_classes: HashMap<ClassId, ParticleClass>,
         wall_classes: HashMap<ClassId, WallClass>,
-        gravity: f64
+        gravity: Vec2,
     ) -> Self {
         Simulation {
             particle_classes,
+            particle_pair_rules: ParticlePairRules::new(),
             particles: Vec::new(),
+            next_particle_id: 0,
+            bonds: Vec::new(),
             wall_classes,
             walls: Vec::new(),
-            gravity,
+            gravity: Arc::new(move |_| gravity),
+            gravity_zones: Vec::new(),
+            mutual_gravity: None,
+            boundary_condition: BoundaryCondition::default(),
+            units: Units::default(),
+            max_particles: None,
+            overflow_policy: OverflowPolicy::default(),
+            trajectories: HashMap::new(),
+            last_step_wall_impulses: Vec::new(),
+            time: Duration::ZERO,
         }
     }
 
+    /// Creates simulation that resumes from the given state, e.g. from the particles and
+    /// walls of a displayed frame. Particles keep their ids
+    pub fn with_state(
+        particle_classes: HashMap<ClassId, ParticleClass>,
+        wall_classes: HashMap<ClassId, WallClass>,
+        gravity: GravityFn,
+        particles: Vec<Particle>,
+        walls: Vec<Wall>,
+    ) -> Self {
+        let mut simulation = Simulation::new(particle_classes, wall_classes, Vec2::ZERO);
+        simulation.set_gravity_fn(gravity);
+        simulation.restore_state(particles, walls);
+        return simulation;
+    }
+
+    /// Returns copy of this simulation with particles and walls replaced by the given state.
+    /// Classes, rules, bonds and gravity are kept
+    pub fn clone_state_at(&self, particles: &[Particle], walls: &[Wall]) -> Self {
+        let mut simulation = self.clone();
+        simulation.restore_state(particles.to_vec(), walls.to_vec());
+        return simulation;
+    }
+
+    /// Captures the state at the given simulation time. Time dependent gravity is stored
+    /// as its value at that time. Tracked trajectories are not captured
+    pub fn to_snapshot(&self, time: Duration) -> SimulationSnapshot {
+        let mut particle_pair_rules: Vec<_> = self.particle_pair_rules.rules().collect();
+        particle_pair_rules.sort_by_key(|&(class1, class2, _)| (class1, class2));
+        let mut restitutions: Vec<_> = self.particle_pair_rules.restitutions().collect();
+        restitutions.sort_by_key(|&(class1, class2, _)| (class1, class2));
+        return SimulationSnapshot {
+            time_sec: time.as_secs_f64(),
+            particle_classes: self.particle_classes.clone(),
+            particle_pair_rules,
+            restitutions,
+            particles: self.particles.clone(),
+            next_particle_id: self.next_particle_id,
+            bonds: self.bonds.clone(),
+            wall_classes: self.wall_classes.clone(),
+            walls: self.walls.clone(),
+            gravity: self.gravity_at(time),
+            gravity_zones: self.gravity_zones.clone(),
+            mutual_gravity: self.mutual_gravity,
+            boundary_condition: self.boundary_condition,
+            units: self.units,
+            max_particles: self.max_particles,
+            overflow_policy: self.overflow_policy,
+        };
+    }
+
+    /// Creates simulation from the saved state. Returns error if particles, walls
+    /// or rules reference classes that are missing in the snapshot
+    pub fn from_snapshot(snapshot: SimulationSnapshot) -> Result<Self, SimError> {
+        let mut simulation = Simulation::new(snapshot.particle_classes, snapshot.wall_classes, snapshot.gravity);
+        for &(class1, class2, rule) in &snapshot.particle_pair_rules {
+            simulation.check_particle_class(class1)?;
+            simulation.check_particle_class(class2)?;
+            simulation.set_particle_pair_rule(class1, class2, rule);
+        }
+        for &(class1, class2, restitution) in &snapshot.restitutions {
+            simulation.check_particle_class(class1)?;
+            simulation.check_particle_class(class2)?;
+            simulation.set_restitution(class1, class2, restitution);
+        }
+        for particle in &snapshot.particles {
+            simulation.check_particle_class(particle.class())?;
+        }
+        for wall in &snapshot.walls {
+            simulation.check_wall_class(wall.class())?;
+        }
+        simulation.bonds = snapshot.bonds;
+        simulation.gravity_zones = snapshot.gravity_zones;
+        simulation.mutual_gravity = snapshot.mutual_gravity;
+        simulation.boundary_condition = snapshot.boundary_condition;
+        simulation.units = snapshot.units;
+        simulation.max_particles = snapshot.max_particles;
+        simulation.overflow_policy = snapshot.overflow_policy;
+        simulation.restore_state(snapshot.particles, snapshot.walls);
+        simulation.time = Duration::from_secs_f64(snapshot.time_sec);
+        // Ids of removed particles are not reused either
+        simulation.next_particle_id = simulation.next_particle_id.max(snapshot.next_particle_id);
+        return Ok(simulation);
+    }
+
+    fn restore_state(&mut self, particles: Vec<Particle>, walls: Vec<Wall>) {
+        for particle in &particles {
+            panic_on_error(self.check_particle_class(particle.class()));
+        }
+        for wall in &walls {
+            panic_on_error(self.check_wall_class(wall.class()));
+        }
+        // Tracked particles start over from the restored state
+        for trajectory in self.trajectories.values_mut() {
+            trajectory.clear();
+        }
+        // New ids must not collide with the existing ones, nor with ids given out before
+        let max_id = particles.iter().filter_map(|p| p.id()).max();
+        if let Some(max_id) = max_id {
+            self.next_particle_id = self.next_particle_id.max(max_id + 1);
+        }
+        self.put_particles(particles);
+        self.walls = walls;
+        self.last_step_wall_impulses.clear();
+    }
+
     pub fn particle_classes(&self) -> &HashMap<ClassId, ParticleClass> {
         &self.particle_classes
     }
 
+    pub fn particle_pair_rules(&self) -> &ParticlePairRules {
+        &self.particle_pair_rules
+    }
+
+    /// Sets the rule for collisions between particles of given classes
+    pub fn set_particle_pair_rule(&mut self, class1: ClassId, class2: ClassId, rule: ParticlePairRule) {
+        panic_on_error(self.check_particle_class(class1));
+        panic_on_error(self.check_particle_class(class2));
+        self.particle_pair_rules.set(class1, class2, rule);
+    }
+
+    /// Sets the coefficient of restitution for bounces between particles of given classes.
+    /// It overrides the collision model of the integrator for this pair
+    pub fn set_restitution(&mut self, class1: ClassId, class2: ClassId, restitution: f64) {
+        panic_on_error(self.check_particle_class(class1));
+        panic_on_error(self.check_particle_class(class2));
+        self.particle_pair_rules.set_restitution(class1, class2, restitution);
+    }
+
     pub fn particles(&self) -> &[Particle] {
         &self.particles
     }
 
+    /// Pushes overlapping particles apart without changing their velocities.
+    /// Returns the deepest overlap that is left
+    pub fn relax_overlaps(&mut self, relaxation: &Relaxation) -> f64 {
+        return relaxation::relax_overlaps(&mut self.particles, &self.particle_classes, relaxation);
+    }
+
     pub fn particles_mut(&mut self) -> &mut [Particle] {
         &mut self.particles
     }
@@ -42,8 +261,108 @@ impl Simulation {
         std::mem::take(&mut self.particles)
     }
 
-    pub fn put_particles(&mut self, particles: Vec<Particle>) {
+    /// Puts particles back. Particles without id (i.e. produced during the step)
+    /// are given new ids. Particles over the limit are removed according to the policy
+    pub fn put_particles(&mut self, mut particles: Vec<Particle>) {
+        for particle in particles.iter_mut().filter(|p| p.id().is_none()) {
+            particle.set_id(Some(self.next_id()));
+        }
         self.particles = particles;
+        if let Some(max_particles) = self.max_particles {
+            if self.particles.len() > max_particles {
+                let excess = self.particles.len() - max_particles;
+                let newest = self.overflow_policy == OverflowPolicy::Reject;
+                self.remove_by_age(excess, newest);
+            }
+        }
+        if !self.trajectories.is_empty() {
+            for particle in &self.particles {
+                if let Some(trajectory) = particle.id().and_then(|id| self.trajectories.get_mut(&id)) {
+                    trajectory.push(particle.position);
+                }
+            }
+        }
+    }
+
+    /// Removes the particle at `index` and returns it. Panics if the index is out of range.
+    /// Particles after it move one index down, so indices taken before the call are stale.
+    /// Persistent ids don't change and are never given out again. Bonds of the particle are dropped
+    pub fn remove_particle(&mut self, index: usize) -> Particle {
+        let particle = self.particles.remove(index);
+        if let Some(id) = particle.id() {
+            self.bonds.retain(|(id1, id2, _)| *id1 != id && *id2 != id);
+        }
+        return particle;
+    }
+
+    /// Removes all particles matching the predicate and returns how many were removed.
+    /// Survivors keep their relative order, but their indices shift down as in `remove_particle`
+    pub fn remove_particles(&mut self, predicate: impl Fn(&Particle) -> bool) -> usize {
+        let count = self.particles.len();
+        let mut removed_ids = HashSet::new();
+        self.particles.retain(|p| {
+            if !predicate(p) {
+                return true;
+            }
+            removed_ids.extend(p.id());
+            return false;
+        });
+        if !removed_ids.is_empty() {
+            self.bonds.retain(|(id1, id2, _)| !removed_ids.contains(id1) && !removed_ids.contains(id2));
+        }
+        return count - self.particles.len();
+    }
+
+    /// Starts recording the position of the particle every time particles are put back
+    /// after the step. Current position is the first point. Recording stops when the
+    /// particle is removed
+    pub fn track_particle(&mut self, id: ParticleId) {
+        if self.trajectories.contains_key(&id) {
+            return;
+        }
+        let start = self.particles.iter().find(|p| p.id() == Some(id)).map(|p| p.position);
+        self.trajectories.insert(id, start.into_iter().collect());
+    }
+
+    /// Recorded positions of the tracked particle. None if the particle isn't tracked
+    pub fn tracked_trajectory(&self, id: ParticleId) -> Option<&[Vec2]> {
+        self.trajectories.get(&id).map(|t| t.as_slice())
+    }
+
+    /// Limits number of particles. Applies to spawning and to particles put back after the step
+    pub fn set_max_particles(&mut self, max_particles: Option<usize>, overflow_policy: OverflowPolicy) {
+        assert!(max_particles != Some(0));
+        self.max_particles = max_particles;
+        self.overflow_policy = overflow_policy;
+    }
+
+    pub fn max_particles(&self) -> Option<usize> {
+        self.max_particles
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    // Removes `count` particles with the lowest ids, or with the highest if `newest`
+    fn remove_by_age(&mut self, count: usize, newest: bool) {
+        let mut ids: Vec<ParticleId> = self.particles.iter().filter_map(|p| p.id()).collect();
+        ids.sort_unstable();
+        if newest {
+            ids.reverse();
+        }
+        let removed: HashSet<ParticleId> = ids.into_iter().take(count).collect();
+        self.remove_particles(|p| p.id().is_some_and(|id| removed.contains(&id)));
+    }
+
+    pub fn bonds(&self) -> &[Bond] {
+        &self.bonds
+    }
+
+    /// Connects 2 particles with a spring. Particles are referenced by persistent id
+    pub fn add_bond(&mut self, particle1: ParticleId, particle2: ParticleId, spring: SpringParams) {
+        assert!(particle1 != particle2);
+        self.bonds.push((particle1, particle2, spring));
     }
 
     pub fn wall_classes(&self) -> &HashMap<ClassId, WallClass> {
@@ -54,39 +373,234 @@ impl Simulation {
         &self.walls
     }
 
-    pub fn gravity(&self) -> f64 {
-        self.gravity
+    /// Gives walls the heat from the step report. Walls without heat capacity
+    /// keep their temperature
+    pub fn exchange_wall_heat(&mut self, wall_heat: &[f64]) {
+        for (wall, &heat) in self.walls.iter_mut().zip(wall_heat) {
+            wall.exchange_heat(get_class(&self.wall_classes, wall.class()), heat);
+        }
+    }
+
+    /// Keeps the impulses from the step report, i.e. `StepReport::wall_impulse`
+    pub fn record_wall_impulses(&mut self, wall_impulse: &[f64]) {
+        self.last_step_wall_impulses = wall_impulse.to_vec();
+        self.last_step_wall_impulses.resize(self.walls.len(), 0.0);
+    }
+
+    /// Magnitude of the impulse each wall received in the last recorded step, indexed as the
+    /// walls. Empty until a step is recorded
+    pub fn last_step_wall_impulses(&self) -> &[f64] {
+        &self.last_step_wall_impulses
+    }
+
+    /// Advances the simulation by `time_step` with the integrator. Walls get the heat and
+    /// record the impulses of the step. Then `on_step` is called with the simulation and
+    /// the time at the end of the step
+    pub fn step(
+        &mut self,
+        integrator: &dyn Integrator,
+        time_step: Duration,
+        on_step: Option<&mut StepHook>,
+    ) -> StepReport {
+        let mut particles = self.take_particles();
+        let report = integrator.step(&mut particles, &self.step_environment(), time_step);
+        self.put_particles(particles);
+        self.exchange_wall_heat(&report.wall_heat);
+        self.record_wall_impulses(&report.wall_impulse);
+        self.time += time_step;
+        if let Some(on_step) = on_step {
+            on_step(self, self.time);
+        }
+        return report;
+    }
+
+    /// Simulation time reached by `step`
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Classes, walls, forces and boundary the particles are stepped in, with the gravity
+    /// of the current simulation time
+    pub fn step_environment(&self) -> StepEnvironment<'_> {
+        StepEnvironment::new(&self.particle_classes, &self.particle_pair_rules, &self.walls, &self.wall_classes)
+            .with_bonds(&self.bonds)
+            .with_gravity(self.gravity_at(self.time))
+            .with_gravity_zones(&self.gravity_zones)
+            .with_mutual_gravity(self.mutual_gravity.as_ref())
+            .with_units(self.units)
+            .with_boundary(self.boundary_condition)
     }
 
-    pub fn spawn_particle(&mut self, particle: Particle) {
-        assert!(self.particle_classes.contains_key(&particle.class()));
+    /// Area of the bounding box of all walls. For chamber formed by thin walls
+    /// this is a good approximation of area available to particles.
+    /// Returns None if there are no walls
+    pub fn walls_bounding_area(&self) -> Option<f64> {
+        let mut points = self.walls.iter().flat_map(|w| w.polygon().points.iter());
+        let first = points.next()?;
+        let (mut min, mut max) = (*first, *first);
+        for p in points {
+            min = Vec2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vec2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        return Some((max.x - min.x) * (max.y - min.y));
+    }
+
+    /// Gravity acceleration at given simulation time
+    pub fn gravity_at(&self, time: Duration) -> Vec2 {
+        (self.gravity)(time)
+    }
+
+    /// Replaces constant gravity with time dependent one
+    pub fn set_gravity_fn(&mut self, gravity: GravityFn) {
+        self.gravity = gravity;
+    }
+
+    /// Regions where gravity differs from the global one, in the order of precedence
+    pub fn gravity_zones(&self) -> &[(Polygon, Vec2)] {
+        &self.gravity_zones
+    }
+
+    /// Adds region with constant gravity. Particles whose center is inside the zone get its
+    /// gravity instead of the global one. Zones added earlier take precedence where they overlap
+    pub fn add_gravity_zone(&mut self, zone: Polygon, gravity: Vec2) {
+        self.gravity_zones.push((zone, gravity));
+    }
+
+    /// Attraction between particles. None if disabled
+    pub fn mutual_gravity(&self) -> Option<&MutualGravity> {
+        self.mutual_gravity.as_ref()
+    }
+
+    pub fn set_mutual_gravity(&mut self, mutual_gravity: Option<MutualGravity>) {
+        self.mutual_gravity = mutual_gravity;
+    }
+
+    /// What happens to particles at the edges of the domain
+    pub fn boundary_condition(&self) -> BoundaryCondition {
+        self.boundary_condition
+    }
+
+    pub fn set_boundary_condition(&mut self, boundary_condition: BoundaryCondition) {
+        self.boundary_condition = boundary_condition;
+    }
+
+    /// Unit system used to convert between temperature and energy
+    pub fn units(&self) -> &Units {
+        &self.units
+    }
+
+    pub fn set_units(&mut self, units: Units) {
+        self.units = units;
+    }
+
+    /// Returns error if particle class with this id isn't registered
+    pub fn check_particle_class(&self, class: ClassId) -> Result<(), SimError> {
+        if !self.particle_classes.contains_key(&class) {
+            return Err(SimError::UnknownClass(class));
+        }
+        return Ok(());
+    }
+
+    /// Returns error if wall class with this id isn't registered
+    pub fn check_wall_class(&self, class: ClassId) -> Result<(), SimError> {
+        if !self.wall_classes.contains_key(&class) {
+            return Err(SimError::UnknownClass(class));
+        }
+        return Ok(());
+    }
+
+    /// Spawns particle and returns its persistent id. Panics if its class is unknown
+    pub fn spawn_particle(&mut self, particle: Particle) -> ParticleId {
+        return panic_on_error(self.try_spawn_particle(particle));
+    }
+
+    /// Spawns particle and returns its persistent id. Fails if the particle limit
+    /// is reached and the policy rejects new particles
+    pub fn try_spawn_particle(&mut self, mut particle: Particle) -> Result<ParticleId, SimError> {
+        self.check_particle_class(particle.class())?;
+        if let Some(max_particles) = self.max_particles {
+            if self.particles.len() >= max_particles {
+                match self.overflow_policy {
+                    OverflowPolicy::Reject => return Err(SimError::ParticleLimit(max_particles)),
+                    OverflowPolicy::RemoveOldest => {
+                        self.remove_by_age(self.particles.len() + 1 - max_particles, false);
+                    }
+                }
+            }
+        }
+        let id = self.next_id();
+        particle.set_id(Some(id));
         self.particles.push(particle);
+        return Ok(id);
     }
 
+    /// Panics if class of any particle is unknown
     pub fn spawn_particles(&mut self, particles: &[Particle]) {
-        assert!(particles
-            .iter()
-            .all(|p| self.particle_classes.contains_key(&p.class())));
-        self.particles.extend_from_slice(particles);
+        panic_on_error(self.try_spawn_particles(particles));
+    }
+
+    /// Spawns all particles, or none of them if class of any particle is unknown
+    /// or they don't fit under the particle limit that rejects new particles
+    pub fn try_spawn_particles(&mut self, particles: &[Particle]) -> Result<(), SimError> {
+        for particle in particles {
+            self.check_particle_class(particle.class())?;
+        }
+        if let Some(max_particles) = self.max_particles {
+            let rejects = self.overflow_policy == OverflowPolicy::Reject;
+            if rejects && self.particles.len() + particles.len() > max_particles {
+                return Err(SimError::ParticleLimit(max_particles));
+            }
+        }
+        for particle in particles {
+            self.try_spawn_particle(*particle)?;
+        }
+        return Ok(());
     }
 
+    fn next_id(&mut self) -> ParticleId {
+        let id = self.next_particle_id;
+        self.next_particle_id += 1;
+        return id;
+    }
+
+    /// Panics if wall class is unknown
     pub fn spawn_wall(&mut self, wall: Wall) {
-        assert!(self.wall_classes.contains_key(&wall.class()));
+        panic_on_error(self.try_spawn_wall(wall));
+    }
+
+    pub fn try_spawn_wall(&mut self, wall: Wall) -> Result<(), SimError> {
+        self.check_wall_class(wall.class())?;
         self.walls.push(wall);
+        return Ok(());
     }
 
+    /// Panics if class of any wall is unknown
     pub fn spawn_walls(&mut self, walls: &[Wall]) {
-        assert!(walls
-            .iter()
-            .all(|w| self.wall_classes.contains_key(&w.class())));
+        panic_on_error(self.try_spawn_walls(walls));
+    }
+
+    /// Spawns all walls, or none of them if class of any wall is unknown
+    pub fn try_spawn_walls(&mut self, walls: &[Wall]) -> Result<(), SimError> {
+        for wall in walls {
+            self.check_wall_class(wall.class())?;
+        }
         self.walls.extend_from_slice(walls);
+        return Ok(());
+    }
+}
+
+// Panicking API keeps the error message
+fn panic_on_error<T>(result: Result<T, SimError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => panic!("{}", e),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Polygon, Vec2};
+    use crate::{math_core, Polygon, Statistics, Vec2, VelocityVerletIntegrator};
 
     #[test]
     fn test_spawn_particles() {
@@ -94,7 +608,7 @@ mod tests {
         classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
         classes.insert(20, ParticleClass::new("Class20", 2.0, 1.0));
 
-        let mut simulation = Simulation::new(classes, HashMap::new(), 0.0);
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
 
         // Spawn single
         simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
@@ -110,6 +624,108 @@ mod tests {
         assert_eq!(simulation.particles()[0].class(), 1);
         assert_eq!(simulation.particles()[1].class(), 20);
         assert_eq!(simulation.particles()[2].class(), 1);
+        // Each particle gets unique id
+        assert_eq!(simulation.particles()[0].id(), Some(0));
+        assert_eq!(simulation.particles()[1].id(), Some(1));
+        assert_eq!(simulation.particles()[2].id(), Some(2));
+
+        // Particles that appear during the step get new ids
+        let mut particles = simulation.take_particles();
+        particles.push(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
+        simulation.put_particles(particles);
+        assert_eq!(simulation.particles()[3].id(), Some(3));
+    }
+
+    #[test]
+    fn test_with_state_matches_original() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Class1", 0.0, 0.0));
+        let mut simulation = Simulation::new(p_classes.clone(), w_classes.clone(), Vec2::new(0.0, -9.8));
+        simulation.spawn_wall(Wall::new(Polygon::new_rectangle(-10.0, -11.0, 10.0, -10.0), 1));
+        for i in 0..10 {
+            let position = Vec2::new(i as f64 * 2.0 - 9.0, (i % 3) as f64);
+            let velocity = Vec2::new((i % 4) as f64 - 1.5, (i % 5) as f64);
+            simulation.spawn_particle(Particle::new(position, velocity, 1));
+        }
+
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(10);
+        for _ in 0..20 {
+            simulation.step(&integrator, time_step, None);
+        }
+
+        // Copy state as the frame does and reconstruct
+        let frame_particles = simulation.particles().to_vec();
+        let frame_walls = simulation.walls().to_vec();
+        let mut reconstructed = Simulation::with_state(
+            p_classes,
+            w_classes,
+            Arc::new(|_| Vec2::new(0.0, -9.8)),
+            frame_particles.clone(),
+            frame_walls.clone(),
+        );
+        let mut cloned = simulation.clone_state_at(&frame_particles, &frame_walls);
+
+        for _ in 0..20 {
+            simulation.step(&integrator, time_step, None);
+            reconstructed.step(&integrator, time_step, None);
+            cloned.step(&integrator, time_step, None);
+        }
+        for other in [&reconstructed, &cloned] {
+            assert_eq!(simulation.particles().len(), other.particles().len());
+            for (p1, p2) in simulation.particles().iter().zip(other.particles()) {
+                assert_eq!(p1.id(), p2.id());
+                assert!(p1.approx_eq(p2, DOUBLE_COMPARE_EPS_STRICT, DOUBLE_COMPARE_EPS_STRICT));
+            }
+        }
+        // New particles don't reuse existing ids
+        assert_eq!(reconstructed.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1)), 10);
+    }
+
+    #[test]
+    fn test_clone_state_at_keeps_ids_unique() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        for i in 0..3 {
+            simulation.spawn_particle(Particle::new(Vec2::new(i as f64, 0.0), Vec2::ZERO, 1));
+        }
+        let frame_particles = simulation.particles().to_vec();
+        assert_eq!(simulation.spawn_particle(Particle::new(Vec2::new(5.0, 0.0), Vec2::ZERO, 1)), 3);
+
+        // Going back to the earlier frame doesn't give out the id of the later particle again
+        let mut cloned = simulation.clone_state_at(&frame_particles, &[]);
+        assert_eq!(cloned.particles().len(), 3);
+        assert_eq!(cloned.spawn_particle(Particle::new(Vec2::new(5.0, 0.0), Vec2::ZERO, 1)), 4);
+    }
+
+    #[test]
+    fn test_remove_particles() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        for i in 0..6 {
+            simulation.spawn_particle(Particle::new(Vec2::new(i as f64, 0.0), Vec2::ZERO, 1));
+        }
+        simulation.add_bond(1, 2, SpringParams::new(1.0, 1.0, 0.0));
+        simulation.add_bond(4, 5, SpringParams::new(1.0, 1.0, 0.0));
+
+        // Odd positions go away, the rest keep their order and ids
+        assert_eq!(simulation.remove_particles(|p| p.position.x as usize % 2 == 1), 3);
+        let ids: Vec<Option<ParticleId>> = simulation.particles().iter().map(|p| p.id()).collect();
+        assert_eq!(ids, vec![Some(0), Some(2), Some(4)]);
+        assert!(simulation.bonds().is_empty());
+
+        // Index of the next particle shifts down
+        let removed = simulation.remove_particle(1);
+        assert_eq!(removed.id(), Some(2));
+        assert_eq!(simulation.particles()[1].id(), Some(4));
+
+        // Ids of removed particles are not reused
+        let id = simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
+        assert_eq!(id, 6);
     }
 
     #[test]
@@ -120,7 +736,7 @@ mod tests {
 
         let polygon = Polygon::new_rectangle(0.0, 0.0, 1.0, 1.0);
 
-        let mut simulation = Simulation::new(HashMap::new(), classes, 0.0);
+        let mut simulation = Simulation::new(HashMap::new(), classes, Vec2::ZERO);
 
         // Spawn single
         simulation.spawn_wall(Wall::new(polygon.clone(), 1));
@@ -137,4 +753,285 @@ mod tests {
         assert_eq!(simulation.walls()[1].class(), 20);
         assert_eq!(simulation.walls()[2].class(), 1);
     }
+
+    #[test]
+    fn test_particle_limit() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        simulation.set_max_particles(Some(10), OverflowPolicy::RemoveOldest);
+
+        // Source emits 3 particles every step
+        let integrator = VelocityVerletIntegrator::new();
+        for step in 0..20 {
+            for i in 0..3 {
+                let position = Vec2::new(i as f64, step as f64);
+                simulation.spawn_particle(Particle::new(position, Vec2::new(0.0, 1.0), 1));
+            }
+            simulation.step(&integrator, Duration::from_millis(10), None);
+            assert_eq!(simulation.particles().len(), 10.min(3 * (step + 1)));
+        }
+        // The newest particles stay
+        let mut ids: Vec<ParticleId> = simulation.particles().iter().map(|p| p.id().unwrap()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (50..60).collect::<Vec<_>>());
+
+        // Rejecting policy keeps the old particles
+        simulation.set_max_particles(Some(10), OverflowPolicy::Reject);
+        let particle = Particle::new(Vec2::ZERO, Vec2::ZERO, 1);
+        assert_eq!(simulation.try_spawn_particle(particle), Err(SimError::ParticleLimit(10)));
+        let mut particles = simulation.take_particles();
+        particles.push(particle);
+        simulation.put_particles(particles);
+        assert_eq!(simulation.particles().len(), 10);
+        assert!(simulation.particles().iter().all(|p| p.id().unwrap() < 60));
+
+        // Bonds of particles removed by the limit go away with them
+        simulation.set_max_particles(Some(10), OverflowPolicy::RemoveOldest);
+        simulation.add_bond(50, 59, SpringParams::new(1.0, 1.0, 0.0));
+        simulation.add_bond(58, 59, SpringParams::new(1.0, 1.0, 0.0));
+        simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
+        assert_eq!(simulation.particles().len(), 10);
+        assert!(simulation.particles().iter().all(|p| p.id() != Some(50)));
+        assert_eq!(simulation.bonds().len(), 1);
+        assert_eq!((simulation.bonds()[0].0, simulation.bonds()[0].1), (58, 59));
+    }
+
+    #[test]
+    fn test_finite_heat_capacity_wall_cools() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Cold", 1.0, 0.2));
+        let mut w_classes = HashMap::new();
+        let heat_capacity = 2.0;
+        w_classes.insert(1, WallClass::new("Hot", 100.0, 0.5).with_heat_capacity(heat_capacity));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::ZERO);
+        simulation.spawn_walls(&Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1));
+        for i in 0..9 {
+            let position = Vec2::new((i % 3) as f64 * 2.0 - 2.0, (i / 3) as f64 * 2.0 - 2.0);
+            let velocity = Vec2::from_angle_rad(i as f64) * 3.0;
+            simulation.spawn_particle(Particle::new(position, velocity, 1));
+        }
+        // Kinetic energy of particles plus heat stored in walls
+        let total_energy = |simulation: &Simulation| {
+            let stats = Statistics::build(simulation.particles(), simulation.particle_classes(), simulation.units());
+            let class = &simulation.wall_classes()[&1];
+            let heat: f64 = simulation.walls().iter().map(|w| w.temperature(class) * heat_capacity).sum();
+            return stats.total_energy + heat;
+        };
+        let initial_energy = total_energy(&simulation);
+
+        let integrator = VelocityVerletIntegrator::new();
+        let mut temperatures = vec![];
+        for _ in 0..200 {
+            simulation.step(&integrator, Duration::from_millis(10), None);
+            let stats = Statistics::build_with_walls(
+                simulation.particles(),
+                simulation.particle_classes(),
+                simulation.units(),
+                simulation.walls(),
+                simulation.wall_classes(),
+            );
+            temperatures.push(stats.wall_temperatures[&1]);
+        }
+        // Wall temperature is sampled on each hit, so single hits may warm the wall up.
+        // But cold particles take heat away on average
+        assert!(*temperatures.last().unwrap() < 90.0);
+        // Heat is conserved
+        assert!(math_core::approx_eq(total_energy(&simulation), initial_energy, 1e-6 * initial_energy));
+    }
+
+    #[test]
+    fn test_wall_impulses_match_momentum_change() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 2.0, 0.5));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.0));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::ZERO);
+        simulation.spawn_walls(&Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1));
+        simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::new(7.0, 0.0), 1));
+        assert!(simulation.last_step_wall_impulses().is_empty());
+
+        let integrator = VelocityVerletIntegrator::new();
+        let mut total_impulses = vec![0.0; simulation.walls().len()];
+        let mut momentum_change = 0.0;
+        for _ in 0..300 {
+            let velocity_before = simulation.particles()[0].velocity;
+            simulation.step(&integrator, Duration::from_millis(10), None);
+            for (total, impulse) in total_impulses.iter_mut().zip(simulation.last_step_wall_impulses()) {
+                *total += impulse;
+            }
+            // Particle only hits walls. At most one per step at this speed
+            momentum_change += 2.0 * (simulation.particles()[0].velocity - velocity_before).length();
+        }
+
+        // Particle flies along x and bounces between the right and left walls, 2 * m * v each time
+        assert_eq!(simulation.last_step_wall_impulses().len(), 4);
+        assert!(momentum_change > 0.0);
+        let total: f64 = total_impulses.iter().sum();
+        assert!(math_core::approx_eq(total, momentum_change, 1e-9 * momentum_change));
+        assert!(math_core::approx_eq(total, 28.0 * (total / 28.0).round(), 1e-9));
+        assert_eq!(total_impulses[0], 0.0);
+        assert_eq!(total_impulses[2], 0.0);
+        assert!(total_impulses[1] > 0.0 && total_impulses[3] > 0.0);
+    }
+
+    #[test]
+    fn test_snapshot_resume() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.3));
+        p_classes.insert(2, ParticleClass::new("Heavy", 3.0, 0.5).with_gravity_scale(0.5));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.0));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::new(0.0, -1.0));
+        simulation.set_restitution(1, 2, 0.8);
+        simulation.set_particle_pair_rule(2, 2, ParticlePairRule::PassThrough);
+        simulation.spawn_walls(&Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1));
+        for i in 0..12 {
+            let position = Vec2::new(-4.0 + 0.7 * i as f64, 0.3 * (i % 3) as f64);
+            let velocity = Vec2::new(3.0 - 0.5 * i as f64, 2.0 - 0.3 * i as f64);
+            simulation.spawn_particle(Particle::new(position, velocity, 1 + i % 2));
+        }
+        simulation.add_bond(0, 2, SpringParams::new(1.0, 5.0, 0.1));
+        simulation.add_gravity_zone(Polygon::new_rectangle(0.0, 0.0, 5.0, 5.0), Vec2::new(0.0, 1.0));
+        simulation.remove_particle(11);
+
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(20);
+        let run = |simulation: &mut Simulation, steps: u32| {
+            for _ in 0..steps {
+                simulation.step(&integrator, time_step, None);
+            }
+        };
+
+        let mut resumed = simulation.clone();
+        run(&mut simulation, 20);
+        run(&mut resumed, 10);
+        let yaml = serde_yaml::to_string(&resumed.to_snapshot(time_step * 10)).unwrap();
+        let snapshot: SimulationSnapshot = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(snapshot.time_sec, 0.2);
+        assert_eq!(snapshot.restitutions, vec![(1, 2, 0.8)]);
+        let mut resumed = Simulation::from_snapshot(snapshot).unwrap();
+        run(&mut resumed, 10);
+
+        assert_eq!(resumed.particles().len(), simulation.particles().len());
+        for (p1, p2) in resumed.particles().iter().zip(simulation.particles()) {
+            assert_eq!(p1.id(), p2.id());
+            assert_eq!(p1.class(), p2.class());
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+        }
+        // Id of the removed particle is not given out again
+        assert_eq!(resumed.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1)), 12);
+    }
+
+    #[test]
+    fn test_step_hook() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.5));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.0));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::ZERO);
+        simulation.spawn_walls(&Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1));
+        simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::new(7.0, 0.0), 1));
+        // Gravity switches on after 0.5 s
+        simulation.set_gravity_fn(Arc::new(|time| {
+            if time >= Duration::from_millis(500) {
+                Vec2::new(0.0, -10.0)
+            } else {
+                Vec2::ZERO
+            }
+        }));
+
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(100);
+        let mut times = vec![];
+        let mut positions = vec![];
+        let mut on_step = |simulation: &Simulation, time: Duration| {
+            times.push(time);
+            positions.push(simulation.particles()[0].position);
+        };
+        let mut reports = vec![];
+        for _ in 0..10 {
+            reports.push(simulation.step(&integrator, time_step, Some(&mut on_step)));
+        }
+        // Without the hook the step is the same
+        simulation.step(&integrator, time_step, None);
+
+        let expected: Vec<Duration> = (1..=10).map(|i| time_step * i).collect();
+        assert_eq!(times, expected);
+        assert_eq!(simulation.time(), time_step * 11);
+        // Hook sees the state after the step
+        assert!(positions[0].approx_eq(Vec2::new(0.7, 0.0), DISTANCE_EPS));
+        // Gravity is taken at the time of the step start
+        assert!(positions[4].y == 0.0 && positions[5].y < 0.0);
+        // Wall impulses are recorded by the step
+        assert!(reports.iter().any(|r| r.collisions > 0));
+        assert_eq!(simulation.last_step_wall_impulses().len(), 4);
+    }
+
+    #[test]
+    fn test_snapshot_unknown_class() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.3));
+        let mut simulation = Simulation::new(p_classes, HashMap::new(), Vec2::ZERO);
+        simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
+        let mut snapshot = simulation.to_snapshot(Duration::ZERO);
+        snapshot.particle_classes.clear();
+        assert_eq!(Simulation::from_snapshot(snapshot).err(), Some(SimError::UnknownClass(1)));
+    }
+
+    #[test]
+    fn test_pass_through_pair() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.5));
+        p_classes.insert(2, ParticleClass::new("Ghost", 1.0, 0.5));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.5));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::ZERO);
+        simulation.set_particle_pair_rule(1, 2, ParticlePairRule::PassThrough);
+        simulation.spawn_walls(&Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1));
+        // Head-on. Without the rule they would bounce back at 0.5 s
+        simulation.spawn_particle(Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(3.0, 0.0), 2));
+        simulation.spawn_particle(Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-3.0, 0.0), 1));
+
+        let integrator = VelocityVerletIntegrator::new();
+        let mut run = |num_steps: usize| {
+            for _ in 0..num_steps {
+                simulation.step(&integrator, Duration::from_millis(10), None);
+            }
+            return simulation.particles().to_vec();
+        };
+        // Passed through each other untouched
+        let particles = run(100);
+        assert!(particles[0].position.approx_eq(Vec2::new(1.0, 0.0), 1e-9));
+        assert!(particles[1].position.approx_eq(Vec2::new(-1.0, 0.0), 1e-9));
+        // Walls still turn them back
+        let particles = run(150);
+        assert!(particles[0].velocity.x < 0.0);
+        assert!(particles[1].velocity.x > 0.0);
+        assert!(particles.iter().all(|p| p.position.x.abs() < 5.0));
+    }
+
+    #[test]
+    fn test_tracked_trajectory() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        let tracked = simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::new(10.0, 0.0), 1));
+        let other = simulation.spawn_particle(Particle::new(Vec2::new(3.0, 0.2), Vec2::ZERO, 1));
+        simulation.track_particle(tracked);
+        assert_eq!(simulation.tracked_trajectory(other), None);
+
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(50);
+        let mut path = vec![Vec2::ZERO];
+        for _ in 0..10 {
+            simulation.step(&integrator, time_step, None);
+            let particle = simulation.particles().iter().find(|p| p.id() == Some(tracked)).unwrap();
+            path.push(particle.position);
+        }
+        // The path bends at the collision with the other particle
+        assert!(path.last().unwrap().y.abs() > 0.0);
+        assert_eq!(simulation.tracked_trajectory(tracked), Some(path.as_slice()));
+    }
 }
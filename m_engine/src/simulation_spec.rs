@@ -1,9 +1,21 @@
 use crate::generators;
 use crate::{prelude::*, Vec2};
-use crate::{ParticleClass, Simulation, Wall, WallClass};
+use crate::{
+    AdaptiveTimeStep, EquilibriumCriterion, MutualGravity, OverflowPolicy, Particle, ParticleClass,
+    ParticlePairRule, Polygon, Relaxation, RestitutionCurve, SimError, Simulation, Units, Wall,
+    WallClass,
+};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
@@ -17,6 +29,26 @@ pub struct ParticleClassSpec {
     pub mass: f64,
     pub radius: f64,
     pub color: RGBA,
+    /// Cosmetic scale of the rendered particle. Doesn't affect the physics radius
+    #[serde(default = "default_render_scale")]
+    pub render_scale: f32,
+    /// Multiplier of gravity for this class. Negative values make particles float
+    #[serde(default = "default_gravity_scale")]
+    pub gravity_scale: f64,
+    /// Range of soft forces. Collision radius if not set
+    #[serde(default)]
+    pub interaction_cutoff: Option<f64>,
+    /// Image drawn instead of the flat circle. Path is relative to the assets folder
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+fn default_render_scale() -> f32 {
+    1.0
+}
+
+fn default_gravity_scale() -> f64 {
+    1.0
 }
 
 /// Describes specification for wall class
@@ -26,9 +58,50 @@ pub struct WallClassSpec {
     pub name: String,
     pub temperature: f64,
     pub heat_conductivity: f64,
+    /// Rough walls scatter particles diffusely
+    #[serde(default)]
+    pub diffuse_reflection: bool,
+    /// Absorbing walls remove particles that hit them
+    #[serde(default)]
+    pub absorbing: bool,
+    /// Restitution as function of the impact speed. Collision model decides if not present
+    #[serde(default)]
+    pub restitution: Option<RestitutionCurve>,
+    /// Energy per degree. Walls heat up and cool down while exchanging heat with particles.
+    /// Temperature stays constant if not present
+    #[serde(default)]
+    pub heat_capacity: Option<f64>,
     pub color: RGBA,
 }
 
+/// Describes the rule for collisions between two particle classes
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ParticlePairRuleSpec {
+    pub class_id1: ClassId,
+    pub class_id2: ClassId,
+    pub rule: ParticlePairRule,
+}
+
+/// Describes coefficient of restitution for bounces between two particle classes.
+/// The order of classes doesn't matter
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct RestitutionOverrideSpec {
+    pub class_id1: ClassId,
+    pub class_id2: ClassId,
+    pub value: f64,
+}
+
+/// How speeds of spawned particles are distributed
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VelocityDistribution {
+    /// Every particle moves at the mean speed in a random direction. See `generators::random_velocity`
+    #[default]
+    Uniform,
+    /// Maxwell-Boltzmann distribution with the mean speed of the grid
+    Maxwell,
+}
+
 /// Describes spawning of grid of particles
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SpawnParticlesGrid {
@@ -41,17 +114,72 @@ pub struct SpawnParticlesGrid {
     pub num_cells_x: usize,
     pub num_cells_y: usize,
     pub mean_speed: f64,
+    /// Directions are uniform either way
+    #[serde(default)]
+    pub velocity_distribution: VelocityDistribution,
 }
 
 /// Describes spawning of single wall
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SpawnStraightWall {
-    class_id: ClassId,
-    from_x: f64,
-    from_y: f64,
-    to_x: f64,
-    to_y: f64,
-    width: f64,
+    pub class_id: ClassId,
+    pub from_x: f64,
+    pub from_y: f64,
+    pub to_x: f64,
+    pub to_y: f64,
+    pub width: f64,
+}
+
+/// Describes spawning of single particle in exact state
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SpawnParticle {
+    pub class_id: ClassId,
+    pub x: f64,
+    pub y: f64,
+    pub vx: f64,
+    pub vy: f64,
+    /// Overrides the class mass, e.g. for particles produced by coalescence
+    #[serde(default)]
+    pub mass: Option<f64>,
+    /// Overrides the class radius
+    #[serde(default)]
+    pub radius: Option<f64>,
+}
+
+/// Describes spawning of wall of arbitrary shape. Points go counter-clockwise
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SpawnPolygonWall {
+    pub class_id: ClassId,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Describes region with own gravity. Unlike `gravity`, acceleration is a vector
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct GravityZoneSpec {
+    pub points: Vec<(f64, f64)>,
+    pub gravity_x: f64,
+    pub gravity_y: f64,
+}
+
+/// Describes gravity that changes linearly from `start` to `end` over `duration`
+/// and stays at `end` afterwards. Values are acceleration along the global gravity,
+/// see `SimulationSpec::gravity_at`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct GravityRamp {
+    pub start: f64,
+    pub end: f64,
+    pub duration: Duration,
+}
+
+impl GravityRamp {
+    /// Acceleration at given time
+    pub fn value_at(&self, time: Duration) -> f64 {
+        if time >= self.duration {
+            return self.end;
+        }
+        let t = time.as_secs_f64() / self.duration.as_secs_f64();
+        return self.start + (self.end - self.start) * t;
+    }
 }
 
 /// Describes the specification for the simulation scene that ought to be created
@@ -60,11 +188,89 @@ pub struct SimulationSpec {
     pub name: String,
     pub duration: Duration,
     pub time_step: Duration,
+    /// Overrides constant `time_step` if present
+    #[serde(default)]
+    pub adaptive_time_step: Option<AdaptiveTimeStep>,
+    /// Each step is split into this many equal parts. Collisions are resolved in each of them
+    #[serde(default = "default_substeps")]
+    pub substeps: usize,
+    /// Particles move along the x-axis only (1D mode), so their collisions are head-on
+    #[serde(default)]
+    pub line_motion: bool,
+    /// Downward acceleration. Ignored if `gravity_x` or `gravity_y` is present
+    #[serde(default)]
     pub gravity: f64,
+    /// Global gravity as a vector, e.g. for a tilted rig. If only one of the components
+    /// is present, the other one is zero
+    #[serde(default)]
+    pub gravity_x: Option<f64>,
+    #[serde(default)]
+    pub gravity_y: Option<f64>,
+    /// Overrides constant gravity if present. Acts along the direction of the constant gravity
+    #[serde(default)]
+    pub gravity_ramp: Option<GravityRamp>,
+    /// Regions where gravity differs from the global one. First matching zone wins
+    #[serde(default)]
+    pub gravity_zones: Vec<GravityZoneSpec>,
+    #[serde(default)]
     pub particle_classes: Vec<ParticleClassSpec>,
+    #[serde(default)]
     pub wall_classes: Vec<WallClassSpec>,
+    #[serde(default)]
+    pub particle_pair_rules: Vec<ParticlePairRuleSpec>,
+    /// Own coefficients of restitution of particle class pairs. Other pairs bounce as the collision
+    /// model of the integrator decides
+    #[serde(default)]
+    pub restitution_overrides: Vec<RestitutionOverrideSpec>,
+    /// Attraction between particles. Disabled if not present
+    #[serde(default)]
+    pub mutual_gravity: Option<MutualGravity>,
+    /// Unit system. Defaults to Boltzmann constant of 1
+    #[serde(default)]
+    pub units: Units,
+    /// Statistics are computed every this many frames. Frames in between reuse
+    /// the most recent statistics
+    #[serde(default = "default_statistics_interval")]
+    pub statistics_interval: usize,
+    /// Overlapping particles are pushed apart before the first frame. Disabled if not present
+    #[serde(default)]
+    pub relaxation: Option<Relaxation>,
+    /// Generation stops early once statistics settle. Runs for the full duration if not present
+    #[serde(default)]
+    pub equilibrium: Option<EquilibriumCriterion>,
+    /// Number of independent runs of this spec. Members differ by random initial state
+    #[serde(default = "default_ensemble_size")]
+    pub ensemble_size: usize,
+    /// Seed of the random initial velocities and of the thermal walls, so the run can be
+    /// reproduced. Ensemble members get their own seeds derived from it, see `member_spec`.
+    /// Every run is different if not present
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Particles over this number are handled by `particle_overflow`. Unlimited if not present
+    #[serde(default)]
+    pub max_particles: Option<usize>,
+    #[serde(default)]
+    pub particle_overflow: OverflowPolicy,
+    #[serde(default)]
     pub particle_grids: Vec<SpawnParticlesGrid>,
+    #[serde(default)]
     pub straight_walls: Vec<SpawnStraightWall>,
+    #[serde(default)]
+    pub particles: Vec<SpawnParticle>,
+    #[serde(default)]
+    pub polygon_walls: Vec<SpawnPolygonWall>,
+}
+
+fn default_ensemble_size() -> usize {
+    1
+}
+
+fn default_statistics_interval() -> usize {
+    1
+}
+
+fn default_substeps() -> usize {
+    1
 }
 
 impl Default for SimulationSpec {
@@ -73,47 +279,710 @@ impl Default for SimulationSpec {
             name: "Unnamed".to_string(),
             duration: Duration::from_secs(10),
             time_step: Duration::from_millis(10),
+            adaptive_time_step: None,
+            substeps: 1,
+            line_motion: false,
             gravity: 0.0,
+            gravity_x: None,
+            gravity_y: None,
+            gravity_ramp: None,
+            gravity_zones: Vec::new(),
             particle_classes: Vec::new(),
             wall_classes: Vec::new(),
+            particle_pair_rules: Vec::new(),
+            restitution_overrides: Vec::new(),
+            mutual_gravity: None,
+            units: Units::default(),
+            statistics_interval: 1,
+            relaxation: None,
+            equilibrium: None,
+            ensemble_size: 1,
+            seed: None,
+            max_particles: None,
+            particle_overflow: OverflowPolicy::default(),
             particle_grids: Vec::new(),
             straight_walls: Vec::new(),
+            particles: Vec::new(),
+            polygon_walls: Vec::new(),
+        }
+    }
+}
+
+/// Error of loading or saving the spec file
+#[derive(Debug)]
+pub enum SpecFileError {
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+}
+
+impl fmt::Display for SpecFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecFileError::Io(e) => write!(f, "Error reading file: {}", e),
+            SpecFileError::Yaml(e) => write!(f, "Error loading YAML simulation file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SpecFileError {}
+
+impl From<io::Error> for SpecFileError {
+    fn from(e: io::Error) -> Self {
+        SpecFileError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for SpecFileError {
+    fn from(e: serde_yaml::Error) -> Self {
+        SpecFileError::Yaml(e)
+    }
+}
+
+/// Error of merging two specs
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpecMergeError {
+    /// Both specs declare the particle class with different definitions
+    ConflictingParticleClass(ClassId),
+    /// Both specs declare the wall class with different definitions
+    ConflictingWallClass(ClassId),
+}
+
+impl fmt::Display for SpecMergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecMergeError::ConflictingParticleClass(id) => {
+                write!(
+                    f,
+                    "Particle class {} is defined differently in merged specs",
+                    id
+                )
+            }
+            SpecMergeError::ConflictingWallClass(id) => {
+                write!(
+                    f,
+                    "Wall class {} is defined differently in merged specs",
+                    id
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecMergeError {}
+
+// Derives a seed for the item with the given index. Consecutive seeds and indices
+// give unrelated sequences
+fn mix_seed(seed: u64, index: u64) -> u64 {
+    return seed ^ index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+}
+
+// Files with this extension are gzip compressed
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Problem found in the spec by `SimulationSpec::validate` or `SimulationSpec::merge`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpecDiagnostic {
+    /// Fastest particle moves further than the wall width in one step,
+    /// so it may pass through the wall
+    TunnelingLikely {
+        wall_index: usize,
+        width: f64,
+        step_displacement: f64,
+    },
+    /// Time step (or minimal adaptive step) is zero. Simulation would never advance
+    ZeroTimeStep,
+    /// Number of substeps is zero
+    ZeroSubsteps,
+    /// Simulation is shorter than a single step. Only the initial frame is produced
+    DurationShorterThanTimeStep {
+        duration: Duration,
+        time_step: Duration,
+    },
+    /// Particle limit is zero. No particle could be spawned
+    ZeroParticleLimit,
+    /// Restitution curve of the wall class is out of [0, 1] or its points are not sorted
+    InvalidRestitution { class_id: ClassId },
+    /// Heat capacity of the wall class isn't positive
+    InvalidHeatCapacity { class_id: ClassId },
+    /// Restitution of the particle class pair is out of [0, 1]
+    InvalidPairRestitution {
+        class_id1: ClassId,
+        class_id2: ClassId,
+    },
+    /// Fragment rule of the particle class pair breaks particles into less than 2 fragments
+    TooFewFragments {
+        class_id1: ClassId,
+        class_id2: ClassId,
+        num_fragments: usize,
+    },
+    /// Equilibrium window is shorter than 2 samples or tolerance is negative
+    InvalidEquilibrium,
+    /// Merged specs give different values of the setting. The last one is used
+    MergedValueOverridden { field: &'static str },
+    /// Both downward `gravity` and its vector components are given. The vector is used
+    ScalarGravityIgnored,
+}
+
+impl SpecDiagnostic {
+    /// Errors make the spec unusable. Other diagnostics are warnings
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            SpecDiagnostic::ZeroTimeStep
+                | SpecDiagnostic::ZeroSubsteps
+                | SpecDiagnostic::ZeroParticleLimit
+                | SpecDiagnostic::InvalidRestitution { .. }
+                | SpecDiagnostic::InvalidHeatCapacity { .. }
+                | SpecDiagnostic::InvalidPairRestitution { .. }
+                | SpecDiagnostic::TooFewFragments { .. }
+                | SpecDiagnostic::InvalidEquilibrium
+        )
+    }
+}
+
+impl fmt::Display for SpecDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecDiagnostic::TunnelingLikely {
+                wall_index,
+                width,
+                step_displacement,
+            } => write!(
+                f,
+                "Warning: straight wall {} is {} wide, but particles may move up to {} per step. \
+                Tunneling is likely. Consider a smaller time step or substepping",
+                wall_index, width, step_displacement
+            ),
+            SpecDiagnostic::ZeroTimeStep => write!(f, "Error: time step must be positive"),
+            SpecDiagnostic::ZeroSubsteps => write!(f, "Error: substeps must be positive"),
+            SpecDiagnostic::DurationShorterThanTimeStep {
+                duration,
+                time_step,
+            } => write!(
+                f,
+                "Warning: duration {:?} is shorter than time step {:?}. \
+                Only the initial frame will be produced",
+                duration, time_step
+            ),
+            SpecDiagnostic::ZeroParticleLimit => write!(f, "Error: max_particles must be positive"),
+            SpecDiagnostic::InvalidRestitution { class_id } => write!(
+                f,
+                "Error: restitution of wall class {} must be in [0, 1] with points sorted by speed",
+                class_id
+            ),
+            SpecDiagnostic::InvalidHeatCapacity { class_id } => write!(
+                f,
+                "Error: heat capacity of wall class {} must be positive",
+                class_id
+            ),
+            SpecDiagnostic::InvalidPairRestitution {
+                class_id1,
+                class_id2,
+            } => write!(
+                f,
+                "Error: restitution of particle classes {} and {} must be in [0, 1]",
+                class_id1, class_id2
+            ),
+            SpecDiagnostic::TooFewFragments {
+                class_id1,
+                class_id2,
+                num_fragments,
+            } => write!(
+                f,
+                "Error: particle classes {} and {} must break into at least 2 fragments, not {}",
+                class_id1, class_id2, num_fragments
+            ),
+            SpecDiagnostic::InvalidEquilibrium => write!(
+                f,
+                "Error: equilibrium window must be at least 2 samples with non-negative tolerance"
+            ),
+            SpecDiagnostic::MergedValueOverridden { field } => write!(
+                f,
+                "Warning: merged specs have different {}. The value of the last one is used",
+                field
+            ),
+            SpecDiagnostic::ScalarGravityIgnored => write!(
+                f,
+                "Warning: gravity is ignored because gravity_x or gravity_y is given"
+            ),
         }
     }
 }
 
 impl SimulationSpec {
+    /// Checks the spec for likely problems. Returns empty list if none were found
+    pub fn validate(&self) -> Vec<SpecDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        // Durations can't be negative, but they can be zero
+        let shortest_step = match &self.adaptive_time_step {
+            Some(adaptive) => adaptive.min,
+            None => self.time_step,
+        };
+        if shortest_step.is_zero() {
+            diagnostics.push(SpecDiagnostic::ZeroTimeStep);
+        } else if self.duration < shortest_step {
+            diagnostics.push(SpecDiagnostic::DurationShorterThanTimeStep {
+                duration: self.duration,
+                time_step: shortest_step,
+            });
+        }
+
+        if self.substeps == 0 {
+            diagnostics.push(SpecDiagnostic::ZeroSubsteps);
+        }
+
+        if self.max_particles == Some(0) {
+            diagnostics.push(SpecDiagnostic::ZeroParticleLimit);
+        }
+
+        for class in &self.wall_classes {
+            if class
+                .restitution
+                .as_ref()
+                .is_some_and(|curve| !curve.is_valid())
+            {
+                diagnostics.push(SpecDiagnostic::InvalidRestitution { class_id: class.id });
+            }
+            if class
+                .heat_capacity
+                .is_some_and(|c| !(c > 0.0 && c.is_finite()))
+            {
+                diagnostics.push(SpecDiagnostic::InvalidHeatCapacity { class_id: class.id });
+            }
+        }
+
+        for restitution in &self.restitution_overrides {
+            if !(0.0..=1.0).contains(&restitution.value) {
+                diagnostics.push(SpecDiagnostic::InvalidPairRestitution {
+                    class_id1: restitution.class_id1,
+                    class_id2: restitution.class_id2,
+                });
+            }
+        }
+
+        for pair_rule in &self.particle_pair_rules {
+            if let ParticlePairRule::Fragment { num_fragments, .. } = pair_rule.rule {
+                if num_fragments < 2 {
+                    diagnostics.push(SpecDiagnostic::TooFewFragments {
+                        class_id1: pair_rule.class_id1,
+                        class_id2: pair_rule.class_id2,
+                        num_fragments,
+                    });
+                }
+            }
+        }
+
+        if self
+            .equilibrium
+            .is_some_and(|criterion| !criterion.is_valid())
+        {
+            diagnostics.push(SpecDiagnostic::InvalidEquilibrium);
+        }
+
+        let has_gravity_vector = self.gravity_x.is_some() || self.gravity_y.is_some();
+        if has_gravity_vector && self.gravity != 0.0 {
+            diagnostics.push(SpecDiagnostic::ScalarGravityIgnored);
+        }
+
+        // `random_velocity` always gives the mean speed. Maxwell speeds exceed 4 times
+        // the mean with probability of about 4e-6
+        let max_speed = self
+            .particle_grids
+            .iter()
+            .map(|grid| match grid.velocity_distribution {
+                VelocityDistribution::Uniform => grid.mean_speed,
+                VelocityDistribution::Maxwell => 4.0 * grid.mean_speed,
+            })
+            .chain(
+                self.particles
+                    .iter()
+                    .map(|p| Vec2::new(p.vx, p.vy).length()),
+            )
+            .fold(0.0, f64::max);
+        // Adaptive step never exceeds its max. Particles move straight within a substep
+        let longest_step = match &self.adaptive_time_step {
+            Some(adaptive) => adaptive.max,
+            None => self.time_step,
+        };
+        let step_displacement =
+            max_speed * longest_step.as_secs_f64() / self.substeps.max(1) as f64;
+        for (wall_index, wall) in self.straight_walls.iter().enumerate() {
+            if wall.width < step_displacement {
+                diagnostics.push(SpecDiagnostic::TunnelingLikely {
+                    wall_index,
+                    width: wall.width,
+                    step_displacement,
+                });
+            }
+        }
+        return diagnostics;
+    }
+
     pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
         serde_yaml::from_str(yaml)
     }
 
-    pub fn build(&self) -> Simulation {
-        // Make particle classes map
+    /// Reads the spec from YAML file. Files ending with `.gz` (e.g. `scene.yaml.gz`)
+    /// are decompressed transparently
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, SpecFileError> {
+        let path = path.as_ref();
+        let mut yaml = String::new();
+        if is_gzip_path(path) {
+            GzDecoder::new(File::open(path)?).read_to_string(&mut yaml)?;
+        } else {
+            File::open(path)?.read_to_string(&mut yaml)?;
+        }
+        return Ok(Self::from_yaml(&yaml)?);
+    }
+
+    /// Writes the spec to YAML file. Compresses it if path ends with `.gz`
+    pub fn to_path(&self, path: impl AsRef<Path>) -> Result<(), SpecFileError> {
+        let path = path.as_ref();
+        let yaml = serde_yaml::to_string(self)?;
+        if is_gzip_path(path) {
+            let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+            encoder.write_all(yaml.as_bytes())?;
+            encoder.finish()?;
+        } else {
+            File::create(path)?.write_all(yaml.as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    /// Appends the scene of `other` to this one, e.g. particles from one file to walls from another.
+    /// Classes declared in both must be identical. Objects, zones and pair rules are concatenated.
+    /// Settings of `other` win if present: optional ones if set, others if not default.
+    /// Duration and time step are always taken from `other`, with a warning if they differ.
+    /// Name of this spec is kept
+    pub fn merge(&mut self, other: SimulationSpec) -> Result<Vec<SpecDiagnostic>, SpecMergeError> {
+        // Check all classes first, so nothing is changed on error
+        for class in &other.particle_classes {
+            if self
+                .particle_classes
+                .iter()
+                .any(|c| c.id == class.id && c != class)
+            {
+                return Err(SpecMergeError::ConflictingParticleClass(class.id));
+            }
+        }
+        for class in &other.wall_classes {
+            if self
+                .wall_classes
+                .iter()
+                .any(|c| c.id == class.id && c != class)
+            {
+                return Err(SpecMergeError::ConflictingWallClass(class.id));
+            }
+        }
+        for class in other.particle_classes {
+            if !self.particle_classes.contains(&class) {
+                self.particle_classes.push(class);
+            }
+        }
+        for class in other.wall_classes {
+            if !self.wall_classes.contains(&class) {
+                self.wall_classes.push(class);
+            }
+        }
+
+        let mut diagnostics = Vec::new();
+        if self.duration != other.duration {
+            diagnostics.push(SpecDiagnostic::MergedValueOverridden { field: "duration" });
+            self.duration = other.duration;
+        }
+        if self.time_step != other.time_step {
+            diagnostics.push(SpecDiagnostic::MergedValueOverridden { field: "time_step" });
+            self.time_step = other.time_step;
+        }
+
+        let defaults = SimulationSpec::default();
+        if other.gravity != defaults.gravity {
+            self.gravity = other.gravity;
+        }
+        if other.gravity_x.is_some() || other.gravity_y.is_some() {
+            self.gravity_x = other.gravity_x;
+            self.gravity_y = other.gravity_y;
+        }
+        if other.units != defaults.units {
+            self.units = other.units;
+        }
+        if other.substeps != defaults.substeps {
+            self.substeps = other.substeps;
+        }
+        if other.line_motion != defaults.line_motion {
+            self.line_motion = other.line_motion;
+        }
+        if other.statistics_interval != defaults.statistics_interval {
+            self.statistics_interval = other.statistics_interval;
+        }
+        if other.ensemble_size != defaults.ensemble_size {
+            self.ensemble_size = other.ensemble_size;
+        }
+        if other.particle_overflow != defaults.particle_overflow {
+            self.particle_overflow = other.particle_overflow;
+        }
+        if other.adaptive_time_step.is_some() {
+            self.adaptive_time_step = other.adaptive_time_step;
+        }
+        if other.gravity_ramp.is_some() {
+            self.gravity_ramp = other.gravity_ramp;
+        }
+        if other.mutual_gravity.is_some() {
+            self.mutual_gravity = other.mutual_gravity;
+        }
+        if other.relaxation.is_some() {
+            self.relaxation = other.relaxation;
+        }
+        if other.equilibrium.is_some() {
+            self.equilibrium = other.equilibrium;
+        }
+        if other.max_particles.is_some() {
+            self.max_particles = other.max_particles;
+        }
+        if other.seed.is_some() {
+            self.seed = other.seed;
+        }
+
+        self.gravity_zones.extend(other.gravity_zones);
+        self.particle_pair_rules.extend(other.particle_pair_rules);
+        self.restitution_overrides
+            .extend(other.restitution_overrides);
+        self.particle_grids.extend(other.particle_grids);
+        self.straight_walls.extend(other.straight_walls);
+        self.particles.extend(other.particles);
+        self.polygon_walls.extend(other.polygon_walls);
+        return Ok(diagnostics);
+    }
+
+    /// Constant global gravity. Vector components take precedence over downward `gravity`
+    pub fn gravity_vector(&self) -> Vec2 {
+        if self.gravity_x.is_none() && self.gravity_y.is_none() {
+            return Vec2::new(0.0, -self.gravity);
+        }
+        return Vec2::new(self.gravity_x.unwrap_or(0.0), self.gravity_y.unwrap_or(0.0));
+    }
+
+    // Direction the gravity ramp acts along. Scalar gravity and zero vector point down
+    fn gravity_direction(&self) -> Vec2 {
+        let down = Vec2::new(0.0, -1.0);
+        if self.gravity_x.is_none() && self.gravity_y.is_none() {
+            return down;
+        }
+        return self.gravity_vector().normalized().unwrap_or(down);
+    }
+
+    /// Global gravity at given time, ramp included
+    pub fn gravity_at(&self, time: Duration) -> Vec2 {
+        return match &self.gravity_ramp {
+            Some(ramp) => self.gravity_direction() * ramp.value_at(time),
+            None => self.gravity_vector(),
+        };
+    }
+
+    /// Spec of the ensemble member with the given index. Members of a seeded spec get
+    /// distinct seeds. Unseeded members are random anyway
+    pub fn member_spec(&self, member: usize) -> SimulationSpec {
+        let mut spec = self.clone();
+        spec.seed = self.seed.map(|seed| mix_seed(seed, member as u64));
+        spec.ensemble_size = 1;
+        return spec;
+    }
+
+    /// Makes particle classes map
+    pub fn build_particle_classes(&self) -> HashMap<ClassId, ParticleClass> {
         let mut p_classes = HashMap::new();
         for class in &self.particle_classes {
-            let p_class = ParticleClass::new(&class.name, class.mass, class.radius);
+            let mut p_class = ParticleClass::new(&class.name, class.mass, class.radius)
+                .with_gravity_scale(class.gravity_scale);
+            if let Some(cutoff) = class.interaction_cutoff {
+                p_class = p_class.with_interaction_cutoff(cutoff);
+            }
             p_classes.insert(class.id, p_class);
         }
-        // Make wall classes map
+        return p_classes;
+    }
+
+    /// Makes wall classes map
+    pub fn build_wall_classes(&self) -> HashMap<ClassId, WallClass> {
         let mut w_classes = HashMap::new();
         for class in &self.wall_classes {
-            let w_class = WallClass::new(&class.name, class.temperature, class.heat_conductivity);
+            let w_class = WallClass::new(&class.name, class.temperature, class.heat_conductivity)
+                .with_diffuse_reflection(class.diffuse_reflection)
+                .with_absorbing(class.absorbing);
+            let w_class = match &class.restitution {
+                Some(curve) => w_class.with_restitution(curve.clone()),
+                None => w_class,
+            };
+            let w_class = match class.heat_capacity {
+                Some(heat_capacity) => w_class.with_heat_capacity(heat_capacity),
+                None => w_class,
+            };
             w_classes.insert(class.id, w_class);
         }
+        return w_classes;
+    }
+
+    /// Returns copy of the spec that starts from the given state instead of the spawn
+    /// entries. Classes, rules and the rest of settings are kept
+    pub fn snapshot(&self, particles: &[Particle], walls: &[Wall]) -> SimulationSpec {
+        let mut spec = self.clone();
+        spec.particle_grids.clear();
+        spec.straight_walls.clear();
+        // State is exact, members would be the same
+        spec.ensemble_size = 1;
+        spec.particles = particles
+            .iter()
+            .map(|p| {
+                let class = self.particle_classes.iter().find(|c| c.id == p.class());
+                // Only values that differ from the class are stored
+                let (mass, radius) = match class {
+                    Some(c) => {
+                        let p_class = ParticleClass::new(&c.name, c.mass, c.radius);
+                        let mass = p.mass(&p_class);
+                        let radius = p.radius(&p_class);
+                        (
+                            if mass != c.mass { Some(mass) } else { None },
+                            if radius != c.radius {
+                                Some(radius)
+                            } else {
+                                None
+                            },
+                        )
+                    }
+                    None => (None, None),
+                };
+                SpawnParticle {
+                    class_id: p.class(),
+                    x: p.position.x,
+                    y: p.position.y,
+                    vx: p.velocity.x,
+                    vy: p.velocity.y,
+                    mass,
+                    radius,
+                }
+            })
+            .collect();
+        spec.polygon_walls = walls
+            .iter()
+            .map(|w| SpawnPolygonWall {
+                class_id: w.class(),
+                points: w.polygon().points.iter().map(|p| (p.x, p.y)).collect(),
+            })
+            .collect();
+        return spec;
+    }
+
+    /// Panics if the spec references unknown classes. See `try_build`
+    pub fn build(&self) -> Simulation {
+        match self.try_build() {
+            Ok(sim) => sim,
+            Err(e) => panic!("Invalid simulation spec: {}", e),
+        }
+    }
 
-        let mut sim = Simulation::new(p_classes, w_classes, self.gravity);
-        // Spawn grids
-        for grid in &self.particle_grids {
-            sim.spawn_particles(&generators::generate_grid(
+    /// Fails if particles, walls or rules reference classes that aren't declared in the spec.
+    /// Particles are spawned in a fixed order: grids as listed, each in `generate_grid` order,
+    /// then explicit particles as listed
+    pub fn try_build(&self) -> Result<Simulation, SimError> {
+        let mut sim = Simulation::new(
+            self.build_particle_classes(),
+            self.build_wall_classes(),
+            self.gravity_vector(),
+        );
+        if let Some(ramp) = &self.gravity_ramp {
+            let ramp = ramp.clone();
+            let direction = self.gravity_direction();
+            sim.set_gravity_fn(Arc::new(move |time| direction * ramp.value_at(time)));
+        }
+        for zone in &self.gravity_zones {
+            let points = zone.points.iter().map(|&(x, y)| Vec2::new(x, y)).collect();
+            sim.add_gravity_zone(
+                Polygon { points },
+                Vec2::new(zone.gravity_x, zone.gravity_y),
+            );
+        }
+        for rule in &self.particle_pair_rules {
+            sim.check_particle_class(rule.class_id1)?;
+            sim.check_particle_class(rule.class_id2)?;
+            sim.set_particle_pair_rule(rule.class_id1, rule.class_id2, rule.rule);
+        }
+        for restitution in &self.restitution_overrides {
+            sim.check_particle_class(restitution.class_id1)?;
+            sim.check_particle_class(restitution.class_id2)?;
+            sim.set_restitution(
+                restitution.class_id1,
+                restitution.class_id2,
+                restitution.value,
+            );
+        }
+        sim.set_mutual_gravity(self.mutual_gravity);
+        sim.set_units(self.units);
+        sim.set_max_particles(self.max_particles, self.particle_overflow);
+        // Spawn grids. Each grid of a seeded spec has its own sequence of velocities
+        for (index, grid) in self.particle_grids.iter().enumerate() {
+            sim.check_particle_class(grid.class_id)?;
+            let velocity: Box<dyn Fn(Vec2) -> Vec2> = match (grid.velocity_distribution, self.seed)
+            {
+                (VelocityDistribution::Uniform, Some(seed)) => {
+                    Box::new(generators::random_velocity_seeded(
+                        grid.mean_speed,
+                        mix_seed(seed, index as u64),
+                    ))
+                }
+                (VelocityDistribution::Uniform, None) => {
+                    Box::new(generators::random_velocity(grid.mean_speed))
+                }
+                (VelocityDistribution::Maxwell, seed) => {
+                    let mass = sim.particle_classes()[&grid.class_id].mass();
+                    let temperature = generators::maxwell_boltzmann_temperature(
+                        grid.mean_speed,
+                        mass,
+                        &self.units,
+                    );
+                    match seed {
+                        Some(seed) => Box::new(generators::maxwell_boltzmann_velocity_seeded(
+                            temperature,
+                            mass,
+                            &self.units,
+                            mix_seed(seed, index as u64),
+                        )),
+                        None => Box::new(generators::maxwell_boltzmann_velocity(
+                            temperature,
+                            mass,
+                            &self.units,
+                        )),
+                    }
+                }
+            };
+            let layout = generators::GridLayout::new(
                 Vec2::new(grid.origin_x, grid.origin_y),
                 Vec2::from_angle_rad(grid.x_axis_angle.to_radians()),
                 grid.dim_x,
                 grid.dim_y,
                 grid.num_cells_x,
                 grid.num_cells_y,
-                generators::random_velocity(grid.mean_speed),
-                grid.class_id,
-            ));
+            );
+            sim.try_spawn_particles(&generators::generate_grid(&layout, velocity, grid.class_id))?;
+        }
+        // Spawn particles in exact state
+        for spawn in &self.particles {
+            sim.check_particle_class(spawn.class_id)?;
+            let mut particle = Particle::new(
+                Vec2::new(spawn.x, spawn.y),
+                Vec2::new(spawn.vx, spawn.vy),
+                spawn.class_id,
+            );
+            if spawn.mass.is_some() || spawn.radius.is_some() {
+                let class = &sim.particle_classes()[&spawn.class_id];
+                particle = particle.with_mass_and_radius(
+                    spawn.mass.unwrap_or(class.mass()),
+                    spawn.radius.unwrap_or(class.radius()),
+                );
+            }
+            sim.try_spawn_particle(particle)?;
         }
         // Spawn walls
         for wall in &self.straight_walls {
@@ -124,16 +993,21 @@ impl SimulationSpec {
                 wall.class_id,
             );
             if let Some(new_w) = new_w {
-                sim.spawn_wall(new_w);
+                sim.try_spawn_wall(new_w)?;
             }
         }
-        return sim;
+        for wall in &self.polygon_walls {
+            let points = wall.points.iter().map(|&(x, y)| Vec2::new(x, y)).collect();
+            sim.try_spawn_wall(Wall::new(Polygon { points }, wall.class_id))?;
+        }
+        return Ok(sim);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{Integrator, Particle, VelocityVerletIntegrator};
 
     #[test]
     fn test_serialize_deserialize() {
@@ -141,7 +1015,26 @@ mod tests {
             name: "Test".to_string(),
             duration: Duration::from_millis(10100),
             time_step: Duration::from_millis(10),
+            adaptive_time_step: Some(AdaptiveTimeStep::new(
+                0.1,
+                Duration::from_micros(100),
+                Duration::from_millis(10),
+            )),
+            substeps: 3,
+            line_motion: true,
             gravity: 9.8,
+            gravity_x: None,
+            gravity_y: None,
+            gravity_ramp: Some(GravityRamp {
+                start: 0.0,
+                end: 9.8,
+                duration: Duration::from_secs(2),
+            }),
+            gravity_zones: vec![GravityZoneSpec {
+                points: vec![(0.0, 0.0), (5.0, 0.0), (5.0, 5.0), (0.0, 5.0)],
+                gravity_x: 1.0,
+                gravity_y: 0.0,
+            }],
             particle_classes: vec![
                 ParticleClassSpec {
                     id: 0,
@@ -149,6 +1042,10 @@ mod tests {
                     mass: 2.0,
                     radius: 1.0,
                     color: RGBA(1.0, 0.9, 0.8, 0.7),
+                    render_scale: 1.0,
+                    gravity_scale: 1.0,
+                    interaction_cutoff: None,
+                    texture: None,
                 },
                 ParticleClassSpec {
                     id: 1,
@@ -156,6 +1053,10 @@ mod tests {
                     mass: 1.0,
                     radius: 2.0,
                     color: RGBA(0.7, 0.8, 0.9, 1.0),
+                    render_scale: 2.5,
+                    gravity_scale: -0.5,
+                    interaction_cutoff: Some(5.0),
+                    texture: Some("argon.png".to_string()),
                 },
             ],
             wall_classes: vec![
@@ -164,6 +1065,10 @@ mod tests {
                     name: "wall".to_string(),
                     temperature: 10.0,
                     heat_conductivity: 0.5,
+                    diffuse_reflection: false,
+                    absorbing: true,
+                    restitution: None,
+                    heat_capacity: None,
                     color: RGBA(0.5, 0.5, 0.5, 0.5),
                 },
                 WallClassSpec {
@@ -171,9 +1076,32 @@ mod tests {
                     name: "wall2".to_string(),
                     temperature: 100.0,
                     heat_conductivity: 0.8,
+                    diffuse_reflection: true,
+                    absorbing: false,
+                    restitution: Some(RestitutionCurve::Piecewise(vec![(0.0, 1.0), (10.0, 0.6)])),
+                    heat_capacity: Some(50.0),
                     color: RGBA(0.6, 0.6, 0.6, 0.6),
                 },
             ],
+            particle_pair_rules: vec![ParticlePairRuleSpec {
+                class_id1: 0,
+                class_id2: 1,
+                rule: ParticlePairRule::Coalesce,
+            }],
+            restitution_overrides: vec![RestitutionOverrideSpec {
+                class_id1: 1,
+                class_id2: 0,
+                value: 0.8,
+            }],
+            mutual_gravity: Some(MutualGravity::new(0.5, 0.7, 0.1)),
+            units: Units::new(1.380649e-23),
+            statistics_interval: 5,
+            relaxation: Some(Relaxation::new(50, 1e-6)),
+            equilibrium: Some(EquilibriumCriterion::new(20, 0.01)),
+            ensemble_size: 3,
+            seed: Some(42),
+            max_particles: Some(1000),
+            particle_overflow: OverflowPolicy::RemoveOldest,
             particle_grids: vec![SpawnParticlesGrid {
                 class_id: 0,
                 origin_x: 2.0,
@@ -184,6 +1112,7 @@ mod tests {
                 num_cells_x: 10,
                 num_cells_y: 10,
                 mean_speed: 30.0,
+                velocity_distribution: VelocityDistribution::Uniform,
             }],
             straight_walls: vec![SpawnStraightWall {
                 class_id: 0,
@@ -193,9 +1122,737 @@ mod tests {
                 to_y: 0.0,
                 width: 0.1,
             }],
+            particles: vec![SpawnParticle {
+                class_id: 1,
+                x: 1.0,
+                y: 2.0,
+                vx: -3.0,
+                vy: 4.0,
+                mass: Some(5.0),
+                radius: None,
+            }],
+            polygon_walls: vec![SpawnPolygonWall {
+                class_id: 1,
+                points: vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            }],
         };
         let yaml = serde_yaml::to_string(&spec).unwrap();
         let spec2 = SimulationSpec::from_yaml(&yaml).unwrap();
         assert_eq!(spec, spec2);
     }
+
+    #[test]
+    fn test_gravity_ramp() {
+        let spec = SimulationSpec {
+            gravity_ramp: Some(GravityRamp {
+                start: 2.0,
+                end: 10.0,
+                duration: Duration::from_secs(4),
+            }),
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "test".to_string(),
+                mass: 1.0,
+                radius: 1.0,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            ..Default::default()
+        };
+        let mut sim = spec.build();
+        sim.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 0));
+
+        // At the midpoint gravity is half way between start and end
+        let midpoint = Duration::from_secs(2);
+        let expected = Vec2::new(0.0, -6.0);
+        assert!(sim
+            .gravity_at(midpoint)
+            .approx_eq(expected, DOUBLE_COMPARE_EPS_STRICT));
+        // After the ramp it stays at the end value
+        assert!(sim
+            .gravity_at(Duration::from_secs(100))
+            .approx_eq(Vec2::new(0.0, -10.0), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Particle accelerates according to gravity at the midpoint
+        let time_step = Duration::from_millis(10);
+        let mut particles = sim.take_particles();
+        VelocityVerletIntegrator::new().step(
+            &mut particles,
+            &sim.step_environment()
+                .with_gravity(sim.gravity_at(midpoint)),
+            time_step,
+        );
+        let acceleration = particles[0].velocity / time_step.as_secs_f64();
+        assert!(acceleration.approx_eq(expected, DISTANCE_EPS));
+    }
+
+    #[test]
+    fn test_validate_tunneling() {
+        let grid = SpawnParticlesGrid {
+            class_id: 0,
+            origin_x: 0.0,
+            origin_y: 0.0,
+            x_axis_angle: 0.0,
+            dim_x: 10.0,
+            dim_y: 10.0,
+            num_cells_x: 2,
+            num_cells_y: 2,
+            mean_speed: 100.0,
+            velocity_distribution: VelocityDistribution::Uniform,
+        };
+        let wall = |width: f64| SpawnStraightWall {
+            class_id: 0,
+            from_x: -20.0,
+            from_y: 0.0,
+            to_x: 20.0,
+            to_y: 0.0,
+            width,
+        };
+        // Particles move 100 * 0.01 = 1.0 per step
+        let mut spec = SimulationSpec {
+            time_step: Duration::from_millis(10),
+            particle_grids: vec![grid],
+            straight_walls: vec![wall(2.0), wall(0.5)],
+            ..Default::default()
+        };
+        assert_eq!(
+            spec.validate(),
+            vec![SpecDiagnostic::TunnelingLikely {
+                wall_index: 1,
+                width: 0.5,
+                step_displacement: 1.0,
+            }]
+        );
+
+        // Particles move 0.25 per substep
+        spec.substeps = 4;
+        assert!(spec.validate().is_empty());
+        spec.substeps = 1;
+
+        // Slow particles are fine
+        spec.particle_grids[0].mean_speed = 10.0;
+        assert!(spec.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_time_step() {
+        let mut spec = SimulationSpec {
+            duration: Duration::from_secs(1),
+            time_step: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::ZeroTimeStep]);
+        assert!(spec.validate()[0].is_error());
+
+        // Adaptive step replaces the fixed one, so its lower bound matters
+        spec.adaptive_time_step = Some(AdaptiveTimeStep::new(
+            0.1,
+            Duration::ZERO,
+            Duration::from_millis(10),
+        ));
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::ZeroTimeStep]);
+        spec.adaptive_time_step = None;
+
+        spec.time_step = Duration::from_millis(10);
+        spec.substeps = 0;
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::ZeroSubsteps]);
+        assert!(spec.validate()[0].is_error());
+        spec.substeps = 1;
+
+        spec.time_step = Duration::from_secs(2);
+        let diagnostics = spec.validate();
+        assert_eq!(
+            diagnostics,
+            vec![SpecDiagnostic::DurationShorterThanTimeStep {
+                duration: Duration::from_secs(1),
+                time_step: Duration::from_secs(2),
+            }]
+        );
+        assert!(!diagnostics[0].is_error());
+
+        spec.time_step = Duration::from_millis(10);
+        assert!(spec.validate().is_empty());
+
+        spec.max_particles = Some(0);
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::ZeroParticleLimit]);
+        assert!(spec.validate()[0].is_error());
+        spec.max_particles = None;
+
+        spec.equilibrium = Some(EquilibriumCriterion::new(1, 0.01));
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::InvalidEquilibrium]);
+        assert!(spec.validate()[0].is_error());
+    }
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        let spec = SimulationSpec {
+            name: "Compressed".to_string(),
+            gravity: 3.0,
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "test".to_string(),
+                mass: 1.0,
+                radius: 1.0,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            ..Default::default()
+        };
+        let dir = std::env::temp_dir();
+        let gz_path = dir.join(format!("m_sim_spec_{}.yaml.gz", std::process::id()));
+        let plain_path = dir.join(format!("m_sim_spec_{}.yaml", std::process::id()));
+        spec.to_path(&gz_path).unwrap();
+        spec.to_path(&plain_path).unwrap();
+
+        // Compressed file is not plain YAML
+        let raw = std::fs::read(&gz_path).unwrap();
+        assert!(SimulationSpec::from_yaml(&String::from_utf8_lossy(&raw)).is_err());
+        assert_eq!(SimulationSpec::from_path(&gz_path).unwrap(), spec);
+        assert_eq!(SimulationSpec::from_path(&plain_path).unwrap(), spec);
+
+        std::fs::remove_file(gz_path).unwrap();
+        std::fs::remove_file(plain_path).unwrap();
+    }
+
+    fn spec_with_unknown_class() -> SimulationSpec {
+        SimulationSpec {
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "test".to_string(),
+                mass: 1.0,
+                radius: 0.5,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            // Grid references class that isn't declared
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 7,
+                origin_x: 0.0,
+                origin_y: 0.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 2,
+                num_cells_y: 2,
+                mean_speed: 1.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_unknown_class() {
+        let mut spec = spec_with_unknown_class();
+        assert_eq!(spec.try_build().err(), Some(SimError::UnknownClass(7)));
+
+        // Walls are checked against wall classes
+        spec.particle_grids[0].class_id = 0;
+        spec.straight_walls.push(SpawnStraightWall {
+            class_id: 3,
+            from_x: -20.0,
+            from_y: 0.0,
+            to_x: 20.0,
+            to_y: 0.0,
+            width: 1.0,
+        });
+        assert_eq!(spec.try_build().err(), Some(SimError::UnknownClass(3)));
+
+        spec.straight_walls.clear();
+        assert!(spec.try_build().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown class id 7")]
+    fn test_build_panics_with_class_id() {
+        spec_with_unknown_class().build();
+    }
+
+    #[test]
+    fn test_large_class_ids() {
+        use crate::Statistics;
+
+        let yaml = "
+name: Large ids
+duration:
+  secs: 1
+  nanos: 0
+time_step:
+  secs: 0
+  nanos: 10000000
+gravity: 0.0
+particle_classes:
+- id: 1000
+  name: gas
+  mass: 1.0
+  radius: 0.5
+  color: [1.0, 1.0, 1.0, 1.0]
+wall_classes:
+- id: 300
+  name: wall
+  temperature: 0.0
+  heat_conductivity: 0.0
+  color: [1.0, 1.0, 1.0, 1.0]
+particle_grids:
+- class_id: 1000
+  origin_x: -5.0
+  origin_y: -5.0
+  x_axis_angle: 0.0
+  dim_x: 10.0
+  dim_y: 10.0
+  num_cells_x: 3
+  num_cells_y: 3
+  mean_speed: 5.0
+straight_walls:
+- class_id: 300
+  from_x: -20.0
+  from_y: -10.0
+  to_x: 20.0
+  to_y: -10.0
+  width: 1.0
+";
+        let spec = SimulationSpec::from_yaml(yaml).unwrap();
+        assert_eq!(spec.particle_classes[0].id, 1000);
+        let yaml = serde_yaml::to_string(&spec).unwrap();
+        assert_eq!(
+            SimulationSpec::from_yaml(&yaml).unwrap().wall_classes[0].id,
+            300
+        );
+
+        let mut sim = spec.try_build().unwrap();
+        assert_eq!(sim.walls()[0].class(), 300);
+        let integrator = VelocityVerletIntegrator::new();
+        for _ in 0..10 {
+            sim.step(&integrator, spec.time_step, None);
+        }
+        assert!(sim.particles().iter().all(|p| p.class() == 1000));
+        let stats = Statistics::build(sim.particles(), sim.particle_classes(), sim.units());
+        assert_eq!(stats.class_counts.get(&1000), Some(&sim.particles().len()));
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let spec = SimulationSpec {
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "gas".to_string(),
+                mass: 1.0,
+                radius: 0.5,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            wall_classes: vec![WallClassSpec {
+                id: 0,
+                name: "wall".to_string(),
+                temperature: 0.0,
+                heat_conductivity: 0.0,
+                diffuse_reflection: false,
+                absorbing: false,
+                restitution: None,
+                heat_capacity: None,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+            }],
+            ensemble_size: 4,
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 0,
+                origin_x: -5.0,
+                origin_y: -5.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 4,
+                num_cells_y: 4,
+                mean_speed: 5.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            straight_walls: vec![SpawnStraightWall {
+                class_id: 0,
+                from_x: -20.0,
+                from_y: -10.0,
+                to_x: 20.0,
+                to_y: -10.0,
+                width: 1.0,
+            }],
+            ..Default::default()
+        };
+        let mut sim = spec.build();
+        let mut particles = sim.take_particles();
+        // Coalesced particle doesn't match its class
+        particles[0] = particles[0].with_mass_and_radius(3.0, 0.5);
+
+        let snapshot = spec.snapshot(&particles, sim.walls());
+        let yaml = serde_yaml::to_string(&snapshot).unwrap();
+        let snapshot = SimulationSpec::from_yaml(&yaml).unwrap();
+        assert!(snapshot.validate().iter().all(|d| !d.is_error()));
+        assert!(snapshot.particle_grids.is_empty());
+        assert_eq!(snapshot.ensemble_size, 1);
+        assert_eq!(snapshot.particles[0].mass, Some(3.0));
+        assert_eq!(snapshot.particles[0].radius, None);
+        assert_eq!(snapshot.particles[1].mass, None);
+
+        // Rebuilt simulation starts in the same state
+        let mut rebuilt = snapshot.try_build().unwrap();
+        let rebuilt_particles = rebuilt.take_particles();
+        assert_eq!(rebuilt_particles.len(), particles.len());
+        for (p1, p2) in particles.iter().zip(rebuilt_particles.iter()) {
+            assert_eq!(p1.position, p2.position);
+            assert_eq!(p1.velocity, p2.velocity);
+            let class = &sim.particle_classes()[&p1.class()];
+            assert_eq!(p1.mass(class), p2.mass(class));
+        }
+        assert_eq!(rebuilt.walls().len(), 1);
+        assert_eq!(rebuilt.walls()[0].polygon(), sim.walls()[0].polygon());
+    }
+
+    #[test]
+    fn test_explicit_particles_with_grid() {
+        let spec = SimulationSpec {
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "gas".to_string(),
+                mass: 1.0,
+                radius: 0.5,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 0,
+                origin_x: -5.0,
+                origin_y: -5.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 3,
+                num_cells_y: 3,
+                mean_speed: 5.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            particles: vec![
+                SpawnParticle {
+                    class_id: 0,
+                    x: 20.0,
+                    y: 1.0,
+                    vx: -3.0,
+                    vy: 0.5,
+                    mass: None,
+                    radius: None,
+                },
+                SpawnParticle {
+                    class_id: 0,
+                    x: 20.0,
+                    y: -4.0,
+                    vx: 0.0,
+                    vy: 7.0,
+                    mass: None,
+                    radius: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let yaml = serde_yaml::to_string(&spec).unwrap();
+        assert_eq!(SimulationSpec::from_yaml(&yaml).unwrap(), spec);
+
+        let particles = spec.build().take_particles();
+        // Grid puts particles at the cell corners
+        assert_eq!(particles.len(), 16 + 2);
+        // Explicit particles are spawned exactly as given, next to the grid
+        for spawn in &spec.particles {
+            let position = Vec2::new(spawn.x, spawn.y);
+            let particle = particles.iter().find(|p| p.position == position).unwrap();
+            assert_eq!(particle.velocity, Vec2::new(spawn.vx, spawn.vy));
+            assert_eq!(particle.class(), spawn.class_id);
+        }
+    }
+
+    #[test]
+    fn test_merge_walls_and_particles() {
+        let walls_yaml = r#"
+name: Box
+duration: { secs: 5, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+gravity: 0.0
+wall_classes:
+  - { id: 0, name: Wall, temperature: 10.0, heat_conductivity: 1.0, color: [0.5, 0.5, 0.5, 1.0] }
+straight_walls:
+  - { class_id: 0, from_x: -10.0, from_y: -10.0, to_x: 10.0, to_y: -10.0, width: 1.0 }
+  - { class_id: 0, from_x: 10.0, from_y: -10.0, to_x: 10.0, to_y: 10.0, width: 1.0 }
+"#;
+        let particles_yaml = r#"
+name: Gas
+duration: { secs: 8, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+gravity: 9.8
+particle_classes:
+  - { id: 0, name: Gas, mass: 1.0, radius: 0.5, color: [1.0, 0.0, 0.0, 1.0] }
+particles:
+  - { class_id: 0, x: 0.0, y: 0.0, vx: 1.0, vy: 0.0 }
+  - { class_id: 0, x: 2.0, y: 0.0, vx: 0.0, vy: 1.0 }
+"#;
+        let mut spec = SimulationSpec::from_yaml(walls_yaml).unwrap();
+        let particles_spec = SimulationSpec::from_yaml(particles_yaml).unwrap();
+        let diagnostics = spec.merge(particles_spec.clone()).unwrap();
+        assert_eq!(
+            diagnostics,
+            vec![SpecDiagnostic::MergedValueOverridden { field: "duration" }]
+        );
+        assert_eq!(spec.name, "Box");
+        assert_eq!(spec.duration, Duration::from_secs(8));
+        assert_eq!(spec.gravity, 9.8);
+
+        let sim = spec.try_build().unwrap();
+        assert_eq!(sim.particles().len(), 2);
+        assert_eq!(sim.walls().len(), 2);
+
+        // Merging the same classes again is fine, but a changed definition is not
+        assert!(spec.merge(particles_spec.clone()).is_ok());
+        let mut conflicting = particles_spec;
+        conflicting.particle_classes[0].mass = 2.0;
+        let before = spec.clone();
+        assert_eq!(
+            spec.merge(conflicting),
+            Err(SpecMergeError::ConflictingParticleClass(0))
+        );
+        assert_eq!(spec, before);
+    }
+
+    #[test]
+    fn test_seeded_build() {
+        let spec = SimulationSpec {
+            particle_classes: vec![ParticleClassSpec {
+                id: 0,
+                name: "Gas".to_string(),
+                mass: 1.0,
+                radius: 0.1,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+                render_scale: 1.0,
+                gravity_scale: 1.0,
+                interaction_cutoff: None,
+                texture: None,
+            }],
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 0,
+                origin_x: 0.0,
+                origin_y: 0.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 3,
+                num_cells_y: 3,
+                mean_speed: 5.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            seed: Some(11),
+            ensemble_size: 3,
+            ..Default::default()
+        };
+        let velocities = |spec: &SimulationSpec| -> Vec<Vec2> {
+            return spec
+                .build()
+                .particles()
+                .iter()
+                .map(|p| p.velocity)
+                .collect();
+        };
+        assert_eq!(velocities(&spec), velocities(&spec));
+
+        // Members differ from each other, but each is reproducible
+        let member0 = spec.member_spec(0);
+        let member1 = spec.member_spec(1);
+        assert_eq!(member0.ensemble_size, 1);
+        assert_eq!(velocities(&member0), velocities(&spec.member_spec(0)));
+        assert_ne!(velocities(&member0), velocities(&member1));
+
+        // Unseeded members stay unseeded
+        let mut unseeded = spec.clone();
+        unseeded.seed = None;
+        assert_eq!(unseeded.member_spec(2).seed, None);
+    }
+
+    #[test]
+    fn test_maxwell_grid() {
+        let yaml = "
+name: Maxwell gas
+duration: { secs: 1, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+particle_classes:
+- { id: 0, name: Gas, mass: 2.0, radius: 0.1, color: [1.0, 1.0, 1.0, 1.0] }
+particle_grids:
+- { class_id: 0, origin_x: 0.0, origin_y: 0.0, x_axis_angle: 0.0, dim_x: 40.0, dim_y: 40.0,
+    num_cells_x: 39, num_cells_y: 39, mean_speed: 5.0, velocity_distribution: maxwell }
+seed: 3
+";
+        let spec = SimulationSpec::from_yaml(yaml).unwrap();
+        assert_eq!(
+            spec.particle_grids[0].velocity_distribution,
+            VelocityDistribution::Maxwell
+        );
+        let speeds = |spec: &SimulationSpec| -> Vec<f64> {
+            return spec
+                .build()
+                .particles()
+                .iter()
+                .map(|p| p.velocity.length())
+                .collect();
+        };
+        let maxwell = speeds(&spec);
+        assert_eq!(maxwell.len(), 1600);
+        let mean = maxwell.iter().sum::<f64>() / maxwell.len() as f64;
+        assert!((mean - 5.0).abs() < 0.25, "{}", mean);
+        // Tail goes beyond twice the mean
+        assert!(maxwell.iter().any(|&speed| speed > 10.0));
+        assert_eq!(maxwell, speeds(&spec));
+
+        let mut uniform = spec.clone();
+        uniform.particle_grids[0].velocity_distribution = VelocityDistribution::Uniform;
+        assert!(speeds(&uniform)
+            .iter()
+            .all(|&speed| (speed - 5.0).abs() < DISTANCE_EPS));
+        // Older scenes keep the uniform distribution
+        let older =
+            SimulationSpec::from_yaml(&yaml.replace(", velocity_distribution: maxwell", ""))
+                .unwrap();
+        assert_eq!(
+            older.particle_grids[0].velocity_distribution,
+            VelocityDistribution::Uniform
+        );
+    }
+
+    #[test]
+    fn test_gravity_vector() {
+        // Older scenes give downward gravity only
+        let yaml = r#"
+name: Tilted
+duration: { secs: 1, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+gravity: 9.8
+"#;
+        let mut spec = SimulationSpec::from_yaml(yaml).unwrap();
+        assert_eq!(spec.gravity_vector(), Vec2::new(0.0, -9.8));
+        assert!(spec.validate().is_empty());
+
+        // Rig tilted by 30 degrees
+        let angle = (-60.0_f64).to_radians();
+        spec.gravity_x = Some(9.8 * angle.cos());
+        spec.gravity_y = Some(9.8 * angle.sin());
+        assert_eq!(spec.validate(), vec![SpecDiagnostic::ScalarGravityIgnored]);
+        spec.gravity = 0.0;
+        assert!(spec.validate().is_empty());
+        let sim = spec.build();
+        assert!(sim
+            .gravity_at(Duration::ZERO)
+            .approx_eq(Vec2::from_angle_rad(angle) * 9.8, 1e-12));
+
+        // Missing component is zero, scalar gravity may be left out of the file
+        let yaml = r#"
+name: Sideways
+duration: { secs: 1, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+gravity_x: 1.0
+"#;
+        let spec = SimulationSpec::from_yaml(yaml).unwrap();
+        assert_eq!(spec.gravity_vector(), Vec2::new(1.0, 0.0));
+
+        // Ramp acts along the vector
+        let mut ramped = spec.clone();
+        ramped.gravity_ramp = Some(GravityRamp {
+            start: 0.0,
+            end: 4.0,
+            duration: Duration::from_secs(2),
+        });
+        assert_eq!(
+            ramped.gravity_at(Duration::from_secs(1)),
+            Vec2::new(2.0, 0.0)
+        );
+        assert_eq!(
+            ramped.build().gravity_at(Duration::from_secs(3)),
+            Vec2::new(4.0, 0.0)
+        );
+
+        // Merged vector replaces both components
+        let mut merged = SimulationSpec::from_yaml(yaml).unwrap();
+        let mut other = merged.clone();
+        other.gravity_x = None;
+        other.gravity_y = Some(-2.0);
+        merged.merge(other).unwrap();
+        assert_eq!(merged.gravity_vector(), Vec2::new(0.0, -2.0));
+    }
+
+    #[test]
+    fn test_restitution_overrides() {
+        let yaml = r#"
+name: Granular
+duration: { secs: 1, nanos: 0 }
+time_step: { secs: 0, nanos: 10000000 }
+particle_classes:
+  - { id: 0, name: Sand, mass: 1.0, radius: 0.5, color: [1.0, 1.0, 0.0, 1.0] }
+  - { id: 1, name: Rock, mass: 3.0, radius: 1.0, color: [0.5, 0.5, 0.5, 1.0] }
+restitution_overrides:
+  - { class_id1: 1, class_id2: 0, value: 0.3 }
+"#;
+        let mut spec = SimulationSpec::from_yaml(yaml).unwrap();
+        assert!(spec.validate().is_empty());
+        let sim = spec.try_build().unwrap();
+        assert_eq!(sim.particle_pair_rules().restitution(0, 1), Some(0.3));
+        assert_eq!(sim.particle_pair_rules().restitution(0, 0), None);
+
+        spec.restitution_overrides[0].value = 1.5;
+        let diagnostics = spec.validate();
+        assert_eq!(
+            diagnostics,
+            vec![SpecDiagnostic::InvalidPairRestitution {
+                class_id1: 1,
+                class_id2: 0
+            }]
+        );
+        assert!(diagnostics[0].is_error());
+
+        // Unknown class
+        spec.restitution_overrides[0].class_id1 = 7;
+        assert!(spec.try_build().is_err());
+    }
+
+    #[test]
+    fn test_validate_fragment_count() {
+        let fragment = |num_fragments| ParticlePairRuleSpec {
+            class_id1: 0,
+            class_id2: 1,
+            rule: ParticlePairRule::Fragment {
+                energy_threshold: 1.0,
+                num_fragments,
+            },
+        };
+        let mut spec = SimulationSpec {
+            particle_pair_rules: vec![fragment(2)],
+            ..Default::default()
+        };
+        assert!(spec.validate().is_empty());
+
+        // Single fragment would silently turn into a bounce
+        spec.particle_pair_rules = vec![fragment(1)];
+        let diagnostics = spec.validate();
+        assert_eq!(
+            diagnostics,
+            vec![SpecDiagnostic::TooFewFragments {
+                class_id1: 0,
+                class_id2: 1,
+                num_fragments: 1
+            }]
+        );
+        assert!(diagnostics[0].is_error());
+    }
 }
@@ -1,55 +1,325 @@
 use crate::math_core;
 use crate::prelude::*;
-use crate::{Particle, ParticleClass};
-use std::collections::HashMap;
+use crate::sim_error::get_class;
+use crate::{Particle, ParticleClass, StepReport, Tensor2, Units, Vec2, Wall, WallClass};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use statrs::statistics;
 
-#[derive(Debug, Clone)]
+/// Stats of the particles of one class. Their number is in `Statistics::class_counts`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub struct ClassStats {
+    /// Mean temperature of the particles of the class
+    pub temperature: f64,
+    pub total_energy: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Statistics {
     pub num_particles: usize,
+    /// Number of particles of each class. Classes without particles are absent
+    pub class_counts: BTreeMap<ClassId, usize>,
     pub total_energy: f64,
     pub temperature: f64,
+    pub mean_speed: f64,
+    pub rms_speed: f64,
+    pub min_speed: f64,
+    pub max_speed: f64,
+    /// 5th, 50th and 95th percentiles of speed. Unlike min and max, single outliers
+    /// don't move them, so they give a robust range for color scaling
+    pub speed_percentiles: [f64; 3],
+    /// Pressure tensor from the virial. Only available if step report is known
+    pub pressure_tensor: Option<Tensor2>,
+    /// Temperature of each wall class that has walls, averaged over the walls of the class.
+    /// Only available if walls are known
+    pub wall_temperatures: BTreeMap<ClassId, f64>,
+    /// Kinetic energy of the center of mass motion. Only available in the center of mass frame,
+    /// where it's the difference from the lab frame total energy
+    #[serde(default)]
+    pub bulk_kinetic_energy: Option<f64>,
+    /// Mean of |r(t) - r(0)|² over particles of each class. Only available if reference
+    /// positions are known. Grows as 4Dt for diffusive motion and quadratically for free flight
+    #[serde(default)]
+    pub mean_square_displacement: BTreeMap<ClassId, f64>,
+    /// Impulse each wall received over the recent time window, indexed as the walls.
+    /// Only available if step reports are known
+    #[serde(default)]
+    pub wall_impulses: Vec<f64>,
+    /// Mean pressure on each wall, indexed as the walls. Impulse is spread over the whole
+    /// outline of the wall, so a thin wall hit from one side gets about half of the pressure
+    /// on that side. Only available if wall impulses are known
+    #[serde(default)]
+    pub wall_pressures: Vec<f64>,
+    /// Count, temperature and energy of each class. Classes without particles are absent
+    #[serde(default)]
+    pub per_class: BTreeMap<ClassId, ClassStats>,
 }
 
 impl Default for Statistics {
     fn default() -> Self {
         Self {
             num_particles: 0,
+            class_counts: BTreeMap::new(),
             total_energy: 0.0,
             temperature: 0.0,
+            mean_speed: 0.0,
+            rms_speed: 0.0,
+            min_speed: 0.0,
+            max_speed: 0.0,
+            speed_percentiles: [0.0; 3],
+            pressure_tensor: None,
+            wall_temperatures: BTreeMap::new(),
+            bulk_kinetic_energy: None,
+            mean_square_displacement: BTreeMap::new(),
+            wall_impulses: Vec::new(),
+            wall_pressures: Vec::new(),
+            per_class: BTreeMap::new(),
         }
     }
 }
 
 impl Statistics {
+    /// Temperature is reported in the given unit system. Energy doesn't depend on it
     pub fn build(
         particles: &[Particle],
         particle_classes: &HashMap<ClassId, ParticleClass>,
+        units: &Units,
     ) -> Self {
-        let mut res = Self::default();
-        res.num_particles = particles.len();
+        let mut res = Self {
+            num_particles: particles.len(),
+            ..Self::default()
+        };
+        for p in particles {
+            *res.class_counts.entry(p.class()).or_insert(0) += 1;
+        }
 
         let get_energy = |p: &Particle| {
-            let class = particle_classes.get(&p.class()).expect("Particle class expected in the map");
-            return math_core::kinetic_energy_from_velocity(class.mass(), p.velocity.length());
+            let class = get_class(particle_classes, p.class());
+            return math_core::kinetic_energy_from_velocity(p.mass(class), p.velocity.length());
         };
         let energies : Vec<f64> = particles.iter().map(get_energy).collect();
-        let temps : Vec<f64> = energies.iter().map(|&e| math_core::temp_from_energy(e)).collect();
+        let temps : Vec<f64> = energies.iter().map(|&e| math_core::temp_from_energy(e, units.boltzmann)).collect();
         // Calc mean and variance
         res.temperature = statistics::Statistics::mean(&temps);
         res.total_energy = energies.iter().sum();
+        for ((p, &energy), &temp) in particles.iter().zip(&energies).zip(&temps) {
+            let class_stats = res.per_class.entry(p.class()).or_default();
+            class_stats.temperature += temp;
+            class_stats.total_energy += energy;
+        }
+        for (class, class_stats) in res.per_class.iter_mut() {
+            class_stats.temperature /= res.class_counts[class] as f64;
+        }
+
+        // Speeds are computed from raw velocities. They don't depend on mass
+        let mut speeds : Vec<f64> = particles.iter().map(|p| p.velocity.length()).collect();
+        if !speeds.is_empty() {
+            res.mean_speed = statistics::Statistics::mean(&speeds);
+            let mean_sq = speeds.iter().map(|s| s * s).sum::<f64>() / speeds.len() as f64;
+            res.rms_speed = mean_sq.sqrt();
+            speeds.sort_by(f64::total_cmp);
+            res.min_speed = speeds[0];
+            res.max_speed = speeds[speeds.len() - 1];
+            res.speed_percentiles = [5.0, 50.0, 95.0].map(|p| percentile(&speeds, p));
+        }
+
+        return res;
+    }
 
+    /// Same as `build`, but velocities are taken relative to the center of mass.
+    /// Bulk drift of the whole ensemble then doesn't count as thermal energy.
+    /// Energy, temperature and speeds are all in that frame. Particles are not changed
+    pub fn build_in_com_frame(
+        particles: &[Particle],
+        particle_classes: &HashMap<ClassId, ParticleClass>,
+        units: &Units,
+    ) -> Self {
+        let mut total_mass = 0.0;
+        let mut momentum = Vec2::ZERO;
+        for p in particles {
+            let mass = p.mass(get_class(particle_classes, p.class()));
+            total_mass += mass;
+            momentum += p.velocity * mass;
+        }
+        if total_mass <= 0.0 {
+            return Self::build(particles, particle_classes, units);
+        }
+        let com_velocity = momentum / total_mass;
+        let relative: Vec<Particle> = particles
+            .iter()
+            .map(|p| {
+                let mut p = *p;
+                p.velocity -= com_velocity;
+                p
+            })
+            .collect();
+        let mut res = Self::build(&relative, particle_classes, units);
+        res.bulk_kinetic_energy = Some(math_core::kinetic_energy_from_velocity(
+            total_mass,
+            com_velocity.length(),
+        ));
         return res;
     }
 
+    /// Same as `build`, but also includes the stats of walls
+    pub fn build_with_walls(
+        particles: &[Particle],
+        particle_classes: &HashMap<ClassId, ParticleClass>,
+        units: &Units,
+        walls: &[Wall],
+        wall_classes: &HashMap<ClassId, WallClass>,
+    ) -> Self {
+        let mut res = Self::build(particles, particle_classes, units);
+        // Walls with heat capacity have own temperatures. Classes get the mean
+        let mut sums: BTreeMap<ClassId, (f64, usize)> = BTreeMap::new();
+        for wall in walls {
+            let class = get_class(wall_classes, wall.class());
+            let sum = sums.entry(wall.class()).or_insert((0.0, 0));
+            sum.0 += wall.temperature(class);
+            sum.1 += 1;
+        }
+        for (class_id, (sum, count)) in sums {
+            res.wall_temperatures.insert(class_id, sum / count as f64);
+        }
+        return res;
+    }
+
+    /// Adds pressure tensor calculated from the virial:
+    /// P = (sum(m * v ⊗ v) + sum(r ⊗ J) / dt) / area
+    /// `report` must be the report of the step that produced `particles`.
+    /// `area` is the area available to particles
+    pub fn add_pressure_tensor(
+        &mut self,
+        particles: &[Particle],
+        particle_classes: &HashMap<ClassId, ParticleClass>,
+        report: &StepReport,
+        time_step_sec: f64,
+        area: f64,
+    ) {
+        let mut kinetic = Tensor2::ZERO;
+        for p in particles {
+            let class = get_class(particle_classes, p.class());
+            kinetic += Tensor2::outer(p.velocity, p.velocity) * p.mass(class);
+        }
+        let virial = report.collision_virial / time_step_sec;
+        self.pressure_tensor = Some((kinetic + virial) / area);
+    }
+
+    /// Adds pressure on each wall: normal impulse the wall received over `duration_sec`,
+    /// divided by its perimeter and the duration. Walls without impulses get zero.
+    /// The whole perimeter is used, even the parts particles can't reach. A thin wall of
+    /// `Wall::make_box` is hit on one long side only, so its pressure is about half of the
+    /// pressure of the gas
+    pub fn add_wall_pressures(&mut self, walls: &[Wall], wall_impulses: &[f64], duration_sec: f64) {
+        self.wall_pressures = walls
+            .iter()
+            .enumerate()
+            .map(|(i, wall)| {
+                let impulse = wall_impulses.get(i).copied().unwrap_or(0.0);
+                return impulse / (wall.perimeter() * duration_sec);
+            })
+            .collect();
+    }
+
+    /// Adds mean square displacement of each class. `reference_positions` are the positions
+    /// of particles by persistent id at the start of the measurement. Particles that
+    /// appeared later aren't counted
+    pub fn add_mean_square_displacement(
+        &mut self,
+        particles: &[Particle],
+        reference_positions: &HashMap<ParticleId, Vec2>,
+    ) {
+        let mut sums: BTreeMap<ClassId, (f64, usize)> = BTreeMap::new();
+        for p in particles {
+            if let Some(reference) = p.id().and_then(|id| reference_positions.get(&id)) {
+                let sum = sums.entry(p.class()).or_insert((0.0, 0));
+                sum.0 += (p.position - *reference).length_sq();
+                sum.1 += 1;
+            }
+        }
+        self.mean_square_displacement =
+            sums.into_iter().map(|(class, (sum, count))| (class, sum / count as f64)).collect();
+    }
+
+    /// Scalar pressure. In 2D it's half of the trace of pressure tensor
+    pub fn pressure(&self) -> Option<f64> {
+        self.pressure_tensor.map(|t| t.trace() / 2.0)
+    }
+
     pub fn to_strings(&self) -> Vec<String> {
-        vec![
+        let mut res = vec![
             format!("Number of particles: {}", self.num_particles),
             format!("Total energy: {}", self.total_energy),
             format!("Temperature: {} simuK", self.temperature),
+            format!("Mean speed: {}", self.mean_speed),
+            format!("RMS speed: {}", self.rms_speed),
+            format!(
+                "Speed 5% / 50% / 95%: {:.3} / {:.3} / {:.3}",
+                self.speed_percentiles[0], self.speed_percentiles[1], self.speed_percentiles[2]
+            ),
             // Add more strings as needed
-        ]
+        ];
+        if self.class_counts.len() > 1 {
+            let counts: Vec<String> = self
+                .class_counts
+                .iter()
+                .map(|(class, count)| format!("{}: {}", class, count))
+                .collect();
+            res.push(format!("Particles per class: {}", counts.join(", ")));
+            for (class, class_stats) in &self.per_class {
+                res.push(format!(
+                    "Class {}: temperature {} simuK, energy {}",
+                    class, class_stats.temperature, class_stats.total_energy
+                ));
+            }
+        }
+        if !self.wall_temperatures.is_empty() {
+            let temperatures: Vec<String> = self
+                .wall_temperatures
+                .iter()
+                .map(|(class, temperature)| format!("{}: {}", class, temperature))
+                .collect();
+            res.push(format!("Wall temperatures: {}", temperatures.join(", ")));
+        }
+        if !self.mean_square_displacement.is_empty() {
+            let msd: Vec<String> = self
+                .mean_square_displacement
+                .iter()
+                .map(|(class, msd)| format!("{}: {:.3}", class, msd))
+                .collect();
+            res.push(format!("Mean square displacement: {}", msd.join(", ")));
+        }
+        if let Some(bulk) = self.bulk_kinetic_energy {
+            res.push(format!("Bulk kinetic energy: {}", bulk));
+        }
+        if let Some(t) = self.pressure_tensor {
+            res.push(format!("Pressure: {}", t.trace() / 2.0));
+            res.push(format!("Pressure tensor: [{:.3}, {:.3}; {:.3}, {:.3}]", t.xx, t.xy, t.yx, t.yy));
+        }
+        return res;
+    }
+}
+
+/// Percentile `p` in [0, 100] of sorted non-empty `values`. Interpolates linearly
+/// between the closest ranks, so the 0th is the minimum and the 100th is the maximum
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    return sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64);
+}
+
+#[cfg(test)]
+impl Statistics {
+    /// Statistics with only the given values set, for tests of their consumers
+    pub(crate) fn sample(temperature: f64, total_energy: f64, pressure: Option<f64>) -> Self {
+        return Statistics {
+            temperature,
+            total_energy,
+            pressure_tensor: pressure.map(|p| Tensor2::new(p, 0.0, 0.0, p)),
+            ..Default::default()
+        };
     }
 }
 
@@ -58,4 +328,295 @@ impl fmt::Display for Statistics {
         let strings = self.to_strings();
         write!(f, "{}", strings.join(", "))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wall_pressures() {
+        // Bottom wall is 10 x 1, right one is 1 x 8
+        let walls = Wall::make_box(-5.0, -5.0, 5.0, 5.0, 1.0, 1);
+        let mut stats = Statistics::default();
+        // Impulses of the last walls are missing
+        stats.add_wall_pressures(&walls, &[11.0, 36.0], 2.0);
+        assert_eq!(stats.wall_pressures.len(), 4);
+        assert!(math_core::approx_eq(stats.wall_pressures[0], 0.25, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(stats.wall_pressures[1], 1.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert_eq!(stats.wall_pressures[2], 0.0);
+        assert_eq!(stats.wall_pressures[3], 0.0);
+    }
+
+    #[test]
+    fn test_mean_and_rms_speed() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+
+        // Speeds are 3, 4 and 5
+        let particles = vec![
+            Particle::new(Vec2::ZERO, Vec2::new(3.0, 0.0), 1),
+            Particle::new(Vec2::ZERO, Vec2::new(0.0, -4.0), 1),
+            Particle::new(Vec2::ZERO, Vec2::new(3.0, 4.0), 1),
+        ];
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        assert!(math_core::approx_eq(stats.mean_speed, 4.0, DOUBLE_COMPARE_EPS_STRICT));
+        // sqrt((9 + 16 + 25) / 3)
+        assert!(math_core::approx_eq(
+            stats.rms_speed,
+            (50.0_f64 / 3.0).sqrt(),
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+
+        // No particles - no speeds
+        let stats = Statistics::build(&[], &classes, &Units::default());
+        assert_eq!(stats.mean_speed, 0.0);
+        assert_eq!(stats.rms_speed, 0.0);
+    }
+
+    #[test]
+    fn test_speed_percentiles_ignore_outlier() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        // Speeds 1..=20 and a single very fast particle
+        let mut particles: Vec<Particle> = (1..=20)
+            .map(|i| Particle::new(Vec2::ZERO, Vec2::new(i as f64, 0.0), 1))
+            .collect();
+        particles.push(Particle::new(Vec2::ZERO, Vec2::new(0.0, 1000.0), 1));
+
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        assert_eq!(stats.min_speed, 1.0);
+        assert_eq!(stats.max_speed, 1000.0);
+        let [p5, p50, p95] = stats.speed_percentiles;
+        assert!(math_core::approx_eq(p5, 2.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(p50, 11.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(p95, 20.0, DOUBLE_COMPARE_EPS_STRICT));
+
+        // Single particle is every percentile
+        let stats = Statistics::build(&particles[..1], &classes, &Units::default());
+        assert_eq!(stats.speed_percentiles, [1.0; 3]);
+    }
+
+    #[test]
+    fn test_com_frame_removes_drift() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Light", 1.0, 1.0));
+        classes.insert(2, ParticleClass::new("Heavy", 3.0, 1.0));
+        let drift = Vec2::new(4.0, -2.0);
+        // Whole ensemble moves together
+        let particles: Vec<Particle> = (0..10)
+            .map(|i| Particle::new(Vec2::new(i as f64, 0.0), drift, 1 + i % 2))
+            .collect();
+        let lab = Statistics::build(&particles, &classes, &Units::default());
+        let com = Statistics::build_in_com_frame(&particles, &classes, &Units::default());
+        assert!(lab.temperature > 1.0);
+        assert!(com.temperature.abs() < DOUBLE_COMPARE_EPS_STRICT);
+        assert!(com.max_speed < DOUBLE_COMPARE_EPS_STRICT);
+        assert_eq!(lab.bulk_kinetic_energy, None);
+        // Total mass is 20
+        let bulk = com.bulk_kinetic_energy.unwrap();
+        assert!(math_core::approx_eq(bulk, 10.0 * drift.length_sq(), DOUBLE_COMPARE_EPS_STRICT));
+
+        // Thermal motion on top of the drift. Momentum of the thermal part is zero
+        let mut particles = particles;
+        particles[0].velocity += Vec2::new(3.0, 0.0);
+        particles[2].velocity -= Vec2::new(3.0, 0.0);
+        particles[1].velocity += Vec2::new(0.0, 1.0);
+        particles[3].velocity -= Vec2::new(0.0, 1.0);
+        let lab = Statistics::build(&particles, &classes, &Units::default());
+        let com = Statistics::build_in_com_frame(&particles, &classes, &Units::default());
+        // 2 * 1/2 * 1 * 3^2 + 2 * 1/2 * 3 * 1^2
+        assert!(math_core::approx_eq(com.total_energy, 12.0, 1e-9));
+        let bulk = com.bulk_kinetic_energy.unwrap();
+        assert!(math_core::approx_eq(lab.total_energy - com.total_energy, bulk, 1e-9));
+        assert!(com.to_strings().iter().any(|s| s.starts_with("Bulk kinetic energy")));
+    }
+
+    #[test]
+    fn test_per_class_temperatures() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Slow", 1.0, 1.0));
+        classes.insert(2, ParticleClass::new("Fast", 1.0, 1.0));
+        let mut particles: Vec<Particle> =
+            (0..3).map(|_| Particle::new(Vec2::ZERO, Vec2::new(1.0, 0.0), 1)).collect();
+        particles.push(Particle::new(Vec2::ZERO, Vec2::new(0.0, 2.0), 2));
+        particles.push(Particle::new(Vec2::ZERO, Vec2::new(-2.0, 0.0), 2));
+
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        let slow = stats.per_class[&1];
+        let fast = stats.per_class[&2];
+        assert_eq!(stats.class_counts[&1], 3);
+        assert_eq!(stats.class_counts[&2], 2);
+        assert!(math_core::approx_eq(slow.total_energy, 1.5, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(fast.total_energy, 4.0, DOUBLE_COMPARE_EPS_STRICT));
+        // Twice the speed is four times the temperature
+        assert!(math_core::approx_eq(fast.temperature, 4.0 * slow.temperature, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(
+            slow.temperature,
+            math_core::temp_from_energy(0.5, Units::default().boltzmann),
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+        // Global fields cover all classes
+        assert!(math_core::approx_eq(
+            stats.total_energy,
+            slow.total_energy + fast.total_energy,
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+        assert!(math_core::approx_eq(
+            stats.temperature,
+            (3.0 * slow.temperature + 2.0 * fast.temperature) / 5.0,
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+        let strings = stats.to_strings();
+        assert!(strings.iter().any(|s| s.starts_with("Class 1: temperature")));
+        assert!(strings.iter().any(|s| s.starts_with("Class 2: temperature")));
+    }
+
+    #[test]
+    fn test_boltzmann_scales_temperature() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 2.0, 1.0));
+        let particles = vec![
+            Particle::new(Vec2::ZERO, Vec2::new(3.0, 0.0), 1),
+            Particle::new(Vec2::ZERO, Vec2::new(0.0, -1.0), 1),
+        ];
+        let simu_k = Statistics::build(&particles, &classes, &Units::default());
+        let scaled = Statistics::build(&particles, &classes, &Units::new(4.0));
+        // Energy is the same. Temperature is inversely proportional to the constant
+        assert_eq!(simu_k.total_energy, scaled.total_energy);
+        assert!(math_core::approx_eq(simu_k.total_energy, 10.0, DOUBLE_COMPARE_EPS_STRICT));
+        assert!(math_core::approx_eq(
+            scaled.temperature * 4.0,
+            simu_k.temperature,
+            DOUBLE_COMPARE_EPS_STRICT
+        ));
+        // Conversions back and forth agree
+        let energy = math_core::energy_from_temp(scaled.temperature, 4.0);
+        assert!(math_core::approx_eq(energy, simu_k.total_energy / 2.0, DOUBLE_COMPARE_EPS_STRICT));
+    }
+
+    #[test]
+    fn test_wall_stats_only_with_walls() {
+        use crate::Polygon;
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(0, WallClass::new("Cold", 10.0, 1.0));
+        wall_classes.insert(1, WallClass::new("Hot", 300.0, 1.0));
+        wall_classes.insert(2, WallClass::new("Unused", 50.0, 1.0));
+        let walls = vec![
+            Wall::new(Polygon::new_rectangle(0.0, 0.0, 1.0, 1.0), 0),
+            Wall::new(Polygon::new_rectangle(2.0, 0.0, 3.0, 1.0), 1),
+            Wall::new(Polygon::new_rectangle(4.0, 0.0, 5.0, 1.0), 1),
+        ];
+        let particles = vec![Particle::new(Vec2::ZERO, Vec2::new(3.0, 0.0), 1)];
+
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        assert!(stats.wall_temperatures.is_empty());
+        assert!(!stats.to_strings().iter().any(|s| s.starts_with("Wall")));
+
+        let with_walls =
+            Statistics::build_with_walls(&particles, &classes, &Units::default(), &walls, &wall_classes);
+        assert_eq!(with_walls.wall_temperatures, BTreeMap::from([(0, 10.0), (1, 300.0)]));
+        assert!(with_walls.to_strings().contains(&"Wall temperatures: 0: 10, 1: 300".to_string()));
+        // Particle stats are the same
+        assert_eq!(with_walls.total_energy, stats.total_energy);
+        assert_eq!(with_walls.class_counts, stats.class_counts);
+
+        // Scene without walls has no wall stats
+        let no_walls = Statistics::build_with_walls(&particles, &classes, &Units::default(), &[], &wall_classes);
+        assert!(no_walls.wall_temperatures.is_empty());
+    }
+
+    #[test]
+    fn test_pressure_tensor_isotropic() {
+        use crate::{Integrator, ParticlePairRules, StepEnvironment, VelocityVerletIntegrator, Wall, WallClass};
+        use rand::{Rng, SeedableRng};
+        use std::time::Duration;
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Wall", 0.0, 0.0));
+        // Box with 20x20 inner area
+        let walls = Wall::make_box(-11.0, -11.0, 11.0, 11.0, 1.0, 1);
+        let area = 20.0 * 20.0;
+
+        // Dense gas with random directions, so that collisions matter
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut particles = vec![];
+        for i in 0..12 {
+            for j in 0..12 {
+                let pos = Vec2::new(-8.8 + i as f64 * 1.6, -8.8 + j as f64 * 1.6);
+                let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+                particles.push(Particle::new(pos, Vec2::from_angle_rad(angle) * 5.0, 1));
+            }
+        }
+
+        // Average over many steps to smooth out fluctuations
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(10);
+        let mut sum = Tensor2::ZERO;
+        let mut kinetic_only = 0.0;
+        let num_steps = 200;
+        for _ in 0..num_steps {
+            let report = integrator.step(
+                &mut particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                time_step,
+            );
+            let mut stats = Statistics::build(&particles, &classes, &Units::default());
+            stats.add_pressure_tensor(&particles, &classes, &report, time_step.as_secs_f64(), area);
+            sum += stats.pressure_tensor.unwrap();
+            kinetic_only += stats.total_energy / area;
+        }
+        let tensor = sum / num_steps as f64;
+        let pressure = tensor.trace() / 2.0;
+
+        // Off diagonal terms vanish. Diagonal terms match the scalar pressure
+        assert!(tensor.xy.abs() < pressure * 0.05);
+        assert!(tensor.yx.abs() < pressure * 0.05);
+        assert!(math_core::approx_eq(tensor.xx, pressure, pressure * 0.05));
+        assert!(math_core::approx_eq(tensor.yy, pressure, pressure * 0.05));
+        // Dense gas. Collisions add to the ideal gas pressure
+        assert!(pressure > kinetic_only / num_steps as f64 * 1.1);
+    }
+
+    #[test]
+    fn test_ballistic_mean_square_displacement() {
+        use crate::{Simulation, VelocityVerletIntegrator};
+        use std::time::Duration;
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Slow", 1.0, 0.5));
+        classes.insert(2, ParticleClass::new("Fast", 1.0, 0.5));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        // Particles fly apart and never collide
+        for i in 0..4 {
+            let direction = Vec2::from_angle_rad(i as f64 * std::f64::consts::FRAC_PI_2);
+            simulation.spawn_particle(Particle::new(direction * 5.0, direction * 1.0, 1));
+            simulation.spawn_particle(Particle::new(direction * 10.0, direction * 3.0, 2));
+        }
+        let reference: HashMap<ParticleId, Vec2> =
+            simulation.particles().iter().map(|p| (p.id().unwrap(), p.position)).collect();
+
+        let integrator = VelocityVerletIntegrator::new();
+        let mut msd_at = |steps: usize| {
+            for _ in 0..steps {
+                simulation.step(&integrator, Duration::from_millis(100), None);
+            }
+            let mut stats = Statistics::build(simulation.particles(), simulation.particle_classes(), &Units::default());
+            stats.add_mean_square_displacement(simulation.particles(), &reference);
+            stats.mean_square_displacement
+        };
+        // (v * t)^2 at t = 1
+        let msd1 = msd_at(10);
+        assert!(math_core::approx_eq(msd1[&1], 1.0, 1e-9));
+        assert!(math_core::approx_eq(msd1[&2], 9.0, 1e-9));
+        // Free flight grows quadratically. Diffusion would only double it
+        let msd2 = msd_at(10);
+        assert!(math_core::approx_eq(msd2[&1] / msd1[&1], 4.0, 1e-9));
+        assert!(math_core::approx_eq(msd2[&2] / msd1[&2], 4.0, 1e-9));
+    }
+}
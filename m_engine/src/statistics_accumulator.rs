@@ -0,0 +1,148 @@
+use crate::Statistics;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Mean of a quantity over the window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WindowedValue {
+    pub mean: f64,
+    /// Standard error of the mean, assuming independent samples.
+    /// Not available with fewer than 2 samples
+    pub standard_error: Option<f64>,
+}
+
+impl WindowedValue {
+    /// None if there are no values
+    pub fn from_values(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let standard_error = if values.len() >= 2 {
+            let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0);
+            Some((variance / n).sqrt())
+        } else {
+            None
+        };
+        return Some(WindowedValue { mean, standard_error });
+    }
+}
+
+/// Averages of the scalar statistics over the window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WindowedStatistics {
+    pub num_samples: usize,
+    pub total_energy: WindowedValue,
+    pub temperature: WindowedValue,
+    pub mean_speed: WindowedValue,
+    /// Only samples that have pressure are averaged. None if none of them has it
+    pub pressure: Option<WindowedValue>,
+}
+
+/// Scalar quantities of one statistics sample
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: Duration,
+    total_energy: f64,
+    temperature: f64,
+    mean_speed: f64,
+    pressure: Option<f64>,
+}
+
+/// Keeps the statistics samples of the last `window` of simulation time and averages them.
+/// Samples are fed one per frame. Raw statistics are not changed
+#[derive(Debug, Clone)]
+pub struct StatisticsAccumulator {
+    window: Duration,
+    samples: VecDeque<Sample>,
+}
+
+impl StatisticsAccumulator {
+    pub fn new(window: Duration) -> Self {
+        StatisticsAccumulator {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds the sample taken at `time`. Samples older than `window` before it are dropped.
+    /// Sample earlier than the last one (e.g. after rewinding) restarts the window
+    pub fn add(&mut self, time: Duration, statistics: &Statistics) {
+        if self.samples.back().is_some_and(|s| s.time > time) {
+            self.samples.clear();
+        }
+        self.samples.push_back(Sample {
+            time,
+            total_energy: statistics.total_energy,
+            temperature: statistics.temperature,
+            mean_speed: statistics.mean_speed,
+            pressure: statistics.pressure(),
+        });
+        let start = time.saturating_sub(self.window);
+        while self.samples.front().is_some_and(|s| s.time < start) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Averages over the samples in the window. None if there are no samples
+    pub fn averages(&self) -> Option<WindowedStatistics> {
+        let collect = |quantity: fn(&Sample) -> f64| -> Vec<f64> {
+            return self.samples.iter().map(quantity).collect();
+        };
+        let pressures: Vec<f64> = self.samples.iter().filter_map(|s| s.pressure).collect();
+        return Some(WindowedStatistics {
+            num_samples: self.samples.len(),
+            total_energy: WindowedValue::from_values(&collect(|s| s.total_energy))?,
+            temperature: WindowedValue::from_values(&collect(|s| s.temperature))?,
+            mean_speed: WindowedValue::from_values(&collect(|s| s.mean_speed))?,
+            pressure: WindowedValue::from_values(&pressures),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windowed_average() {
+        let mut accumulator = StatisticsAccumulator::new(Duration::from_secs(2));
+        assert!(accumulator.averages().is_none());
+
+        accumulator.add(Duration::from_secs(0), &Statistics::sample(1.0, 2.0, None));
+        let averages = accumulator.averages().unwrap();
+        assert_eq!(averages.temperature.mean, 1.0);
+        assert_eq!(averages.temperature.standard_error, None);
+        assert_eq!(averages.pressure, None);
+
+        // Window covers times 1..=3, so the first sample is dropped
+        for (time, temperature) in [(1, 2.0), (2, 3.0), (3, 4.0)] {
+            let statistics = Statistics::sample(temperature, 2.0 * temperature, Some(temperature));
+            accumulator.add(Duration::from_secs(time), &statistics);
+        }
+        let averages = accumulator.averages().unwrap();
+        assert_eq!(averages.num_samples, 3);
+        assert_eq!(averages.temperature.mean, 3.0);
+        assert_eq!(averages.total_energy.mean, 6.0);
+        // Sample variance is 1, standard error is sqrt(1/3)
+        let error = averages.temperature.standard_error.unwrap();
+        assert!((error - (1.0_f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(averages.pressure.unwrap().mean, 3.0);
+
+        // Going back in time restarts the window
+        accumulator.add(Duration::from_secs(1), &Statistics::sample(10.0, 20.0, None));
+        let averages = accumulator.averages().unwrap();
+        assert_eq!(averages.num_samples, 1);
+        assert_eq!(averages.temperature.mean, 10.0);
+    }
+}
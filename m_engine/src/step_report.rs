@@ -0,0 +1,51 @@
+use crate::collisions::CollisionEvent;
+use crate::Tensor2;
+
+/// Information gathered while simulating single time step
+#[derive(Debug, Clone, Default)]
+pub struct StepReport {
+    /// Sum of r ⊗ J over all particle-particle collisions of the step, where r is the
+    /// separation of particle centers and J is the impulse received by the first particle.
+    /// Divided by step duration this is the collisional part of the virial
+    pub collision_virial: Tensor2,
+    /// Indices of particles whose position or velocity became NaN or infinite during the
+    /// step. Indices refer to the particles as they were before the guard handled them
+    pub non_finite_particles: Vec<usize>,
+    /// Number of particle pairs tested for collision. Measures the cost of the search
+    pub pair_checks: usize,
+    /// Number of resolved collisions with particles and walls
+    pub collisions: usize,
+    /// Kinetic energy that particles gave to each wall in collisions. Negative if the wall
+    /// heated them up. Indexed as the walls of the step. Empty if there were no collisions
+    pub wall_heat: Vec<f64>,
+    /// Normal impulse that particles gave to each wall in collisions.
+    /// Indexed as the walls of the step. Empty if there were no collisions
+    pub wall_impulse: Vec<f64>,
+    /// Number of parts the step was split into. Collisions are resolved once per part
+    pub substeps: usize,
+    /// Resolved bounces in time order, if the integrator records them. Coalescence,
+    /// fragmentation and absorption aren't recorded. Particle indices are those at the start
+    /// of the substep of the collision
+    pub collision_events: Vec<CollisionEvent>,
+}
+
+impl StepReport {
+    /// Accumulates heat of another report of the same walls
+    pub fn add_wall_heat(&mut self, wall_heat: &[f64]) {
+        add_per_wall(&mut self.wall_heat, wall_heat);
+    }
+
+    /// Accumulates impulse of another report of the same walls
+    pub fn add_wall_impulse(&mut self, wall_impulse: &[f64]) {
+        add_per_wall(&mut self.wall_impulse, wall_impulse);
+    }
+}
+
+fn add_per_wall(totals: &mut Vec<f64>, values: &[f64]) {
+    if totals.len() < values.len() {
+        totals.resize(values.len(), 0.0);
+    }
+    for (total, value) in totals.iter_mut().zip(values) {
+        *total += value;
+    }
+}
@@ -0,0 +1,66 @@
+use crate::Vec2;
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Div, Mul};
+
+/// 2x2 tensor. Used for stress and pressure
+#[derive(Clone, Debug, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub struct Tensor2 {
+    pub xx: f64,
+    pub xy: f64,
+    pub yx: f64,
+    pub yy: f64,
+}
+
+impl Tensor2 {
+    pub const ZERO: Self = Self {
+        xx: 0.0,
+        xy: 0.0,
+        yx: 0.0,
+        yy: 0.0,
+    };
+
+    pub fn new(xx: f64, xy: f64, yx: f64, yy: f64) -> Self {
+        Self { xx, xy, yx, yy }
+    }
+
+    /// Outer product a ⊗ b
+    pub fn outer(a: Vec2, b: Vec2) -> Self {
+        Self::new(a.x * b.x, a.x * b.y, a.y * b.x, a.y * b.y)
+    }
+
+    pub fn trace(&self) -> f64 {
+        self.xx + self.yy
+    }
+}
+
+impl Add for Tensor2 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.xx + rhs.xx,
+            self.xy + rhs.xy,
+            self.yx + rhs.yx,
+            self.yy + rhs.yy,
+        )
+    }
+}
+
+impl AddAssign for Tensor2 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Mul<f64> for Tensor2 {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self::new(self.xx * rhs, self.xy * rhs, self.yx * rhs, self.yy * rhs)
+    }
+}
+
+impl Div<f64> for Tensor2 {
+    type Output = Self;
+    fn div(self, rhs: f64) -> Self::Output {
+        Self::new(self.xx / rhs, self.xy / rhs, self.yx / rhs, self.yy / rhs)
+    }
+}
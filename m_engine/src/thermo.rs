@@ -0,0 +1,61 @@
+//! Conversions between kinetic energy, speed and temperature. These are the definitions
+//! the engine uses for statistics and for heat exchange with walls, so analysis built on
+//! them agrees with the reported values.
+//!
+//! Temperature is tied to the mean kinetic energy of a particle through the Boltzmann
+//! constant of `Units`. With default units temperature is measured in "simuK".
+
+use crate::math_core;
+use crate::Units;
+
+/// Kinetic energy of a particle with given mass and speed
+///
+/// ```
+/// use m_engine::thermo;
+///
+/// let energy = thermo::kinetic_energy(2.0, 3.0);
+/// assert_eq!(energy, 9.0);
+/// assert_eq!(thermo::speed_from_kinetic_energy(2.0, energy), 3.0);
+/// ```
+pub fn kinetic_energy(mass: f64, speed: f64) -> f64 {
+    return math_core::kinetic_energy_from_velocity(mass, speed);
+}
+
+/// Speed of a particle with given mass and kinetic energy
+pub fn speed_from_kinetic_energy(mass: f64, energy: f64) -> f64 {
+    return math_core::velocity_from_kinetic_energy(mass, energy);
+}
+
+/// Temperature that corresponds to the kinetic energy of a particle
+///
+/// ```
+/// use m_engine::{thermo, Units};
+///
+/// let units = Units::new(4.0);
+/// let temperature = thermo::temperature_from_energy(10.0, &units);
+/// assert!((thermo::energy_from_temperature(temperature, &units) - 10.0).abs() < 1e-12);
+/// // Larger Boltzmann constant means lower temperature for the same energy
+/// assert!(temperature < thermo::temperature_from_energy(10.0, &Units::default()));
+/// ```
+pub fn temperature_from_energy(energy: f64, units: &Units) -> f64 {
+    return math_core::temp_from_energy(energy, units.boltzmann);
+}
+
+/// Kinetic energy of a particle at the given temperature
+pub fn energy_from_temperature(temperature: f64, units: &Units) -> f64 {
+    return math_core::energy_from_temp(temperature, units.boltzmann);
+}
+
+/// Speed of a particle with given mass at the given temperature
+///
+/// ```
+/// use m_engine::{thermo, Units};
+///
+/// let units = Units::default();
+/// let speed = thermo::speed_from_temperature(2.0, 300.0, &units);
+/// let energy = thermo::kinetic_energy(2.0, speed);
+/// assert!((thermo::temperature_from_energy(energy, &units) - 300.0).abs() < 1e-9);
+/// ```
+pub fn speed_from_temperature(mass: f64, temperature: f64, units: &Units) -> f64 {
+    return speed_from_kinetic_energy(mass, energy_from_temperature(temperature, units));
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Physical constants that define the unit system. By default the simulation works in
+/// "simuK" units where Boltzmann constant is 1, i.e. temperature is measured in energy units.
+/// Setting the constant to its value in the units of mass, length and time used by the
+/// scene makes reported temperatures physical
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Units {
+    pub boltzmann: f64,
+}
+
+impl Units {
+    pub fn new(boltzmann: f64) -> Self {
+        assert!(boltzmann > 0.0);
+        Units { boltzmann }
+    }
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Units { boltzmann: 1.0 }
+    }
+}
@@ -1,9 +1,10 @@
 use crate::prelude::*;
 use crate::math_core::*;
 use std::fmt::Display;
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign};
 
-#[derive(Clone, Debug, Copy, PartialEq)]
+#[derive(Clone, Debug, Copy, PartialEq, Deserialize, Serialize)]
 pub struct Vec2 {
     pub x: f64,
     pub y: f64,
@@ -22,6 +23,11 @@ impl Vec2 {
         Self::new(angle.cos(), angle.sin())
     }
 
+    /// True if neither component is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
     pub fn length(&self) -> f64 {
         (self.x.powi(2) + self.y.powi(2)).sqrt()
     }
@@ -39,6 +45,11 @@ impl Vec2 {
         }
     }
 
+    /// Unit vector in the same direction, or zero if the vector is too short to have one
+    pub fn normalized_or_zero(&self) -> Vec2 {
+        return self.normalized().unwrap_or(Vec2::ZERO);
+    }
+
     pub fn approx_eq(&self, other: Self, epsilon: f64) -> bool {
         approx_eq(self.x, other.x, epsilon) && approx_eq(self.y, other.y, epsilon)
     }
@@ -193,6 +204,28 @@ mod tests {
         assert!(approx_eq(Vec2::new(3.0, 4.0).dot(Vec2::new(-5.0, -6.0)), -39.0, DISTANCE_EPS));
     }
 
+    #[test]
+    fn test_normalized() {
+        let unit = Vec2::new(3.0, 4.0).normalized().unwrap();
+        assert!(unit.approx_eq(Vec2::new(0.6, 0.8), DISTANCE_EPS));
+        assert!(unit.is_unit());
+        assert!(Vec2::ZERO.normalized().is_none());
+        assert!(Vec2::new(DISTANCE_EPS / 2.0, 0.0).normalized().is_none());
+
+        assert!(Vec2::new(0.0, -2.0).normalized_or_zero().approx_eq(-Vec2::UNIT_Y, DISTANCE_EPS));
+        assert_eq!(Vec2::ZERO.normalized_or_zero(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_from_angle_rad() {
+        let half_sqrt2 = 0.5_f64.sqrt();
+        let diagonal = Vec2::from_angle_rad(std::f64::consts::FRAC_PI_4);
+        assert!(diagonal.approx_eq(Vec2::new(half_sqrt2, half_sqrt2), DISTANCE_EPS));
+        assert!(diagonal.is_unit());
+        assert!(Vec2::from_angle_rad(0.0).approx_eq(Vec2::UNIT_X, DISTANCE_EPS));
+        assert!(Vec2::from_angle_rad(std::f64::consts::FRAC_PI_2).approx_eq(Vec2::UNIT_Y, DISTANCE_EPS));
+    }
+
     #[test]
     fn test_cross() {
         assert!(approx_eq(Vec2::new(1.0, 0.0).cross(Vec2::new(1.0, 0.0)), 0.0, DISTANCE_EPS));
@@ -1,51 +1,727 @@
-use crate::motion_resolver;
+use crate::bond;
+use crate::force_field::{self, ForceField};
+use crate::motion_resolver::{self, CollisionHandling, WarmStart};
+use crate::mutual_gravity;
+use crate::neighbor_grid;
 use crate::prelude::*;
-use crate::{Integrator, Particle, ParticleClass, Vec2, Wall, WallClass};
-use std::collections::HashMap;
+use crate::sim_error::get_class;
+use crate::{Integrator, NeighborGrid, Particle, StepEnvironment, StepReport, Vec2};
+use crate::collision_model::{CollisionModel, ElasticModel};
+use std::fmt;
 use std::time::Duration;
 
-#[derive(Debug)]
-pub struct VelocityVerletIntegrator {}
+// Cutoff of the neighbor grid relative to the one needed for collisions at the start of the step
+pub(crate) const NEIGHBOR_CUTOFF_MARGIN: f64 = 1.5;
+
+/// What the integrator does with particles whose state became NaN or infinite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinitePolicy {
+    /// Particle is removed from the simulation
+    #[default]
+    Remove,
+    /// Particle is kept, but stops. Lost coordinates of its position are set to zero
+    Clamp,
+}
+
+/// How the integrator resolves several collisions that happen at the same moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContactResolution {
+    /// One collision at a time in the order of the collision times
+    #[default]
+    Sequential,
+    /// Particle collisions that touch the same particles at the same moment are solved
+    /// together with an iterative impulse solver. This removes the bias of the order
+    Batched,
+}
+
+pub struct VelocityVerletIntegrator {
+    collision_model: Box<dyn CollisionModel>,
+    non_finite_policy: NonFinitePolicy,
+    contact_resolution: ContactResolution,
+    collision_time_tolerance: f64,
+    substeps: usize,
+    island_threads: usize,
+    seed: Option<u64>,
+    line_motion: bool,
+    record_collisions: bool,
+    force_field: Option<Box<dyn ForceField>>,
+}
 
 impl VelocityVerletIntegrator {
+    /// Creates integrator with elastic collisions
     pub fn new() -> Self {
-        VelocityVerletIntegrator {}
+        VelocityVerletIntegrator {
+            collision_model: Box::new(ElasticModel),
+            non_finite_policy: NonFinitePolicy::default(),
+            contact_resolution: ContactResolution::default(),
+            collision_time_tolerance: TIME_SEC_EPS,
+            substeps: 1,
+            island_threads: 1,
+            seed: None,
+            line_motion: false,
+            record_collisions: false,
+            force_field: None,
+        }
+    }
+
+    /// Returns integrator that resolves collisions with the given model
+    pub fn with_collision_model(mut self, collision_model: Box<dyn CollisionModel>) -> Self {
+        self.collision_model = collision_model;
+        self
+    }
+
+    /// Returns integrator that handles non-finite particles with the given policy
+    pub fn with_non_finite_policy(mut self, non_finite_policy: NonFinitePolicy) -> Self {
+        self.non_finite_policy = non_finite_policy;
+        self
+    }
+
+    /// Returns integrator that resolves simultaneous collisions the given way
+    pub fn with_contact_resolution(mut self, contact_resolution: ContactResolution) -> Self {
+        self.contact_resolution = contact_resolution;
+        self
+    }
+
+    /// Returns integrator that accepts collisions up to `tolerance` seconds in the past.
+    /// Such collisions are floating point errors of particles that slightly overlap.
+    /// Default is `TIME_SEC_EPS`, which is too tight for large and fast scenes,
+    /// where particles then pass through each other
+    pub fn with_collision_time_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance >= 0.0);
+        self.collision_time_tolerance = tolerance;
+        self
+    }
+
+    /// Returns integrator that splits each step into `substeps` equal parts.
+    /// Forces are applied and collisions are resolved in each of them
+    pub fn with_substeps(mut self, substeps: usize) -> Self {
+        assert!(substeps > 0);
+        self.substeps = substeps;
+        self
+    }
+
+    /// Returns integrator that resolves independent islands of colliding particles on up to
+    /// `num_threads` threads. Results are the same as with single thread. Substeps don't
+    /// reuse the collision search then
+    pub fn with_island_threads(mut self, num_threads: usize) -> Self {
+        assert!(num_threads > 0);
+        self.island_threads = num_threads;
+        self
+    }
+
+    /// Returns integrator whose wall collisions draw random numbers (sampled wall temperature,
+    /// diffuse scattering) from generators seeded with `seed` and the state of the colliding
+    /// particle. Runs from the same initial state are then identical
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Returns integrator that keeps particles on the x-axis (1D mode). The y-components of
+    /// velocities are zeroed before collisions are searched, and positions and velocities are
+    /// projected onto the axis after them. Particle collisions are then head-on
+    pub fn with_line_motion(mut self, line_motion: bool) -> Self {
+        self.line_motion = line_motion;
+        self
+    }
+
+    /// Returns integrator that records resolved collisions in `StepReport::collision_events`
+    pub fn with_collision_events(mut self, record_collisions: bool) -> Self {
+        self.record_collisions = record_collisions;
+        self
+    }
+
+    /// Returns integrator that applies the smooth pairwise force between particles within
+    /// their interaction cutoffs. Half of its impulse is applied before the move and half
+    /// at the new positions after it, collisions still handle hard contacts
+    pub fn with_force_field(mut self, force_field: Box<dyn ForceField>) -> Self {
+        self.force_field = Some(force_field);
+        self
     }
 }
 
-impl Integrator for VelocityVerletIntegrator {
-    fn step(
-        &self,
-        particles: &mut [Particle],
-        particle_classes: &HashMap<ClassId, ParticleClass>,
-        walls: &[Wall],
-        wall_classes: &HashMap<ClassId, WallClass>,
-        gravity: f64,
-        time_step: Duration,
-    ) {
-        let time_step_sec = time_step.as_secs_f64();
-
-        // apply gravity
-        for particle in particles.iter_mut() {
-            particle.velocity =
-                particle.velocity - Vec2::new(0.0, gravity * time_step.as_secs_f64());
+/// Moves particles onto the x-axis and drops the y-components of their velocities
+fn project_to_line(particles: &mut [Particle]) {
+    for particle in particles.iter_mut() {
+        particle.position.y = 0.0;
+        particle.velocity.y = 0.0;
+    }
+}
+
+/// Finds particles with NaN or infinite position or velocity and handles them
+/// according to the policy, so they don't poison statistics. Returns their indices
+fn guard_non_finite(particles: &mut Vec<Particle>, policy: NonFinitePolicy) -> Vec<usize> {
+    let non_finite: Vec<usize> = particles
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| !p.position.is_finite() || !p.velocity.is_finite())
+        .map(|(i, _)| i)
+        .collect();
+    if non_finite.is_empty() {
+        return non_finite;
+    }
+    match policy {
+        NonFinitePolicy::Remove => {
+            particles.retain(|p| p.position.is_finite() && p.velocity.is_finite());
+        }
+        NonFinitePolicy::Clamp => {
+            let zero_if_not_finite = |v: f64| if v.is_finite() { v } else { 0.0 };
+            for &i in &non_finite {
+                let p = &mut particles[i];
+                p.velocity = Vec2::ZERO;
+                p.position = Vec2::new(
+                    zero_if_not_finite(p.position.x),
+                    zero_if_not_finite(p.position.y),
+                );
+            }
         }
+    }
+    return non_finite;
+}
+
+impl fmt::Debug for VelocityVerletIntegrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VelocityVerletIntegrator").finish_non_exhaustive()
+    }
+}
+
+impl Integrator for VelocityVerletIntegrator {
+    fn step(&self, particles: &mut Vec<Particle>, environment: &StepEnvironment, time_step: Duration) -> StepReport {
+        let time_step_sec = time_step.as_secs_f64() / self.substeps as f64;
+        let particle_classes = environment.particle_classes;
 
         // Lamda that resolve velocity
-        let particle_vs_particle_resolver =
-            motion_resolver::default_particle_vs_particle_velocity_resovler(particle_classes);
-        let particle_vs_wall_resolver = motion_resolver::default_particle_vs_wall_velocity_resolver(
+        let particle_vs_particle_resolver = motion_resolver::particle_vs_particle_velocity_resolver(
+            self.collision_model.as_ref(),
             particle_classes,
-            wall_classes,
+            environment.particle_pair_rules,
         );
-
-        motion_resolver::resolve(
-            particles,
+        let particle_vs_wall_resolver = motion_resolver::particle_vs_wall_velocity_resolver(
+            self.collision_model.as_ref(),
             particle_classes,
-            walls,
-            time_step_sec,
-            &particle_vs_particle_resolver,
-            &particle_vs_wall_resolver,
+            environment.wall_classes,
+            &environment.units,
+            self.seed,
+        );
+        let collision_handling = CollisionHandling {
+            contact_resolution: self.contact_resolution,
+            past_tolerance: self.collision_time_tolerance,
+            ..CollisionHandling::new(&particle_vs_particle_resolver, &particle_vs_wall_resolver)
+        };
+
+        // Substeps reuse the collision search where particles keep their trajectories
+        let mut warm_start = if self.substeps > 1 { Some(WarmStart::default()) } else { None };
+        let mut report = StepReport::default();
+        for substep in 0..self.substeps {
+            let mut collision_events = vec![];
+            let events_sink = if self.record_collisions { Some(&mut collision_events) } else { None };
+            // Positions don't change until collisions are resolved, so single neighbor grid
+            // serves all phases of the substep. Forces may speed particles up before collisions
+            // are searched, so the collision cutoff has a margin. Soft forces may reach further
+            let mut neighbor_grid = NeighborGrid::new();
+            let collision_cutoff = motion_resolver::collision_cutoff(particles, particle_classes, time_step_sec);
+            let cutoff = (collision_cutoff * NEIGHBOR_CUTOFF_MARGIN)
+                .max(neighbor_grid::interaction_range(particles, particle_classes));
+            if cutoff > 0.0 {
+                neighbor_grid.rebuild(particles, cutoff);
+            }
+
+            // apply gravity of the zone (or global one) scaled by the particle class
+            for particle in particles.iter_mut() {
+                let scale = get_class(particle_classes, particle.class()).gravity_scale();
+                particle.velocity += environment.gravity_at(particle.position) * (scale * time_step_sec);
+            }
+
+            // apply attraction between particles
+            if let Some(mutual_gravity) = environment.mutual_gravity {
+                mutual_gravity::apply_mutual_gravity(
+                    particles,
+                    particle_classes,
+                    mutual_gravity,
+                    time_step_sec,
+                );
+            }
+
+            // apply spring forces of bonds
+            bond::apply_bonds(particles, particle_classes, environment.bonds, environment.boundary, time_step_sec);
+
+            // first half of the pairwise force impulse, at the starting positions
+            if let Some(force_field) = &self.force_field {
+                if !neighbor_grid.is_empty() {
+                    force_field::apply_force_field(
+                        particles,
+                        particle_classes,
+                        &neighbor_grid,
+                        environment.boundary,
+                        force_field.as_ref(),
+                        time_step_sec / 2.0,
+                    );
+                }
+            }
+
+            // Forces may push particles off the line
+            if self.line_motion {
+                project_to_line(particles);
+            }
+
+            let substep_report = if self.island_threads > 1 {
+                motion_resolver::resolve_islands(
+                    particles,
+                    environment,
+                    &neighbor_grid,
+                    time_step_sec,
+                    &collision_handling,
+                    self.island_threads,
+                    events_sink,
+                )
+            } else {
+                motion_resolver::resolve(
+                    particles,
+                    environment,
+                    &neighbor_grid,
+                    time_step_sec,
+                    &collision_handling,
+                    warm_start.as_mut(),
+                    events_sink,
+                )
+            };
+            report.collision_virial += substep_report.collision_virial;
+            report.pair_checks += substep_report.pair_checks;
+            report.collisions += substep_report.collisions;
+            report.add_wall_heat(&substep_report.wall_heat);
+            report.add_wall_impulse(&substep_report.wall_impulse);
+            report.substeps += 1;
+            // second half of the pairwise force impulse, at the new positions
+            if let Some(force_field) = &self.force_field {
+                let interaction_range = neighbor_grid::interaction_range(particles, particle_classes);
+                if interaction_range > 0.0 {
+                    neighbor_grid.rebuild(particles, interaction_range);
+                    force_field::apply_force_field(
+                        particles,
+                        particle_classes,
+                        &neighbor_grid,
+                        environment.boundary,
+                        force_field.as_ref(),
+                        time_step_sec / 2.0,
+                    );
+                }
+            }
+            // Times of events are measured from the start of the whole step
+            for mut event in collision_events {
+                event.time += substep as f64 * time_step_sec;
+                report.collision_events.push(event);
+            }
+            // Walls at an angle deflect particles off the line
+            if self.line_motion {
+                project_to_line(particles);
+            }
+        }
+
+        report.non_finite_particles = guard_non_finite(particles, self.non_finite_policy);
+        return report;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math_core, ParticleClass, ParticlePairRules, Polygon, Simulation, SpringParams};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_non_finite_guard() {
+        use crate::{Statistics, Units};
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let make_particles = || {
+            vec![
+                Particle::new(Vec2::new(-5.0, 0.0), Vec2::new(1.0, 0.0), 1),
+                Particle::new(Vec2::new(0.0, 0.0), Vec2::new(f64::NAN, 1.0), 1),
+                Particle::new(Vec2::new(5.0, 0.0), Vec2::new(0.0, 2.0), 1),
+            ]
+        };
+        let step = |integrator: VelocityVerletIntegrator, particles: &mut Vec<Particle>| {
+            integrator.step(
+                particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+                Duration::from_millis(100),
+            )
+        };
+
+        // Bad particle is removed by default
+        let mut particles = make_particles();
+        let report = step(VelocityVerletIntegrator::new(), &mut particles);
+        assert_eq!(report.non_finite_particles, vec![1]);
+        assert_eq!(particles.len(), 2);
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        assert!(stats.total_energy.is_finite());
+        assert!(stats.temperature.is_finite());
+        assert!(stats.mean_speed.is_finite());
+
+        // Or stopped
+        let mut particles = make_particles();
+        let integrator = VelocityVerletIntegrator::new().with_non_finite_policy(NonFinitePolicy::Clamp);
+        let report = step(integrator, &mut particles);
+        assert_eq!(report.non_finite_particles, vec![1]);
+        assert_eq!(particles.len(), 3);
+        assert_eq!(particles[1].velocity, Vec2::ZERO);
+        assert_eq!(particles[1].position.x, 0.0);
+        let stats = Statistics::build(&particles, &classes, &Units::default());
+        assert!(stats.total_energy.is_finite());
+        assert!(stats.temperature.is_finite());
+    }
+
+    #[test]
+    fn test_collision_events() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 1.0));
+        let step = |integrator: VelocityVerletIntegrator| {
+            let mut particles = vec![
+                Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(1.0, 0.0), 1),
+                Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), 1),
+            ];
+            integrator.step(
+                &mut particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+                Duration::from_millis(1500),
+            )
+        };
+
+        // Not recorded unless asked for
+        assert!(step(VelocityVerletIntegrator::new()).collision_events.is_empty());
+        // Collision in the second substep is timed from the start of the step
+        let report = step(VelocityVerletIntegrator::new().with_substeps(2).with_collision_events(true));
+        assert_eq!(report.collisions, 1);
+        assert_eq!(report.collision_events.len(), 1);
+        assert!(math_core::approx_eq(report.collision_events[0].time, 1.0, DISTANCE_EPS));
+        assert!(math_core::approx_eq(report.collision_events[0].impulse, 2.0, DISTANCE_EPS));
+    }
+
+    #[test]
+    fn test_gravity_scale() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Heavy", 1.0, 0.1));
+        classes.insert(2, ParticleClass::new("Floating", 1.0, 0.1).with_gravity_scale(-1.0));
+        let mut particles = vec![
+            Particle::new(Vec2::new(-5.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(5.0, 0.0), Vec2::ZERO, 2),
+        ];
+        VelocityVerletIntegrator::new().step(
+            &mut particles,
+            &StepEnvironment::new(
+                &classes,
+                &ParticlePairRules::new(),
+                &[],
+                &HashMap::new(),
+            )
+            .with_gravity(Vec2::new(0.0, -10.0)),
+            Duration::from_millis(100),
+        );
+        assert!(particles[0].velocity.approx_eq(Vec2::new(0.0, -1.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[1].velocity.approx_eq(Vec2::new(0.0, 1.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[0].position.y < 0.0);
+        assert!(particles[1].position.y > 0.0);
+    }
+
+    #[test]
+    fn test_pair_restitution() {
+        // Sand vs rock bounces are inelastic, sand vs sand stay elastic
+        let mut classes = HashMap::new();
+        classes.insert(0, ParticleClass::new("Sand", 1.0, 0.5));
+        classes.insert(1, ParticleClass::new("Rock", 1.0, 0.5));
+        let run = |class2: ClassId| {
+            let mut simulation = Simulation::new(classes.clone(), HashMap::new(), Vec2::ZERO);
+            simulation.set_restitution(1, 0, 0.5);
+            simulation.spawn_particle(Particle::new(Vec2::new(-2.0, 0.0), Vec2::new(1.0, 0.0), 0));
+            simulation.spawn_particle(Particle::new(Vec2::new(2.0, 0.0), Vec2::new(-1.0, 0.0), class2));
+            let integrator = VelocityVerletIntegrator::new();
+            let time_step = Duration::from_millis(100);
+            for _ in 0..30 {
+                simulation.step(&integrator, time_step, None);
+            }
+            return simulation.particles().iter().map(|p| p.velocity).collect::<Vec<Vec2>>();
+        };
+
+        let elastic = run(0);
+        assert!(elastic[0].approx_eq(Vec2::new(-1.0, 0.0), 1e-9));
+        assert!(elastic[1].approx_eq(Vec2::new(1.0, 0.0), 1e-9));
+
+        // Separation speed is half of the approach speed, so 3/4 of the energy is lost
+        let inelastic = run(1);
+        assert!(inelastic[0].approx_eq(Vec2::new(-0.5, 0.0), 1e-9));
+        assert!(inelastic[1].approx_eq(Vec2::new(0.5, 0.0), 1e-9));
+        let energy = |velocities: &[Vec2]| velocities.iter().map(|v| 0.5 * v.length_sq()).sum::<f64>();
+        assert!(math_core::approx_eq(energy(&inelastic), 0.25 * energy(&elastic), 1e-9));
+    }
+
+    #[test]
+    fn test_sideways_gravity() {
+        let mut classes = HashMap::new();
+        classes.insert(0, ParticleClass::new("Test", 1.0, 0.1));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::new(1.0, 0.0));
+        simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 0));
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(10);
+        for _ in 0..100 {
+            simulation.step(&integrator, time_step, None);
+        }
+        // One second of unit acceleration along x. Nothing pulls it down
+        let particle = &simulation.particles()[0];
+        assert!(particle.velocity.approx_eq(Vec2::new(1.0, 0.0), 1e-9));
+        // Velocity is kicked before the drift, so the path is a step's worth longer than a t^2 / 2
+        assert!(math_core::approx_eq(particle.position.x, 0.5, 0.01));
+        assert_eq!(particle.position.y, 0.0);
+    }
+
+    #[test]
+    fn test_gravity_zones() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.1));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::new(0.0, -10.0));
+        // Zero-g chamber on the left. Overlapping zone after it has no effect there
+        simulation.add_gravity_zone(Polygon::new_rectangle(-10.0, -10.0, 0.0, 10.0), Vec2::ZERO);
+        simulation.add_gravity_zone(Polygon::new_rectangle(-20.0, -10.0, 0.0, 10.0), Vec2::new(5.0, 0.0));
+        simulation.spawn_particle(Particle::new(Vec2::new(-5.0, 0.0), Vec2::ZERO, 1));
+        simulation.spawn_particle(Particle::new(Vec2::new(5.0, 0.0), Vec2::ZERO, 1));
+        simulation.spawn_particle(Particle::new(Vec2::new(-15.0, 0.0), Vec2::ZERO, 1));
+
+        let mut particles = simulation.take_particles();
+        VelocityVerletIntegrator::new().step(
+            &mut particles,
+            &StepEnvironment::new(
+                simulation.particle_classes(),
+                simulation.particle_pair_rules(),
+                simulation.walls(),
+                simulation.wall_classes(),
+            )
+            .with_bonds(simulation.bonds())
+            .with_gravity(simulation.gravity_at(Duration::ZERO))
+            .with_gravity_zones(simulation.gravity_zones())
+            .with_mutual_gravity(simulation.mutual_gravity())
+            .with_units(*simulation.units()),
+            Duration::from_millis(100),
+        );
+        assert_eq!(particles[0].velocity, Vec2::ZERO);
+        assert_eq!(particles[0].position, Vec2::new(-5.0, 0.0));
+        assert!(particles[1].velocity.approx_eq(Vec2::new(0.0, -1.0), DOUBLE_COMPARE_EPS_STRICT));
+        assert!(particles[2].velocity.approx_eq(Vec2::new(0.5, 0.0), DOUBLE_COMPARE_EPS_STRICT));
+    }
+
+    #[test]
+    fn test_collision_time_tolerance() {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Large", 1.0, 1000.0));
+        // Far from the origin and fast. Rounding of positions at this scale leaves the pair
+        // overlapping by 1e-2, so the contact was 5e-6 seconds ago
+        let make_particles = || {
+            vec![
+                Particle::new(Vec2::new(1e6, 0.0), Vec2::new(1000.0, 0.0), 1),
+                Particle::new(Vec2::new(1e6 + 2000.0 - 1e-2, 0.0), Vec2::new(-1000.0, 0.0), 1),
+            ]
+        };
+        let step = |integrator: VelocityVerletIntegrator, particles: &mut Vec<Particle>| {
+            integrator.step(
+                particles,
+                &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+                Duration::from_millis(10),
+            );
+        };
+
+        // Default tolerance takes the contact for the past one. Particles pass through
+        let mut particles = make_particles();
+        step(VelocityVerletIntegrator::new(), &mut particles);
+        assert_eq!(particles[0].velocity, Vec2::new(1000.0, 0.0));
+        assert_eq!(particles[1].velocity, Vec2::new(-1000.0, 0.0));
+
+        // Tolerance that covers the error bounces them
+        let mut particles = make_particles();
+        step(VelocityVerletIntegrator::new().with_collision_time_tolerance(1e-4), &mut particles);
+        assert!(particles[0].velocity.approx_eq(Vec2::new(-1000.0, 0.0), 1e-6));
+        assert!(particles[1].velocity.approx_eq(Vec2::new(1000.0, 0.0), 1e-6));
+        assert!(particles[0].position.x < particles[1].position.x);
+    }
+
+    #[test]
+    fn test_spring_oscillation_frequency() {
+        // Tiny particles so they never collide
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.01));
+        let mut simulation = Simulation::new(classes, HashMap::new(), Vec2::ZERO);
+        // Particles are spawned in reverse order to make sure bonds don't rely on indices
+        let id2 = simulation.spawn_particle(Particle::new(Vec2::new(2.5, 0.0), Vec2::ZERO, 1));
+        let id1 = simulation.spawn_particle(Particle::new(Vec2::ZERO, Vec2::ZERO, 1));
+        let stiffness = 2.0;
+        simulation.add_bond(id1, id2, SpringParams::new(2.0, stiffness, 0.0));
+
+        // Expected period. Reduced mass is 0.5
+        let omega = (stiffness / 0.5_f64).sqrt();
+        let expected_period = 2.0 * std::f64::consts::PI / omega;
+
+        // Simulate and record moments when spring passes the rest length while stretching
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_micros(100);
+        let mut time = 0.0;
+        let mut prev_stretch = 0.5;
+        let mut crossings = vec![];
+        while crossings.len() < 3 {
+            simulation.step(&integrator, time_step, None);
+            time += time_step.as_secs_f64();
+
+            let particles = simulation.particles();
+            let stretch = (particles[0].position - particles[1].position).length() - 2.0;
+            if prev_stretch < 0.0 && stretch >= 0.0 {
+                crossings.push(time);
+            }
+            prev_stretch = stretch;
+        }
+
+        assert!(math_core::approx_eq(
+            crossings[1] - crossings[0],
+            expected_period,
+            expected_period * 0.01
+        ));
+        assert!(math_core::approx_eq(
+            crossings[2] - crossings[1],
+            expected_period,
+            expected_period * 0.01
+        ));
+    }
+
+    #[test]
+    fn test_seeded_thermal_walls() {
+        use crate::{Wall, WallClass};
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        // Hot walls sample their temperature, rough one also scatters particles
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Hot", 5.0, 0.5));
+        wall_classes.insert(2, WallClass::new("Rough", 5.0, 0.5).with_diffuse_reflection(true));
+        // Rough floor replaces the bottom wall of the box
+        let mut walls = Wall::make_box(-10.0, -10.0, 10.0, 10.0, 1.0, 1);
+        walls[0] = Wall::new(Polygon::new_rectangle(-10.0, -10.0, 10.0, -9.0), 2);
+        let particles: Vec<Particle> = (0..10)
+            .map(|i| {
+                let angle = i as f64 * 0.7;
+                Particle::new(
+                    Vec2::new(i as f64 * 1.8 - 8.0, i as f64 * 1.5 - 7.0),
+                    Vec2::new(angle.cos(), angle.sin()) * 4.0,
+                    1,
+                )
+            })
+            .collect();
+        let run = |integrator: VelocityVerletIntegrator| {
+            let mut particles = particles.clone();
+            for _ in 0..300 {
+                integrator.step(
+                    &mut particles,
+                    &StepEnvironment::new(&classes, &ParticlePairRules::new(), &walls, &wall_classes),
+                    Duration::from_millis(20),
+                );
+            }
+            return particles.iter().map(|p| p.velocity).collect::<Vec<_>>();
+        };
+
+        let first = run(VelocityVerletIntegrator::new().with_seed(42));
+        assert_eq!(first, run(VelocityVerletIntegrator::new().with_seed(42)));
+        // Order of the islands doesn't matter
+        assert_eq!(first, run(VelocityVerletIntegrator::new().with_seed(42).with_island_threads(4)));
+        assert_ne!(first, run(VelocityVerletIntegrator::new().with_seed(7)));
+    }
+
+    #[test]
+    fn test_line_motion_cradle() {
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Class1", 1.0, 0.5));
+        // Row of resting particles with small gaps. Striker comes from the left slightly
+        // off the line and at an angle, gravity pulls everything down
+        let mut particles = vec![Particle::new(Vec2::new(-3.0, 0.2), Vec2::new(2.0, 0.5), 1)];
+        for i in 0..4 {
+            particles.push(Particle::new(Vec2::new(i as f64 * 1.1, 0.0), Vec2::ZERO, 1));
+        }
+        let integrator = VelocityVerletIntegrator::new().with_line_motion(true);
+        for _ in 0..300 {
+            integrator.step(
+                &mut particles,
+                &StepEnvironment::new(
+                    &classes,
+                    &ParticlePairRules::new(),
+                    &[],
+                    &HashMap::new(),
+                )
+                .with_gravity(Vec2::new(0.0, -10.0)),
+                Duration::from_millis(10),
+            );
+        }
+
+        assert!(particles.iter().all(|p| p.position.y == 0.0 && p.velocity.y == 0.0));
+        // Momentum went down the line to the last particle, the rest stopped in order
+        for (i, particle) in particles.iter().enumerate() {
+            let expected = if i == 4 { 2.0 } else { 0.0 };
+            assert!(math_core::approx_eq(particle.velocity.x, expected, 1e-9), "particle {}", i);
+        }
+        assert!(particles.windows(2).all(|w| w[0].position.x < w[1].position.x));
+    }
+
+    #[test]
+    fn test_lennard_jones_pair() {
+        use crate::LennardJones;
+
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Atom", 1.0, 0.2).with_interaction_cutoff(1.25));
+        let field = LennardJones::new(1.0, 1.0);
+        let r0 = field.equilibrium_distance();
+        let integrator = VelocityVerletIntegrator::new().with_force_field(Box::new(field));
+        let run = |particles: &mut Vec<Particle>, steps: usize| {
+            for _ in 0..steps {
+                integrator.step(
+                    particles,
+                    &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+                    Duration::from_millis(1),
+                );
+            }
+        };
+
+        // Pair at the equilibrium distance stays at rest
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(r0, 0.0), Vec2::ZERO, 1),
+        ];
+        run(&mut particles, 100);
+        for particle in &particles {
+            assert!(particle.velocity.length() < 1e-12);
+        }
+        assert!(math_core::approx_eq(particles[1].position.x - particles[0].position.x, r0, 1e-12));
+
+        // Stretched pair oscillates around it and conserves energy
+        let energy = |particles: &[Particle]| {
+            let kinetic: f64 = particles.iter().map(|p| 0.5 * p.velocity.length_sq()).sum();
+            kinetic + field.potential((particles[1].position - particles[0].position).length())
+        };
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(1.2 * r0, 0.0), Vec2::ZERO, 1),
+        ];
+        let initial_energy = energy(&particles);
+        let mut min_distance = f64::MAX;
+        for _ in 0..200 {
+            run(&mut particles, 10);
+            min_distance = min_distance.min(particles[1].position.x - particles[0].position.x);
+            assert!(math_core::approx_eq(energy(&particles), initial_energy, 1e-4));
+        }
+        assert!(min_distance < r0);
+        // Without the field the particles don't move
+        let mut particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(1.2 * r0, 0.0), Vec2::ZERO, 1),
+        ];
+        VelocityVerletIntegrator::new().step(
+            &mut particles,
+            &StepEnvironment::new(&classes, &ParticlePairRules::new(), &[], &HashMap::new()),
+            Duration::from_millis(1),
         );
+        assert_eq!(particles[0].velocity, Vec2::ZERO);
     }
 }
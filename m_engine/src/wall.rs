@@ -1,15 +1,64 @@
 use crate::prelude::*;
-use crate::{Polygon, Vec2};
+use crate::{Polygon, Vec2, WallClass};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+// Number of segments of the polygon that approximates circular wall
+const CIRCLE_SEGMENTS: usize = 64;
+
+/// Exact geometry of the wall that particles collide with
+#[derive(Clone, Copy, Debug, PartialEq, Default, Deserialize, Serialize)]
+pub enum WallGeometry {
+    /// The polygon of the wall
+    #[default]
+    Polygon,
+    /// Circle. Inward circle is a container that keeps particles inside,
+    /// otherwise it's a solid disk that particles bounce off from outside
+    Circle { center: Vec2, radius: f64, inward: bool },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Wall {
     polygon: Polygon,
+    #[serde(default)]
+    geometry: WallGeometry,
     class: ClassId,
+    /// Own temperature of a wall with finite heat capacity. None until the wall
+    /// exchanges any heat, then the class temperature is the starting point
+    #[serde(default)]
+    temperature: Option<f64>,
 }
 
 impl Wall {
     pub fn new(polygon: Polygon, class: ClassId) -> Self {
-        Wall { polygon, class }
+        Wall { polygon, geometry: WallGeometry::Polygon, class, temperature: None }
+    }
+
+    /// Makes circular wall. Particles collide with the exact circle. The polygon of the wall
+    /// approximates it for drawing: the disk, or for inward wall the ring of given thickness
+    /// around the circle
+    pub fn make_circle(center: Vec2, radius: f64, inward: bool, thickness: f64, class: ClassId) -> Wall {
+        let circle = |r: f64| -> Vec<Vec2> {
+            (0..=CIRCLE_SEGMENTS)
+                .map(|i| {
+                    let angle = 2.0 * std::f64::consts::PI * i as f64 / CIRCLE_SEGMENTS as f64;
+                    center + Vec2::from_angle_rad(angle) * r
+                })
+                .collect()
+        };
+        let points = if inward {
+            // Polygons have no holes. Ring is cut open along the x-axis: outer circle ccw,
+            // then inner one cw. Both ends of the cut are the same points
+            let mut points = circle(radius + thickness);
+            points.extend(circle(radius).into_iter().rev());
+            points
+        } else {
+            let mut points = circle(radius);
+            points.pop();
+            points
+        };
+        let mut wall = Wall::new(Polygon::from(points), class);
+        wall.geometry = WallGeometry::Circle { center, radius, inward };
+        return wall;
     }
 
     pub fn make_box(
@@ -67,7 +116,109 @@ impl Wall {
         self.class
     }
 
+    /// Polygon of the wall. It approximates circular walls and is only drawn then
     pub fn polygon(&self) -> &Polygon {
         &self.polygon
     }
+
+    pub fn geometry(&self) -> WallGeometry {
+        self.geometry
+    }
+
+    /// Length of the surface particles hit
+    pub fn perimeter(&self) -> f64 {
+        match self.geometry {
+            WallGeometry::Polygon => self.polygon.perimeter(),
+            WallGeometry::Circle { radius, .. } => 2.0 * std::f64::consts::PI * radius,
+        }
+    }
+
+    /// Current temperature of the wall. `class` must be the class of the wall
+    pub fn temperature(&self, class: &WallClass) -> f64 {
+        self.temperature.unwrap_or(class.temperature())
+    }
+
+    /// Adds `energy` received from particles to the wall. Only walls whose class has
+    /// heat capacity change their temperature. Temperature doesn't go below zero
+    pub fn exchange_heat(&mut self, class: &WallClass, energy: f64) {
+        if let Some(heat_capacity) = class.heat_capacity() {
+            self.temperature = Some((self.temperature(class) + energy / heat_capacity).max(0.0));
+        }
+    }
+
+    /// Walls are equal if they have the same class and the points of their polygons match
+    /// within `epsilon` in the same order. Own temperatures are not compared
+    pub fn approx_eq(&self, other: &Wall, epsilon: f64) -> bool {
+        self.class == other.class
+            && self.geometry == other.geometry
+            && self.polygon.points.len() == other.polygon.points.len()
+            && self.polygon.points.iter().zip(&other.polygon.points).all(|(a, b)| a.approx_eq(*b, epsilon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math_core, Integrator, Particle, ParticleClass, ParticlePairRules, StepEnvironment};
+    use crate::VelocityVerletIntegrator;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_approx_eq() {
+        let wall = Wall::new(Polygon::new_rectangle(0.0, 0.0, 2.0, 1.0), 1);
+        let close = Wall::new(Polygon::new_rectangle(0.0, 1e-7, 2.0, 1.0), 1);
+        assert!(wall.approx_eq(&close, 1e-6));
+        assert!(!wall.approx_eq(&close, 1e-8));
+
+        let other_class = Wall::new(Polygon::new_rectangle(0.0, 0.0, 2.0, 1.0), 2);
+        assert!(!wall.approx_eq(&other_class, 1e-6));
+        // Triangle that shares the first points
+        let mut points = wall.polygon().points.clone();
+        points.pop();
+        let triangle = Wall::new(Polygon::from(points), 1);
+        assert!(!wall.approx_eq(&triangle, 1e-6));
+        assert!(!triangle.approx_eq(&wall, 1e-6));
+    }
+
+    #[test]
+    fn test_circle_container() {
+        let center = Vec2::new(2.0, -1.0);
+        let radius = 5.0;
+        let wall = Wall::make_circle(center, radius, true, 0.5, 1);
+        assert!(math_core::approx_eq(wall.perimeter(), 2.0 * std::f64::consts::PI * radius, 1e-12));
+        // Drawn ring doesn't cover the inside
+        assert!(!wall.polygon().contains_point(center));
+        assert!(wall.polygon().contains_point(center + Vec2::new(0.0, radius + 0.25)));
+
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.25));
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.0));
+        let walls = vec![wall];
+        // Off-center shot. Particle goes around the container along a star of chords
+        let velocity = Vec2::new(3.0, 1.0);
+        let mut particles = vec![Particle::new(center + Vec2::new(0.0, 2.0), velocity, 1)];
+        let integrator = VelocityVerletIntegrator::new();
+        let mut bounces = 0;
+        let mut angular_momentum = None;
+        for _ in 0..1000 {
+            let report = integrator.step(
+                &mut particles,
+                &StepEnvironment::new(&p_classes, &ParticlePairRules::new(), &walls, &w_classes),
+                Duration::from_millis(10),
+            );
+            bounces += report.collisions;
+            let particle = &particles[0];
+            let offset = particle.position - center;
+            assert!(offset.length() <= radius - 0.25 + 1e-9);
+            assert!(math_core::approx_eq(particle.velocity.length(), velocity.length(), 1e-9));
+            // Normal of the circle goes through its center, so bounces don't change it
+            let momentum = offset.cross(particle.velocity);
+            let expected = *angular_momentum.get_or_insert(momentum);
+            assert!(math_core::approx_eq(momentum, expected, 1e-9));
+        }
+        // Chord is about 8.6 long and takes under 3 s
+        assert!(bounces > 3);
+    }
 }
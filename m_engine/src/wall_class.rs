@@ -1,9 +1,70 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+/// Coefficient of restitution of the wall as function of the normal impact speed.
+/// Real materials bounce less when hit harder
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum RestitutionCurve {
+    /// Same coefficient at any speed
+    Constant(f64),
+    /// Linear interpolation between (speed, restitution) points sorted by speed.
+    /// Outside of the points the nearest one is used
+    Piecewise(Vec<(f64, f64)>),
+    /// Decays from `max` at rest towards `min` at high speed:
+    /// min + (max - min) * exp(-speed / decay_speed)
+    Exponential { max: f64, min: f64, decay_speed: f64 },
+}
+
+impl RestitutionCurve {
+    /// Restitution at given normal impact speed
+    pub fn at(&self, normal_speed: f64) -> f64 {
+        match self {
+            RestitutionCurve::Constant(restitution) => *restitution,
+            RestitutionCurve::Piecewise(points) => {
+                let after = points.iter().position(|&(speed, _)| speed > normal_speed);
+                match after {
+                    None => points.last().map_or(1.0, |p| p.1),
+                    Some(0) => points[0].1,
+                    Some(i) => {
+                        let (s0, e0) = points[i - 1];
+                        let (s1, e1) = points[i];
+                        e0 + (e1 - e0) * (normal_speed - s0) / (s1 - s0)
+                    }
+                }
+            }
+            RestitutionCurve::Exponential { max, min, decay_speed } => {
+                min + (max - min) * (-normal_speed / decay_speed).exp()
+            }
+        }
+    }
+
+    /// Restitution must stay in [0, 1]. Points of piecewise curve must be sorted by
+    /// speed and there must be at least one
+    pub fn is_valid(&self) -> bool {
+        let in_range = |e: f64| (0.0..=1.0).contains(&e);
+        match self {
+            RestitutionCurve::Constant(restitution) => in_range(*restitution),
+            RestitutionCurve::Piecewise(points) => {
+                !points.is_empty()
+                    && points.iter().all(|&(speed, e)| speed >= 0.0 && in_range(e))
+                    && points.windows(2).all(|w| w[0].0 < w[1].0)
+            }
+            RestitutionCurve::Exponential { max, min, decay_speed } => {
+                in_range(*max) && in_range(*min) && *decay_speed > 0.0
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 /// Wall class. Describes the properties of the wall.
 pub struct WallClass {
     name: String,
     temperature: f64,
     heat_conductivity: f64,
+    diffuse_reflection: bool,
+    absorbing: bool,
+    restitution: Option<RestitutionCurve>,
+    heat_capacity: Option<f64>,
 }
 
 impl WallClass {
@@ -19,9 +80,44 @@ impl WallClass {
             name: name.to_string(),
             temperature,
             heat_conductivity,
+            diffuse_reflection: false,
+            absorbing: false,
+            restitution: None,
+            heat_capacity: None,
         }
     }
 
+    /// Returns copy of the wall class with diffuse reflection enabled or disabled.
+    /// Rough walls scatter particles in random directions instead of mirroring them
+    pub fn with_diffuse_reflection(mut self, diffuse_reflection: bool) -> Self {
+        self.diffuse_reflection = diffuse_reflection;
+        self
+    }
+
+    /// Returns copy of the wall class that absorbs or reflects particles.
+    /// Absorbing walls remove particles that hit them from the simulation
+    pub fn with_absorbing(mut self, absorbing: bool) -> Self {
+        self.absorbing = absorbing;
+        self
+    }
+
+    /// Returns copy of the wall class that damps the bounce according to the curve.
+    /// The normal component of the outgoing velocity is scaled by the restitution
+    /// at the impact speed, on top of the collision model. Panics if the curve is invalid
+    pub fn with_restitution(mut self, restitution: RestitutionCurve) -> Self {
+        assert!(restitution.is_valid(), "Invalid restitution curve {:?}", restitution);
+        self.restitution = Some(restitution);
+        self
+    }
+
+    /// Energy that changes the temperature of a wall of this class by one degree.
+    /// Walls without it are infinite reservoirs that keep the class temperature
+    pub fn with_heat_capacity(mut self, heat_capacity: f64) -> Self {
+        assert!(heat_capacity > 0.0 && heat_capacity.is_finite(), "Invalid heat capacity {}", heat_capacity);
+        self.heat_capacity = Some(heat_capacity);
+        self
+    }
+
     /// Get the name of the wall.
     pub fn name(&self) -> &str {
         &self.name
@@ -36,4 +132,46 @@ impl WallClass {
     pub fn heat_conductivity(&self) -> f64 {
         self.heat_conductivity
     }
+
+    /// Whether the wall scatters particles diffusely
+    pub fn diffuse_reflection(&self) -> bool {
+        self.diffuse_reflection
+    }
+
+    /// Whether the wall removes particles that hit it
+    pub fn absorbing(&self) -> bool {
+        self.absorbing
+    }
+
+    /// Restitution curve of the wall. None if the collision model alone decides
+    pub fn restitution(&self) -> Option<&RestitutionCurve> {
+        self.restitution.as_ref()
+    }
+
+    pub fn heat_capacity(&self) -> Option<f64> {
+        self.heat_capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restitution_curves() {
+        let piecewise = RestitutionCurve::Piecewise(vec![(1.0, 0.9), (3.0, 0.5)]);
+        assert!(piecewise.is_valid());
+        assert_eq!(piecewise.at(0.0), 0.9);
+        assert!((piecewise.at(2.0) - 0.7).abs() < 1e-12);
+        assert_eq!(piecewise.at(10.0), 0.5);
+
+        let exponential = RestitutionCurve::Exponential { max: 1.0, min: 0.2, decay_speed: 5.0 };
+        assert_eq!(exponential.at(0.0), 1.0);
+        assert!(exponential.at(5.0) < exponential.at(1.0));
+        assert!((exponential.at(1000.0) - 0.2).abs() < 1e-12);
+
+        assert!(!RestitutionCurve::Constant(1.5).is_valid());
+        assert!(!RestitutionCurve::Piecewise(vec![]).is_valid());
+        assert!(!RestitutionCurve::Piecewise(vec![(2.0, 0.5), (1.0, 0.9)]).is_valid());
+    }
 }
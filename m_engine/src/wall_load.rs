@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Sums the impulse each wall received over the last `window` of simulation time.
+/// Fed with `StepReport::wall_impulse` after every step. Shows which walls are under load
+#[derive(Debug, Clone)]
+pub struct WallLoad {
+    window: Duration,
+    /// End time of the step and impulses of the walls during it
+    steps: VecDeque<(Duration, Vec<f64>)>,
+}
+
+impl WallLoad {
+    pub fn new(window: Duration) -> Self {
+        WallLoad {
+            window,
+            steps: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds impulses of the step that ended at `time`. Steps that ended more than `window`
+    /// before it are dropped
+    pub fn add(&mut self, time: Duration, wall_impulse: &[f64]) {
+        self.steps.push_back((time, wall_impulse.to_vec()));
+        let start = time.saturating_sub(self.window);
+        while self.steps.front().is_some_and(|(t, _)| *t <= start) {
+            self.steps.pop_front();
+        }
+    }
+
+    /// Total impulse of each wall over the window. Walls without hits may be missing at the end
+    pub fn impulses(&self) -> Vec<f64> {
+        let mut totals = vec![];
+        for (_, impulses) in &self.steps {
+            if totals.len() < impulses.len() {
+                totals.resize(impulses.len(), 0.0);
+            }
+            for (total, impulse) in totals.iter_mut().zip(impulses) {
+                *total += impulse;
+            }
+        }
+        return totals;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Particle, ParticleClass, Simulation, Vec2, VelocityVerletIntegrator, Wall, WallClass};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_stream_loads_struck_wall() {
+        let mut p_classes = HashMap::new();
+        p_classes.insert(1, ParticleClass::new("Gas", 1.0, 0.2));
+        // No heat exchange, so walls reflect particles without changing their speed
+        let mut w_classes = HashMap::new();
+        w_classes.insert(1, WallClass::new("Wall", 1.0, 0.0));
+        let mut simulation = Simulation::new(p_classes, w_classes, Vec2::ZERO);
+        // Bottom, right, top and left walls
+        simulation.spawn_walls(&Wall::make_box(-10.0, -10.0, 10.0, 10.0, 1.0, 1));
+        // Stream towards the right wall. Each particle gives it 2 * m * v
+        for i in 0..5 {
+            simulation.spawn_particle(Particle::new(Vec2::new(0.0, i as f64 * 2.0 - 4.0), Vec2::new(5.0, 0.0), 1));
+        }
+
+        let integrator = VelocityVerletIntegrator::new();
+        let time_step = Duration::from_millis(10);
+        let mut load = WallLoad::new(Duration::from_secs(2));
+        let mut time = Duration::ZERO;
+        // Particles reach the right wall after 1.76 s and the left one after 5.4 s
+        for _ in 0..300 {
+            let report = simulation.step(&integrator, time_step, None);
+            time += time_step;
+            load.add(time, &report.wall_impulse);
+        }
+        let impulses = load.impulses();
+        assert_eq!(impulses.len(), 4);
+        assert!((impulses[1] - 50.0).abs() < 1e-6);
+        for i in [0, 2, 3] {
+            assert!(impulses[i].abs() < 1e-9);
+        }
+
+        // Hits leave the window after it passes
+        load.add(time + Duration::from_secs(2), &[]);
+        assert!(load.impulses().iter().all(|&i| i == 0.0));
+    }
+}
@@ -1,23 +1,46 @@
-use crate::components::{FramesTimeline, PlaybackControl, StatisticsReport, TimeIndicator};
-use crate::resources::{GlobalMaterials, GlobalMeshes, SimInfo, SkinGraphics, TextStyles};
-use crate::systems;
+use crate::components::debug_overlay::MAX_PARTICLE_LABELS;
+use crate::components::{
+    DebugOverlay, FramesTimeline, ObjectVisibility, ParticleLabel, ParticleSizing, ParticleSelection, PlaybackControl, StatisticsReport,
+    TimeIndicator, TimeSeriesOverlay, WallInfo, WallSelection, WallTint,
+};
+use crate::input_log::{InputMode, INPUT_FRAME_TIME};
+use crate::resources::{
+    GlobalMaterials, GlobalMeshes, InputRecorder, InputReplay, SimInfo, SkinGraphics, TextStyles,
+};
+use crate::systems::{self, skins_update};
+use crate::utils::{self, VIEW_MIN_HEIGHT, VIEW_MIN_WIDTH};
 use crate::{Frame, ParticleSkin, WallSkin};
 use bevy::app::App;
 use bevy::prelude::*;
 use bevy::sprite::ColorMaterial;
+use bevy::time::TimeUpdateStrategy;
 use bevy::window::{Window, WindowPlugin};
 use bevy::DefaultPlugins;
 use m_engine::prelude::*;
+use m_engine::{ParticleClass, SimulationSpec, WallClass};
 use std::collections::HashMap;
 use std::sync::mpsc::Receiver;
 use std::time::Duration;
 
+/// How the front-end runs, apart from the frames and what they look like
+pub struct FrontOptions {
+    pub window_name: String,
+    /// Playback starts right away. Otherwise it waits for the user to press [Space]
+    pub autoplay: bool,
+    /// Where the playback commands come from
+    pub input_mode: InputMode,
+    /// Scene the frames come from. Snapshots of frames are saved in its terms
+    pub scene: SimulationSpec,
+}
+
 pub fn run(
-    window_name: &str,
-    frames_rx: Receiver<(Duration, Frame)>,
+    frames_rxs: Vec<Receiver<(Duration, Frame)>>,
     total_duration: Duration,
     particle_skins: HashMap<ClassId, ParticleSkin>,
     wall_skins: HashMap<ClassId, WallSkin>,
+    particle_classes: HashMap<ClassId, ParticleClass>,
+    wall_classes: HashMap<ClassId, WallClass>,
+    options: FrontOptions,
 ) {
     // Work around the known bevy bug:
     // https://github.com/bevyengine/bevy/issues/8395
@@ -26,7 +49,7 @@ pub fn run(
     let mut app = App::new();
 
     let app_window: Option<Window> = Some(Window {
-        title: window_name.to_string(),
+        title: options.window_name,
         ..default()
     });
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
@@ -34,21 +57,41 @@ pub fn run(
         ..default()
     }));
 
-    app.add_systems(Startup, (setup, generate_skin_graphics));
+    app.add_systems(
+        Startup,
+        (
+            setup,
+            generate_skin_graphics,
+            systems::legend::spawn_legend,
+            systems::view_indicators::spawn_view_indicators,
+        ),
+    );
     app.add_systems(PostStartup, systems::playback::start_playback);
     app.add_systems(
         PreUpdate,
         (
             systems::playback::poll_frames,
-            systems::playback::read_user_input,
+            systems::playback::read_user_input.run_if(not(resource_exists::<InputReplay>())),
+            systems::playback::select_stream
+                .after(systems::playback::poll_frames)
+                .run_if(not(resource_exists::<InputReplay>())),
+            systems::playback::replay_input
+                .after(systems::playback::poll_frames)
+                .run_if(resource_exists::<InputReplay>()),
             systems::playback::advance_time
                 .after(systems::playback::poll_frames)
-                .after(systems::playback::read_user_input),
+                .after(systems::playback::read_user_input)
+                .after(systems::playback::replay_input),
             systems::playback::update_time_indicator.after(systems::playback::advance_time),
-            systems::statistics_update::update_statistics.after(systems::playback::advance_time),
+            systems::statistics_update::update_statistics
+                .after(systems::playback::advance_time)
+                .after(systems::playback::select_stream),
+            systems::skins_update::add_missing_skins
+                .after(systems::playback::advance_time)
+                .after(systems::playback::select_stream),
             systems::particles_update::particle_spawn_despawn
                 .after(systems::playback::advance_time),
-            systems::walls_update::wall_spawn_despawn.after(systems::playback::advance_time),
+            systems::walls_update::wall_spawn_despawn.after(systems::skins_update::add_missing_skins),
         ),
     );
     app.add_systems(
@@ -57,18 +100,57 @@ pub fn run(
             systems::particles_update::particle_update,
             systems::particles_update::update_skins
                 .after(systems::particles_update::particle_update),
+            systems::particles_update::read_user_input,
+            systems::particles_update::update_visibility
+                .after(systems::particles_update::read_user_input),
+            systems::debug_overlay::read_user_input,
+            systems::debug_overlay::update_particle_labels
+                .after(systems::debug_overlay::read_user_input),
+            systems::wall_picking::pick_wall,
+            systems::collision_ghost::pick_particle,
+            systems::collision_ghost::draw_collision_ghost.after(systems::collision_ghost::pick_particle),
+            systems::walls_update::read_user_input,
+            systems::wall_picking::update_wall_highlight
+                .after(systems::wall_picking::pick_wall)
+                .after(systems::walls_update::read_user_input),
+            systems::wall_picking::update_wall_info.after(systems::wall_picking::pick_wall),
+            systems::walls_update::update_visibility.after(systems::walls_update::read_user_input),
+            systems::legend::update_legend,
+            systems::time_series::read_user_input,
+            systems::time_series::draw_time_series.after(systems::time_series::read_user_input),
+            systems::view::fit_view,
+            systems::view_indicators::draw_view_indicators.after(systems::view::fit_view),
+            systems::snapshot::save_snapshot,
         ),
     );
 
     // Add resources
-    app.insert_resource(SimInfo::new(total_duration, particle_skins, wall_skins));
+    app.insert_resource(
+        SimInfo::new(total_duration, particle_skins, wall_skins, particle_classes, wall_classes)
+            .with_autoplay(options.autoplay)
+            .with_scene(options.scene),
+    );
     app.insert_resource(GlobalMeshes::new());
     app.insert_resource(GlobalMaterials::new());
     app.insert_resource(SkinGraphics::new());
     app.insert_resource(TextStyles::new());
 
+    // Recorded and replayed runs advance time by the same amount every frame.
+    // Commands are then applied at the same playback moments on replay
+    match options.input_mode {
+        InputMode::Live => {}
+        InputMode::Record(path) => {
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(INPUT_FRAME_TIME));
+            app.insert_resource(InputRecorder::new(Some(path)));
+        }
+        InputMode::Replay(events) => {
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(INPUT_FRAME_TIME));
+            app.insert_resource(InputReplay::new(events));
+        }
+    }
+
     // Spawn entity for timeline
-    app.world.spawn(FramesTimeline::new(frames_rx));
+    app.world.spawn(FramesTimeline::from_streams(frames_rxs));
 
     // And run!
     app.run();
@@ -83,11 +165,11 @@ fn setup(
     text_styles: Res<TextStyles>,
     mut commands: Commands,
 ) {
-    // Spawn orthogonal camera
+    // Spawn orthogonal camera. View is refitted whenever the window is resized
     let mut camera_bundle = Camera2dBundle::default();
     camera_bundle.projection.scaling_mode = bevy::render::camera::ScalingMode::AutoMin {
-        min_width: 200.0,
-        min_height: 160.0,
+        min_width: VIEW_MIN_WIDTH,
+        min_height: VIEW_MIN_HEIGHT,
     };
     commands.spawn(camera_bundle);
 
@@ -125,7 +207,7 @@ fn setup(
     // Spawn text for instructions
     commands.spawn(
         TextBundle::from_section(
-            "Controls: [Space] - play/pause, [Left]/[Right] - rewind/forward",
+            "Controls: [Space] - play/pause, [Left]/[Right] - rewind/forward, [L] - particle labels, [Click] - select wall or particle, [Tab] - next ensemble member, [G] - particle count / displacement plot, [S] - save snapshot, [T]/[F] - walls by temperature/load, [P]/[W] - hide particles/walls, [R] - particle size by scalar",
             text_styles.main_style.clone(),
         )
         .with_text_alignment(TextAlignment::Left)
@@ -137,6 +219,39 @@ fn setup(
         }),
     );
 
+    // Spawn entity for wall selection and selected wall info text
+    commands.spawn(WallSelection::new());
+    commands.spawn(ParticleSelection::new());
+    commands.spawn(WallTint::new());
+    commands.spawn(ObjectVisibility::new());
+    commands.spawn(ParticleSizing::new());
+    commands.spawn((
+        TextBundle::from_section("", text_styles.main_style.clone())
+            .with_text_alignment(TextAlignment::Right)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                right: Val::Px(5.0),
+                ..default()
+            }),
+        WallInfo {},
+    ));
+
+    // Spawn entity for debug overlay and pool of particle labels
+    commands.spawn(DebugOverlay::new());
+    for _ in 0..MAX_PARTICLE_LABELS {
+        commands.spawn((
+            TextBundle::from_section("", text_styles.label_style.clone()).with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+            ParticleLabel {},
+        ));
+    }
+
+    // Spawn entity for time series overlay
+    commands.spawn(TimeSeriesOverlay::new());
+
     // Prepare global meshes
     let unit_circle_mesh = mesh_assets.add(Mesh::from(shape::Circle::new(1.0)));
     global_mesh_res.unit_circle = Some(unit_circle_mesh);
@@ -146,25 +261,49 @@ fn setup(
     global_materials_res.white_solid = Some(white_solid_material);
 }
 
-/// This system generates graphics for all skins
+/// This system generates graphics for all skins. Classes that appear later get theirs
+/// from `skins_update::add_missing_skins`
 fn generate_skin_graphics(
     sim_info: Res<SimInfo>,
     mut skin_graphics_res: ResMut<SkinGraphics>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
     mut material_assets: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
 ) {
     // Generate graphics for particles
     for (class_id, skin) in sim_info.particle_skins.iter() {
-        let mesh = mesh_assets.add(Mesh::from(shape::Circle::new(skin.radius())));
-        let material = material_assets.add(ColorMaterial::from(skin.color()));
-        skin_graphics_res.particle_meshes.insert(*class_id, mesh);
-        skin_graphics_res
-            .particle_materials
-            .insert(*class_id, material);
+        // Textured classes are drawn as sprites. Mesh and material are still made for them,
+        // so every class has an entry there
+        if let Some(texture) = skin.texture() {
+            let image: Handle<Image> = asset_server.load(texture.to_string());
+            skin_graphics_res.particle_textures.insert(*class_id, image);
+        }
+        skins_update::add_particle_skin_graphics(
+            *class_id,
+            skin,
+            &mut skin_graphics_res,
+            &mut mesh_assets,
+            &mut material_assets,
+        );
     }
     // Generate graphics for walls
     for (class_id, skin) in sim_info.wall_skins.iter() {
-        let material = material_assets.add(ColorMaterial::from(skin.color()));
-        skin_graphics_res.wall_materials.insert(*class_id, material);
+        skins_update::add_wall_skin_graphics(*class_id, skin, &mut skin_graphics_res, &mut material_assets);
+    }
+    // Wall colors by temperature. Gradient spans the temperatures of all wall classes
+    let temperatures = sim_info.wall_classes.values().map(|c| c.temperature());
+    let min = temperatures.clone().fold(f64::INFINITY, f64::min);
+    let max = temperatures.fold(f64::NEG_INFINITY, f64::max);
+    for (class_id, class) in sim_info.wall_classes.iter() {
+        let color = utils::temperature_color(class.temperature(), min, max);
+        let material = material_assets.add(ColorMaterial::from(color));
+        skin_graphics_res.wall_temperature_materials.insert(*class_id, material);
+    }
+    // Wall colors by load. Same gradient as temperature
+    let max_level = (utils::WALL_LOAD_LEVELS - 1) as f64;
+    for level in 0..utils::WALL_LOAD_LEVELS {
+        let color = utils::temperature_color(level as f64, 0.0, max_level);
+        let material = material_assets.add(ColorMaterial::from(color));
+        skin_graphics_res.wall_load_materials.push(material);
     }
 }
@@ -0,0 +1,28 @@
+use bevy::prelude::Component;
+
+/// Maximum number of particle labels shown at once
+pub(crate) const MAX_PARTICLE_LABELS: usize = 20;
+
+/// This component stores the state of debug overlay
+#[derive(Debug, Clone, Component)]
+pub(crate) struct DebugOverlay {
+    show_labels: bool,
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        DebugOverlay { show_labels: false }
+    }
+
+    pub fn show_labels(&self) -> bool {
+        self.show_labels
+    }
+
+    pub fn set_show_labels(&mut self, show_labels: bool) {
+        self.show_labels = show_labels;
+    }
+}
+
+/// This component is marker for the particle label text
+#[derive(Debug, Clone, Component)]
+pub(crate) struct ParticleLabel;
@@ -1,6 +1,6 @@
 use crate::Frame;
 use std::collections::BTreeMap;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, TryRecvError};
 use std::sync::Mutex;
 use std::time::Duration;
 use bevy::prelude::Component;
@@ -8,37 +8,79 @@ use bevy::prelude::Component;
 /// Buffer that stores all the calculated frames.
 /// Frontend can display any of calculated frames. Frames are calculated
 /// externally and sent to this buffer through a channel.
+/// Timeline may have several independent streams (ensemble members). All of them
+/// are buffered, but only the selected one is visible through the accessors.
 #[derive(Debug, Component)]
 pub(crate) struct FramesTimeline {
-    frames: BTreeMap<Duration, Frame>,
+    streams: Vec<BTreeMap<Duration, Frame>>,
     // Protected by mutex because of Component must be Sync.
-    frames_rx: Mutex<Receiver<(Duration, Frame)>>,
+    frames_rxs: Vec<Mutex<Receiver<(Duration, Frame)>>>,
+    // Stream is complete when its sender is gone. No more frames will come
+    complete: Vec<bool>,
+    selected: usize,
 }
 
 impl FramesTimeline {
-    pub fn new(frames_rx: Receiver<(Duration, Frame)>) -> Self {
+    pub fn from_streams(frames_rxs: Vec<Receiver<(Duration, Frame)>>) -> Self {
+        assert!(!frames_rxs.is_empty());
         FramesTimeline {
-            frames: BTreeMap::new(),
-            frames_rx: Mutex::new(frames_rx),
+            streams: frames_rxs.iter().map(|_| BTreeMap::new()).collect(),
+            complete: frames_rxs.iter().map(|_| false).collect(),
+            frames_rxs: frames_rxs.into_iter().map(Mutex::new).collect(),
+            selected: 0,
         }
     }
 
     pub fn poll_frames(&mut self) {
-        while let Ok((timestamp, frame)) = self.frames_rx.lock().unwrap().try_recv() {
-            self.frames.insert(timestamp, frame);
+        for (i, frames_rx) in self.frames_rxs.iter().enumerate() {
+            let frames_rx = frames_rx.lock().unwrap();
+            loop {
+                match frames_rx.try_recv() {
+                    Ok((timestamp, frame)) => {
+                        self.streams[i].insert(timestamp, frame);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.complete[i] = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Timestamp of the last frame of the selected stream, if the stream is complete.
+    /// Generation may stop before the simulation duration, e.g. on reaching equilibrium
+    pub fn end_time(&self) -> Option<Duration> {
+        if !self.complete[self.selected] {
+            return None;
         }
+        return self.last_frame().map(|(ts, _)| ts);
+    }
+
+    pub fn num_streams(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn selected_stream(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects the stream to view. Index wraps around the number of streams
+    pub fn select_stream(&mut self, index: usize) {
+        self.selected = index % self.streams.len();
     }
 
     pub fn _num_frames(&self) -> usize {
-        self.frames.len()
+        self.frames().len()
     }
 
     pub fn last_frame(&self) -> Option<(Duration, &Frame)> {
-        self.frames.iter().last().map(|(ts, frame)| (*ts, frame))
+        self.frames().iter().last().map(|(ts, frame)| (*ts, frame))
     }
 
     pub fn last_frame_for(&self, timestamp: Duration) -> Option<(Duration, &Frame)> {
-        let last = self.frames.range(..=timestamp).last();
+        let last = self.frames().range(..=timestamp).last();
         // Get rid of reference to key
         return last.map(|(&ts, frame)| (ts, frame));
     }
@@ -46,13 +88,30 @@ impl FramesTimeline {
     /// Time span [from, to] of all frames in the timeline. Returns None if there are
     /// no frames
     pub fn _time_span(&self) -> Option<(Duration, Duration)> {
-        if self.frames.is_empty() {
+        if self.frames().is_empty() {
             return None;
         }
-        let first = self.frames.keys().next().unwrap();
-        let last = self.frames.keys().last().unwrap();
+        let first = self.frames().keys().next().unwrap();
+        let last = self.frames().keys().last().unwrap();
         Some((*first, *last))
     }
+
+    /// Frames of the selected stream up to the timestamp. If there are more than
+    /// `max_samples` of them, evenly spaced frames are picked
+    pub fn sample_frames_until(&self, timestamp: Duration, max_samples: usize) -> Vec<(Duration, &Frame)> {
+        let frames: Vec<(Duration, &Frame)> = self
+            .frames()
+            .range(..=timestamp)
+            .map(|(&ts, frame)| (ts, frame))
+            .collect();
+        let step = (frames.len() + max_samples - 1) / max_samples.max(1);
+        return frames.into_iter().step_by(step.max(1)).collect();
+    }
+
+    // Frames of the selected stream
+    fn frames(&self) -> &BTreeMap<Duration, Frame> {
+        &self.streams[self.selected]
+    }
     
 }
 
@@ -83,7 +142,7 @@ mod test {
     // For testing purposes first frame doesn't start from 0
     pub fn make_test_timeline(num_frames: usize, time_step: Duration) -> FramesTimeline {
         let (sender, receiver) = mpsc::channel();
-        let mut timeline = FramesTimeline::new(receiver);
+        let mut timeline = FramesTimeline::from_streams(vec![receiver]);
 
         let mut timestamp = time_step;
         for i in 0..num_frames {
@@ -101,7 +160,7 @@ mod test {
         let (sender, receiver) = mpsc::channel();
 
         // Create a FramesTimeline with the receiver
-        let mut timeline = FramesTimeline::new(receiver);
+        let mut timeline = FramesTimeline::from_streams(vec![receiver]);
 
         // Spawn a thread to simulate frame calculations and sending
         let handle = thread::spawn(move || {
@@ -150,6 +209,46 @@ mod test {
         assert!(last_frame.is_none());
     }
 
+    #[test]
+    fn test_select_stream() {
+        let (sender1, receiver1) = mpsc::channel();
+        let (sender2, receiver2) = mpsc::channel();
+        let mut timeline = FramesTimeline::from_streams(vec![receiver1, receiver2]);
+        assert_eq!(timeline.num_streams(), 2);
+        sender1.send((Duration::from_secs(1), make_test_frame(1))).unwrap();
+        sender2.send((Duration::from_secs(1), make_test_frame(2))).unwrap();
+        sender2.send((Duration::from_secs(2), make_test_frame(2))).unwrap();
+        timeline.poll_frames();
+
+        // First stream is selected by default
+        assert_eq!(timeline.selected_stream(), 0);
+        assert_eq!(timeline._num_frames(), 1);
+        assert_eq!(timeline.last_frame().unwrap().1.particles.len(), 1);
+
+        timeline.select_stream(1);
+        assert_eq!(timeline._num_frames(), 2);
+        assert_eq!(timeline.last_frame().unwrap().1.particles.len(), 2);
+
+        // Selection wraps around
+        timeline.select_stream(2);
+        assert_eq!(timeline.selected_stream(), 0);
+    }
+
+    #[test]
+    fn test_end_time() {
+        let (sender, receiver) = mpsc::channel();
+        let mut timeline = FramesTimeline::from_streams(vec![receiver]);
+        sender.send((Duration::from_secs(1), make_test_frame(1))).unwrap();
+        sender.send((Duration::from_secs(2), make_test_frame(1))).unwrap();
+        timeline.poll_frames();
+        // Generation may still go on
+        assert!(timeline.end_time().is_none());
+
+        drop(sender);
+        timeline.poll_frames();
+        assert_eq!(timeline.end_time(), Some(Duration::from_secs(2)));
+    }
+
     #[test]
     fn test_time_span() {
         let timeline = make_test_timeline(5, Duration::from_secs(1));
@@ -159,7 +258,7 @@ mod test {
 
         // Also test empty timeline
         let (_, receiver) = mpsc::channel();
-        let timeline = FramesTimeline::new(receiver);
+        let timeline = FramesTimeline::from_streams(vec![receiver]);
         assert!(timeline._time_span().is_none());
     }
 }
@@ -0,0 +1,8 @@
+use bevy::prelude::Component;
+use m_engine::prelude::ClassId;
+
+/// This component marks the swatch and the name text of a particle class in the legend
+#[derive(Debug, Clone, Component)]
+pub(crate) struct LegendEntry {
+    pub class: ClassId,
+}
@@ -1,27 +1,92 @@
-use bevy::prelude::Component;
+use bevy::prelude::{Component, Visibility};
 use m_engine::prelude::ClassId;
 
 #[derive(Debug, Clone, Component)]
 pub(crate) struct Particle {
-    pub class : ClassId
+    pub class : ClassId,
+    /// Size the particle is drawn at, see `utils::size_level`. None draws the skin size
+    pub size_level : Option<usize>,
 }
 
 impl Particle {
     pub fn new() -> Self {
         Particle {
-            class: 0
+            class: 0,
+            size_level: None,
         }
     }
 }
 
+/// This component stores how the drawn size of particles is chosen. By default particles are
+/// drawn at their skin size. Sized by scalar, the drawn radius follows the per-particle scalar
+/// of the frame. Physics radius is not affected either way
+#[derive(Debug, Clone, Component)]
+pub(crate) struct ParticleSizing {
+    by_scalar: bool,
+}
+
+impl ParticleSizing {
+    pub fn new() -> Self {
+        ParticleSizing { by_scalar: false }
+    }
+
+    pub fn by_scalar(&self) -> bool {
+        self.by_scalar
+    }
+
+    pub fn set_by_scalar(&mut self, by_scalar: bool) {
+        self.by_scalar = by_scalar;
+    }
+}
+
 
 #[derive(Debug, Clone, Component)]
 pub(crate) struct Wall {
+    // Index of the wall in the frame
+    pub index : usize,
+    pub class : ClassId
 }
 
 impl Wall {
-    pub fn new() -> Self {
+    pub fn new(index: usize, class: ClassId) -> Self {
         Wall {
+            index,
+            class
         }
     }
 }
+
+/// This component stores which kinds of objects are shown. Hidden objects are still
+/// spawned and updated, so showing them again is instant
+#[derive(Debug, Clone, Component)]
+pub(crate) struct ObjectVisibility {
+    show_particles: bool,
+    show_walls: bool,
+}
+
+impl ObjectVisibility {
+    pub fn new() -> Self {
+        ObjectVisibility { show_particles: true, show_walls: true }
+    }
+
+    pub fn show_particles(&self) -> bool {
+        self.show_particles
+    }
+
+    pub fn set_show_particles(&mut self, show_particles: bool) {
+        self.show_particles = show_particles;
+    }
+
+    pub fn show_walls(&self) -> bool {
+        self.show_walls
+    }
+
+    pub fn set_show_walls(&mut self, show_walls: bool) {
+        self.show_walls = show_walls;
+    }
+
+    /// Bevy visibility of the shown or hidden objects
+    pub fn visibility(shown: bool) -> Visibility {
+        if shown { Visibility::Inherited } else { Visibility::Hidden }
+    }
+}
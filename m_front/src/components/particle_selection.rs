@@ -0,0 +1,24 @@
+use bevy::prelude::Component;
+use m_engine::prelude::ParticleId;
+
+/// This component stores the particle selected by user.
+/// Selection is stored as persistent id, so it follows the particle across frames.
+/// The next collision of the selected particle is shown as a ghost
+#[derive(Debug, Clone, Component)]
+pub(crate) struct ParticleSelection {
+    selected: Option<ParticleId>,
+}
+
+impl ParticleSelection {
+    pub fn new() -> Self {
+        ParticleSelection { selected: None }
+    }
+
+    pub fn selected(&self) -> Option<ParticleId> {
+        self.selected
+    }
+
+    pub fn select(&mut self, selected: Option<ParticleId>) {
+        self.selected = selected;
+    }
+}
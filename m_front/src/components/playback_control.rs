@@ -28,6 +28,10 @@ impl PlaybackControl {
         self.current_time
     }
 
+    pub fn rewind(&self) -> Option<f64> {
+        self.rewind
+    }
+
     pub fn set_playing(&mut self, is_playing: bool) {
         self.is_playing = is_playing;
     }
@@ -0,0 +1,107 @@
+use bevy::prelude::{Component, Vec2};
+
+/// Maximum number of samples drawn per series. Longer series are thinned out
+pub(crate) const MAX_PLOT_SAMPLES: usize = 200;
+
+/// Quantity plotted against time by the time series overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PlotQuantity {
+    ParticleCount,
+    MeanSquareDisplacement,
+}
+
+/// This component stores the state of the time series overlay
+#[derive(Debug, Clone, Component)]
+pub(crate) struct TimeSeriesOverlay {
+    visible: bool,
+    quantity: PlotQuantity,
+}
+
+impl TimeSeriesOverlay {
+    pub fn new() -> Self {
+        TimeSeriesOverlay {
+            visible: false,
+            quantity: PlotQuantity::ParticleCount,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn quantity(&self) -> PlotQuantity {
+        self.quantity
+    }
+
+    /// Hidden overlay shows particle counts, then mean square displacement, then hides again
+    pub fn cycle(&mut self) {
+        (self.visible, self.quantity) = match (self.visible, self.quantity) {
+            (false, _) => (true, PlotQuantity::ParticleCount),
+            (true, PlotQuantity::ParticleCount) => (true, PlotQuantity::MeanSquareDisplacement),
+            (true, PlotQuantity::MeanSquareDisplacement) => (false, PlotQuantity::ParticleCount),
+        };
+    }
+}
+
+/// Maps samples (time, value) into the plot rectangle given by its bottom left corner
+/// and size. Time axis spans [0, `max_time`]. Value axis spans the range of all `values`
+/// so that both growing and shrinking series fit
+pub(crate) fn plot_points(
+    samples: &[(f32, f32)],
+    max_time: f32,
+    value_range: (f32, f32),
+    corner: Vec2,
+    size: Vec2,
+) -> Vec<Vec2> {
+    let (min_value, max_value) = value_range;
+    // Flat series is drawn in the middle
+    let span = max_value - min_value;
+    let max_time = max_time.max(f32::EPSILON);
+    return samples
+        .iter()
+        .map(|&(time, value)| {
+            let y = if span > 0.0 { (value - min_value) / span } else { 0.5 };
+            corner + Vec2::new(time / max_time * size.x, y * size.y)
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_quantities() {
+        let mut overlay = TimeSeriesOverlay::new();
+        assert!(!overlay.visible());
+        overlay.cycle();
+        assert!(overlay.visible());
+        assert_eq!(overlay.quantity(), PlotQuantity::ParticleCount);
+        overlay.cycle();
+        assert_eq!(overlay.quantity(), PlotQuantity::MeanSquareDisplacement);
+        overlay.cycle();
+        assert!(!overlay.visible());
+    }
+
+    #[test]
+    fn test_plot_points() {
+        let corner = Vec2::new(10.0, 20.0);
+        let size = Vec2::new(100.0, 50.0);
+        // Count goes up then down
+        let samples = [(0.0, 10.0), (1.0, 30.0), (2.0, 20.0), (4.0, 10.0)];
+        let points = plot_points(&samples, 4.0, (10.0, 30.0), corner, size);
+        assert_eq!(
+            points,
+            vec![
+                Vec2::new(10.0, 20.0),
+                Vec2::new(35.0, 70.0),
+                Vec2::new(60.0, 45.0),
+                Vec2::new(110.0, 20.0),
+            ]
+        );
+
+        // Constant count
+        let points = plot_points(&[(0.0, 5.0), (2.0, 5.0)], 4.0, (5.0, 5.0), corner, size);
+        assert_eq!(points, vec![Vec2::new(10.0, 45.0), Vec2::new(60.0, 45.0)]);
+    }
+}
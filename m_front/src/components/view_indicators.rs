@@ -0,0 +1,9 @@
+use bevy::prelude::Component;
+
+/// This component marks the text with the gravity magnitude next to the gravity arrow
+#[derive(Debug, Clone, Component)]
+pub(crate) struct GravityIndicator {}
+
+/// This component marks the text with the length of the scale bar
+#[derive(Debug, Clone, Component)]
+pub(crate) struct ScaleBarLabel {}
@@ -0,0 +1,26 @@
+use bevy::prelude::Component;
+
+/// This component stores the wall selected by user.
+/// Selection is stored as index of the wall in the frame, so it persists across frames.
+#[derive(Debug, Clone, Component)]
+pub(crate) struct WallSelection {
+    selected: Option<usize>,
+}
+
+impl WallSelection {
+    pub fn new() -> Self {
+        WallSelection { selected: None }
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn select(&mut self, selected: Option<usize>) {
+        self.selected = selected;
+    }
+}
+
+/// This component is marker for the selected wall info text
+#[derive(Debug, Clone, Component)]
+pub(crate) struct WallInfo;
@@ -0,0 +1,35 @@
+use bevy::prelude::Component;
+
+/// This component stores how walls are colored. By default walls use their skin color.
+/// Walls tinted by temperature are colored by the temperature of their class, cold in blue,
+/// hot in red. Walls tinted by load are colored by the recent impulse, idle in blue, most
+/// loaded in red. Only one tint is active at a time
+#[derive(Debug, Clone, Component)]
+pub(crate) struct WallTint {
+    by_temperature: bool,
+    by_load: bool,
+}
+
+impl WallTint {
+    pub fn new() -> Self {
+        WallTint { by_temperature: false, by_load: false }
+    }
+
+    pub fn by_temperature(&self) -> bool {
+        self.by_temperature
+    }
+
+    pub fn set_by_temperature(&mut self, by_temperature: bool) {
+        self.by_temperature = by_temperature;
+        self.by_load &= !by_temperature;
+    }
+
+    pub fn by_load(&self) -> bool {
+        self.by_load
+    }
+
+    pub fn set_by_load(&mut self, by_load: bool) {
+        self.by_load = by_load;
+        self.by_temperature &= !by_load;
+    }
+}
@@ -1,19 +1,149 @@
-use m_engine::{Particle, Statistics, Wall};
+use m_engine::{Particle, Statistics, Vec2, Wall};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Values are rounded to this step before hashing, so that
+// the hash doesn't depend on the float representation details
+const HASH_QUANTUM: f64 = 1e-9;
 
 /// Represents information about displayed frame
 #[derive(Debug, Clone)]
 pub struct Frame {
     pub particles: Vec<Particle>,
     pub walls: Vec<Wall>,
-    pub statistics: Statistics,
+    /// Statistics may be sampled less often than frames are produced.
+    /// Frames in between share the most recent statistics
+    pub statistics: Arc<Statistics>,
+    /// Time step that produced this frame from the previous one. Zero for the first frame.
+    /// Time step may vary between frames
+    pub time_step: Duration,
+    /// Number of parts the time step was split into
+    pub substeps: usize,
+    /// Per-particle values normalized to [0, 1], e.g. speed, in the order of particles.
+    /// Only used for drawing. Empty if the producer doesn't compute them
+    pub scalars: Vec<f32>,
 }
 
 impl Frame {
-    pub fn new(particles: Vec<Particle>, walls: Vec<Wall>, statistics: Statistics) -> Self {
+    pub fn new(
+        particles: Vec<Particle>,
+        walls: Vec<Wall>,
+        statistics: impl Into<Arc<Statistics>>,
+    ) -> Self {
         Frame {
             particles,
             walls,
-            statistics,
+            statistics: statistics.into(),
+            time_step: Duration::ZERO,
+            substeps: 1,
+            scalars: Vec::new(),
+        }
+    }
+
+    pub fn with_time_step(mut self, time_step: Duration) -> Self {
+        self.time_step = time_step;
+        self
+    }
+
+    pub fn with_substeps(mut self, substeps: usize) -> Self {
+        self.substeps = substeps;
+        self
+    }
+
+    pub fn with_scalars(mut self, scalars: Vec<f32>) -> Self {
+        self.scalars = scalars;
+        self
+    }
+
+    /// Duration of a single substep
+    pub fn substep_duration(&self) -> Duration {
+        self.time_step / self.substeps.max(1) as u32
+    }
+
+    /// Cheap fingerprint of particles and walls. Identical runs produce identical
+    /// hashes, which helps to catch nondeterminism. Statistics are not included
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.particles.len().hash(&mut hasher);
+        for particle in &self.particles {
+            particle.class().hash(&mut hasher);
+            hash_vec2(particle.position, &mut hasher);
+            hash_vec2(particle.velocity, &mut hasher);
         }
+        self.walls.len().hash(&mut hasher);
+        for wall in &self.walls {
+            wall.class().hash(&mut hasher);
+            for edge in wall.polygon().edges_iter() {
+                hash_vec2(edge.begin, &mut hasher);
+            }
+        }
+        return hasher.finish();
+    }
+}
+
+fn hash_vec2(v: Vec2, hasher: &mut impl Hasher) {
+    ((v.x / HASH_QUANTUM).round() as i64).hash(hasher);
+    ((v.y / HASH_QUANTUM).round() as i64).hash(hasher);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m_engine::{ParticleClass, Polygon, Simulation, VelocityVerletIntegrator, WallClass};
+    use std::collections::HashMap;
+
+    fn make_frame(velocity: Vec2) -> Frame {
+        let particles = vec![
+            Particle::new(Vec2::new(1.0, 2.0), velocity, 0),
+            Particle::new(Vec2::new(-1.0, 0.5), Vec2::ZERO, 1),
+        ];
+        let walls = vec![Wall::new(Polygon::new_rectangle(0.0, 0.0, 1.0, 1.0), 0)];
+        return Frame::new(particles, walls, Statistics::default());
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let frame = make_frame(Vec2::new(3.0, 4.0));
+        assert_eq!(frame.state_hash(), frame.clone().state_hash());
+        assert_eq!(frame.state_hash(), make_frame(Vec2::new(3.0, 4.0)).state_hash());
+        assert_ne!(frame.state_hash(), make_frame(Vec2::new(3.0, 4.1)).state_hash());
+
+        let mut moved_wall = frame.clone();
+        moved_wall.walls[0] = Wall::new(Polygon::new_rectangle(0.0, 0.0, 1.0, 2.0), 0);
+        assert_ne!(frame.state_hash(), moved_wall.state_hash());
+    }
+
+    #[test]
+    fn test_run_reproduces_hash_sequence() {
+        let run = || {
+            let mut p_classes = HashMap::new();
+            p_classes.insert(0, ParticleClass::new("Class0", 1.0, 0.5));
+            let mut w_classes = HashMap::new();
+            // Heat exchange with walls is random. Keep walls insulating
+            w_classes.insert(0, WallClass::new("Class0", 10.0, 0.0));
+            let mut simulation = Simulation::new(p_classes, w_classes, Vec2::new(0.0, -9.8));
+            simulation.spawn_walls(&Wall::make_box(-10.0, -10.0, 10.0, 10.0, 1.0, 0));
+            for i in 0..20 {
+                let position = Vec2::new((i % 5) as f64 * 3.0 - 6.0, (i / 5) as f64 * 3.0 - 6.0);
+                let velocity = Vec2::from_angle_rad(i as f64) * 5.0;
+                simulation.spawn_particle(Particle::new(position, velocity, 0));
+            }
+            let integrator = VelocityVerletIntegrator::new();
+            let time_step = Duration::from_millis(10);
+            let mut hashes = vec![];
+            for _ in 0..100 {
+                simulation.step(&integrator, time_step, None);
+                let frame = Frame::new(
+                    simulation.particles().to_vec(),
+                    simulation.walls().to_vec(),
+                    Statistics::default(),
+                );
+                hashes.push(frame.state_hash());
+            }
+            hashes
+        };
+        assert_eq!(run(), run());
     }
 }
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Duration of each front-end frame while recording or replaying input.
+/// Time advances by the same amount every frame, so replay doesn't depend on the frame rate
+pub const INPUT_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+/// User action that changes the playback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputCommand {
+    Play,
+    Pause,
+    /// Playback speed while rewinding. None stops rewinding
+    Rewind(Option<f64>),
+    /// Index of the viewed ensemble member
+    SelectStream(usize),
+}
+
+/// Command with the time since the front-end started
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub time: Duration,
+    pub command: InputCommand,
+}
+
+impl InputEvent {
+    pub fn new(time: Duration, command: InputCommand) -> Self {
+        InputEvent { time, command }
+    }
+}
+
+/// Where the playback commands come from
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum InputMode {
+    /// Keyboard
+    #[default]
+    Live,
+    /// Keyboard. Commands are also written into the file
+    Record(PathBuf),
+    /// Commands are taken from the log. Keyboard is ignored
+    Replay(Vec<InputEvent>),
+}
+
+/// Error of reading the input log
+#[derive(Debug)]
+pub enum InputLogError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for InputLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputLogError::Io(e) => write!(f, "Error reading input log: {}", e),
+            InputLogError::Parse { line, message } => {
+                write!(f, "Error parsing input log at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputLogError {}
+
+impl From<io::Error> for InputLogError {
+    fn from(e: io::Error) -> Self {
+        InputLogError::Io(e)
+    }
+}
+
+/// Writes events as text, one per line: time in nanoseconds followed by the command.
+/// For example `1500000000 rewind -5`
+pub fn write_input_log(mut writer: impl Write, events: &[InputEvent]) -> io::Result<()> {
+    for event in events {
+        let command = match event.command {
+            InputCommand::Play => "play".to_string(),
+            InputCommand::Pause => "pause".to_string(),
+            InputCommand::Rewind(Some(speed)) => format!("rewind {}", speed),
+            InputCommand::Rewind(None) => "rewind none".to_string(),
+            InputCommand::SelectStream(index) => format!("stream {}", index),
+        };
+        writeln!(writer, "{} {}", event.time.as_nanos(), command)?;
+    }
+    return writer.flush();
+}
+
+/// Reads events written by `write_input_log`. Empty lines are skipped
+pub fn read_input_log(reader: impl BufRead) -> Result<Vec<InputEvent>, InputLogError> {
+    let mut events = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parse_error = |message: &str| InputLogError::Parse {
+            line: i + 1,
+            message: format!("{}: {}", message, line),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let time = match words[0].parse::<u64>() {
+            Ok(nanos) => Duration::from_nanos(nanos),
+            Err(_) => return Err(parse_error("Bad time")),
+        };
+        let command = match words[1..] {
+            ["play"] => InputCommand::Play,
+            ["pause"] => InputCommand::Pause,
+            ["rewind", "none"] => InputCommand::Rewind(None),
+            ["rewind", speed] => match speed.parse::<f64>() {
+                Ok(speed) if speed.is_finite() => InputCommand::Rewind(Some(speed)),
+                _ => return Err(parse_error("Bad rewind speed")),
+            },
+            ["stream", index] => match index.parse::<usize>() {
+                Ok(index) => InputCommand::SelectStream(index),
+                Err(_) => return Err(parse_error("Bad stream index")),
+            },
+            _ => return Err(parse_error("Unknown command")),
+        };
+        events.push(InputEvent::new(time, command));
+    }
+    return Ok(events);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_roundtrip() {
+        let events = vec![
+            InputEvent::new(Duration::ZERO, InputCommand::Play),
+            InputEvent::new(INPUT_FRAME_TIME * 3, InputCommand::Rewind(Some(-5.0))),
+            InputEvent::new(INPUT_FRAME_TIME * 9, InputCommand::Rewind(None)),
+            InputEvent::new(INPUT_FRAME_TIME * 10, InputCommand::SelectStream(2)),
+            InputEvent::new(INPUT_FRAME_TIME * 12, InputCommand::Pause),
+        ];
+        let mut buffer = vec![];
+        write_input_log(&mut buffer, &events).unwrap();
+        assert_eq!(read_input_log(buffer.as_slice()).unwrap(), events);
+
+        let error = read_input_log("10 play\n20 jump\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, InputLogError::Parse { line: 2, .. }));
+    }
+}
@@ -2,6 +2,8 @@ pub mod prelude;
 pub mod bevy_front;
 pub mod skins;
 pub mod frame;
+pub mod video;
+pub mod input_log;
 
 pub use skins::{ParticleSkin, WallSkin};
 pub use frame::Frame;
@@ -12,19 +14,30 @@ mod systems
     pub(crate) mod playback;
     pub(crate) mod particles_update;
     pub(crate) mod walls_update;
+    pub(crate) mod skins_update;
     pub(crate) mod statistics_update;
+    pub(crate) mod debug_overlay;
+    pub(crate) mod wall_picking;
+    pub(crate) mod collision_ghost;
+    pub(crate) mod legend;
+    pub(crate) mod time_series;
+    pub(crate) mod view;
+    pub(crate) mod snapshot;
+    pub(crate) mod view_indicators;
 }
 
 mod resources
 {
     pub(crate) mod sim_info;
     pub(crate) mod graphic_resources;
+    pub(crate) mod input_recording;
 
     pub(crate) use sim_info::SimInfo;
     pub(crate) use graphic_resources::GlobalMeshes;
     pub(crate) use graphic_resources::GlobalMaterials;
     pub(crate) use graphic_resources::SkinGraphics;
     pub(crate) use graphic_resources::TextStyles;
+    pub(crate) use input_recording::{InputRecorder, InputReplay};
 }
 
 mod components
@@ -33,11 +46,27 @@ mod components
     pub(crate) mod playback_control;
     pub(crate) mod objects;
     pub(crate) mod statistics;
+    pub(crate) mod debug_overlay;
+    pub(crate) mod wall_selection;
+    pub(crate) mod particle_selection;
+    pub(crate) mod legend;
+    pub(crate) mod time_series;
+    pub(crate) mod wall_tint;
+    pub(crate) mod view_indicators;
 
     pub(crate) use frames_timeline::FramesTimeline;
     pub(crate) use playback_control::{PlaybackControl, TimeIndicator};
     pub(crate) use objects::Particle;
     pub(crate) use objects::Wall;
+    pub(crate) use objects::ObjectVisibility;
+    pub(crate) use objects::ParticleSizing;
     pub(crate) use statistics::StatisticsReport;
+    pub(crate) use debug_overlay::{DebugOverlay, ParticleLabel};
+    pub(crate) use wall_selection::{WallInfo, WallSelection};
+    pub(crate) use particle_selection::ParticleSelection;
+    pub(crate) use legend::LegendEntry;
+    pub(crate) use time_series::TimeSeriesOverlay;
+    pub(crate) use wall_tint::WallTint;
+    pub(crate) use view_indicators::{GravityIndicator, ScaleBarLabel};
 }
 
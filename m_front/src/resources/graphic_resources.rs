@@ -34,7 +34,14 @@ impl GlobalMaterials{
 pub(crate) struct SkinGraphics{
     pub particle_materials : HashMap<ClassId, Handle<ColorMaterial>>,
     pub particle_meshes : HashMap<ClassId, Handle<Mesh>>,
+    /// Meshes of every size level, for drawing particles sized by scalar. See `utils::size_level`
+    pub particle_sized_meshes : HashMap<ClassId, Vec<Handle<Mesh>>>,
+    /// Only classes whose skin has a texture. Others are drawn as circle meshes
+    pub particle_textures : HashMap<ClassId, Handle<Image>>,
     pub wall_materials : HashMap<ClassId, Handle<ColorMaterial>>,
+    pub wall_temperature_materials : HashMap<ClassId, Handle<ColorMaterial>>,
+    /// Gradient from idle to most loaded wall, see `utils::load_level`
+    pub wall_load_materials : Vec<Handle<ColorMaterial>>,
 }
 
 impl SkinGraphics{
@@ -42,14 +49,19 @@ impl SkinGraphics{
         Self {
             particle_materials : HashMap::new(),
             particle_meshes : HashMap::new(),
+            particle_sized_meshes : HashMap::new(),
+            particle_textures : HashMap::new(),
             wall_materials : HashMap::new(),
+            wall_temperature_materials : HashMap::new(),
+            wall_load_materials : Vec::new(),
         }
     }
 }
 
 #[derive(Debug, Clone, Resource)]
 pub(crate) struct TextStyles{
-    pub main_style : TextStyle
+    pub main_style : TextStyle,
+    pub label_style : TextStyle,
 }
 
 impl TextStyles{
@@ -59,6 +71,11 @@ impl TextStyles{
                 font_size: 16.0,
                 font: Default::default(),
                 ..default()
+            },
+            label_style : TextStyle {
+                font_size: 12.0,
+                font: Default::default(),
+                color: Color::YELLOW,
             }
         }
     }
@@ -0,0 +1,60 @@
+use crate::input_log::{self, InputCommand, InputEvent};
+
+use bevy::prelude::*;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// This resource collects playback commands given by the user
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct InputRecorder {
+    /// The log is rewritten on every command, so nothing is lost when the window is closed
+    path: Option<PathBuf>,
+    events: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path, events: vec![] }
+    }
+
+    #[cfg(test)]
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn record(&mut self, time: Duration, command: InputCommand) {
+        self.events.push(InputEvent::new(time, command));
+        if let Some(path) = &self.path {
+            let result = File::create(path)
+                .and_then(|file| input_log::write_input_log(BufWriter::new(file), &self.events));
+            if let Err(e) = result {
+                println!("Error writing input log {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// This resource holds recorded playback commands that are not applied yet.
+/// When it's present, the keyboard doesn't control the playback
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct InputReplay {
+    events: VecDeque<InputEvent>,
+}
+
+impl InputReplay {
+    pub fn new(events: Vec<InputEvent>) -> Self {
+        Self { events: events.into() }
+    }
+
+    /// Removes and returns the next command if it's due at `time`
+    pub fn pop_due(&mut self, time: Duration) -> Option<InputCommand> {
+        match self.events.front() {
+            Some(event) if event.time <= time => self.events.pop_front().map(|e| e.command),
+            _ => None,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{ParticleSkin, WallSkin};
 
 use m_engine::prelude::ClassId;
+use m_engine::{ParticleClass, SimulationSpec, Vec2, WallClass};
 
 use bevy::prelude::*;
 
@@ -13,6 +14,13 @@ pub(crate) struct SimInfo {
     pub total_duration: Duration,
     pub particle_skins: HashMap<ClassId, ParticleSkin>,
     pub wall_skins: HashMap<ClassId, WallSkin>,
+    /// Physical parameters of the classes. Read-only, the simulation owns them
+    pub particle_classes: HashMap<ClassId, ParticleClass>,
+    pub wall_classes: HashMap<ClassId, WallClass>,
+    /// Playback starts right away. Otherwise it waits for the user to press [Space]
+    pub autoplay: bool,
+    /// Scene the frames come from. Snapshots of frames are saved in its terms
+    pub scene: Option<SimulationSpec>,
 }
 
 impl SimInfo {
@@ -20,11 +28,117 @@ impl SimInfo {
         total_duration: Duration,
         particle_skins: HashMap<ClassId, ParticleSkin>,
         wall_skins: HashMap<ClassId, WallSkin>,
+        particle_classes: HashMap<ClassId, ParticleClass>,
+        wall_classes: HashMap<ClassId, WallClass>,
     ) -> Self {
         Self {
             total_duration,
             particle_skins,
             wall_skins,
+            particle_classes,
+            wall_classes,
+            autoplay: false,
+            scene: None,
         }
     }
+
+    pub fn with_autoplay(mut self, autoplay: bool) -> Self {
+        self.autoplay = autoplay;
+        self
+    }
+
+    pub fn with_scene(mut self, scene: SimulationSpec) -> Self {
+        self.scene = Some(scene);
+        self
+    }
+
+    /// Gravity acceleration of the scene at given playback time. Zero if the scene is unknown
+    pub fn gravity_at(&self, time: Duration) -> Vec2 {
+        let Some(scene) = &self.scene else {
+            return Vec2::ZERO;
+        };
+        return scene.gravity_at(time);
+    }
+
+    /// Human readable name of the particle class. Falls back to the class id
+    /// if the name is unknown
+    pub fn particle_class_name(&self, class: ClassId) -> String {
+        match self.particle_skins.get(&class).map(|skin| skin.name()) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("Class {}", class),
+        }
+    }
+
+    /// Name of the particle class with its mass and radius, if the class is known
+    pub fn particle_class_label(&self, class: ClassId) -> String {
+        let name = self.particle_class_name(class);
+        match self.particle_classes.get(&class) {
+            Some(c) => format!("{} (m {}, r {})", name, c.mass(), c.radius()),
+            None => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_particle_class_names() {
+        let mut particle_skins = HashMap::new();
+        particle_skins.insert(0, ParticleSkin::new(1.0, Color::RED).with_name("Argon"));
+        particle_skins.insert(1, ParticleSkin::new(1.0, Color::BLUE));
+        let sim_info = SimInfo::new(
+            Duration::from_secs(1),
+            particle_skins,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        assert_eq!(sim_info.particle_class_name(0), "Argon");
+        // Unnamed and unknown classes are shown by id
+        assert_eq!(sim_info.particle_class_name(1), "Class 1");
+        assert_eq!(sim_info.particle_class_name(7), "Class 7");
+    }
+
+    #[test]
+    fn test_gravity_at() {
+        let sim_info = SimInfo::new(Duration::from_secs(1), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new());
+        assert_eq!(sim_info.gravity_at(Duration::ZERO), Vec2::ZERO);
+
+        let scene = SimulationSpec {
+            gravity: 9.8,
+            gravity_ramp: Some(m_engine::simulation_spec::GravityRamp {
+                start: 0.0,
+                end: 10.0,
+                duration: Duration::from_secs(2),
+            }),
+            ..Default::default()
+        };
+        let sim_info = sim_info.with_scene(scene);
+        // Ramp overrides constant gravity
+        assert_eq!(sim_info.gravity_at(Duration::from_secs(1)), Vec2::new(0.0, -5.0));
+        assert_eq!(sim_info.gravity_at(Duration::from_secs(5)), Vec2::new(0.0, -10.0));
+    }
+
+    #[test]
+    fn test_class_parameters() {
+        let mut particle_classes = HashMap::new();
+        particle_classes.insert(3, ParticleClass::new("Argon", 40.0, 0.7));
+        let mut wall_classes = HashMap::new();
+        wall_classes.insert(1, WallClass::new("Hot", 500.0, 0.3));
+        let sim_info = SimInfo::new(
+            Duration::from_secs(1),
+            HashMap::new(),
+            HashMap::new(),
+            particle_classes,
+            wall_classes,
+        );
+        let argon = &sim_info.particle_classes[&3];
+        assert_eq!(argon.mass(), 40.0);
+        assert_eq!(argon.radius(), 0.7);
+        assert_eq!(sim_info.wall_classes[&1].temperature(), 500.0);
+        assert_eq!(sim_info.particle_class_label(3), "Class 3 (m 40, r 0.7)");
+        assert_eq!(sim_info.particle_class_label(4), "Class 4");
+    }
 }
@@ -1,10 +1,18 @@
 use bevy::prelude::Color;
 use m_engine::ParticleClass;
 
+/// Color of classes without a skin, e.g. the ones that appeared at runtime
+pub const PLACEHOLDER_COLOR: Color = Color::FUCHSIA;
+/// Radius of the placeholder skin of a class whose radius is unknown
+pub const PLACEHOLDER_RADIUS: f32 = 1.0;
+
 #[derive(Clone, Debug)] // no Copy, since I expect this class to grow into something more complex
 pub struct ParticleSkin {
     radius: f32,
     color: Color,
+    render_scale: f32,
+    name: String,
+    texture: Option<String>,
 }
 
 impl ParticleSkin {
@@ -12,6 +20,9 @@ impl ParticleSkin {
         ParticleSkin {
             radius,
             color,
+            render_scale: 1.0,
+            name: String::new(),
+            texture: None,
         }
     }
 
@@ -19,16 +30,53 @@ impl ParticleSkin {
         ParticleSkin {
             radius: particle_class.radius() as f32,
             color: *color,
+            render_scale: 1.0,
+            name: particle_class.name().to_string(),
+            texture: None,
         }
     }
 
+    /// Returns copy of the skin that draws particles `render_scale` times larger than
+    /// their radius. This is purely cosmetic: the physics radius used for collisions
+    /// is not affected. Useful to make point-like particles visible.
+    pub fn with_render_scale(mut self, render_scale: f32) -> Self {
+        self.render_scale = render_scale;
+        self
+    }
+
+    /// Returns copy of the skin with the class name shown in the legend
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Returns copy of the skin drawn as a sprite with the image instead of a flat circle.
+    /// Path is relative to the assets folder. Sprite size follows the radius and render scale
+    pub fn with_texture(mut self, path: &str) -> Self {
+        self.texture = Some(path.to_string());
+        self
+    }
+
+    /// Physics radius of the particle class
     pub fn radius(&self) -> f32 {
         self.radius
     }
 
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
     pub fn color(&self) -> Color {
         self.color
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn texture(&self) -> Option<&str> {
+        self.texture.as_deref()
+    }
 }
 
 #[derive(Clone, Debug)] // no Copy, since I expect this class to grow into something more complex
@@ -0,0 +1,101 @@
+use crate::components::{FramesTimeline, ParticleSelection, PlaybackControl};
+use crate::resources::SimInfo;
+use crate::utils;
+
+use m_engine::collisions;
+use m_engine::ParticlePairRules;
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// How far ahead the next collision is searched. In seconds of simulation time
+const GHOST_HORIZON_SEC: f64 = 10.0;
+/// Length of the dashes of the path to the ghost. In world units
+const GHOST_DASH: f32 = 1.0;
+const GHOST_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.6);
+const NORMAL_COLOR: Color = Color::YELLOW;
+
+/// Selects the particle under the cursor on mouse click. Clicking elsewhere clears selection
+pub fn pick_particle(
+    mut selection_query: Query<&mut ParticleSelection>,
+    mouse: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    sim_info: Res<SimInfo>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(cursor) = window_query.single().cursor_position() else {
+        return;
+    };
+    let (camera, camera_transform) = camera_query.single();
+    let Some(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    let current_time = playback_query.single().current_time();
+    let picked = match timeline_query.single().last_frame_for(current_time) {
+        Some((_, frame)) => utils::pick_particle(
+            &frame.particles,
+            &sim_info.particle_classes,
+            m_engine::Vec2::new(world_pos.x as f64, world_pos.y as f64),
+        )
+        .and_then(|index| frame.particles[index].id()),
+        None => None,
+    };
+    selection_query.single_mut().select(picked);
+}
+
+/// Draws the ghost of the selected particle at its next collision: dashed path from the
+/// particle, outline where it will touch the partner and the collision normal at the contact.
+/// Prediction assumes that particles keep moving straight and that all of them collide.
+/// It's updated every frame, so it follows the playback
+pub fn draw_collision_ghost(
+    mut gizmos: Gizmos,
+    selection_query: Query<&ParticleSelection>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    sim_info: Res<SimInfo>,
+) {
+    let Some(id) = selection_query.single().selected() else {
+        return;
+    };
+    let current_time = playback_query.single().current_time();
+    let Some((_, frame)) = timeline_query.single().last_frame_for(current_time) else {
+        return;
+    };
+    // Selected particle may be gone, e.g. absorbed
+    let Some(index) = frame.particles.iter().position(|p| p.id() == Some(id)) else {
+        return;
+    };
+    let particle = &frame.particles[index];
+    let Some(class) = sim_info.particle_classes.get(&particle.class()) else {
+        return;
+    };
+    let to_bevy = |v: m_engine::Vec2| Vec2::new(v.x as f32, v.y as f32);
+    let radius = particle.radius(class) as f32;
+    // Selected particle is circled even if it won't collide soon
+    gizmos.circle_2d(to_bevy(particle.position), radius, GHOST_COLOR);
+
+    let Some(collision) = collisions::detect_earliest_for_particle(
+        index,
+        &frame.particles,
+        &sim_info.particle_classes,
+        &ParticlePairRules::new(),
+        &frame.walls,
+        GHOST_HORIZON_SEC,
+    ) else {
+        return;
+    };
+    let start = to_bevy(particle.position);
+    let ghost = to_bevy(particle.position + particle.velocity * collision.time);
+    for (from, to) in utils::dashes(start, ghost, GHOST_DASH) {
+        gizmos.line_2d(from, to, GHOST_COLOR);
+    }
+    gizmos.circle_2d(ghost, radius, GHOST_COLOR);
+    let contact = to_bevy(collision.contact);
+    gizmos.linestrip_2d(utils::arrow_points(contact, to_bevy(collision.normal) * radius), NORMAL_COLOR);
+}
@@ -0,0 +1,70 @@
+use crate::components::{DebugOverlay, FramesTimeline, ParticleLabel, PlaybackControl};
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// Reads the keyboard input and toggles debug overlay
+pub fn read_user_input(mut overlay_query: Query<&mut DebugOverlay>, input: Res<Input<KeyCode>>) {
+    let mut overlay = overlay_query.single_mut();
+    if input.just_pressed(KeyCode::L) {
+        let show = overlay.show_labels();
+        overlay.set_show_labels(!show);
+    }
+}
+
+/// This system places labels with particle ids next to particles.
+/// Only particles nearest to the cursor are labeled.
+pub fn update_particle_labels(
+    mut labels: Query<(&mut Text, &mut Style, &mut Visibility), With<ParticleLabel>>,
+    overlay_query: Query<&DebugOverlay>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    // Collect label candidates in viewport coordinates
+    let mut candidates: Vec<(Vec2, String)> = vec![];
+    let current_time = playback_query.single().current_time();
+    let timeline = timeline_query.single();
+    if let (true, Some((_, frame))) = (
+        overlay_query.single().show_labels(),
+        timeline.last_frame_for(current_time),
+    ) {
+        let (camera, camera_transform) = camera_query.single();
+        for (i, particle) in frame.particles.iter().enumerate() {
+            let world_pos = Vec3::new(particle.position.x as f32, particle.position.y as f32, 0.0);
+            if let Some(viewport_pos) = camera.world_to_viewport(camera_transform, world_pos) {
+                // Particles that never were spawned have no id. Show index instead
+                let label = match particle.id() {
+                    Some(id) => id.to_string(),
+                    None => format!("#{}", i),
+                };
+                candidates.push((viewport_pos, label));
+            }
+        }
+    }
+
+    // Keep the nearest to the cursor first
+    if let Some(cursor) = window_query.single().cursor_position() {
+        candidates.sort_by(|a, b| {
+            a.0.distance_squared(cursor)
+                .total_cmp(&b.0.distance_squared(cursor))
+        });
+    }
+
+    // Labels are pre-spawned. Use as many as needed and hide the rest
+    let mut candidates_iter = candidates.into_iter();
+    for (mut text, mut style, mut visibility) in labels.iter_mut() {
+        match candidates_iter.next() {
+            Some((pos, label)) => {
+                text.sections[0].value = label;
+                style.left = Val::Px(pos.x);
+                style.top = Val::Px(pos.y);
+                *visibility = Visibility::Visible;
+            }
+            None => {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::components::{FramesTimeline, LegendEntry, PlaybackControl};
+use crate::resources::{SimInfo, TextStyles};
+
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+// Alpha of legend entries for classes that are not present in the current frame
+const DIMMED_ALPHA: f32 = 0.3;
+
+/// This system spawns the legend with the color swatch, name, mass and radius of each particle class
+pub fn spawn_legend(sim_info: Res<SimInfo>, text_styles: Res<TextStyles>, mut commands: Commands) {
+    let mut classes: Vec<_> = sim_info.particle_skins.iter().collect();
+    classes.sort_by_key(|(class_id, _)| **class_id);
+
+    // Bottom right corner, above the time indicator. Statistics and wall info take the top
+    let legend = NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            bottom: Val::Px(30.0),
+            right: Val::Px(5.0),
+            ..default()
+        },
+        ..default()
+    };
+    commands.spawn(legend).with_children(|legend| {
+        for (class_id, skin) in classes {
+            let row = NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            };
+            legend.spawn(row).with_children(|row| {
+                row.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(12.0),
+                            height: Val::Px(12.0),
+                            margin: UiRect::right(Val::Px(5.0)),
+                            ..default()
+                        },
+                        background_color: skin.color().into(),
+                        ..default()
+                    },
+                    LegendEntry { class: *class_id },
+                ));
+                row.spawn((
+                    TextBundle::from_section(
+                        sim_info.particle_class_label(*class_id),
+                        text_styles.main_style.clone(),
+                    ),
+                    LegendEntry { class: *class_id },
+                ));
+            });
+        }
+    });
+}
+
+/// This system dims legend entries of classes that are not present in the current frame
+pub fn update_legend(
+    mut swatch_query: Query<(&LegendEntry, &mut BackgroundColor)>,
+    mut text_query: Query<(&LegendEntry, &mut Text)>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    sim_info: Res<SimInfo>,
+) {
+    let current_time = playback_query.single().current_time();
+    let present: HashSet<_> = match timeline_query.single().last_frame_for(current_time) {
+        Some((_, frame)) => frame.particles.iter().map(|p| p.class()).collect(),
+        None => HashSet::new(),
+    };
+    let alpha = |entry: &LegendEntry| {
+        if present.contains(&entry.class) { 1.0 } else { DIMMED_ALPHA }
+    };
+
+    for (entry, mut color) in &mut swatch_query {
+        let skin_color = sim_info.particle_skins[&entry.class].color();
+        color.0 = skin_color.with_a(skin_color.a() * alpha(entry));
+    }
+    for (entry, mut text) in &mut text_query {
+        let text_color = text.sections[0].style.color;
+        text.sections[0].style.color = text_color.with_a(alpha(entry));
+    }
+}
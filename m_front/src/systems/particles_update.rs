@@ -1,5 +1,6 @@
-use crate::components::{FramesTimeline, Particle, PlaybackControl};
-use crate::resources::SkinGraphics;
+use crate::components::{FramesTimeline, ObjectVisibility, Particle, ParticleSizing, PlaybackControl};
+use crate::resources::{SimInfo, SkinGraphics};
+use crate::utils;
 
 use bevy::prelude::*;
 use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
@@ -31,9 +32,11 @@ pub fn particle_spawn_despawn(
         let spawn_count = required_count - current_count;
         let mut particles = vec![];
         for _ in 0..spawn_count {
+            // Sprite is drawn only while the entity has an image, see `update_skins`
             particles.push((
                 Particle::new(),
                 MaterialMesh2dBundle::<ColorMaterial>::default(),
+                Sprite::default(),
             ));
         }
         commands.spawn_batch(particles);
@@ -45,11 +48,13 @@ pub fn particle_spawn_despawn(
     }
 }
 
-/// This system moves particles and update it's class
+/// This system moves particles and update it's class and drawn size
 pub fn particle_update(
     mut query: Query<(&mut Transform, &mut Particle)>,
     playback_control: Query<&PlaybackControl>,
     timeline: Query<&FramesTimeline>,
+    sizing_query: Query<&ParticleSizing>,
+    sim_info: Res<SimInfo>,
 ) {
         // Get current time
         let current_time = playback_control.single().current_time();
@@ -59,23 +64,283 @@ pub fn particle_update(
         let current_frame = current_frame_opt.as_ref().unwrap().1;
         // Check that spawn despawn worked as expected
         assert_eq!(current_frame.particles.len(), query.iter().count());
+        let by_scalar = sizing_query.single().by_scalar();
         // Now loop and copy positions and particle class
         for (i, (mut transform, mut dst_particle)) in query.iter_mut().enumerate() {
             let src_particle = &current_frame.particles[i];
             *transform = Transform::from_translation(Vec3::new(
                 src_particle.position.x as f32, src_particle.position.y as f32, 0.0));
+            // Mesh is made for the class radius (times render scale). Scale it if particle has its own radius
+            if let Some(radius) = src_particle.radius_override() {
+                let skin_radius = sim_info.particle_skins[&src_particle.class()].radius();
+                transform.scale = Vec3::splat(radius as f32 / skin_radius);
+            }
             dst_particle.class = src_particle.class();
-
+            // Frames without scalars are drawn at the skin size
+            dst_particle.size_level = match by_scalar {
+                true => current_frame.scalars.get(i).map(|scalar| utils::size_level(*scalar)),
+                false => None,
+            };
         }
 }
 
-/// This system updates particles skin based on the class
+/// Graphics components of a particle entity
+type SkinComponents<'a> = (
+    Entity,
+    &'a mut Mesh2dHandle,
+    &'a mut Handle<ColorMaterial>,
+    &'a mut Sprite,
+    Option<&'a Handle<Image>>,
+    &'a Particle,
+);
+
+/// This system updates particles skin based on the class.
+/// Classes with a texture are drawn as sprites, others as circle meshes. Particle entities are
+/// reused across classes, so the image is added or removed when the class changes.
+/// Particles with a size level get the mesh or sprite of that size
 pub fn update_skins(
-    mut query: Query<(&mut Mesh2dHandle, &mut Handle<ColorMaterial>, &Particle)>,
-    skins: Res<SkinGraphics>) {
+    mut query: Query<SkinComponents>,
+    skins: Res<SkinGraphics>,
+    sim_info: Res<SimInfo>,
+    mut commands: Commands) {
     
-    for (mut mesh, mut material, particle) in query.iter_mut() {
-        *mesh = skins.particle_meshes.get(&particle.class).unwrap().clone().into();
+    for (entity, mut mesh, mut material, mut sprite, image, particle) in query.iter_mut() {
         *material = skins.particle_materials.get(&particle.class).unwrap().clone();
+        match skins.particle_textures.get(&particle.class) {
+            Some(texture) => {
+                // Empty mesh handle draws nothing
+                *mesh = Mesh2dHandle::default();
+                let skin = &sim_info.particle_skins[&particle.class];
+                let size_scale = particle.size_level.map_or(1.0, utils::size_level_scale);
+                let diameter = 2.0 * skin.radius() * skin.render_scale() * size_scale;
+                sprite.custom_size = Some(Vec2::splat(diameter));
+                if image != Some(texture) {
+                    commands.entity(entity).insert(texture.clone());
+                }
+            }
+            None => {
+                let class_mesh = match particle.size_level {
+                    Some(level) => &skins.particle_sized_meshes[&particle.class][level],
+                    None => &skins.particle_meshes[&particle.class],
+                };
+                *mesh = class_mesh.clone().into();
+                if image.is_some() {
+                    commands.entity(entity).remove::<Handle<Image>>();
+                }
+            }
+        }
+    }
+}
+
+/// Reads the keyboard input. Toggles visibility of particles and sizing them by scalar
+pub fn read_user_input(
+    mut visibility_query: Query<&mut ObjectVisibility>,
+    mut sizing_query: Query<&mut ParticleSizing>,
+    input: Res<Input<KeyCode>>,
+) {
+    let mut visibility = visibility_query.single_mut();
+    if input.just_pressed(KeyCode::P) {
+        let show_particles = visibility.show_particles();
+        visibility.set_show_particles(!show_particles);
+    }
+    let mut sizing = sizing_query.single_mut();
+    if input.just_pressed(KeyCode::R) {
+        let by_scalar = sizing.by_scalar();
+        sizing.set_by_scalar(!by_scalar);
+    }
+}
+
+/// This system hides or shows all particles. Particles are never despawned for that,
+/// so the count still matches the frame. Newly spawned particles get the same visibility
+pub fn update_visibility(
+    mut query: Query<&mut Visibility, With<Particle>>,
+    visibility_query: Query<&ObjectVisibility>,
+) {
+    let visibility = ObjectVisibility::visibility(visibility_query.single().show_particles());
+    for mut particle_visibility in query.iter_mut() {
+        particle_visibility.set_if_neq(visibility);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m_engine::{Statistics, Vec2};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn visible_count(app: &mut App) -> (usize, usize) {
+        let mut query = app.world.query_filtered::<&Visibility, With<Particle>>();
+        let visibilities: Vec<&Visibility> = query.iter(&app.world).collect();
+        let visible = visibilities.iter().filter(|v| ***v != Visibility::Hidden).count();
+        return (visibilities.len(), visible);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_hidden_particles_keep_count() {
+        // 3 particles first, 5 after one second
+        let (frames_tx, frames_rx) = std::sync::mpsc::channel();
+        for (time, count) in [(0, 3), (1, 5)] {
+            let particles = (0..count)
+                .map(|i| m_engine::Particle::new(Vec2::new(i as f64, 0.0), Vec2::ZERO, 0))
+                .collect();
+            let frame = crate::Frame::new(particles, vec![], Statistics::default());
+            frames_tx.send((Duration::from_secs(time), frame)).unwrap();
+        }
+        let mut timeline = FramesTimeline::from_streams(vec![frames_rx]);
+        timeline.poll_frames();
+
+        let mut app = App::new();
+        app.insert_resource(SimInfo::new(Duration::from_secs(1), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new()));
+        app.insert_resource(Input::<KeyCode>::default());
+        app.world.spawn(PlaybackControl::new());
+        app.world.spawn(timeline);
+        app.world.spawn(ObjectVisibility::new());
+        app.world.spawn(ParticleSizing::new());
+        app.add_systems(PreUpdate, particle_spawn_despawn);
+        app.add_systems(Update, (read_user_input, update_visibility.after(read_user_input), particle_update));
+
+        app.update();
+        assert_eq!(visible_count(&mut app), (3, 3));
+
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::P);
+        app.update();
+        assert_eq!(visible_count(&mut app), (3, 0));
+
+        // Hidden particles are still spawned as the frame requires
+        let mut input = app.world.resource_mut::<Input<KeyCode>>();
+        input.release(KeyCode::P);
+        input.clear();
+        app.world.query::<&mut PlaybackControl>().single_mut(&mut app.world)._seek(Duration::from_secs(1));
+        app.update();
+        assert_eq!(visible_count(&mut app), (5, 0));
+
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::P);
+        app.update();
+        assert_eq!(visible_count(&mut app), (5, 5));
+    }
+
+    #[test]
+    fn test_textured_and_flat_classes() {
+        // Class 0 is textured, class 1 is a flat circle. After one second the classes swap
+        let (frames_tx, frames_rx) = std::sync::mpsc::channel();
+        for (time, classes) in [(0, [0, 1, 1]), (1, [1, 0, 0])] {
+            let particles = classes.iter()
+                .map(|c| m_engine::Particle::new(Vec2::ZERO, Vec2::ZERO, *c))
+                .collect();
+            let frame = crate::Frame::new(particles, vec![], Statistics::default());
+            frames_tx.send((Duration::from_secs(time), frame)).unwrap();
+        }
+        let mut timeline = FramesTimeline::from_streams(vec![frames_rx]);
+        timeline.poll_frames();
+
+        let mut particle_skins = HashMap::new();
+        particle_skins.insert(0, crate::ParticleSkin::new(2.0, Color::RED).with_texture("argon.png").with_render_scale(1.5));
+        particle_skins.insert(1, crate::ParticleSkin::new(1.0, Color::BLUE));
+        let mut skin_graphics = SkinGraphics::new();
+        for class in [0, 1] {
+            skin_graphics.particle_meshes.insert(class, Handle::weak_from_u128(10 + class as u128));
+            skin_graphics.particle_materials.insert(class, Handle::weak_from_u128(20 + class as u128));
+        }
+        let texture: Handle<Image> = Handle::weak_from_u128(30);
+        skin_graphics.particle_textures.insert(0, texture.clone());
+
+        let mut app = App::new();
+        app.insert_resource(SimInfo::new(Duration::from_secs(1), particle_skins, HashMap::new(), HashMap::new(), HashMap::new()));
+        app.insert_resource(skin_graphics);
+        app.world.spawn(PlaybackControl::new());
+        app.world.spawn(timeline);
+        app.world.spawn(ParticleSizing::new());
+        app.add_systems(PreUpdate, particle_spawn_despawn);
+        app.add_systems(Update, (particle_update, update_skins.after(particle_update)));
+
+        let check = |app: &mut App, textured_count: usize| {
+            let mut query = app.world.query::<(&Particle, &Mesh2dHandle, &Sprite, Option<&Handle<Image>>)>();
+            let mut textured = 0;
+            for (particle, mesh, sprite, image) in query.iter(&app.world) {
+                if particle.class == 0 {
+                    textured += 1;
+                    assert_eq!(image, Some(&texture));
+                    assert_eq!(mesh.0, Handle::default());
+                    assert_eq!(sprite.custom_size, Some(bevy::math::Vec2::splat(6.0)));
+                } else {
+                    assert!(image.is_none());
+                    assert_eq!(mesh.0, Handle::weak_from_u128(11));
+                }
+            }
+            assert_eq!(textured, textured_count);
+        };
+
+        app.update();
+        check(&mut app, 1);
+
+        // Entities are reused, so images must follow the new classes
+        app.world.query::<&mut PlaybackControl>().single_mut(&mut app.world)._seek(Duration::from_secs(1));
+        app.update();
+        check(&mut app, 2);
+    }
+
+    #[test]
+    fn test_size_by_scalar() {
+        // Last frame has no scalars
+        let (frames_tx, frames_rx) = std::sync::mpsc::channel();
+        for (time, scalars) in [(0, vec![0.0, 1.0, 0.4]), (1, vec![])] {
+            let particles = (0..3)
+                .map(|i| m_engine::Particle::new(Vec2::new(i as f64, 0.0), Vec2::ZERO, 0))
+                .collect();
+            let frame = crate::Frame::new(particles, vec![], Statistics::default()).with_scalars(scalars);
+            frames_tx.send((Duration::from_secs(time), frame)).unwrap();
+        }
+        let mut timeline = FramesTimeline::from_streams(vec![frames_rx]);
+        timeline.poll_frames();
+
+        let mut particle_skins = HashMap::new();
+        particle_skins.insert(0, crate::ParticleSkin::new(1.0, Color::BLUE));
+        let mut skin_graphics = SkinGraphics::new();
+        skin_graphics.particle_meshes.insert(0, Handle::weak_from_u128(10));
+        skin_graphics.particle_materials.insert(0, Handle::weak_from_u128(20));
+        let sized_meshes = (0..utils::PARTICLE_SIZE_LEVELS).map(|level| Handle::weak_from_u128(100 + level as u128)).collect();
+        skin_graphics.particle_sized_meshes.insert(0, sized_meshes);
+
+        let mut app = App::new();
+        app.insert_resource(SimInfo::new(Duration::from_secs(1), particle_skins, HashMap::new(), HashMap::new(), HashMap::new()));
+        app.insert_resource(skin_graphics);
+        app.insert_resource(Input::<KeyCode>::default());
+        app.world.spawn(PlaybackControl::new());
+        app.world.spawn(timeline);
+        app.world.spawn(ObjectVisibility::new());
+        app.world.spawn(ParticleSizing::new());
+        app.add_systems(PreUpdate, particle_spawn_despawn);
+        app.add_systems(Update, (read_user_input, particle_update.after(read_user_input), update_skins.after(particle_update)));
+
+        // Meshes by particle position. Drawn size never goes into the transform
+        let meshes = |app: &mut App| {
+            let mut query = app.world.query::<(&Transform, &Mesh2dHandle)>();
+            let mut meshes: Vec<(f32, Handle<Mesh>)> = query.iter(&app.world)
+                .map(|(transform, mesh)| {
+                    assert_eq!(transform.scale, Vec3::ONE);
+                    (transform.translation.x, mesh.0.clone())
+                })
+                .collect();
+            meshes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            return meshes.into_iter().map(|(_, mesh)| mesh).collect::<Vec<_>>();
+        };
+
+        app.update();
+        assert_eq!(meshes(&mut app), vec![Handle::weak_from_u128(10); 3]);
+
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::R);
+        app.update();
+        let expected: Vec<Handle<Mesh>> = [100, 100 + utils::PARTICLE_SIZE_LEVELS as u128 - 1, 101]
+            .iter().map(|id| Handle::weak_from_u128(*id)).collect();
+        assert_eq!(meshes(&mut app), expected);
+
+        // Frame without scalars falls back to the skin size
+        let mut input = app.world.resource_mut::<Input<KeyCode>>();
+        input.release(KeyCode::R);
+        input.clear();
+        app.world.query::<&mut PlaybackControl>().single_mut(&mut app.world)._seek(Duration::from_secs(1));
+        app.update();
+        assert_eq!(meshes(&mut app), vec![Handle::weak_from_u128(10); 3]);
+    }
+}
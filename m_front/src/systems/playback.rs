@@ -1,5 +1,6 @@
 use crate::components::{FramesTimeline, PlaybackControl, TimeIndicator};
-use crate::resources::SimInfo;
+use crate::input_log::InputCommand;
+use crate::resources::{InputRecorder, InputReplay, SimInfo};
 use bevy::prelude::*;
 
 /// This system is polling frames from the incoming channel.
@@ -9,25 +10,80 @@ pub fn poll_frames(mut query: Query<&mut FramesTimeline>) {
     }
 }
 
-/// Reads the keyboard input and sets playback parameters
+/// Reads the keyboard input and sets playback parameters.
+/// Changes are recorded if the recorder is present
 pub fn read_user_input(
     mut playback_query: Query<&mut PlaybackControl>,
     input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut recorder: Option<ResMut<InputRecorder>>,
 ) {
     let mut playback_control = playback_query.single_mut();
+    let mut commands = vec![];
     if input.just_pressed(KeyCode::Space) {
-        let p = playback_control.is_playing();
-        playback_control.set_playing(!p);
+        if playback_control.is_playing() {
+            commands.push(InputCommand::Pause);
+        } else {
+            commands.push(InputCommand::Play);
+        }
     }
 
-    if input.pressed(KeyCode::Right) {
-        playback_control.set_rewind(Some(5.0));
+    let rewind = if input.pressed(KeyCode::Right) {
+        Some(5.0)
+    } else if input.pressed(KeyCode::Left) {
+        Some(-5.0)
+    } else {
+        None
+    };
+    if rewind != playback_control.rewind() {
+        commands.push(InputCommand::Rewind(rewind));
     }
-    else if input.pressed(KeyCode::Left) {
-        playback_control.set_rewind(Some(-5.0));
+
+    for command in commands {
+        match command {
+            InputCommand::Play => playback_control.set_playing(true),
+            InputCommand::Pause => playback_control.set_playing(false),
+            InputCommand::Rewind(rewind) => playback_control.set_rewind(rewind),
+            InputCommand::SelectStream(_) => {}
+        }
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(time.elapsed(), command);
+        }
     }
-    else {
-        playback_control.set_rewind(None);
+}
+
+/// Switches the viewed ensemble member on [Tab]
+pub fn select_stream(
+    mut query: Query<&mut FramesTimeline>,
+    input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut recorder: Option<ResMut<InputRecorder>>,
+) {
+    if input.just_pressed(KeyCode::Tab) {
+        let mut timeline = query.single_mut();
+        let next = timeline.selected_stream() + 1;
+        timeline.select_stream(next);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(time.elapsed(), InputCommand::SelectStream(timeline.selected_stream()));
+        }
+    }
+}
+
+/// Applies recorded commands instead of the keyboard input
+pub fn replay_input(
+    time: Res<Time>,
+    mut replay: ResMut<InputReplay>,
+    mut playback_query: Query<&mut PlaybackControl>,
+    mut timeline_query: Query<&mut FramesTimeline>,
+) {
+    let mut playback_control = playback_query.single_mut();
+    while let Some(command) = replay.pop_due(time.elapsed()) {
+        match command {
+            InputCommand::Play => playback_control.set_playing(true),
+            InputCommand::Pause => playback_control.set_playing(false),
+            InputCommand::Rewind(rewind) => playback_control.set_rewind(rewind),
+            InputCommand::SelectStream(index) => timeline_query.single_mut().select_stream(index),
+        }
     }
 }
 
@@ -49,8 +105,8 @@ pub fn advance_time(
     if let Some((last_frame_timestamp, _)) = timeline.last_frame() {
         // last frame timetamp is soft stop.
         let soft_end = last_frame_timestamp;
-        // Hard stop is total simulation length
-        let hard_end = sim_info.total_duration;
+        // Hard stop is total simulation length, or the end of a run that stopped early
+        let hard_end = timeline.end_time().unwrap_or(sim_info.total_duration);
         // Step time is time passed since last frame
         playback_control.step(time_passed, soft_end, hard_end);
 
@@ -58,10 +114,10 @@ pub fn advance_time(
     }
 }
 
-// System that start playback
-pub fn start_playback(mut playback_query: Query<&mut PlaybackControl>) {
+// System that starts playback if autoplay is on. Otherwise playback stays paused
+pub fn start_playback(mut playback_query: Query<&mut PlaybackControl>, sim_info: Res<SimInfo>) {
     for mut playback_control in &mut playback_query {
-        playback_control.set_playing(true);
+        playback_control.set_playing(sim_info.autoplay);
     }
 }
 
@@ -82,3 +138,145 @@ pub fn update_time_indicator(
     let mut text = query.single_mut().1;
     text.sections[0].value = time_string;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_log::{read_input_log, write_input_log, InputMode, INPUT_FRAME_TIME};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn make_app(autoplay: bool) -> App {
+        let mut app = App::new();
+        let sim_info = SimInfo::new(Duration::from_secs(1), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new())
+            .with_autoplay(autoplay);
+        app.insert_resource(sim_info);
+        app.insert_resource(Input::<KeyCode>::default());
+        app.insert_resource(Time::<()>::default());
+        app.world.spawn(PlaybackControl::new());
+        app.add_systems(PostStartup, start_playback);
+        app.add_systems(Update, read_user_input);
+        return app;
+    }
+
+    fn is_playing(app: &mut App) -> bool {
+        let mut query = app.world.query::<&PlaybackControl>();
+        return query.single(&app.world).is_playing();
+    }
+
+    #[test]
+    fn test_initial_pause() {
+        let mut app = make_app(false);
+        app.update();
+        app.update();
+        assert!(!is_playing(&mut app));
+
+        // Space starts the playback
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Space);
+        app.update();
+        assert!(is_playing(&mut app));
+
+        let mut app = make_app(true);
+        app.update();
+        assert!(is_playing(&mut app));
+    }
+
+    // Timeline with 2 streams of frames every 100ms for 10 seconds
+    fn make_timeline() -> FramesTimeline {
+        let mut frames_rxs = vec![];
+        for _ in 0..2 {
+            let (frames_tx, frames_rx) = std::sync::mpsc::channel();
+            for i in 0..=100 {
+                let frame = crate::Frame::new(vec![], vec![], m_engine::Statistics::default());
+                frames_tx.send((Duration::from_millis(100 * i), frame)).unwrap();
+            }
+            frames_rxs.push(frames_rx);
+        }
+        return FramesTimeline::from_streams(frames_rxs);
+    }
+
+    fn make_input_app(input_mode: InputMode) -> App {
+        let mut app = App::new();
+        app.add_plugins(bevy::time::TimePlugin);
+        app.insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(INPUT_FRAME_TIME));
+        let sim_info = SimInfo::new(Duration::from_secs(10), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new());
+        app.insert_resource(sim_info);
+        app.insert_resource(Input::<KeyCode>::default());
+        app.world.spawn(PlaybackControl::new());
+        app.world.spawn(make_timeline());
+        app.add_systems(PreUpdate, poll_frames);
+        match input_mode {
+            InputMode::Replay(events) => {
+                app.insert_resource(InputReplay::new(events));
+                app.add_systems(Update, replay_input);
+            }
+            _ => {
+                app.insert_resource(InputRecorder::new(None));
+                app.add_systems(Update, (read_user_input, select_stream));
+            }
+        }
+        app.add_systems(PostUpdate, advance_time);
+        return app;
+    }
+
+    fn playback_state(app: &mut App) -> (bool, Option<f64>, Duration, usize) {
+        let playback_control = app.world.query::<&PlaybackControl>().single(&app.world).clone();
+        let selected = app.world.query::<&FramesTimeline>().single(&app.world).selected_stream();
+        return (
+            playback_control.is_playing(),
+            playback_control.rewind(),
+            playback_control.current_time(),
+            selected,
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        // Keys pressed at the given frames
+        let script: Vec<(usize, KeyCode, bool)> = vec![
+            (2, KeyCode::Space, true),
+            (3, KeyCode::Space, false),
+            (10, KeyCode::Right, true),
+            (15, KeyCode::Right, false),
+            (20, KeyCode::Tab, true),
+            (21, KeyCode::Tab, false),
+            (25, KeyCode::Left, true),
+            (27, KeyCode::Left, false),
+            (30, KeyCode::Space, true),
+        ];
+        let num_frames = 40;
+
+        let mut app = make_input_app(InputMode::Live);
+        let mut recorded_states = vec![];
+        for frame in 0..num_frames {
+            let mut input = app.world.resource_mut::<Input<KeyCode>>();
+            input.clear();
+            for &(_, key, pressed) in script.iter().filter(|(f, _, _)| *f == frame) {
+                if pressed {
+                    input.press(key);
+                } else {
+                    input.release(key);
+                }
+            }
+            app.update();
+            recorded_states.push(playback_state(&mut app));
+        }
+        let events = app.world.resource::<InputRecorder>().events().to_vec();
+        // Play, rewind, stop rewind, stream, rewind back, stop rewind, pause
+        assert_eq!(events.len(), 7);
+        let (is_playing, _, current_time, selected) = recorded_states[29];
+        assert!(is_playing);
+        assert!(current_time > Duration::ZERO);
+        assert_eq!(selected, 1);
+
+        // Replay through the text log
+        let mut buffer = vec![];
+        write_input_log(&mut buffer, &events).unwrap();
+        let events = read_input_log(buffer.as_slice()).unwrap();
+        let mut app = make_input_app(InputMode::Replay(events));
+        for (frame, recorded_state) in recorded_states.iter().enumerate() {
+            app.update();
+            assert_eq!(playback_state(&mut app), *recorded_state, "Frame {}", frame);
+        }
+    }
+}
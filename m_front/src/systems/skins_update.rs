@@ -0,0 +1,160 @@
+use crate::components::{FramesTimeline, PlaybackControl};
+use crate::resources::{SimInfo, SkinGraphics};
+use crate::skins::{PLACEHOLDER_COLOR, PLACEHOLDER_RADIUS};
+use crate::utils;
+use crate::{ParticleSkin, WallSkin};
+
+use m_engine::prelude::ClassId;
+
+use bevy::prelude::*;
+use bevy::sprite::ColorMaterial;
+
+/// Makes circle meshes and material of the particle skin. Texture isn't loaded here
+pub(crate) fn add_particle_skin_graphics(
+    class_id: ClassId,
+    skin: &ParticleSkin,
+    skin_graphics: &mut SkinGraphics,
+    mesh_assets: &mut Assets<Mesh>,
+    material_assets: &mut Assets<ColorMaterial>,
+) {
+    // Render scale is cosmetic. Only the drawn circle is affected
+    let radius = skin.radius() * skin.render_scale();
+    let mesh = mesh_assets.add(Mesh::from(shape::Circle::new(radius)));
+    let sized_meshes = (0..utils::PARTICLE_SIZE_LEVELS)
+        .map(|level| mesh_assets.add(Mesh::from(shape::Circle::new(radius * utils::size_level_scale(level)))))
+        .collect();
+    let material = material_assets.add(ColorMaterial::from(skin.color()));
+    skin_graphics.particle_meshes.insert(class_id, mesh);
+    skin_graphics.particle_sized_meshes.insert(class_id, sized_meshes);
+    skin_graphics.particle_materials.insert(class_id, material);
+}
+
+pub(crate) fn add_wall_skin_graphics(
+    class_id: ClassId,
+    skin: &WallSkin,
+    skin_graphics: &mut SkinGraphics,
+    material_assets: &mut Assets<ColorMaterial>,
+) {
+    let material = material_assets.add(ColorMaterial::from(skin.color()));
+    skin_graphics.wall_materials.insert(class_id, material);
+}
+
+/// Classes may appear at runtime, e.g. when the scene is reloaded. This system gives placeholder
+/// skins to classes of the current frame that have none, and makes graphics for skins that
+/// have no graphics yet. Class radius is used for the placeholder if the class is known
+pub fn add_missing_skins(
+    mut sim_info: ResMut<SimInfo>,
+    mut skin_graphics: ResMut<SkinGraphics>,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    mut material_assets: ResMut<Assets<ColorMaterial>>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+) {
+    let current_time = playback_query.single().current_time();
+    let Some((_, frame)) = timeline_query.single().last_frame_for(current_time) else {
+        return;
+    };
+
+    for particle in &frame.particles {
+        let class_id = particle.class();
+        if skin_graphics.particle_materials.contains_key(&class_id) {
+            continue;
+        }
+        if !sim_info.particle_skins.contains_key(&class_id) {
+            println!("Particle class {} has no skin. Placeholder is used", class_id);
+            let radius = sim_info
+                .particle_classes
+                .get(&class_id)
+                .map_or(PLACEHOLDER_RADIUS, |class| class.radius() as f32);
+            let skin = ParticleSkin::new(radius, PLACEHOLDER_COLOR).with_name(&format!("Class {}", class_id));
+            sim_info.particle_skins.insert(class_id, skin);
+        }
+        let skin = &sim_info.particle_skins[&class_id];
+        add_particle_skin_graphics(class_id, skin, &mut skin_graphics, &mut mesh_assets, &mut material_assets);
+    }
+
+    for wall in &frame.walls {
+        let class_id = wall.class();
+        if skin_graphics.wall_materials.contains_key(&class_id) {
+            continue;
+        }
+        let skin = sim_info.wall_skins.entry(class_id).or_insert_with(|| {
+            println!("Wall class {} has no skin. Placeholder is used", class_id);
+            return WallSkin::new(PLACEHOLDER_COLOR);
+        });
+        add_wall_skin_graphics(class_id, skin, &mut skin_graphics, &mut material_assets);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{Particle, ParticleSizing, Wall};
+    use crate::systems::{particles_update, walls_update};
+    use m_engine::{Polygon, Statistics, Vec2};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unknown_classes_get_placeholder() {
+        // Class 0 has a skin, classes 5 and 7 are unknown to the front-end
+        let (frames_tx, frames_rx) = std::sync::mpsc::channel();
+        let particles = vec![
+            m_engine::Particle::new(Vec2::ZERO, Vec2::ZERO, 0),
+            m_engine::Particle::new(Vec2::new(2.0, 0.0), Vec2::ZERO, 5).with_mass_and_radius(1.0, 0.5),
+        ];
+        let walls = vec![m_engine::Wall::new(Polygon::new_rectangle(-5.0, -5.0, 5.0, -4.0), 7)];
+        frames_tx.send((Duration::ZERO, crate::Frame::new(particles, walls, Statistics::default()))).unwrap();
+        let mut timeline = FramesTimeline::from_streams(vec![frames_rx]);
+        timeline.poll_frames();
+
+        let mut particle_skins = HashMap::new();
+        particle_skins.insert(0, ParticleSkin::new(1.0, Color::BLUE));
+        let mut skin_graphics = SkinGraphics::new();
+        skin_graphics.particle_meshes.insert(0, Handle::weak_from_u128(10));
+        skin_graphics.particle_materials.insert(0, Handle::weak_from_u128(20));
+
+        let mut app = App::new();
+        app.insert_resource(SimInfo::new(Duration::from_secs(1), particle_skins, HashMap::new(), HashMap::new(), HashMap::new()));
+        app.insert_resource(skin_graphics);
+        app.insert_resource(Assets::<Mesh>::default());
+        app.insert_resource(Assets::<ColorMaterial>::default());
+        app.world.spawn(PlaybackControl::new());
+        app.world.spawn(timeline);
+        app.world.spawn(ParticleSizing::new());
+        app.add_systems(
+            PreUpdate,
+            (
+                add_missing_skins,
+                particles_update::particle_spawn_despawn,
+                walls_update::wall_spawn_despawn.after(add_missing_skins),
+            ),
+        );
+        app.add_systems(
+            Update,
+            (particles_update::particle_update, particles_update::update_skins.after(particles_update::particle_update)),
+        );
+        app.update();
+        app.update();
+
+        let sim_info = app.world.resource::<SimInfo>();
+        assert_eq!(sim_info.particle_skins[&5].color(), PLACEHOLDER_COLOR);
+        assert_eq!(sim_info.particle_skins[&5].radius(), PLACEHOLDER_RADIUS);
+        assert_eq!(sim_info.particle_skins[&0].color(), Color::BLUE);
+        assert_eq!(sim_info.wall_skins[&7].color(), PLACEHOLDER_COLOR);
+        let skin_graphics = app.world.resource::<SkinGraphics>();
+        // Known class keeps its graphics
+        assert_eq!(skin_graphics.particle_materials[&0], Handle::weak_from_u128(20));
+        let placeholder = skin_graphics.particle_materials[&5].clone();
+        assert_eq!(skin_graphics.particle_sized_meshes[&5].len(), utils::PARTICLE_SIZE_LEVELS);
+        let wall_material = skin_graphics.wall_materials[&7].clone();
+
+        let mut query = app.world.query::<(&Particle, &Handle<ColorMaterial>)>();
+        let materials: Vec<(ClassId, Handle<ColorMaterial>)> =
+            query.iter(&app.world).map(|(p, m)| (p.class, m.clone())).collect();
+        assert_eq!(materials.len(), 2);
+        assert!(materials.contains(&(5, placeholder)));
+        let mut query = app.world.query::<(&Wall, &Handle<ColorMaterial>)>();
+        assert_eq!(query.single(&app.world).1, &wall_material);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::components::{FramesTimeline, PlaybackControl};
+use crate::resources::SimInfo;
+
+use bevy::prelude::*;
+
+/// This system saves the displayed frame as a new scene file on [S].
+/// File is named after the frame time and written to the working directory
+pub fn save_snapshot(
+    input: Res<Input<KeyCode>>,
+    sim_info: Res<SimInfo>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+) {
+    if !input.just_pressed(KeyCode::S) {
+        return;
+    }
+    let Some(scene) = &sim_info.scene else {
+        println!("Snapshot is not available without the scene");
+        return;
+    };
+    let playback_control = playback_query.single();
+    let timeline = timeline_query.single();
+    let Some((timestamp, frame)) = timeline.last_frame_for(playback_control.current_time()) else {
+        return;
+    };
+
+    let mut snapshot = scene.snapshot(&frame.particles, &frame.walls);
+    snapshot.name = format!("{} at {:.3}s", scene.name, timestamp.as_secs_f64());
+    let path = format!("snapshot_{}ms.yaml", timestamp.as_millis());
+    match snapshot.to_path(&path) {
+        Ok(()) => println!("Saved snapshot to {}", path),
+        Err(e) => println!("{}", e),
+    }
+}
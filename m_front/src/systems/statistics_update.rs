@@ -12,12 +12,21 @@ pub fn update_statistics(
     // Get current time
     let current_time = playback_query.single().current_time();
     // Get current frame
-    let current_frame_opt = timeline_query.single().last_frame_for(current_time);
+    let timeline = timeline_query.single();
+    let current_frame_opt = timeline.last_frame_for(current_time);
     if current_frame_opt.is_none() { return };
 
     let mut text = query.single_mut().1;
     // Combine all statistics into one string
-    let combined = current_frame_opt.unwrap().1.statistics.to_strings().join("\n");
-    text.sections[0].value = combined;
+    let current_frame = current_frame_opt.unwrap().1;
+    let mut strings = current_frame.statistics.to_strings();
+    strings.push(format!("Time step: {:.3} ms", current_frame.time_step.as_secs_f64() * 1000.0));
+    strings.push(format!("Substeps: {} of {:.3} ms",
+        current_frame.substeps, current_frame.substep_duration().as_secs_f64() * 1000.0));
+    if timeline.num_streams() > 1 {
+        strings.insert(0, format!("Ensemble member: {}/{}",
+            timeline.selected_stream() + 1, timeline.num_streams()));
+    }
+    text.sections[0].value = strings.join("\n");
 
 }
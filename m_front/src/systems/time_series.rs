@@ -0,0 +1,102 @@
+use crate::components::time_series::{plot_points, PlotQuantity, MAX_PLOT_SAMPLES};
+use crate::components::{FramesTimeline, PlaybackControl, TimeSeriesOverlay};
+use crate::resources::SimInfo;
+
+use bevy::prelude::*;
+
+// Plot area in world coordinates. It sticks to the top right corner of the visible area
+const PLOT_SIZE: Vec2 = Vec2::new(50.0, 25.0);
+const PLOT_MARGIN: Vec2 = Vec2::new(5.0, 5.0);
+
+/// Reads the keyboard input and switches the time series overlay to the next quantity
+pub fn read_user_input(mut overlay_query: Query<&mut TimeSeriesOverlay>, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::G) {
+        overlay_query.single_mut().cycle();
+    }
+}
+
+/// This system plots the selected quantity against time from the start up to the current time.
+/// Particle count is plotted in total and, if there are several classes, for each class in
+/// the class color. Mean square displacement is plotted for each class
+pub fn draw_time_series(
+    mut gizmos: Gizmos,
+    overlay_query: Query<&TimeSeriesOverlay>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    projection_query: Query<&OrthographicProjection>,
+    sim_info: Res<SimInfo>,
+) {
+    let overlay = overlay_query.single();
+    if !overlay.visible() {
+        return;
+    }
+    let current_time = playback_query.single().current_time();
+    let frames = timeline_query
+        .single()
+        .sample_frames_until(current_time, MAX_PLOT_SAMPLES);
+    if frames.is_empty() {
+        return;
+    }
+
+    let mut classes: Vec<_> = sim_info.particle_skins.keys().copied().collect();
+    classes.sort();
+    let mut series = vec![];
+    match overlay.quantity() {
+        PlotQuantity::ParticleCount => {
+            // Total count goes first
+            series.push((
+                Color::WHITE,
+                frames
+                    .iter()
+                    .map(|(ts, frame)| (ts.as_secs_f32(), frame.statistics.num_particles as f32))
+                    .collect::<Vec<_>>(),
+            ));
+            if classes.len() > 1 {
+                for &class in &classes {
+                    let samples = frames
+                        .iter()
+                        .map(|(ts, frame)| {
+                            let count = frame.statistics.class_counts.get(&class).copied().unwrap_or(0);
+                            (ts.as_secs_f32(), count as f32)
+                        })
+                        .collect();
+                    series.push((sim_info.particle_skins[&class].color(), samples));
+                }
+            }
+        }
+        PlotQuantity::MeanSquareDisplacement => {
+            // Frames without the measurement are skipped
+            for &class in &classes {
+                let samples: Vec<_> = frames
+                    .iter()
+                    .filter_map(|(ts, frame)| {
+                        let msd = frame.statistics.mean_square_displacement.get(&class)?;
+                        Some((ts.as_secs_f32(), *msd as f32))
+                    })
+                    .collect();
+                if !samples.is_empty() {
+                    series.push((sim_info.particle_skins[&class].color(), samples));
+                }
+            }
+            if series.is_empty() {
+                return;
+            }
+        }
+    }
+
+    // Common value axis for all series
+    let values = series.iter().flat_map(|(_, samples)| samples.iter().map(|s| s.1));
+    let value_range = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+    // Time axis covers the whole run, so the plot grows from left to right
+    let max_time = sim_info.total_duration.as_secs_f32();
+
+    // Follow the window shape
+    let plot_corner = projection_query.single().area.max - PLOT_SIZE - PLOT_MARGIN;
+    gizmos.rect_2d(plot_corner + PLOT_SIZE / 2.0, 0.0, PLOT_SIZE, Color::GRAY);
+    for (color, samples) in &series {
+        let points = plot_points(samples, max_time, value_range, plot_corner, PLOT_SIZE);
+        gizmos.linestrip_2d(points, *color);
+    }
+}
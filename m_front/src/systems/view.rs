@@ -0,0 +1,29 @@
+use crate::utils::view_scale;
+
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::window::PrimaryWindow;
+
+/// This system fits the view into the window when it's created or resized.
+/// World is scaled equally along both axes, so particles stay round.
+/// View stays centered at the origin
+pub fn fit_view(
+    window_query: Query<&Window, (With<PrimaryWindow>, Changed<Window>)>,
+    mut projection_query: Query<&mut OrthographicProjection>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let (width, height) = (window.width(), window.height());
+    // Minimized window has no size
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let scale = view_scale(width, height);
+    for mut projection in projection_query.iter_mut() {
+        // Window changes for many reasons. Don't touch the camera if the size is the same
+        if !matches!(projection.scaling_mode, ScalingMode::WindowSize(s) if s == scale) {
+            projection.scaling_mode = ScalingMode::WindowSize(scale);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::components::{GravityIndicator, PlaybackControl, ScaleBarLabel};
+use crate::resources::{SimInfo, TextStyles};
+use crate::utils::{arrow_points, scale_bar_length};
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+// Indicators are placed in pixels from the bottom left corner of the window,
+// above the controls text. They keep their place and size when the view is refitted
+const GRAVITY_CENTER_PX: Vec2 = Vec2::new(45.0, 100.0);
+const GRAVITY_RADIUS_PX: f32 = 25.0;
+const SCALE_BAR_START_PX: Vec2 = Vec2::new(20.0, 45.0);
+const SCALE_BAR_MAX_PX: f32 = 120.0;
+const SCALE_BAR_TICK_PX: f32 = 4.0;
+const LABEL_GAP_PX: f32 = 8.0;
+const LABEL_HALF_HEIGHT_PX: f32 = 10.0;
+
+/// This system spawns the texts of the gravity arrow and the scale bar
+pub fn spawn_view_indicators(text_styles: Res<TextStyles>, mut commands: Commands) {
+    let label_style = |position: Vec2| Style {
+        position_type: PositionType::Absolute,
+        left: Val::Px(position.x),
+        bottom: Val::Px(position.y - LABEL_HALF_HEIGHT_PX),
+        ..default()
+    };
+    commands.spawn((
+        TextBundle::from_section("", text_styles.main_style.clone()).with_style(label_style(
+            GRAVITY_CENTER_PX + Vec2::new(GRAVITY_RADIUS_PX + LABEL_GAP_PX, 0.0),
+        )),
+        GravityIndicator {},
+    ));
+    commands.spawn((
+        TextBundle::from_section("", text_styles.main_style.clone())
+            .with_style(label_style(SCALE_BAR_START_PX)),
+        ScaleBarLabel {},
+    ));
+}
+
+/// This system draws the arrow pointing where gravity pulls at the current time and
+/// the scale bar of world units. Both are read-only
+pub fn draw_view_indicators(
+    mut gizmos: Gizmos,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    projection_query: Query<&OrthographicProjection>,
+    playback_query: Query<&PlaybackControl>,
+    mut gravity_text_query: Query<&mut Text, (With<GravityIndicator>, Without<ScaleBarLabel>)>,
+    mut scale_text_query: Query<(&mut Text, &mut Style), With<ScaleBarLabel>>,
+    sim_info: Res<SimInfo>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let area = projection_query.single().area;
+    if window.width() <= 0.0 || area.width() <= 0.0 {
+        return;
+    }
+    // Gizmos are drawn in world units
+    let px_per_unit = window.width() / area.width();
+    let to_world = |px: Vec2| area.min + px / px_per_unit;
+
+    // Gravity arrow has fixed length. Magnitude is given by the text
+    let gravity = sim_info.gravity_at(playback_query.single().current_time());
+    let center = to_world(GRAVITY_CENTER_PX);
+    let radius = GRAVITY_RADIUS_PX / px_per_unit;
+    gizmos.circle_2d(center, radius, Color::GRAY);
+    let magnitude = gravity.length();
+    if magnitude > 0.0 {
+        let direction = Vec2::new(gravity.x as f32, gravity.y as f32) / magnitude as f32;
+        gizmos.linestrip_2d(arrow_points(center, direction * radius * 0.9), Color::WHITE);
+    }
+    gravity_text_query.single_mut().sections[0].value = format!("g = {:.2}", magnitude);
+
+    // Scale bar with ticks at the ends
+    let length = scale_bar_length(SCALE_BAR_MAX_PX / px_per_unit);
+    let start = to_world(SCALE_BAR_START_PX);
+    let end = start + Vec2::new(length, 0.0);
+    let tick = Vec2::new(0.0, SCALE_BAR_TICK_PX / px_per_unit);
+    gizmos.line_2d(start, end, Color::WHITE);
+    gizmos.line_2d(start - tick, start + tick, Color::WHITE);
+    gizmos.line_2d(end - tick, end + tick, Color::WHITE);
+    let (mut text, mut style) = scale_text_query.single_mut();
+    text.sections[0].value = format!("{} units", length);
+    style.left = Val::Px(SCALE_BAR_START_PX.x + length * px_per_unit + LABEL_GAP_PX);
+}
@@ -0,0 +1,114 @@
+use crate::components::{FramesTimeline, PlaybackControl, Wall, WallInfo, WallSelection, WallTint};
+use crate::resources::{SimInfo, SkinGraphics, GlobalMaterials};
+use crate::utils;
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// How far from the wall the click still selects it. In world units
+const PICK_DISTANCE: f64 = 2.0;
+
+/// Selects the wall under the cursor on mouse click
+pub fn pick_wall(
+    mut selection_query: Query<&mut WallSelection>,
+    mouse: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(cursor) = window_query.single().cursor_position() else {
+        return;
+    };
+    let (camera, camera_transform) = camera_query.single();
+    let Some(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    // Pick among the walls of the current frame
+    let current_time = playback_query.single().current_time();
+    let picked = match timeline_query.single().last_frame_for(current_time) {
+        Some((_, frame)) => utils::pick_wall(
+            &frame.walls,
+            m_engine::Vec2::new(world_pos.x as f64, world_pos.y as f64),
+            PICK_DISTANCE,
+        ),
+        None => None,
+    };
+    // Clicking on empty space clears selection
+    selection_query.single_mut().select(picked);
+}
+
+/// Highlights the selected wall. Other walls get the skin, temperature or load color
+pub fn update_wall_highlight(
+    mut query: Query<(&Wall, &mut Handle<ColorMaterial>)>,
+    selection_query: Query<&WallSelection>,
+    tint_query: Query<&WallTint>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    skins: Res<SkinGraphics>,
+    global_materials: Res<GlobalMaterials>,
+) {
+    let selected = selection_query.single().selected();
+    let by_temperature = tint_query.single().by_temperature();
+    // Loads are relative to the most loaded wall of the current frame
+    let current_time = playback_query.single().current_time();
+    let wall_impulses = match (tint_query.single().by_load(), timeline_query.single().last_frame_for(current_time)) {
+        (true, Some((_, frame))) => Some(frame.statistics.wall_impulses.clone()),
+        _ => None,
+    };
+    let max_impulse = wall_impulses.iter().flatten().copied().fold(0.0, f64::max);
+    for (wall, mut material) in query.iter_mut() {
+        let new_material = if selected == Some(wall.index) {
+            global_materials.white_solid.clone().unwrap()
+        } else if let (Some(impulses), false) = (&wall_impulses, skins.wall_load_materials.is_empty()) {
+            let impulse = impulses.get(wall.index).copied().unwrap_or(0.0);
+            skins.wall_load_materials[utils::load_level(impulse, max_impulse)].clone()
+        } else if let (true, Some(tinted)) =
+            (by_temperature, skins.wall_temperature_materials.get(&wall.class))
+        {
+            tinted.clone()
+        } else {
+            skins.wall_materials[&wall.class].clone()
+        };
+        if *material != new_material {
+            *material = new_material;
+        }
+    }
+}
+
+/// Updates text with the class and properties of the selected wall
+pub fn update_wall_info(
+    mut query: Query<(&WallInfo, &mut Text)>,
+    selection_query: Query<&WallSelection>,
+    playback_query: Query<&PlaybackControl>,
+    timeline_query: Query<&FramesTimeline>,
+    sim_info: Res<SimInfo>,
+) {
+    let current_time = playback_query.single().current_time();
+    let current_frame = timeline_query.single().last_frame_for(current_time);
+    let selected_wall = match (selection_query.single().selected(), current_frame) {
+        (Some(index), Some((_, frame))) => frame.walls.get(index),
+        _ => None,
+    };
+
+    let info = match selected_wall {
+        Some(wall) => match sim_info.wall_classes.get(&wall.class()) {
+            Some(class) => format!(
+                "Wall class: {} ({})\nTemperature: {} simuK\nHeat conductivity: {}",
+                class.name(),
+                wall.class(),
+                class.temperature(),
+                class.heat_conductivity()
+            ),
+            None => format!("Wall class: {}", wall.class()),
+        },
+        None => String::new(),
+    };
+
+    let mut text = query.single_mut().1;
+    text.sections[0].value = info;
+}
@@ -1,4 +1,4 @@
-use crate::components::{FramesTimeline, PlaybackControl, Wall};
+use crate::components::{FramesTimeline, ObjectVisibility, PlaybackControl, Wall, WallTint};
 use crate::resources::SkinGraphics;
 use crate::utils;
 
@@ -41,12 +41,60 @@ pub fn wall_spawn_despawn(
 
     // Spawn new walls
     let src_walls = &current_frame.unwrap().1.walls;
-    for src_wall in src_walls.iter() {
-        let mesh = utils::create_mesh(&src_wall.polygon());
-        commands.spawn((Wall::new(), MaterialMesh2dBundle {
+    for (index, src_wall) in src_walls.iter().enumerate() {
+        let polygon = src_wall.polygon();
+        // Wall that can't be filled is still shown, so the problem is visible
+        let mesh = match utils::create_mesh(polygon) {
+            Ok(mesh) => mesh,
+            Err(e) => {
+                println!(
+                    "Wall {} of class {} with {} points is drawn as outline: {}{}",
+                    index,
+                    src_wall.class(),
+                    polygon.points.len(),
+                    e,
+                    if polygon.is_simple() { "" } else { ". Polygon is self-intersecting" },
+                );
+                utils::create_outline_mesh(polygon)
+            }
+        };
+        commands.spawn((Wall::new(index, src_wall.class()), MaterialMesh2dBundle {
             material: skins.wall_materials[&src_wall.class()].clone(),
             mesh: Mesh2dHandle(meshes.add(mesh)),
             ..Default::default()
         }));
     }
 }
+
+/// Reads the keyboard input. Toggles coloring of walls by temperature or load and visibility of walls
+pub fn read_user_input(
+    mut tint_query: Query<&mut WallTint>,
+    mut visibility_query: Query<&mut ObjectVisibility>,
+    input: Res<Input<KeyCode>>,
+) {
+    let mut tint = tint_query.single_mut();
+    if input.just_pressed(KeyCode::T) {
+        let by_temperature = tint.by_temperature();
+        tint.set_by_temperature(!by_temperature);
+    }
+    if input.just_pressed(KeyCode::F) {
+        let by_load = tint.by_load();
+        tint.set_by_load(!by_load);
+    }
+    let mut visibility = visibility_query.single_mut();
+    if input.just_pressed(KeyCode::W) {
+        let show_walls = visibility.show_walls();
+        visibility.set_show_walls(!show_walls);
+    }
+}
+
+/// This system hides or shows all walls. Respawned walls get the same visibility
+pub fn update_visibility(
+    mut query: Query<&mut Visibility, With<Wall>>,
+    visibility_query: Query<&ObjectVisibility>,
+) {
+    let visibility = ObjectVisibility::visibility(visibility_query.single().show_walls());
+    for mut wall_visibility in query.iter_mut() {
+        wall_visibility.set_if_neq(visibility);
+    }
+}
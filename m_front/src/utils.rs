@@ -1,39 +1,204 @@
-use m_engine::Polygon;
+use m_engine::prelude::*;
+use m_engine::{Particle, ParticleClass, Polygon, Vec2, Wall};
+use bevy::prelude::Color;
 use bevy::render::mesh::{Mesh, PrimitiveTopology};
 
 use earcutr::earcut;
+use std::collections::HashMap;
+use std::fmt;
 
-/// Triangulates the polygon. Returns vertices and indices
-pub(crate) fn triangulate_polygon(polygon: &Polygon) -> Vec<usize> {
-    // Map polygon points to different format
-    let vertices: Vec<f32> = polygon.points2d_flat_iter().collect();
-    let res = earcut(&vertices, &[], 2);
-    return match res {
-        Ok(indices) => indices,
-        Err(e) => {
-            println!("Error triangulating polygon: {:?}", e);
-            vec![]
+/// Smallest visible area in world units. Window of any shape shows at least this
+pub(crate) const VIEW_MIN_WIDTH: f32 = 200.0;
+pub(crate) const VIEW_MIN_HEIGHT: f32 = 160.0;
+
+/// Reason the polygon can't be turned into a filled mesh
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MeshError {
+    /// Triangulation library failed
+    Triangulation(String),
+    /// Triangulation produced no triangles, e.g. polygon has no area
+    Degenerate,
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Triangulation(e) => write!(f, "triangulation failed: {}", e),
+            MeshError::Degenerate => write!(f, "polygon has no area"),
         }
-    };
+    }
 }
 
-/// Creates mesh from polygn
+/// Triangulates the polygon. Returns indices of the triangle vertices
+pub(crate) fn triangulate_polygon(polygon: &Polygon) -> Result<Vec<usize>, MeshError> {
+    // Map polygon points to different format
+    let vertices: Vec<f32> = polygon.points2d_flat_iter().collect();
+    let indices = earcut(&vertices, &[], 2).map_err(|e| MeshError::Triangulation(format!("{:?}", e)))?;
+    if indices.is_empty() {
+        return Err(MeshError::Degenerate);
+    }
+    return Ok(indices);
+}
 
-pub(crate) fn create_mesh(polygon: &Polygon) -> Mesh {
-    let indices = triangulate_polygon(polygon);
+/// Creates filled mesh from polygon
+pub(crate) fn create_mesh(polygon: &Polygon) -> Result<Mesh, MeshError> {
+    let indices = triangulate_polygon(polygon)?;
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     let points3d: Vec<[f32; 3]> = polygon.points3d_arrays_iter().collect();
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points3d);
     mesh.set_indices(Some(bevy::render::mesh::Indices::U32(
         indices.into_iter().map(|i| i as u32).collect())));
+    return Ok(mesh);
+}
+
+/// Creates closed line strip along the polygon edges. Used when polygon can't be filled
+pub(crate) fn create_outline_mesh(polygon: &Polygon) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+    let mut points3d: Vec<[f32; 3]> = polygon.points3d_arrays_iter().collect();
+    if let Some(&first) = points3d.first() {
+        points3d.push(first);
+    }
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points3d);
     return mesh;
 }
 
+/// Finds the wall at given point. Wall that contains the point wins. Otherwise
+/// the wall with the nearest edge is picked if it's closer than `max_distance`.
+/// Returns index of the wall
+pub(crate) fn pick_wall(walls: &[Wall], point: Vec2, max_distance: f64) -> Option<usize> {
+    if let Some(index) = walls.iter().position(|w| w.polygon().contains_point(point)) {
+        return Some(index);
+    }
+    let edges = walls.iter().enumerate().flat_map(|(index, wall)| {
+        wall.polygon().edges_iter().map(move |edge| (index, edge.distance_to(point)))
+    });
+    return nearest(edges.filter(|&(_, distance)| distance < max_distance));
+}
+
+/// Index of the candidate with the smallest distance. The first one wins on ties
+fn nearest(candidates: impl Iterator<Item = (usize, f64)>) -> Option<usize> {
+    let mut nearest: Option<(usize, f64)> = None;
+    for (index, distance) in candidates {
+        if nearest.is_none_or(|(_, d)| distance < d) {
+            nearest = Some((index, distance));
+        }
+    }
+    return nearest.map(|(index, _)| index);
+}
+
+/// Color of the temperature on the gradient from `min` (blue) through white to `max` (red).
+/// Temperatures outside of the range are clamped. Equal bounds give the middle color
+pub(crate) fn temperature_color(temperature: f64, min: f64, max: f64) -> Color {
+    let t = if max > min {
+        ((temperature - min) / (max - min)).clamp(0.0, 1.0) as f32
+    } else {
+        0.5
+    };
+    let cold = [0.2, 0.4, 1.0];
+    let middle = [1.0, 1.0, 1.0];
+    let hot = [1.0, 0.2, 0.1];
+    let (from, to, f) = if t < 0.5 { (cold, middle, t * 2.0) } else { (middle, hot, t * 2.0 - 1.0) };
+    let mix = |i: usize| from[i] + (to[i] - from[i]) * f;
+    return Color::rgb(mix(0), mix(1), mix(2));
+}
+
+/// Finds the particle under the point. If particles overlap, the one whose center is
+/// the nearest wins. Returns index of the particle
+pub(crate) fn pick_particle(
+    particles: &[Particle],
+    particle_classes: &HashMap<ClassId, ParticleClass>,
+    point: Vec2,
+) -> Option<usize> {
+    let hits = particles.iter().enumerate().filter_map(|(index, particle)| {
+        let class = particle_classes.get(&particle.class())?;
+        let distance = (particle.position - point).length();
+        (distance <= particle.radius(class)).then_some((index, distance))
+    });
+    return nearest(hits);
+}
+
+/// Splits the segment into dashes of `dash` length separated by gaps of the same length.
+/// The last dash may be shorter
+pub(crate) fn dashes(
+    from: bevy::math::Vec2,
+    to: bevy::math::Vec2,
+    dash: f32,
+) -> Vec<(bevy::math::Vec2, bevy::math::Vec2)> {
+    let length = from.distance(to);
+    if length == 0.0 || dash <= 0.0 {
+        return vec![];
+    }
+    let direction = (to - from) / length;
+    let mut res = vec![];
+    let mut start = 0.0;
+    while start < length {
+        let end = (start + dash).min(length);
+        res.push((from + direction * start, from + direction * end));
+        start += 2.0 * dash;
+    }
+    return res;
+}
+
+/// Number of colors of walls tinted by load
+pub(crate) const WALL_LOAD_LEVELS: usize = 16;
+
+/// Color level of the wall with the given impulse, from 0 for idle walls to
+/// `WALL_LOAD_LEVELS - 1` for the most loaded one
+pub(crate) fn load_level(impulse: f64, max_impulse: f64) -> usize {
+    if max_impulse <= 0.0 {
+        return 0;
+    }
+    let fraction = (impulse / max_impulse).clamp(0.0, 1.0);
+    return (fraction * (WALL_LOAD_LEVELS - 1) as f64).round() as usize;
+}
+
+/// Number of discrete sizes particles are drawn at when sized by scalar
+pub(crate) const PARTICLE_SIZE_LEVELS: usize = 4;
+
+/// Size level of the particle with the given normalized scalar, from 0 for the smallest
+/// to `PARTICLE_SIZE_LEVELS - 1` for the largest
+pub(crate) fn size_level(scalar: f32) -> usize {
+    // NaN goes to the smallest size
+    let fraction = if scalar.is_nan() { 0.0 } else { scalar.clamp(0.0, 1.0) };
+    return (fraction * (PARTICLE_SIZE_LEVELS - 1) as f32).round() as usize;
+}
+
+/// Drawn radius of the size level relative to the skin radius. Spans from half to one and a half
+pub(crate) fn size_level_scale(level: usize) -> f32 {
+    return 0.5 + level as f32 / (PARTICLE_SIZE_LEVELS - 1) as f32;
+}
+
+/// Pixels per world unit for the window of the given size. Scale is the same along
+/// both axes, so circles stay round. The minimal view fits into the window, the
+/// extra space goes to the longer side
+pub(crate) fn view_scale(window_width: f32, window_height: f32) -> f32 {
+    return (window_width / VIEW_MIN_WIDTH).min(window_height / VIEW_MIN_HEIGHT);
+}
+
+/// Points of the arrow from `origin` along `vector` as a line strip. The head goes
+/// from one barb through the tip to the other barb
+pub(crate) fn arrow_points(origin: bevy::math::Vec2, vector: bevy::math::Vec2) -> [bevy::math::Vec2; 5] {
+    let tip = origin + vector;
+    let back = -vector * 0.3;
+    let side = vector.perp() * 0.2;
+    return [origin, tip, tip + back + side, tip, tip + back - side];
+}
+
+/// Round length of the scale bar that is not longer than `max_length`.
+/// Lengths are 1, 2 or 5 times a power of ten
+pub(crate) fn scale_bar_length(max_length: f32) -> f32 {
+    if max_length <= 0.0 {
+        return 0.0;
+    }
+    let power = 10f32.powf(max_length.log10().floor());
+    let factor = [5.0, 2.0, 1.0].into_iter().find(|f| f * power <= max_length).unwrap_or(1.0);
+    return factor * power;
+}
+
 #[cfg(test)]
 mod tests
 {
     use super::*;
-    use m_engine::Vec2;
 
     #[test]
     fn test_triangulate_polygon()
@@ -45,9 +210,139 @@ mod tests
             Vec2::new(0.0, 1.0),
         ]);
 
-        let indices = triangulate_polygon(&polygon);
+        let indices = triangulate_polygon(&polygon).unwrap();
         assert_eq!(indices.len(), 6);
         // There are multiple valid triangulations for square
         // so we can't check the exact indices
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_degenerate_polygon_outline()
+    {
+        // All points on one line
+        let polygon = Polygon::from(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+        ]);
+        assert_eq!(create_mesh(&polygon).err(), Some(MeshError::Degenerate));
+
+        // Outline is drawn instead. It goes back to the first point
+        let outline = create_outline_mesh(&polygon);
+        assert_eq!(outline.primitive_topology(), PrimitiveTopology::LineStrip);
+        assert_eq!(outline.count_vertices(), 4);
+    }
+
+    #[test]
+    fn test_pick_wall()
+    {
+        let walls = vec![
+            Wall::new(Polygon::new_rectangle(0.0, 0.0, 10.0, 1.0), 0),
+            Wall::new(Polygon::new_rectangle(0.0, 5.0, 10.0, 6.0), 1),
+        ];
+
+        // Inside
+        assert_eq!(pick_wall(&walls, Vec2::new(5.0, 0.5), 1.0), Some(0));
+        assert_eq!(pick_wall(&walls, Vec2::new(5.0, 5.5), 1.0), Some(1));
+        // Near the edge
+        assert_eq!(pick_wall(&walls, Vec2::new(5.0, 1.5), 1.0), Some(0));
+        assert_eq!(pick_wall(&walls, Vec2::new(5.0, 4.2), 1.0), Some(1));
+        // Too far
+        assert_eq!(pick_wall(&walls, Vec2::new(5.0, 3.0), 1.0), None);
+    }
+
+    #[test]
+    fn test_view_scale()
+    {
+        // Wide window. Height limits the scale, width shows more of the world
+        let scale = view_scale(1000.0, 400.0);
+        assert_eq!(scale, 2.5);
+        assert_eq!(1000.0 / scale, 400.0);
+        assert_eq!(400.0 / scale, VIEW_MIN_HEIGHT);
+
+        // Tall window. Width limits the scale
+        let scale = view_scale(400.0, 1000.0);
+        assert_eq!(scale, 2.0);
+        assert_eq!(400.0 / scale, VIEW_MIN_WIDTH);
+        assert!(1000.0 / scale > VIEW_MIN_HEIGHT);
+    }
+
+    #[test]
+    fn test_temperature_color()
+    {
+        let cold = temperature_color(10.0, 10.0, 500.0).as_rgba_f32();
+        let hot = temperature_color(500.0, 10.0, 500.0).as_rgba_f32();
+        // Cold is blue, hot is red
+        assert!(cold[2] > cold[0]);
+        assert!(hot[0] > hot[2]);
+        let difference: f32 = cold.iter().zip(hot.iter()).map(|(a, b)| (a - b).abs()).sum();
+        assert!(difference > 1.0);
+        // Out of range is clamped, single temperature is in the middle
+        assert_eq!(temperature_color(1000.0, 10.0, 500.0), temperature_color(500.0, 10.0, 500.0));
+        assert_eq!(temperature_color(20.0, 20.0, 20.0), Color::WHITE);
+    }
+
+    #[test]
+    fn test_pick_particle()
+    {
+        let mut classes = HashMap::new();
+        classes.insert(1, ParticleClass::new("Small", 1.0, 1.0));
+        classes.insert(2, ParticleClass::new("Large", 1.0, 3.0));
+        let particles = vec![
+            Particle::new(Vec2::new(0.0, 0.0), Vec2::ZERO, 1),
+            Particle::new(Vec2::new(4.0, 0.0), Vec2::ZERO, 2),
+        ];
+        assert_eq!(pick_particle(&particles, &classes, Vec2::new(0.5, 0.5)), Some(0));
+        // Inside both. The nearer center wins
+        assert_eq!(pick_particle(&particles, &classes, Vec2::new(1.0, 0.0)), Some(0));
+        assert_eq!(pick_particle(&particles, &classes, Vec2::new(3.0, 2.0)), Some(1));
+        assert_eq!(pick_particle(&particles, &classes, Vec2::new(0.0, 5.0)), None);
+    }
+
+    #[test]
+    fn test_dashes()
+    {
+        use bevy::math::Vec2;
+        let res = dashes(Vec2::ZERO, Vec2::new(5.0, 0.0), 1.0);
+        assert_eq!(res, vec![
+            (Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)),
+            (Vec2::new(2.0, 0.0), Vec2::new(3.0, 0.0)),
+            (Vec2::new(4.0, 0.0), Vec2::new(5.0, 0.0)),
+        ]);
+        assert!(dashes(Vec2::ZERO, Vec2::ZERO, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_load_level()
+    {
+        assert_eq!(load_level(0.0, 50.0), 0);
+        assert_eq!(load_level(50.0, 50.0), WALL_LOAD_LEVELS - 1);
+        assert_eq!(load_level(25.0, 50.0), WALL_LOAD_LEVELS / 2);
+        // No load anywhere
+        assert_eq!(load_level(0.0, 0.0), 0);
+    }
+
+    #[test]
+    fn test_size_level()
+    {
+        assert_eq!(size_level(0.0), 0);
+        assert_eq!(size_level(1.0), PARTICLE_SIZE_LEVELS - 1);
+        assert_eq!(size_level(0.4), 1);
+        // Out of range values are clamped
+        assert_eq!(size_level(-3.0), 0);
+        assert_eq!(size_level(7.0), PARTICLE_SIZE_LEVELS - 1);
+        assert_eq!(size_level(f32::NAN), 0);
+        assert_eq!(size_level_scale(0), 0.5);
+        assert_eq!(size_level_scale(PARTICLE_SIZE_LEVELS - 1), 1.5);
+    }
+
+    #[test]
+    fn test_scale_bar_length()
+    {
+        assert_eq!(scale_bar_length(120.0), 100.0);
+        assert_eq!(scale_bar_length(37.0), 20.0);
+        assert_eq!(scale_bar_length(5.0), 5.0);
+        assert!((scale_bar_length(0.9) - 0.5).abs() < 1e-6);
+        assert_eq!(scale_bar_length(0.0), 0.0);
+    }
+}
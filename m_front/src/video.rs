@@ -0,0 +1,282 @@
+use crate::skins::{PLACEHOLDER_COLOR, PLACEHOLDER_RADIUS};
+use crate::utils::view_scale;
+use crate::{Frame, ParticleSkin, WallSkin};
+use bevy::prelude::Color;
+use m_engine::prelude::*;
+use m_engine::{Polygon, Vec2};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+// Same as the default clear color of the interactive front-end
+const BACKGROUND: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+// Trade-off between palette quality and encoding time. 1 is the best, 30 is the fastest
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Resolution and frame rate of the offline rendered video
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoSettings {
+    pub width: u16,
+    pub height: u16,
+    /// Video frames per second of simulation time
+    pub fps: u32,
+}
+
+impl VideoSettings {
+    pub fn new(width: u16, height: u16, fps: u32) -> Self {
+        assert!(width > 0 && height > 0);
+        assert!(fps > 0);
+        VideoSettings { width, height, fps }
+    }
+
+    // Pixels per world unit. Same as in the interactive camera
+    fn scale(&self) -> f64 {
+        view_scale(self.width as f32, self.height as f32) as f64
+    }
+
+    // Position of the pixel center in world coordinates. View is centered at the origin
+    fn pixel_to_world(&self, x: usize, y: usize) -> Vec2 {
+        let scale = self.scale();
+        Vec2::new(
+            (x as f64 + 0.5 - self.width as f64 / 2.0) / scale,
+            (self.height as f64 / 2.0 - y as f64 - 0.5) / scale,
+        )
+    }
+
+    // Range of pixels that covers the world rectangle, clipped to the image
+    fn pixel_range(&self, min: Vec2, max: Vec2) -> (usize, usize, usize, usize) {
+        let scale = self.scale();
+        let to_px = |v: f64, size: u16| {
+            (v * scale + size as f64 / 2.0).clamp(0.0, size as f64) as usize
+        };
+        let x0 = to_px(min.x, self.width);
+        let x1 = (to_px(max.x, self.width) + 1).min(self.width as usize);
+        // Image y axis points down
+        let y0 = to_px(-max.y, self.height);
+        let y1 = (to_px(-min.y, self.height) + 1).min(self.height as usize);
+        return (x0, x1, y0, y1);
+    }
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        VideoSettings::new(800, 640, 30)
+    }
+}
+
+/// Error of exporting the video
+#[derive(Debug)]
+pub enum VideoError {
+    Io(io::Error),
+    Encoding(gif::EncodingError),
+}
+
+impl fmt::Display for VideoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoError::Io(e) => write!(f, "Error writing video file: {}", e),
+            VideoError::Encoding(e) => write!(f, "Error encoding video: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VideoError {}
+
+impl From<io::Error> for VideoError {
+    fn from(e: io::Error) -> Self {
+        VideoError::Io(e)
+    }
+}
+
+impl From<gif::EncodingError> for VideoError {
+    fn from(e: gif::EncodingError) -> Self {
+        VideoError::Encoding(e)
+    }
+}
+
+/// Rasterizes the frame without GPU. Returns RGBA pixels, row by row from the top
+pub fn render_frame(
+    frame: &Frame,
+    particle_skins: &HashMap<ClassId, ParticleSkin>,
+    wall_skins: &HashMap<ClassId, WallSkin>,
+    settings: &VideoSettings,
+) -> Vec<u8> {
+    let (width, height) = (settings.width as usize, settings.height as usize);
+    let mut pixels: Vec<[f32; 4]> = vec![BACKGROUND; width * height];
+
+    for wall in &frame.walls {
+        let color = wall_skins.get(&wall.class()).map_or(PLACEHOLDER_COLOR, |skin| skin.color());
+        let polygon = wall.polygon();
+        let (min, max) = bounds(polygon);
+        let (x0, x1, y0, y1) = settings.pixel_range(min, max);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if polygon.contains_point(settings.pixel_to_world(x, y)) {
+                    blend(&mut pixels[y * width + x], color);
+                }
+            }
+        }
+    }
+
+    // Particles are drawn over walls
+    for particle in &frame.particles {
+        // Classes without skins are drawn as placeholders, like in the interactive front-end
+        let (radius, color) = match particle_skins.get(&particle.class()) {
+            Some(skin) => (skin.radius() * skin.render_scale(), skin.color()),
+            None => (PLACEHOLDER_RADIUS, PLACEHOLDER_COLOR),
+        };
+        let radius = radius as f64;
+        let offset = Vec2::new(radius, radius);
+        let (x0, x1, y0, y1) = settings.pixel_range(particle.position - offset, particle.position + offset);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let distance = (settings.pixel_to_world(x, y) - particle.position).length();
+                if distance <= radius {
+                    blend(&mut pixels[y * width + x], color);
+                }
+            }
+        }
+    }
+
+    return pixels
+        .iter()
+        .flat_map(|p| p.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect();
+}
+
+/// Renders frames from the stream into animated GIF until the stream is closed.
+/// Frames are sampled by simulation time at the rate from `settings`, so the
+/// video plays in real time regardless of the simulation time step.
+/// Returns number of video frames written
+pub fn export_gif(
+    frames_rx: Receiver<(Duration, Frame)>,
+    path: impl AsRef<Path>,
+    particle_skins: &HashMap<ClassId, ParticleSkin>,
+    wall_skins: &HashMap<ClassId, WallSkin>,
+    settings: &VideoSettings,
+) -> Result<usize, VideoError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, settings.width, settings.height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    // GIF delay is in hundredths of a second
+    let delay = (100.0 / settings.fps as f64).round().max(1.0) as u16;
+    let frame_interval = Duration::from_secs_f64(1.0 / settings.fps as f64);
+
+    let mut next_time = Duration::ZERO;
+    let mut num_frames = 0;
+    for (time, frame) in frames_rx.iter() {
+        if time < next_time {
+            continue;
+        }
+        let mut pixels = render_frame(&frame, particle_skins, wall_skins, settings);
+        let mut gif_frame = gif::Frame::from_rgba_speed(
+            settings.width,
+            settings.height,
+            &mut pixels,
+            GIF_QUANTIZATION_SPEED,
+        );
+        gif_frame.delay = delay;
+        encoder.write_frame(&gif_frame)?;
+        num_frames += 1;
+        while next_time <= time {
+            next_time += frame_interval;
+        }
+    }
+    return Ok(num_frames);
+}
+
+fn bounds(polygon: &Polygon) -> (Vec2, Vec2) {
+    let mut min = Vec2::new(f64::INFINITY, f64::INFINITY);
+    let mut max = Vec2::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in &polygon.points {
+        min = Vec2::new(min.x.min(p.x), min.y.min(p.y));
+        max = Vec2::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    return (min, max);
+}
+
+// Alpha blends the color over the pixel
+fn blend(pixel: &mut [f32; 4], color: Color) {
+    let [r, g, b, a] = color.as_rgba_f32();
+    for (channel, value) in pixel.iter_mut().zip([r, g, b]) {
+        *channel = *channel * (1.0 - a) + value * a;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m_engine::{Particle, Statistics, Wall};
+    use std::sync::mpsc;
+
+    fn skins() -> (HashMap<ClassId, ParticleSkin>, HashMap<ClassId, WallSkin>) {
+        let mut particle_skins = HashMap::new();
+        particle_skins.insert(0, ParticleSkin::new(5.0, Color::rgb(1.0, 0.0, 0.0)));
+        let mut wall_skins = HashMap::new();
+        wall_skins.insert(0, WallSkin::new(Color::rgb(0.0, 0.0, 1.0)));
+        return (particle_skins, wall_skins);
+    }
+
+    fn pixel(pixels: &[u8], settings: &VideoSettings, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * settings.width as usize + x) * 4;
+        return [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]];
+    }
+
+    #[test]
+    fn test_render_frame() {
+        let (particle_skins, wall_skins) = skins();
+        // 1 pixel per world unit
+        let settings = VideoSettings::new(200, 160, 30);
+        let frame = Frame::new(
+            vec![Particle::new(Vec2::new(-50.0, 0.0), Vec2::ZERO, 0)],
+            vec![Wall::new(Polygon::new_rectangle(40.0, -10.0, 60.0, 10.0), 0)],
+            Statistics::default(),
+        );
+        let pixels = render_frame(&frame, &particle_skins, &wall_skins, &settings);
+        assert_eq!(pixels.len(), 200 * 160 * 4);
+        // Particle center, wall center and empty space at the origin
+        assert_eq!(pixel(&pixels, &settings, 50, 80), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, &settings, 150, 80), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, &settings, 100, 80), [102, 102, 102, 255]);
+
+        // Classes without skins get the placeholder
+        let frame = Frame::new(
+            vec![Particle::new(Vec2::new(-50.0, 0.0), Vec2::ZERO, 9)],
+            vec![Wall::new(Polygon::new_rectangle(40.0, -10.0, 60.0, 10.0), 9)],
+            Statistics::default(),
+        );
+        let pixels = render_frame(&frame, &particle_skins, &wall_skins, &settings);
+        assert_eq!(pixel(&pixels, &settings, 50, 80), [255, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, &settings, 150, 80), [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_export_gif() {
+        let (particle_skins, wall_skins) = skins();
+        let settings = VideoSettings::new(100, 80, 10);
+        let (frames_tx, frames_rx) = mpsc::channel();
+        // 1 second of frames every 10ms
+        for i in 0..=100 {
+            let position = Vec2::new(i as f64 - 50.0, 0.0);
+            let frame = Frame::new(
+                vec![Particle::new(position, Vec2::ZERO, 0)],
+                vec![],
+                Statistics::default(),
+            );
+            frames_tx.send((Duration::from_millis(10 * i), frame)).unwrap();
+        }
+        drop(frames_tx);
+
+        let path = std::env::temp_dir().join(format!("m_sim_video_{}.gif", std::process::id()));
+        let num_frames = export_gif(frames_rx, &path, &particle_skins, &wall_skins, &settings).unwrap();
+        // 10 fps sampling of 0..=1s
+        assert_eq!(num_frames, 11);
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use m_engine::{Particle, Statistics, Wall};
+use m_front::Frame;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+// Frame as it's stored: timestamp, particles, walls, statistics, time step and substeps
+type FrameRecord = (Duration, Vec<Particle>, Vec<Wall>, Statistics, Duration, usize, Vec<f32>);
+
+/// Error of reading or writing the binary frame stream
+#[derive(Debug)]
+pub enum FrameIoError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+}
+
+impl fmt::Display for FrameIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameIoError::Io(e) => write!(f, "Error accessing frames file: {}", e),
+            FrameIoError::Encoding(e) => write!(f, "Error encoding frame: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameIoError {}
+
+impl From<io::Error> for FrameIoError {
+    fn from(e: io::Error) -> Self {
+        FrameIoError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for FrameIoError {
+    fn from(e: bincode::Error) -> Self {
+        FrameIoError::Encoding(e)
+    }
+}
+
+/// Writes frames as a stream of bincode records. Each record is prefixed
+/// with its length in bytes as little endian u64
+pub struct FrameWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(writer: W) -> Self {
+        FrameWriter { writer }
+    }
+
+    pub fn write_frame(&mut self, time: Duration, frame: &Frame) -> Result<(), FrameIoError> {
+        let record = (time, &frame.particles, &frame.walls, &*frame.statistics, frame.time_step, frame.substeps, &frame.scalars);
+        let bytes = bincode::serialize(&record)?;
+        self.writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        return Ok(());
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn into_inner(mut self) -> Result<W, FrameIoError> {
+        self.writer.flush()?;
+        return Ok(self.writer);
+    }
+}
+
+/// Reads frames written by `FrameWriter`
+pub struct FrameReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        FrameReader { reader }
+    }
+
+    /// Returns None at the end of the stream. Stream that ends in the middle of
+    /// a record is an error
+    pub fn read_frame(&mut self) -> Result<Option<(Duration, Frame)>, FrameIoError> {
+        let mut len_bytes = [0u8; 8];
+        let mut filled = 0;
+        while filled < len_bytes.len() {
+            match self.reader.read(&mut len_bytes[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => filled += n,
+            }
+        }
+        let mut bytes = vec![0u8; u64::from_le_bytes(len_bytes) as usize];
+        self.reader.read_exact(&mut bytes)?;
+        let (time, particles, walls, statistics, time_step, substeps, scalars): FrameRecord = bincode::deserialize(&bytes)?;
+        let frame = Frame::new(particles, walls, statistics)
+            .with_time_step(time_step)
+            .with_substeps(substeps)
+            .with_scalars(scalars);
+        return Ok(Some((time, frame)));
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<(Duration, Frame), FrameIoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m_engine::{Polygon, Vec2};
+
+    #[test]
+    fn test_roundtrip() {
+        let walls = vec![Wall::new(Polygon::new_rectangle(-1.0, -1.0, 1.0, 1.0), 1)];
+        let mut frames = vec![];
+        for i in 0..5 {
+            let particles = (0..10)
+                .map(|j| {
+                    let position = Vec2::new(i as f64 * 0.1, j as f64 / 3.0);
+                    let particle = Particle::new(position, Vec2::new(-(j as f64), 1e-7), j % 2);
+                    if j == 3 {
+                        particle.with_mass_and_radius(2.5, 0.7)
+                    } else {
+                        particle
+                    }
+                })
+                .collect();
+            let statistics = Statistics {
+                num_particles: 10,
+                temperature: i as f64,
+                ..Default::default()
+            };
+            let frame = Frame::new(particles, walls.clone(), statistics)
+                .with_time_step(Duration::from_millis(10))
+                .with_substeps(2)
+                .with_scalars((0..10).map(|j| j as f32 / 9.0).collect());
+            frames.push((Duration::from_millis(10 * i), frame));
+        }
+
+        let mut writer = FrameWriter::new(Vec::new());
+        for (time, frame) in &frames {
+            writer.write_frame(*time, frame).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        let read: Vec<(Duration, Frame)> = FrameReader::new(bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), frames.len());
+        for ((time, frame), (read_time, read_frame)) in frames.iter().zip(read.iter()) {
+            assert_eq!(time, read_time);
+            assert_eq!(frame.time_step, read_frame.time_step);
+            assert_eq!(frame.substeps, read_frame.substeps);
+            assert_eq!(frame.scalars, read_frame.scalars);
+            assert_eq!(frame.state_hash(), read_frame.state_hash());
+            assert_eq!(frame.statistics.temperature, read_frame.statistics.temperature);
+            for (p, q) in frame.particles.iter().zip(read_frame.particles.iter()) {
+                assert_eq!(p.position, q.position);
+                assert_eq!(p.velocity, q.velocity);
+                assert_eq!(p.class(), q.class());
+                assert_eq!(p.mass_override(), q.mass_override());
+                assert_eq!(p.radius_override(), q.radius_override());
+            }
+        }
+
+        // Truncated stream is an error, not a silent end
+        let mut reader = FrameReader::new(&bytes[..bytes.len() - 3]);
+        for _ in 0..4 {
+            assert!(reader.read_frame().unwrap().is_some());
+        }
+        assert!(reader.read_frame().is_err());
+    }
+}
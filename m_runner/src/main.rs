@@ -1,46 +1,234 @@
-use m_engine::Statistics;
-use m_engine::{Integrator, SimulationSpec, VelocityVerletIntegrator};
-use m_front::{bevy_front, WallSkin};
-use m_front::{Frame, ParticleSkin};
+mod frame_io;
+mod run_summary;
+mod worker;
+
+use frame_io::{FrameReader, FrameWriter};
+use run_summary::{EnsembleSummary, RunSummary};
+
+use m_engine::SimulationSpec;
+use m_front::input_log::{self, InputMode};
+use m_front::video::{self, VideoSettings};
+use m_front::bevy_front::{self, FrontOptions};
+use m_front::WallSkin;
+use m_front::ParticleSkin;
 
 use bevy::prelude::Color;
 
 use std::collections::HashMap;
 use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
 use std::sync::mpsc;
-use std::time::Duration;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: m_runner <path_to_yaml>");
-        return;
+const USAGE: &str = "Usage: m_runner <path_to_yaml[.gz]> [num_threads] [--merge <other.yaml>]... \
+    [--video <out.gif>] [--fps <n>] [--size <width>x<height>] \
+    [--record <out.bin>] [--replay <in.bin>] [--autoplay] \
+    [--record-input <out.log>] [--replay-input <in.log>] [--summary <out.json|->] \
+    [--ensemble-stats <num_members>]";
+
+/// Parsed command line
+#[derive(Debug, PartialEq)]
+struct Args {
+    spec_path: String,
+    /// Specs merged into the main one, in order
+    merge_paths: Vec<String>,
+    num_threads: Option<usize>,
+    /// Render the run offline into this file instead of showing the window
+    video_path: Option<String>,
+    video_settings: VideoSettings,
+    /// Write frames of a single run into this binary file instead of showing the window
+    record_path: Option<String>,
+    /// Show frames from this binary file instead of running the simulation
+    replay_path: Option<String>,
+    /// Start playing right away instead of waiting for [Space]
+    autoplay: bool,
+    /// Write playback commands given by the user into this text file
+    record_input_path: Option<String>,
+    /// Take playback commands from this text file instead of the keyboard
+    replay_input_path: Option<String>,
+    /// Write JSON summaries of the runs into this file once they end. `-` is stdout
+    summary_path: Option<String>,
+    /// Run this many members without the window and report statistics across them
+    ensemble_stats: Option<usize>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut merge_paths = Vec::new();
+    let mut video_path = None;
+    let mut video_settings = VideoSettings::default();
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut autoplay = false;
+    let mut record_input_path = None;
+    let mut replay_input_path = None;
+    let mut summary_path = None;
+    let mut ensemble_stats = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--merge" => merge_paths.push(value()?.clone()),
+            "--video" => video_path = Some(value()?.clone()),
+            "--record" => record_path = Some(value()?.clone()),
+            "--replay" => replay_path = Some(value()?.clone()),
+            "--autoplay" => autoplay = true,
+            "--record-input" => record_input_path = Some(value()?.clone()),
+            "--replay-input" => replay_input_path = Some(value()?.clone()),
+            "--summary" => summary_path = Some(value()?.clone()),
+            "--fps" => {
+                let fps = value()?;
+                video_settings.fps = match fps.parse::<u32>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("Frame rate must be a positive integer: {}", fps)),
+                };
+            }
+            "--ensemble-stats" => {
+                let members = value()?;
+                ensemble_stats = match members.parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(format!("Number of members must be a positive integer: {}", members)),
+                };
+            }
+            "--size" => {
+                let size = value()?;
+                let parsed = size
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse::<u16>().ok()?, h.parse::<u16>().ok()?)));
+                match parsed {
+                    Some((w, h)) if w > 0 && h > 0 => {
+                        video_settings.width = w;
+                        video_settings.height = h;
+                    }
+                    _ => return Err(format!("Size must look like 800x640: {}", size)),
+                }
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(USAGE.to_string());
+    }
+    let num_outputs = [&video_path, &record_path, &replay_path].iter().filter(|p| p.is_some()).count()
+        + ensemble_stats.iter().count();
+    if num_outputs > 1 {
+        return Err("Only one of --video, --record, --replay and --ensemble-stats may be given".to_string());
+    }
+    if record_input_path.is_some() && replay_input_path.is_some() {
+        return Err("Only one of --record-input and --replay-input may be given".to_string());
+    }
+    if summary_path.is_some() && replay_path.is_some() {
+        return Err("Replayed runs are not simulated, so they have no summary".to_string());
     }
+    let has_input = record_input_path.is_some() || replay_input_path.is_some();
+    if has_input && (video_path.is_some() || record_path.is_some() || ensemble_stats.is_some()) {
+        return Err("Input is only recorded or replayed when the window is shown".to_string());
+    }
+    // Number of worker threads
+    let num_threads = match positional.get(1) {
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Err(format!("Number of threads must be a positive integer: {}", arg)),
+        },
+        None => None,
+    };
+    return Ok(Args {
+        spec_path: positional[0].clone(),
+        merge_paths,
+        num_threads,
+        video_path,
+        video_settings,
+        record_path,
+        replay_path,
+        autoplay,
+        record_input_path,
+        replay_input_path,
+        summary_path,
+        ensemble_stats,
+    });
+}
 
-    // Try read yaml
-    let file_contents = match std::fs::read_to_string(&args[1]) {
-        Ok(contents) => contents,
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
         Err(e) => {
-            println!("Error reading file: {}", e);
+            println!("{}", e);
             return;
         }
     };
 
-    // Parse yaml
-    let spec_res = SimulationSpec::from_yaml(&file_contents);
-    if let Err(e) = spec_res {
-        println!("Error loading YAML simulation file: {}", e);
+    // Read and parse yaml. It may be gzip compressed
+    let mut spec = match SimulationSpec::from_path(&args.spec_path) {
+        Ok(spec) => spec,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    for path in &args.merge_paths {
+        let other = match SimulationSpec::from_path(path) {
+            Ok(other) => other,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                return;
+            }
+        };
+        match spec.merge(other) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    println!("{}: {}", path, diagnostic);
+                }
+            }
+            Err(e) => {
+                println!("{}: {}", path, e);
+                return;
+            }
+        }
+    }
+    let diagnostics = spec.validate();
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.is_error()) {
+        return;
+    }
+    // Catch references to undeclared classes before the worker threads start
+    if let Err(e) = spec.try_build() {
+        println!("Invalid simulation spec: {}", e);
+        return;
+    }
+
+    // By default one thread per ensemble member, limited by available cores
+    let num_members = args.ensemble_stats.unwrap_or(spec.ensemble_size);
+    let num_threads = args.num_threads.unwrap_or_else(|| {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        num_members.min(cores)
+    });
+
+    // Statistics across independent runs. No window is opened
+    if args.ensemble_stats.is_some() {
+        let summaries = worker::run_ensemble_headless(&spec, num_members, num_threads);
+        match EnsembleSummary::from_runs(&summaries) {
+            Some(ensemble) => println!("{}", ensemble),
+            None => println!("Ensemble members have no statistics"),
+        }
+        write_summaries(args.summary_path.as_deref(), &summaries);
         return;
     }
-    let spec = spec_res.unwrap();
 
     // Generate skins for particle
     let mut particle_skins = HashMap::new();
     for c in spec.particle_classes.iter() {
-        let skin = ParticleSkin::new(
+        let mut skin = ParticleSkin::new(
             c.radius as f32,
             Color::rgba(c.color.0, c.color.1, c.color.2, c.color.3),
-        );
+        )
+        .with_render_scale(c.render_scale)
+        .with_name(&c.name);
+        if let Some(texture) = &c.texture {
+            skin = skin.with_texture(texture);
+        }
         particle_skins.insert(c.id, skin);
     }
     // Generate skins for walls
@@ -50,75 +238,199 @@ fn main() {
         wall_skins.insert(c.id, skin);
     }
 
-    // Build simulation from spec
-    let mut simulation = spec.build();
+    // Offline rendering of a single run. No window is opened
+    if let Some(video_path) = &args.video_path {
+        let (mut frames_rxs, handles) = worker::run_ensemble(&spec, 1, 1);
+        let frames_rx = frames_rxs.pop().unwrap();
+        match video::export_gif(frames_rx, video_path, &particle_skins, &wall_skins, &args.video_settings) {
+            Ok(num_frames) => println!("Written {} frames to {}", num_frames, video_path),
+            Err(e) => println!("{}", e),
+        }
+        write_summaries(args.summary_path.as_deref(), &worker::join_ensemble(handles));
+        return;
+    }
 
-    // make integrator
-    let integrator = VelocityVerletIntegrator::new();
+    // Binary recording of a single run. No window is opened
+    if let Some(record_path) = &args.record_path {
+        let (mut frames_rxs, handles) = worker::run_ensemble(&spec, 1, 1);
+        let frames_rx = frames_rxs.pop().unwrap();
+        match record_frames(frames_rx, record_path) {
+            Ok(num_frames) => println!("Written {} frames to {}", num_frames, record_path),
+            Err(e) => println!("{}", e),
+        }
+        write_summaries(args.summary_path.as_deref(), &worker::join_ensemble(handles));
+        return;
+    }
 
-    // Channel for communicating with working thread
-    let (frames_tx, frames_rx) = mpsc::channel();
+    // Front-end shows class details
+    let particle_classes = spec.build_particle_classes();
+    let wall_classes = spec.build_wall_classes();
 
-    // Launch the thread that generate frames
-    let handle = std::thread::spawn(move || {
-        let mut current_time = Duration::new(0, 0);
-        // Add 0 frame
-        if let Err(_) = frames_tx.send((
-            current_time.clone(),
-            Frame::new(
-                simulation.particles().to_vec(),
-                simulation.walls().to_vec(),
-                Statistics::build(&simulation.particles(), simulation.particle_classes()),
-            ),
-        )) {
-            return;
+    // Playback commands come from the keyboard or from the log
+    let input_mode = if let Some(path) = &args.replay_input_path {
+        let events = File::open(path)
+            .map_err(input_log::InputLogError::from)
+            .and_then(|file| input_log::read_input_log(BufReader::new(file)));
+        match events {
+            Ok(events) => InputMode::Replay(events),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
         }
+    } else if let Some(path) = &args.record_input_path {
+        InputMode::Record(path.into())
+    } else {
+        InputMode::Live
+    };
+    let options = FrontOptions {
+        window_name: spec.name.clone(),
+        autoplay: args.autoplay,
+        input_mode,
+        scene: spec.clone(),
+    };
 
-        // Generate frames in a separate thread
-        while current_time < spec.duration {
-            // Take particles out to please borrow checker
-            let mut tmp_particles = simulation.take_particles();
-            // Update simulation
-            integrator.step(
-                &mut tmp_particles,
-                simulation.particle_classes(),
-                simulation.walls(),
-                simulation.wall_classes(),
-                simulation.gravity(),
-                spec.time_step,
-            );
-            // Return particles back
-            simulation.put_particles(tmp_particles);
-            current_time += spec.time_step;
-
-            // Calc statistics
-            let statistics =
-                Statistics::build(simulation.particles(), simulation.particle_classes());
-
-            // Send frame
-            if let Err(_) = frames_tx.send((
-                current_time.clone(),
-                Frame::new(
-                    simulation.particles().to_vec(),
-                    simulation.walls().to_vec(),
-                    statistics,
-                ),
-            )) {
+    // Show the recorded run. Spec still provides the skins and classes
+    if let Some(replay_path) = &args.replay_path {
+        let file = match File::open(replay_path) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Error opening {}: {}", replay_path, e);
                 return;
             }
+        };
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            for result in FrameReader::new(BufReader::new(file)) {
+                match result {
+                    Ok(frame) => {
+                        if frames_tx.send(frame).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                }
+            }
+        });
+        bevy_front::run(
+            vec![frames_rx],
+            spec.duration,
+            particle_skins,
+            wall_skins,
+            particle_classes,
+            wall_classes,
+            options,
+        );
+        handle.join().unwrap();
+        return;
+    }
 
-            // simulate slow work
-            //std::thread::sleep(std::time::Duration::from_millis(1000));
-        }
-    });
+    // Launch the threads that generate frames. Each ensemble member has its own stream
+    let (frames_rxs, handles) = worker::run_ensemble(&spec, spec.ensemble_size, num_threads);
 
     bevy_front::run(
-        &spec.name,
-        frames_rx,
+        frames_rxs,
         spec.duration,
         particle_skins,
         wall_skins,
+        particle_classes,
+        wall_classes,
+        options,
     );
 
-    handle.join().unwrap();
+    write_summaries(args.summary_path.as_deref(), &worker::join_ensemble(handles));
+}
+
+/// Writes summaries of the ensemble members as JSON array to the file, or to stdout
+/// if the path is `-`. Nothing is written without the path
+fn write_summaries(path: Option<&str>, summaries: &[RunSummary]) {
+    let Some(path) = path else {
+        return;
+    };
+    let result = match path {
+        "-" => serde_json::to_writer_pretty(io::stdout().lock(), summaries)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(io::stdout())),
+        _ => File::create(path).and_then(|file| {
+            serde_json::to_writer_pretty(BufWriter::new(file), summaries).map_err(io::Error::from)
+        }),
+    };
+    if let Err(e) = result {
+        println!("Error writing summary to {}: {}", path, e);
+    }
+}
+
+/// Writes frames from the stream into the binary file until the stream is closed.
+/// Returns number of frames written
+fn record_frames(frames_rx: worker::FramesRx, path: &str) -> Result<usize, frame_io::FrameIoError> {
+    let mut writer = FrameWriter::new(BufWriter::new(File::create(path)?));
+    let mut num_frames = 0;
+    for (time, frame) in frames_rx.iter() {
+        writer.write_frame(time, &frame)?;
+        num_frames += 1;
+    }
+    writer.into_inner()?;
+    return Ok(num_frames);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Args, String> {
+        let args: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+        return parse_args(&args);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse("scene.yaml 4").unwrap();
+        assert_eq!(args.spec_path, "scene.yaml");
+        assert_eq!(args.num_threads, Some(4));
+        assert_eq!(args.video_path, None);
+        assert!(args.merge_paths.is_empty());
+
+        let args = parse("walls.yaml --merge gas.yaml --merge tracers.yaml").unwrap();
+        assert_eq!(args.spec_path, "walls.yaml");
+        assert_eq!(args.merge_paths, vec!["gas.yaml", "tracers.yaml"]);
+        assert!(parse("walls.yaml --merge").is_err());
+
+        let args = parse("scene.yaml --video out.gif --fps 25 --size 320x240").unwrap();
+        assert_eq!(args.num_threads, None);
+        assert_eq!(args.video_path.as_deref(), Some("out.gif"));
+        assert_eq!(args.video_settings, VideoSettings::new(320, 240, 25));
+
+        let args = parse("scene.yaml --record run.bin").unwrap();
+        assert_eq!(args.record_path.as_deref(), Some("run.bin"));
+        assert_eq!(args.replay_path, None);
+        assert!(!args.autoplay);
+        assert!(parse("scene.yaml --autoplay").unwrap().autoplay);
+        let args = parse("scene.yaml --replay run.bin").unwrap();
+        assert_eq!(args.replay_path.as_deref(), Some("run.bin"));
+        assert!(parse("scene.yaml --record a.bin --replay b.bin").is_err());
+        let args = parse("scene.yaml --replay run.bin --replay-input run.log").unwrap();
+        assert_eq!(args.replay_input_path.as_deref(), Some("run.log"));
+        assert_eq!(args.record_input_path, None);
+        assert!(parse("scene.yaml --record-input a.log --replay-input b.log").is_err());
+        assert!(parse("scene.yaml --video out.gif --record-input a.log").is_err());
+        let args = parse("scene.yaml --record run.bin --summary run.json").unwrap();
+        assert_eq!(args.summary_path.as_deref(), Some("run.json"));
+        assert_eq!(parse("scene.yaml --summary -").unwrap().summary_path.as_deref(), Some("-"));
+        assert!(parse("scene.yaml --replay run.bin --summary run.json").is_err());
+        let args = parse("scene.yaml 8 --ensemble-stats 32 --summary runs.json").unwrap();
+        assert_eq!(args.ensemble_stats, Some(32));
+        assert_eq!(args.num_threads, Some(8));
+        assert_eq!(parse("scene.yaml").unwrap().ensemble_stats, None);
+        assert!(parse("scene.yaml --ensemble-stats 0").is_err());
+        assert!(parse("scene.yaml --ensemble-stats 4 --record run.bin").is_err());
+        assert!(parse("scene.yaml --ensemble-stats 4 --replay-input run.log").is_err());
+
+        assert!(parse("").is_err());
+        assert!(parse("scene.yaml --video").is_err());
+        assert!(parse("scene.yaml --size 320").is_err());
+        assert!(parse("scene.yaml --fps 0").is_err());
+    }
 }
@@ -0,0 +1,128 @@
+use m_engine::{EnergyDrift, Statistics, StatisticsAccumulator, StepReport, WindowedStatistics, WindowedValue};
+
+use serde::Serialize;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Machine-readable outcome of a single run. Written as JSON with `--summary`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    /// Including the initial frame
+    pub num_frames: usize,
+    pub simulated_time_sec: f64,
+    pub final_particle_count: usize,
+    /// Averages over all statistics samples of the run
+    pub averages: Option<WindowedStatistics>,
+    /// Collisions with particles and walls over all steps
+    pub total_collisions: usize,
+    pub energy_drift: EnergyDrift,
+    /// Wall-clock time spent generating the frames
+    pub compute_time_sec: f64,
+}
+
+/// Gathers the summary while the frames of a run are generated
+pub struct SummaryAccumulator {
+    start: Instant,
+    num_frames: usize,
+    time: Duration,
+    particle_count: usize,
+    collisions: usize,
+    statistics: StatisticsAccumulator,
+    energy_drift: EnergyDrift,
+}
+
+impl SummaryAccumulator {
+    /// `duration` of the run. Statistics samples are averaged over all of it
+    pub fn new(duration: Duration) -> Self {
+        SummaryAccumulator {
+            start: Instant::now(),
+            num_frames: 0,
+            time: Duration::ZERO,
+            particle_count: 0,
+            collisions: 0,
+            statistics: StatisticsAccumulator::new(duration),
+            energy_drift: EnergyDrift::new(),
+        }
+    }
+
+    /// Adds a produced frame
+    pub fn add_frame(&mut self, time: Duration, particle_count: usize) {
+        self.num_frames += 1;
+        self.time = time;
+        self.particle_count = particle_count;
+    }
+
+    pub fn add_step(&mut self, report: &StepReport) {
+        self.collisions += report.collisions;
+    }
+
+    /// Adds a fresh statistics sample taken at `time`
+    pub fn add_statistics(&mut self, time: Duration, statistics: &Statistics) {
+        self.statistics.add(time, statistics);
+        self.energy_drift.add(statistics);
+    }
+
+    pub fn finish(&self) -> RunSummary {
+        RunSummary {
+            num_frames: self.num_frames,
+            simulated_time_sec: self.time.as_secs_f64(),
+            final_particle_count: self.particle_count,
+            averages: self.statistics.averages(),
+            total_collisions: self.collisions,
+            energy_drift: self.energy_drift.clone(),
+            compute_time_sec: self.start.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// Run averages compared across the ensemble members. Every value is the mean of the members'
+/// run averages with its standard error, so it shrinks as members are added
+#[derive(Debug, Clone, Serialize)]
+pub struct EnsembleSummary {
+    /// Members that have statistics. Others are left out
+    pub num_members: usize,
+    pub total_energy: WindowedValue,
+    pub temperature: WindowedValue,
+    pub mean_speed: WindowedValue,
+    /// Only members that have pressure are averaged
+    pub pressure: Option<WindowedValue>,
+    pub final_particle_count: WindowedValue,
+}
+
+impl EnsembleSummary {
+    /// None if none of the runs has statistics
+    pub fn from_runs(runs: &[RunSummary]) -> Option<Self> {
+        let runs: Vec<(&RunSummary, &WindowedStatistics)> =
+            runs.iter().filter_map(|run| run.averages.as_ref().map(|averages| (run, averages))).collect();
+        let collect = |quantity: fn(&RunSummary, &WindowedStatistics) -> f64| -> Vec<f64> {
+            return runs.iter().map(|(run, averages)| quantity(run, averages)).collect();
+        };
+        let pressures: Vec<f64> = runs.iter().filter_map(|(_, averages)| averages.pressure).map(|p| p.mean).collect();
+        return Some(EnsembleSummary {
+            num_members: runs.len(),
+            total_energy: WindowedValue::from_values(&collect(|_, a| a.total_energy.mean))?,
+            temperature: WindowedValue::from_values(&collect(|_, a| a.temperature.mean))?,
+            mean_speed: WindowedValue::from_values(&collect(|_, a| a.mean_speed.mean))?,
+            pressure: WindowedValue::from_values(&pressures),
+            final_particle_count: WindowedValue::from_values(&collect(|run, _| run.final_particle_count as f64))?,
+        });
+    }
+}
+
+impl fmt::Display for EnsembleSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &WindowedValue| match value.standard_error {
+            Some(error) => format!("{} ± {}", value.mean, error),
+            None => format!("{}", value.mean),
+        };
+        writeln!(f, "Ensemble of {} members:", self.num_members)?;
+        writeln!(f, "  Total energy: {}", value(&self.total_energy))?;
+        writeln!(f, "  Temperature: {}", value(&self.temperature))?;
+        writeln!(f, "  Mean speed: {}", value(&self.mean_speed))?;
+        if let Some(pressure) = &self.pressure {
+            writeln!(f, "  Pressure: {}", value(pressure))?;
+        }
+        return write!(f, "  Final particle count: {}", value(&self.final_particle_count));
+    }
+}
@@ -0,0 +1,631 @@
+use m_engine::prelude::ParticleId;
+use m_engine::{EquilibriumDetector, Particle, Simulation, SimulationSpec, Statistics, Vec2, VelocityVerletIntegrator, WallLoad};
+use m_front::Frame;
+
+use crate::run_summary::{RunSummary, SummaryAccumulator};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Wall load in statistics sums the impulses of this much simulation time
+const WALL_LOAD_WINDOW: Duration = Duration::from_secs(1);
+
+/// Stream of frames produced by a single simulation
+pub type FramesRx = Receiver<(Duration, Frame)>;
+
+/// Thread of the ensemble pool. Returns summaries of the members it ran with their indices
+pub type EnsembleHandle = JoinHandle<Vec<(usize, RunSummary)>>;
+
+/// Speed of every particle relative to the fastest one. Front-end can size particles by it
+fn speed_scalars(particles: &[Particle]) -> Vec<f32> {
+    let max_speed = particles.iter().map(|p| p.velocity.length()).fold(0.0, f64::max);
+    if max_speed <= 0.0 {
+        return vec![0.0; particles.len()];
+    }
+    return particles.iter().map(|p| (p.velocity.length() / max_speed) as f32).collect();
+}
+
+/// Runs simulation until the spec duration and sends every frame into the channel.
+/// Overlaps are relaxed before the first frame, if the spec asks for it.
+/// Time stepping, substepping and statistics sampling follow the spec. Only whole steps that fit
+/// into the duration are taken.
+/// Returns early if the receiving side is closed, or once statistics settle if the spec
+/// has an equilibrium criterion. The channel closes either way, which marks the run complete.
+/// Returns the summary of the produced frames
+pub fn generate_frames(
+    mut simulation: Simulation,
+    spec: &SimulationSpec,
+    frames_tx: Sender<(Duration, Frame)>,
+) -> RunSummary {
+    let mut integrator = VelocityVerletIntegrator::new()
+        .with_substeps(spec.substeps.max(1))
+        .with_line_motion(spec.line_motion);
+    if let Some(seed) = spec.seed {
+        integrator = integrator.with_seed(seed);
+    }
+    let mut current_time = Duration::new(0, 0);
+    // Initial overlaps would pop particles apart in the first steps
+    if let Some(relaxation) = &spec.relaxation {
+        let deepest = simulation.relax_overlaps(relaxation);
+        if deepest > relaxation.tolerance {
+            println!("Relaxation left particles overlapping by up to {}", deepest);
+        }
+    }
+    // Displacement is measured from the initial positions
+    let reference_positions: HashMap<ParticleId, Vec2> = simulation
+        .particles()
+        .iter()
+        .filter_map(|p| p.id().map(|id| (id, p.position)))
+        .collect();
+    let mut initial_statistics = Statistics::build_with_walls(
+        &simulation.particles(),
+        simulation.particle_classes(),
+        simulation.units(),
+        simulation.walls(),
+        simulation.wall_classes(),
+    );
+    initial_statistics.add_mean_square_displacement(simulation.particles(), &reference_positions);
+    let mut equilibrium = spec.equilibrium.map(EquilibriumDetector::new);
+    if let Some(detector) = &mut equilibrium {
+        detector.add(&initial_statistics);
+    }
+    let mut summary = SummaryAccumulator::new(spec.duration);
+    summary.add_statistics(current_time, &initial_statistics);
+    summary.add_frame(current_time, simulation.particles().len());
+    let mut statistics = Arc::new(initial_statistics);
+    let mut wall_load = WallLoad::new(WALL_LOAD_WINDOW);
+    // Add 0 frame
+    if let Err(_) = frames_tx.send((
+        current_time.clone(),
+        Frame::new(
+            simulation.particles().to_vec(),
+            simulation.walls().to_vec(),
+            statistics.clone(),
+        )
+        .with_scalars(speed_scalars(simulation.particles())),
+    )) {
+        return summary.finish();
+    }
+
+    let mut frame_index = 0;
+    loop {
+        let time_step = match &spec.adaptive_time_step {
+            Some(adaptive) => adaptive.choose(simulation.particles(), simulation.particle_classes()),
+            None => spec.time_step,
+        };
+        // Zero step would never reach the end. Step that overshoots the duration isn't taken
+        if time_step.is_zero() || current_time + time_step > spec.duration {
+            return summary.finish();
+        }
+        let report = simulation.step(&integrator, time_step, None);
+        current_time += time_step;
+        frame_index += 1;
+        wall_load.add(current_time, &report.wall_impulse);
+        summary.add_step(&report);
+        for index in &report.non_finite_particles {
+            println!("Step {}: particle {} got non-finite position or velocity", frame_index, index);
+        }
+
+        // Calc statistics, if it's time to sample them
+        let mut settled = false;
+        if frame_index % spec.statistics_interval.max(1) == 0 {
+            let mut new_statistics = Statistics::build_with_walls(
+                simulation.particles(),
+                simulation.particle_classes(),
+                simulation.units(),
+                simulation.walls(),
+                simulation.wall_classes(),
+            );
+            if let Some(area) = simulation.walls_bounding_area() {
+                new_statistics.add_pressure_tensor(
+                    simulation.particles(),
+                    simulation.particle_classes(),
+                    &report,
+                    time_step.as_secs_f64(),
+                    area,
+                );
+            }
+            new_statistics.add_mean_square_displacement(simulation.particles(), &reference_positions);
+            // Window isn't full at the start
+            let wall_impulses = wall_load.impulses();
+            let load_duration = wall_load.window().min(current_time).as_secs_f64();
+            new_statistics.add_wall_pressures(simulation.walls(), &wall_impulses, load_duration);
+            new_statistics.wall_impulses = wall_impulses;
+            if let Some(detector) = &mut equilibrium {
+                settled = detector.add(&new_statistics);
+            }
+            summary.add_statistics(current_time, &new_statistics);
+            statistics = Arc::new(new_statistics);
+        }
+
+        // Send frame
+        summary.add_frame(current_time, simulation.particles().len());
+        if let Err(_) = frames_tx.send((
+            current_time.clone(),
+            Frame::new(
+                simulation.particles().to_vec(),
+                simulation.walls().to_vec(),
+                statistics.clone(),
+            )
+            .with_time_step(time_step)
+            .with_substeps(report.substeps)
+            .with_scalars(speed_scalars(simulation.particles())),
+        )) {
+            return summary.finish();
+        }
+        if settled {
+            println!("Equilibrium reached at {:.3}s, generation stopped", current_time.as_secs_f64());
+            return summary.finish();
+        }
+    }
+}
+
+/// Builds `num_members` independent simulations from the same spec and runs them on
+/// a pool of `num_threads` threads. Each member gets its own stream of frames.
+/// Members differ by the random initial state generated by the spec, or by their seeds
+/// if the spec is seeded.
+/// If there are fewer threads than members, remaining members wait for a free thread.
+/// Summaries of the members are collected by `join_ensemble`
+pub fn run_ensemble(
+    spec: &SimulationSpec,
+    num_members: usize,
+    num_threads: usize,
+) -> (Vec<FramesRx>, Vec<EnsembleHandle>) {
+    assert!(num_members > 0);
+    assert!(num_threads > 0);
+
+    // Queue of members waiting for a thread
+    let mut jobs = VecDeque::new();
+    let mut receivers = Vec::new();
+    for member in 0..num_members {
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let member_spec = spec.member_spec(member);
+        let simulation = member_spec.build();
+        jobs.push_back((member, (member_spec, simulation, frames_tx)));
+        receivers.push(frames_rx);
+    }
+    let handles = run_pool(jobs, num_threads, |(spec, simulation, frames_tx)| {
+        let summary = generate_frames(simulation, &spec, frames_tx);
+        // Large drift in an elastic scene without gravity flags a collision bug
+        println!("{}", summary.energy_drift);
+        return summary;
+    });
+    return (receivers, handles);
+}
+
+/// Runs `num_members` members of the spec to completion without showing them, on a pool of
+/// `num_threads` threads. Frames are dropped as they come. Returns summaries in the order
+/// of the members
+pub fn run_ensemble_headless(spec: &SimulationSpec, num_members: usize, num_threads: usize) -> Vec<RunSummary> {
+    assert!(num_members > 0);
+    assert!(num_threads > 0);
+
+    let jobs = (0..num_members).map(|member| (member, spec.member_spec(member))).collect();
+    let handles = run_pool(jobs, num_threads, |spec| {
+        // Generation stops once nobody receives the frames, so they are drained aside
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let drain = std::thread::spawn(move || frames_rx.iter().count());
+        let summary = generate_frames(spec.build(), &spec, frames_tx);
+        drain.join().unwrap();
+        return summary;
+    });
+    return join_ensemble(handles);
+}
+
+/// Runs the queued jobs of the ensemble members on `num_threads` threads. A thread takes
+/// the next job once it's done with the previous one
+fn run_pool<J: Send + 'static>(
+    jobs: VecDeque<(usize, J)>,
+    num_threads: usize,
+    run: fn(J) -> RunSummary,
+) -> Vec<EnsembleHandle> {
+    let num_jobs = jobs.len();
+    let jobs = Arc::new(Mutex::new(jobs));
+    let mut handles = Vec::new();
+    for _ in 0..num_threads.min(num_jobs) {
+        let jobs = jobs.clone();
+        handles.push(std::thread::spawn(move || {
+            let mut summaries = Vec::new();
+            loop {
+                let job = jobs.lock().unwrap().pop_front();
+                match job {
+                    Some((member, job)) => summaries.push((member, run(job))),
+                    None => return summaries,
+                }
+            }
+        }));
+    }
+    return handles;
+}
+
+/// Waits for the ensemble threads. Returns summaries in the order of the members
+pub fn join_ensemble(handles: Vec<EnsembleHandle>) -> Vec<RunSummary> {
+    let mut summaries: Vec<(usize, RunSummary)> =
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+    summaries.sort_by_key(|(member, _)| *member);
+    return summaries.into_iter().map(|(_, summary)| summary).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_summary::EnsembleSummary;
+    use m_engine::simulation_spec::{SpawnParticle, SpawnParticlesGrid, SpawnStraightWall, VelocityDistribution, RGBA};
+    use m_engine::prelude::ClassId;
+    use m_engine::{EquilibriumCriterion, ParticleClassSpec, WallClassSpec};
+
+    #[test]
+    fn test_ensemble_members_produce_full_streams() {
+        let spec = SimulationSpec {
+            duration: Duration::from_millis(100),
+            time_step: Duration::from_millis(10),
+            particle_classes: vec![particle_class_spec(0, 0.5)],
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 0,
+                origin_x: 0.0,
+                origin_y: 0.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 4,
+                num_cells_y: 4,
+                mean_speed: 5.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            ..Default::default()
+        };
+        // More members than threads, so some members wait in the queue
+        let num_members = 5;
+        let (receivers, handles) = run_ensemble(&spec, num_members, 2);
+        assert_eq!(receivers.len(), num_members);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for frames_rx in receivers {
+            let frames: Vec<(Duration, Frame)> = frames_rx.iter().collect();
+            assert_eq!(frames.len(), 11);
+            assert_eq!(frames.last().unwrap().0, spec.duration);
+            let num_particles = frames[0].1.particles.len();
+            assert!(num_particles > 0);
+            assert!(frames.iter().all(|(_, frame)| frame.particles.len() == num_particles));
+        }
+    }
+
+    #[test]
+    fn test_speed_scalars() {
+        let particles = [
+            Particle::new(Vec2::ZERO, Vec2::new(3.0, 4.0), 0),
+            Particle::new(Vec2::ZERO, Vec2::new(0.0, 2.5), 0),
+            Particle::new(Vec2::ZERO, Vec2::ZERO, 0),
+        ];
+        assert_eq!(speed_scalars(&particles), vec![1.0, 0.5, 0.0]);
+        // Nothing moves
+        assert_eq!(speed_scalars(&particles[2..]), vec![0.0]);
+        assert!(speed_scalars(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_degenerate_time_steps() {
+        // Duration shorter than a step produces only the initial frame
+        let mut spec = SimulationSpec {
+            duration: Duration::from_millis(5),
+            time_step: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        assert_eq!(frames_rx.iter().count(), 1);
+
+        // Zero step doesn't spin forever
+        spec.time_step = Duration::ZERO;
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        assert_eq!(frames_rx.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_substeps() {
+        // Single particle without gravity moves the same way however the step is split
+        let mut spec = SimulationSpec {
+            duration: Duration::from_millis(100),
+            time_step: Duration::from_millis(10),
+            particle_classes: vec![particle_class_spec(0, 0.1)],
+            particles: vec![
+                SpawnParticle { class_id: 0, x: 0.0, y: 0.0, vx: 3.0, vy: -1.0, mass: None, radius: None },
+            ],
+            ..Default::default()
+        };
+        let run = |spec: &SimulationSpec| {
+            let (frames_tx, frames_rx) = mpsc::channel();
+            generate_frames(spec.build(), spec, frames_tx);
+            return frames_rx.iter().collect::<Vec<(Duration, Frame)>>();
+        };
+        let single = run(&spec);
+        spec.substeps = 4;
+        let split = run(&spec);
+
+        assert_eq!(single.len(), split.len());
+        // Every step runs the resolver once per substep
+        assert!(split[1..].iter().all(|(_, frame)| frame.substeps == 4));
+        assert!(single[1..].iter().all(|(_, frame)| frame.substeps == 1));
+        assert_eq!(split[1].1.substep_duration(), Duration::from_micros(2500));
+        let p = &single.last().unwrap().1.particles[0];
+        let q = &split.last().unwrap().1.particles[0];
+        assert!(p.position.approx_eq(q.position, 1e-9));
+        assert!(p.position.approx_eq(Vec2::new(0.3, -0.1), 1e-9));
+        assert_eq!(p.velocity, q.velocity);
+    }
+
+    // Class of unit mass
+    fn particle_class_spec(id: ClassId, radius: f64) -> ParticleClassSpec {
+        return ParticleClassSpec {
+            id,
+            name: format!("class{}", id),
+            mass: 1.0,
+            radius,
+            color: RGBA(1.0, 1.0, 1.0, 1.0),
+            render_scale: 1.0,
+            gravity_scale: 1.0,
+            interaction_cutoff: None,
+            texture: None,
+        };
+    }
+
+    // Gas in a closed box with walls that don't exchange heat
+    fn gas_in_box_spec() -> SimulationSpec {
+        let wall = |from_x, from_y, to_x, to_y| SpawnStraightWall {
+            class_id: 0,
+            from_x,
+            from_y,
+            to_x,
+            to_y,
+            width: 2.0,
+        };
+        return SimulationSpec {
+            duration: Duration::from_secs(2),
+            time_step: Duration::from_millis(10),
+            particle_classes: vec![particle_class_spec(0, 0.3)],
+            wall_classes: vec![WallClassSpec {
+                id: 0,
+                name: "box".to_string(),
+                temperature: 0.0,
+                heat_conductivity: 0.0,
+                diffuse_reflection: false,
+                absorbing: false,
+                restitution: None,
+                heat_capacity: None,
+                color: RGBA(1.0, 1.0, 1.0, 1.0),
+            }],
+            particle_grids: vec![SpawnParticlesGrid {
+                class_id: 0,
+                origin_x: -5.0,
+                origin_y: -5.0,
+                x_axis_angle: 0.0,
+                dim_x: 10.0,
+                dim_y: 10.0,
+                num_cells_x: 5,
+                num_cells_y: 5,
+                mean_speed: 5.0,
+                velocity_distribution: VelocityDistribution::Uniform,
+            }],
+            straight_walls: vec![
+                wall(-10.0, -10.0, 10.0, -10.0),
+                wall(10.0, -10.0, 10.0, 10.0),
+                wall(10.0, 10.0, -10.0, 10.0),
+                wall(-10.0, 10.0, -10.0, -10.0),
+            ],
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_energy_drift() {
+        let mut spec = gas_in_box_spec();
+        let (frames_tx, _frames_rx) = mpsc::channel();
+        let drift = generate_frames(spec.build(), &spec, frames_tx).energy_drift;
+        assert!(drift.initial().unwrap() > 0.0);
+        assert!(drift.relative_max_deviation().unwrap() < 1e-6, "{}", drift);
+
+        // Particles that hit absorbing walls take their energy away
+        spec.wall_classes[0].absorbing = true;
+        let (frames_tx, _frames_rx) = mpsc::channel();
+        let drift = generate_frames(spec.build(), &spec, frames_tx).energy_drift;
+        assert!(drift.relative_max_deviation().unwrap() > 0.1, "{}", drift);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let spec = gas_in_box_spec();
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let summary = generate_frames(spec.build(), &spec, frames_tx);
+        let frames: Vec<(Duration, Frame)> = frames_rx.iter().collect();
+
+        assert_eq!(summary.num_frames, frames.len());
+        assert_eq!(summary.simulated_time_sec, spec.duration.as_secs_f64());
+        // Nothing is absorbed
+        assert_eq!(summary.final_particle_count, frames[0].1.particles.len());
+        let averages = summary.averages.unwrap();
+        assert_eq!(averages.num_samples, frames.len());
+        // Energy is conserved, so its average is the initial one
+        let initial_energy = frames[0].1.statistics.total_energy;
+        assert!((averages.total_energy.mean - initial_energy).abs() < 1e-6 * initial_energy);
+        assert!(averages.temperature.mean > 0.0);
+        assert!(averages.pressure.unwrap().mean > 0.0);
+        // Particles cross the box several times in 2 seconds
+        assert!(summary.total_collisions > summary.final_particle_count);
+        assert!(summary.compute_time_sec > 0.0);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        for field in ["num_frames", "final_particle_count", "averages", "total_collisions", "compute_time_sec"] {
+            assert!(json.get(field).is_some(), "{}", field);
+        }
+        assert_eq!(json["averages"]["temperature"]["mean"], averages.temperature.mean);
+    }
+
+    #[test]
+    fn test_ensemble_statistics() {
+        let mut spec = gas_in_box_spec();
+        spec.duration = Duration::from_millis(500);
+        spec.seed = Some(3);
+        let ensemble = |num_members: usize| {
+            let summaries = run_ensemble_headless(&spec, num_members, 4);
+            assert_eq!(summaries.len(), num_members);
+            assert!(summaries.iter().all(|s| s.simulated_time_sec == 0.5));
+            return EnsembleSummary::from_runs(&summaries).unwrap();
+        };
+        let small = ensemble(3);
+        let large = ensemble(24);
+        assert_eq!(large.num_members, 24);
+
+        // All particles start at speed 5 and walls keep the energy.
+        // Temperature is 3/2 of the mean kinetic energy
+        let expected = 1.5 * 0.5 * 25.0;
+        assert!((large.temperature.mean - expected).abs() < 1e-6 * expected, "{}", large);
+        // Wall hits differ between members, so pressures do
+        let small_error = small.pressure.unwrap().standard_error.unwrap();
+        let large_error = large.pressure.unwrap().standard_error.unwrap();
+        assert!(large_error < small_error, "{} vs {}", large_error, small_error);
+        // Walls keep all particles
+        assert_eq!(large.final_particle_count.standard_error, Some(0.0));
+
+        // Seeded ensemble is reproducible
+        assert_eq!(ensemble(3).pressure, small.pressure);
+    }
+
+    #[test]
+    fn test_equilibrium_stops_generation() {
+        // Particles far from each other and without walls keep their energy exactly
+        let spec = SimulationSpec {
+            duration: Duration::from_secs(10),
+            time_step: Duration::from_millis(10),
+            statistics_interval: 2,
+            equilibrium: Some(EquilibriumCriterion::new(5, 1e-6)),
+            particle_classes: vec![particle_class_spec(0, 0.1)],
+            particles: vec![
+                SpawnParticle { class_id: 0, x: 0.0, y: 0.0, vx: 1.0, vy: 0.0, mass: None, radius: None },
+                SpawnParticle { class_id: 0, x: 0.0, y: 10.0, vx: 0.0, vy: 2.0, mass: None, radius: None },
+            ],
+            ..Default::default()
+        };
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        let frames: Vec<(Duration, Frame)> = frames_rx.iter().collect();
+        // Initial sample and 4 more, taken every 2 steps
+        assert_eq!(frames.len(), 9);
+        assert!(frames.last().unwrap().0 < spec.duration);
+
+        // Nothing settles while energy keeps changing, so the run goes to the end
+        let spec = SimulationSpec {
+            duration: Duration::from_millis(100),
+            equilibrium: Some(EquilibriumCriterion::new(5, 0.0)),
+            gravity: -10.0,
+            ..spec
+        };
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        assert_eq!(frames_rx.iter().last().unwrap().0, spec.duration);
+    }
+
+    #[test]
+    fn test_statistics_sampling_interval() {
+        let spec = SimulationSpec {
+            duration: Duration::from_millis(100),
+            time_step: Duration::from_millis(10),
+            statistics_interval: 3,
+            ..Default::default()
+        };
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        let frames: Vec<(Duration, Frame)> = frames_rx.iter().collect();
+        assert_eq!(frames.len(), 11);
+        // Statistics are recomputed on frames 3, 6, 9. Others share the previous ones
+        for i in 1..frames.len() {
+            let shared = Arc::ptr_eq(&frames[i].1.statistics, &frames[i - 1].1.statistics);
+            assert_eq!(shared, i % 3 != 0, "frame {}", i);
+        }
+    }
+
+    #[test]
+    fn test_absorbing_walls_reduce_particle_count() {
+        let grid = |class_id, origin_x| SpawnParticlesGrid {
+            class_id,
+            origin_x,
+            origin_y: 0.0,
+            x_axis_angle: 0.0,
+            dim_x: 3.0,
+            dim_y: 3.0,
+            num_cells_x: 3,
+            num_cells_y: 3,
+            mean_speed: 5.0,
+            velocity_distribution: VelocityDistribution::Uniform,
+        };
+        // Box around the particles, its wide walls absorb them
+        let mut spec = SimulationSpec {
+            duration: Duration::from_secs(3),
+            particle_classes: vec![particle_class_spec(0, 0.2), particle_class_spec(1, 0.2)],
+            particle_grids: vec![grid(0, -5.0), grid(1, 2.0)],
+            ..gas_in_box_spec()
+        };
+        spec.wall_classes[0].absorbing = true;
+        let (frames_tx, frames_rx) = mpsc::channel();
+        generate_frames(spec.build(), &spec, frames_tx);
+        let frames: Vec<(Duration, Frame)> = frames_rx.iter().collect();
+        let counts: Vec<usize> = frames.iter().map(|(_, f)| f.statistics.num_particles).collect();
+        // Per class counts add up to the total
+        for (_, frame) in &frames {
+            let class_total: usize = frame.statistics.class_counts.values().sum();
+            assert_eq!(class_total, frame.statistics.num_particles);
+        }
+
+        // Particles are only lost. Most of them reach the walls within the run
+        assert_eq!(counts[0], 32);
+        assert!(counts.windows(2).all(|w| w[1] <= w[0]));
+        assert!(*counts.last().unwrap() < counts[0] / 2);
+    }
+
+    #[test]
+    fn test_seeded_runs_are_identical() {
+        // Random initial velocities of both kinds and hot rough walls that sample their temperature
+        let grid = |origin_x, velocity_distribution| SpawnParticlesGrid {
+            class_id: 0,
+            origin_x,
+            origin_y: -3.0,
+            x_axis_angle: 0.0,
+            dim_x: 4.0,
+            dim_y: 6.0,
+            num_cells_x: 4,
+            num_cells_y: 6,
+            mean_speed: 5.0,
+            velocity_distribution,
+        };
+        let mut spec = SimulationSpec {
+            duration: Duration::from_secs(1),
+            particle_grids: vec![
+                grid(-6.0, VelocityDistribution::Uniform),
+                grid(2.0, VelocityDistribution::Maxwell),
+            ],
+            seed: Some(17),
+            ..gas_in_box_spec()
+        };
+        let hot = &mut spec.wall_classes[0];
+        hot.temperature = 50.0;
+        hot.heat_conductivity = 0.5;
+        hot.diffuse_reflection = true;
+        let run = |spec: &SimulationSpec| -> Vec<String> {
+            let (frames_tx, frames_rx) = mpsc::channel();
+            generate_frames(spec.build(), spec, frames_tx);
+            // Debug output has every float in full precision
+            return frames_rx.iter().map(|frame| format!("{:?}", frame)).collect();
+        };
+
+        let first = run(&spec);
+        assert_eq!(first.len(), 101);
+        assert_eq!(first, run(&spec));
+        let mut other_seed = spec.clone();
+        other_seed.seed = Some(18);
+        assert_ne!(first, run(&other_seed));
+    }
+}